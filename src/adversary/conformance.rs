@@ -0,0 +1,209 @@
+//! This module contains a conformance suite for adversaries: checks which every `AdversaryTrait`
+//! implementation should pass, so that adversaries written outside this crate get the same
+//! guardrails as the built-in ones.
+//!
+//! For example, for an adversary which is (0.5, 2)-admissible when seeded:
+//! ```ignore
+//! #[test]
+//! fn my_adversary_conforms() {
+//!     let network = construct_path(5);
+//!     let new_adversary = || MyAdversary::from_seed(1);
+//!     conformance::check_adversary(&network, new_adversary, 1000, Some((0.5, 2.0))).unwrap();
+//! }
+//! ```
+
+use crate::adversary::AdversaryTrait;
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory};
+use hashbrown::HashMap;
+
+// Rates are compared with this much slack, for floating-point rates like those of token buckets.
+const RATE_EPSILON: f64 = 1e-9;
+
+/// Run every check of the suite against adversaries built by `new_adversary`, injecting into the
+/// given (unchanging) network for `num_rds` rounds. If a `(rho, sigma)` rate bound is given, the
+/// injections must also be (rho, sigma)-admissible. Returns a description of the first failure,
+/// if any.
+pub fn check_adversary<A, F>(
+    network: &Network,
+    mut new_adversary: F,
+    num_rds: usize,
+    rate: Option<(f64, f64)>,
+) -> Result<(), String>
+where
+    A: AdversaryTrait,
+    F: FnMut() -> A,
+{
+    check_determinism(network, &mut new_adversary, num_rds)?;
+    check_paths(network, &mut new_adversary(), num_rds)?;
+    match rate {
+        Some((rho, sigma)) => check_rate(network, &mut new_adversary(), num_rds, rho, sigma),
+        None => Ok(()),
+    }
+}
+
+/// Check that two adversaries built by `new_adversary` inject the same packets on the same paths
+/// in every round, i.e. that a seeded adversary is reproducible.
+pub fn check_determinism<A, F>(
+    network: &Network,
+    mut new_adversary: F,
+    num_rds: usize,
+) -> Result<(), String>
+where
+    A: AdversaryTrait,
+    F: FnMut() -> A,
+{
+    let first = injections(network, &mut new_adversary(), num_rds);
+    let second = injections(network, &mut new_adversary(), num_rds);
+    let summary = |packets: &[Packet]| -> Vec<_> {
+        packets
+            .iter()
+            .map(|p| (p.get_path().to_vec(), p.get_path_idx(), p.class()))
+            .collect()
+    };
+    for (rd_idx, (first, second)) in first.iter().zip(&second).enumerate() {
+        if summary(first) != summary(second) {
+            return Err(format!(
+                "Determinism, round {}: two adversaries built alike injected different packets.",
+                rd_idx + 1
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that every injected packet starts at the beginning of a path of at least one edge which
+/// exists in the network, and has the round it was injected in as its injection round.
+pub fn check_paths<A: AdversaryTrait>(
+    network: &Network,
+    adversary: &mut A,
+    num_rds: usize,
+) -> Result<(), String> {
+    for (rd_idx, packets) in injections(network, adversary, num_rds).iter().enumerate() {
+        let rd = rd_idx + 1;
+        for p in packets {
+            if p.injection_rd() != rd {
+                return Err(format!(
+                    "Paths, round {}: packet {} has injection round {}.",
+                    rd,
+                    p.id(),
+                    p.injection_rd()
+                ));
+            }
+            if p.get_path_idx() != p.get_path().len() - p.route().len() || p.dist_to_go() == 0 {
+                return Err(format!(
+                    "Paths, round {}: packet {} isn't at the start of a path of at least one edge.",
+                    rd,
+                    p.id()
+                ));
+            }
+            if let Some((from_id, to_id)) = remaining_edges(p)
+                .find(|(from_id, to_id)| network.get_edgebuffer(*from_id, *to_id).is_none())
+            {
+                return Err(format!(
+                    "Paths, round {}: packet {}'s path crosses ({}, {}), which isn't an edge.",
+                    rd,
+                    p.id(),
+                    from_id,
+                    to_id
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that the adversary's injections are (rho, sigma)-admissible: over every interval of `T`
+/// rounds, at most `rho * T + sigma` injected packets cross each edge.
+pub fn check_rate<A: AdversaryTrait>(
+    network: &Network,
+    adversary: &mut A,
+    num_rds: usize,
+    rho: f64,
+    sigma: f64,
+) -> Result<(), String> {
+    let mut counts: HashMap<(NodeID, NodeID), Vec<usize>> = HashMap::new();
+    for (rd_idx, packets) in injections(network, adversary, num_rds).iter().enumerate() {
+        for p in packets {
+            for edge in remaining_edges(p) {
+                counts.entry(edge).or_insert_with(|| vec![0; num_rds])[rd_idx] += 1;
+            }
+        }
+    }
+    for (edge, counts) in counts {
+        // With excess(t) the injections crossing the edge in rounds 1..=t less rho * t, an
+        // interval's excess over its rate is the difference of the excesses at its ends.
+        let mut excess = 0.0;
+        let mut min_excess: f64 = 0.0;
+        let mut min_rd = 0;
+        for (rd_idx, count) in counts.into_iter().enumerate() {
+            excess += count as f64 - rho;
+            if excess - min_excess > sigma + RATE_EPSILON {
+                return Err(format!(
+                    "Rate: {} packets crossing ({}, {}) were injected in rounds {} to {}, more than \
+                     ({}, {}) allows.",
+                    (excess - min_excess + rho * (rd_idx - min_rd + 1) as f64).round(),
+                    edge.0,
+                    edge.1,
+                    min_rd + 1,
+                    rd_idx + 1,
+                    rho,
+                    sigma
+                ));
+            }
+            if excess < min_excess {
+                min_excess = excess;
+                min_rd = rd_idx + 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The packets the adversary injects in each of the first `num_rds` rounds.
+fn injections<A: AdversaryTrait>(
+    network: &Network,
+    adversary: &mut A,
+    num_rds: usize,
+) -> Vec<Vec<Packet>> {
+    let mut factory = PacketFactory::new();
+    (1..=num_rds)
+        .map(|rd| adversary.get_next_packets(network, rd, &mut factory))
+        .collect()
+}
+
+fn remaining_edges(p: &Packet) -> impl Iterator<Item = (NodeID, NodeID)> + '_ {
+    p.get_path()[p.get_path_idx()..]
+        .windows(2)
+        .map(|w| (w[0], w[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::{SDPathRandomAdversary, SDPathRandomBurstyAdversary};
+    use crate::adversary::preset::PresetAdversary;
+    use crate::adversary::shaping::TokenBucketAdversary;
+    use crate::adversary::Adversary;
+    use crate::network::presets::construct_path;
+
+    #[test]
+    fn test_builtin_adversaries_conform() {
+        let network = construct_path(5);
+        let bursty = || SDPathRandomBurstyAdversary::from_seed(3, 2);
+        check_adversary(&network, bursty, 1000, Some((1.0, 2.0))).unwrap();
+        let shaped = || {
+            let inner = Adversary::new(SDPathRandomBurstyAdversary::from_seed(3, 4));
+            TokenBucketAdversary::new(inner, 0.5, 2.0)
+        };
+        check_adversary(&network, shaped, 1000, Some((0.5, 2.0))).unwrap();
+
+        // Unseeded adversaries aren't reproducible, and bursts break tighter bounds.
+        assert!(check_determinism(&network, SDPathRandomAdversary::new, 100).is_err());
+        let result = check_rate(&network, &mut bursty(), 1000, 1.0, 0.0);
+        assert!(result.unwrap_err().starts_with("Rate: "));
+        let mut skipping = PresetAdversary::new(vec![vec![vec![0, 1]], vec![vec![0, 2]]]);
+        let result = check_paths(&network, &mut skipping, 2);
+        assert!(result.unwrap_err().starts_with("Paths, round 2: "));
+    }
+}
@@ -0,0 +1,224 @@
+//! This module contains an adversary which injects fork/join traffic: groups of packets which
+//! leave one source together along node-disjoint routes, and whose completion is measured as a
+//! whole (see the `group_completion` recorder) rather than packet by packet.
+
+use super::{AdversaryTrait, ADVERSARY_NAME_KEY, FORK_JOIN_NAME};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory, PacketPath};
+use crate::simulation::random::SimRng;
+use serde_json::{json, Map, Number, Value};
+use std::collections::VecDeque;
+
+const GROUP_SIZE_KEY: &str = "group_size";
+const SEED_KEY: &str = "seed";
+const DEFAULT_GROUP_SIZE: usize = 2;
+
+/// A fork/join adversary, which injects one group of `group_size` packets per round from a
+/// random source, each to a different destination along a fewest-hop route which shares no node
+/// but the source with the group's other routes. Destinations are tried in random order, and the
+/// source is drawn uniformly from the nodes with enough node-disjoint routes. Every packet of a
+/// group is stamped with the group's id (see `Packet::group`).
+#[derive(Clone)]
+pub struct ForkJoinAdversary {
+    group_size: usize,
+    rng: SimRng,
+    // The nodes with `group_size` node-disjoint routes, each with one such group of routes to fall
+    // back on when the random destination order finds none. Computed on the first round, since
+    // the network's edges are fixed.
+    sources: Option<Vec<(NodeID, Vec<PacketPath>)>>,
+}
+
+impl ForkJoinAdversary {
+    /// Get a new `ForkJoinAdversary` injecting groups of the given size, with the given seed, or
+    /// a random one if none is given.
+    pub fn new(group_size: usize, seed: Option<u64>) -> Self {
+        assert!(group_size > 0, "Fork/join groups must have a packet.");
+        ForkJoinAdversary {
+            group_size,
+            rng: seed.map_or_else(SimRng::new, SimRng::from_seed),
+            sources: None,
+        }
+    }
+
+    fn compute_sources(&self, network: &Network) -> Vec<(NodeID, Vec<PacketPath>)> {
+        let nodes = network.get_nodes();
+        let sources: Vec<_> = nodes
+            .iter()
+            .filter_map(|src_id| {
+                disjoint_routes(network, *src_id, &nodes, self.group_size)
+                    .map(|routes| (*src_id, routes))
+            })
+            .collect();
+        assert!(
+            !sources.is_empty(),
+            "No node has {} node-disjoint routes for the fork/join adversary.",
+            self.group_size
+        );
+        sources
+    }
+}
+
+impl AdversaryTrait for ForkJoinAdversary {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        if self.sources.is_none() {
+            self.sources = Some(self.compute_sources(network));
+        }
+        let sources = self.sources.as_ref().unwrap();
+        let (src_id, fallback) = &sources[self.rng.rand_int(sources.len())];
+        let mut dests = network.get_nodes();
+        self.rng.shuffle(&mut dests);
+        let routes = disjoint_routes(network, *src_id, &dests, self.group_size)
+            .unwrap_or_else(|| fallback.clone());
+
+        let mut packets: Vec<Packet> = Vec::with_capacity(routes.len());
+        for route in routes {
+            let path = factory.path_from(route);
+            let mut p = factory.create_packet(path, rd, 0);
+            let group = packets.first().map_or(p.id(), Packet::id);
+            p.set_group(Some(group.0));
+            packets.push(p);
+        }
+        packets
+    }
+}
+
+// Find routes from the given source to `group_size` of the given destinations, in order, which
+// share no node but the source, or `None` if there aren't enough.
+fn disjoint_routes(
+    network: &Network,
+    src_id: NodeID,
+    dests: &[NodeID],
+    group_size: usize,
+) -> Option<Vec<PacketPath>> {
+    let mut used = vec![false; network.get_num_nodes()];
+    used[src_id] = true;
+    let mut routes = Vec::with_capacity(group_size);
+    for dest_id in dests {
+        if used[*dest_id] {
+            continue;
+        }
+        if let Some(route) = fewest_hop_route(network, src_id, *dest_id, &used) {
+            for node_id in &route[1..] {
+                used[*node_id] = true;
+            }
+            routes.push(route);
+            if routes.len() == group_size {
+                return Some(routes);
+            }
+        }
+    }
+    None
+}
+
+// Find a route from the given source to the given destination with the fewest hops which avoids
+// the used nodes, if there is one.
+fn fewest_hop_route(
+    network: &Network,
+    src_id: NodeID,
+    dest_id: NodeID,
+    used: &[bool],
+) -> Option<PacketPath> {
+    let mut prev: Vec<Option<NodeID>> = vec![None; used.len()];
+    let mut queue = VecDeque::from([src_id]);
+    while let Some(node_id) = queue.pop_front() {
+        if node_id == dest_id {
+            let mut route = vec![dest_id];
+            while let Some(prev_id) = prev[*route.last().unwrap()] {
+                route.push(prev_id);
+            }
+            route.reverse();
+            return Some(route);
+        }
+        for next_id in network.get_neighbors(node_id) {
+            if !used[next_id] && prev[next_id].is_none() {
+                prev[next_id] = Some(node_id);
+                queue.push_back(next_id);
+            }
+        }
+    }
+    None
+}
+
+impl Describable for ForkJoinAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            FORK_JOIN_NAME,
+            "Injects one group of packets per round from a source along node-disjoint routes.",
+        )
+        .optional(
+            GROUP_SIZE_KEY,
+            "The number of packets in each group (2 by default).",
+            json!(3),
+        )
+        .optional(SEED_KEY, "The random seed.", json!(0))
+    }
+}
+
+impl Configurable for ForkJoinAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let group_size = match config.get(GROUP_SIZE_KEY) {
+            Some(group_size) => match group_size.as_u64() {
+                Some(group_size) if group_size > 0 => Ok(group_size as usize),
+                _ => Err(String::from(
+                    "Fork/join group size must be a positive integer.",
+                )),
+            },
+            None => Ok(DEFAULT_GROUP_SIZE),
+        }?;
+        let seed = config.get(SEED_KEY).and_then(Value::as_u64);
+        Ok(Self::new(group_size, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(FORK_JOIN_NAME.to_string()),
+        );
+        map.insert(
+            GROUP_SIZE_KEY.to_string(),
+            Value::Number(Number::from(self.group_size)),
+        );
+        map.insert(
+            SEED_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_share_a_source_and_no_other_node() {
+        // Only node 0 has two node-disjoint routes, e.g. to nodes 1 and 3 or to nodes 2 and 4.
+        let network = Network::from_adj_lists(vec![vec![1, 2], vec![3], vec![4], vec![4], vec![]]);
+        let mut factory = PacketFactory::new();
+        let config = json!({ "adversary_name": "fork_join", "seed": 5 });
+        let mut adversary = ForkJoinAdversary::from_config(config).unwrap();
+        assert_eq!(adversary.to_config()[GROUP_SIZE_KEY], 2);
+        for rd in 1..=20 {
+            let packets = adversary.get_next_packets(&network, rd, &mut factory);
+            assert_eq!(packets.len(), 2);
+            let group = packets[0].id().0;
+            let mut nodes = Vec::new();
+            for p in &packets {
+                assert_eq!((p.src_node(), p.group()), (0, Some(group)));
+                nodes.extend_from_slice(&p.get_path()[1..]);
+            }
+            let num_nodes = nodes.len();
+            nodes.sort_unstable();
+            nodes.dedup();
+            assert_eq!(nodes.len(), num_nodes);
+        }
+    }
+}
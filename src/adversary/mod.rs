@@ -1,33 +1,145 @@
 //! This module contains all implementations of adversaries, which determine where Packets are
 //! injected into the network.
 
-use self::path_random::{SDPathRandomAdversary, SDPathRandomBurstyAdversary};
-use crate::config::{CfgErrorMsg, Configurable};
+use self::fork_join::ForkJoinAdversary;
+use self::path_random::{
+    MultiSinkPathRandomAdversary, PathPowerOfTwoAdversary, SDPathRandomAdversary,
+    SDPathRandomBurstyAdversary,
+};
+use self::preset::{PresetAdversary, WeightedPathAdversary};
+use self::shaping::{ScheduledAdversary, TokenBucketAdversary};
+use self::shortest_path::ShortestPathRandomAdversary;
+use self::utilization::UtilizationAdversary;
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
 use crate::network::Network;
-use crate::packet::Packet;
+use crate::packet::{Packet, PacketFactory};
 use serde_json::Value;
+use std::any::Any;
 
+pub mod conformance;
+pub mod fork_join;
 pub mod path_random;
+pub mod preset;
+pub mod shaping;
+pub mod shortest_path;
+pub mod utilization;
 
-/// Enum to store all adversaries.
-pub enum Adversary {
-    SDPathRandom(SDPathRandomAdversary),
-    SDPathRandomBursty(SDPathRandomBurstyAdversary),
-}
+/// An adversary, behind which any `AdversaryTrait` implementation can be used.
+pub struct Adversary(Box<dyn BoxedAdversary>);
 
 impl Adversary {
+    /// Get an `Adversary` injecting with the given implementation, which may be defined outside
+    /// this crate.
+    pub fn new<A>(adversary: A) -> Self
+    where
+        A: AdversaryTrait + Configurable + Clone + Send + 'static,
+    {
+        Adversary(Box::new(adversary))
+    }
+
+    /// Get the implementation behind this adversary, if it is an `A`.
+    pub fn downcast_ref<A: 'static>(&self) -> Option<&A> {
+        self.0.as_any().downcast_ref()
+    }
+
+    /// Get the `Description` of every adversary.
+    pub fn descriptions() -> Vec<Description> {
+        ADVERSARIES.iter().map(|(describe, _)| describe()).collect()
+    }
+
     /// Get the next packets, through `AdversaryTrait`
-    pub fn get_next_packets(&mut self, network: &Network, rd: usize) -> Vec<Packet> {
-        match self {
-            Self::SDPathRandom(a) => a.get_next_packets(network, rd),
-            Self::SDPathRandomBursty(a) => a.get_next_packets(network, rd),
-        }
+    pub fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        self.0.get_next_packets(network, rd, factory)
+    }
+
+    /// Check whether this adversary has finished injecting, through `AdversaryTrait`.
+    pub fn is_done(&self) -> bool {
+        self.0.is_done()
+    }
+
+    /// Get this adversary's end-of-run report, if it makes one, through `AdversaryTrait`.
+    pub fn report(&self) -> Option<Value> {
+        self.0.report()
+    }
+
+    /// Get the phase this adversary is in, if it has phases, through `AdversaryTrait`.
+    pub fn phase(&self) -> Option<&str> {
+        self.0.phase()
+    }
+}
+
+impl Clone for Adversary {
+    fn clone(&self) -> Self {
+        Adversary(self.0.clone_box())
     }
 }
 
-const ADVERSARY_NAME_KEY: &str = "adversary_name";
+pub const ADVERSARY_NAME_KEY: &str = "adversary_name";
 const SD_PATH_RANDOM_NAME: &str = "sd_path_random";
 const SD_PATH_RANDOM_BURSTY_NAME: &str = "sd_path_random_bursty";
+const MULTI_SINK_PATH_RANDOM_NAME: &str = "multi_sink_path_random";
+const SHORTEST_PATH_RANDOM_NAME: &str = "shortest_path_random";
+const TOKEN_BUCKET_NAME: &str = "token_bucket";
+const SCHEDULED_NAME: &str = "scheduled";
+const PRESET_NAME: &str = "preset";
+const WEIGHTED_PATHS_NAME: &str = "weighted_paths";
+const UTILIZATION_NAME: &str = "utilization";
+const PATH_POWER_OF_TWO_NAME: &str = "path_power_of_two";
+const FORK_JOIN_NAME: &str = "fork_join";
+const INNER_ADVERSARY_KEY: &str = "adversary";
+
+type Builder = fn(Value) -> Result<Adversary, CfgErrorMsg>;
+
+// Every built-in adversary, by its description and how to build it from its config.
+const ADVERSARIES: [(fn() -> Description, Builder); 11] = [
+    (
+        SDPathRandomAdversary::describe,
+        build::<SDPathRandomAdversary>,
+    ),
+    (
+        SDPathRandomBurstyAdversary::describe,
+        build::<SDPathRandomBurstyAdversary>,
+    ),
+    (
+        MultiSinkPathRandomAdversary::describe,
+        build::<MultiSinkPathRandomAdversary>,
+    ),
+    (
+        ShortestPathRandomAdversary::describe,
+        build::<ShortestPathRandomAdversary>,
+    ),
+    (
+        TokenBucketAdversary::describe,
+        build::<TokenBucketAdversary>,
+    ),
+    (ScheduledAdversary::describe, build::<ScheduledAdversary>),
+    (PresetAdversary::describe, build::<PresetAdversary>),
+    (
+        WeightedPathAdversary::describe,
+        build::<WeightedPathAdversary>,
+    ),
+    (
+        UtilizationAdversary::describe,
+        build::<UtilizationAdversary>,
+    ),
+    (
+        PathPowerOfTwoAdversary::describe,
+        build::<PathPowerOfTwoAdversary>,
+    ),
+    (ForkJoinAdversary::describe, build::<ForkJoinAdversary>),
+];
+
+fn build<A>(config: Value) -> Result<Adversary, CfgErrorMsg>
+where
+    A: AdversaryTrait + Configurable + Clone + Send + 'static,
+{
+    Ok(Adversary::new(A::from_config(config)?))
+}
 
 impl Configurable for Adversary {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
@@ -35,28 +147,91 @@ impl Configurable for Adversary {
             Some(Value::String(name)) => Ok(name),
             _ => Err("No adversary name found."),
         }?;
-
-        match &name[..] {
-            SD_PATH_RANDOM_NAME => Ok(Adversary::SDPathRandom(
-                SDPathRandomAdversary::from_config(config.clone()).unwrap(),
-            )),
-            SD_PATH_RANDOM_BURSTY_NAME => Ok(Adversary::SDPathRandomBursty(
-                SDPathRandomBurstyAdversary::from_config(config.clone()).unwrap(),
-            )),
-            _ => Err(format!("No adversary with name {}", name)),
+        match ADVERSARIES
+            .iter()
+            .find(|(describe, _)| describe().name == name)
+        {
+            Some((_, build)) => build(config),
+            None => Err(format!("No adversary with name {}", name)),
         }
     }
 
     fn to_config(&self) -> Value {
-        match self {
-            Self::SDPathRandom(a) => a.to_config(),
-            Self::SDPathRandomBursty(a) => a.to_config(),
-        }
+        self.0.to_config()
+    }
+}
+
+// The object-safe view of an adversary which `Adversary` holds, implemented for every configurable
+// `AdversaryTrait` implementation.
+trait BoxedAdversary: AdversaryTrait + Send {
+    fn clone_box(&self) -> Box<dyn BoxedAdversary>;
+    fn to_config(&self) -> Value;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<A> BoxedAdversary for A
+where
+    A: AdversaryTrait + Configurable + Clone + Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn BoxedAdversary> {
+        Box::new(self.clone())
+    }
+
+    fn to_config(&self) -> Value {
+        Configurable::to_config(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
 /// Trait which all adversaries must implement.
 pub trait AdversaryTrait {
-    /// Create the packets to be injected.
-    fn get_next_packets(&mut self, network: &Network, rd: usize) -> Vec<Packet>;
+    /// Create the packets to be injected. All packets must be created through the given
+    /// `PacketFactory`, which is shared by every adversary in the `Simulation`.
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet>;
+
+    /// Check whether this adversary has finished injecting packets, i.e. it will not inject any
+    /// more packets in later rounds. Adversaries which inject forever never finish.
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// Get a report on this adversary's injections so far, written to the simulation's summary
+    /// at the end of the run. Most adversaries make none.
+    fn report(&self) -> Option<Value> {
+        None
+    }
+
+    /// Get the name of the phase this adversary is in as of the last round it injected for, if
+    /// its behavior switches between phases, such as the active and paused windows of a schedule.
+    /// The simulation emits a phase marker to its recorders whenever this changes.
+    fn phase(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_described_example_configs_are_valid() {
+        for description in Adversary::descriptions() {
+            let config = description.example_config();
+            let adversary = Adversary::from_config(config.clone()).unwrap();
+            assert_eq!(adversary.to_config()[ADVERSARY_NAME_KEY], description.name);
+            for key in description.keys {
+                let mut config = config.clone();
+                config[key.key] = key.example;
+                assert!(Adversary::from_config(config).is_ok(), "{}", key.key);
+            }
+        }
+    }
 }
@@ -1,53 +1,70 @@
 //! This module contains stochastic adversaries which work on a path network.
 
-use super::{AdversaryTrait, ADVERSARY_NAME_KEY, SD_PATH_RANDOM_NAME, SD_PATH_RANDOM_BURSTY_NAME};
-use crate::config::{CfgErrorMsg, Configurable};
+use super::{
+    AdversaryTrait, ADVERSARY_NAME_KEY, MULTI_SINK_PATH_RANDOM_NAME, PATH_POWER_OF_TWO_NAME,
+    SD_PATH_RANDOM_BURSTY_NAME, SD_PATH_RANDOM_NAME,
+};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
 use crate::network::{Network, NodeID};
 use crate::packet::{Packet, PacketFactory};
 use crate::simulation::random::SimRng;
-use serde_json::{Map, Number, Value};
+use serde_json::{json, Map, Number, Value};
 
 /// A single-destination path random adversary, which injects one packet per round into a random
 /// buffer on the path. Here, rho=1 and sigma=0.
+#[derive(Clone)]
 pub struct SDPathRandomAdversary {
-    factory: PacketFactory,
     rng: SimRng,
-    seed: Option<u64>,
 }
 
 impl SDPathRandomAdversary {
-    /// Get a new `SDPathRandomAdversary`
+    /// Get a new `SDPathRandomAdversary` with a random seed.
     pub fn new() -> Self {
-        SDPathRandomAdversary {
-            factory: PacketFactory::new(),
-            rng: SimRng::new(),
-            seed: None,
-        }
+        SDPathRandomAdversary { rng: SimRng::new() }
     }
 
     /// Get a new `SDPathRandomAdversary` from the given seed.
     pub fn from_seed(seed: u64) -> Self {
         SDPathRandomAdversary {
-            factory: PacketFactory::new(),
             rng: SimRng::from_seed(seed),
-            seed: Some(seed),
         }
     }
 }
 
 impl AdversaryTrait for SDPathRandomAdversary {
-    fn get_next_packets(&mut self, network: &Network, rd: usize) -> Vec<Packet> {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
         let dest_id: NodeID = network.get_num_nodes() - 1;
         let src_id = self.rng.rand_int(dest_id - 1);
 
-        vec![self
-            .factory
-            .create_packet((0..dest_id + 1).collect(), rd, src_id)]
+        let path = factory.path_from(0..dest_id + 1);
+        vec![factory.create_packet(path, rd, src_id)]
+    }
+}
+
+impl Default for SDPathRandomAdversary {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 const SEED_NAME_KEY: &str = "seed";
 
+impl Describable for SDPathRandomAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            SD_PATH_RANDOM_NAME,
+            "Injects one packet per round from a random node to the end of the path.",
+        )
+        .optional(SEED_NAME_KEY, "The random seed.", json!(0))
+    }
+}
+
 impl Configurable for SDPathRandomAdversary {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
         let map = config.as_object().unwrap();
@@ -66,30 +83,34 @@ impl Configurable for SDPathRandomAdversary {
             ADVERSARY_NAME_KEY.to_string(),
             Value::String(SD_PATH_RANDOM_NAME.to_string()),
         );
-        if let Some(seed) = self.seed {
-            map.insert(SEED_NAME_KEY.to_string(), Value::Number(Number::from(seed)));
-        }
+        map.insert(
+            SEED_NAME_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
         Value::Object(map)
     }
 }
 
+/// The number of burst sizes a bursty adversary chooses from: 0 to `sigma-xi+1` packets. If `xi`
+/// ever exceeds `sigma`, the only choice is 0, rather than an underflow.
+fn burst_choices(sigma: usize, xi: usize) -> usize {
+    (sigma + 1).saturating_sub(xi) + 1
+}
+
 /// A single-destination path "bursty" random adversary, with base rate rho=1, and sigma set by
 /// the constructor.
+#[derive(Clone)]
 pub struct SDPathRandomBurstyAdversary {
-    factory: PacketFactory,
     rng: SimRng,
-    seed: Option<u64>,
     sigma: usize,
     xi: usize,
 }
 
 impl SDPathRandomBurstyAdversary {
-    /// Get a new `SDPathRandomBurstyAdversary`
+    /// Get a new `SDPathRandomBurstyAdversary` with a random seed.
     pub fn new(sigma: usize) -> Self {
         SDPathRandomBurstyAdversary {
-            factory: PacketFactory::new(),
             rng: SimRng::new(),
-            seed: None,
             sigma,
             xi: 0,
         }
@@ -98,9 +119,7 @@ impl SDPathRandomBurstyAdversary {
     /// Get a new `SDPathRandomAdversary` from the given seed.
     pub fn from_seed(seed: u64, sigma: usize) -> Self {
         SDPathRandomBurstyAdversary {
-            factory: PacketFactory::new(),
             rng: SimRng::from_seed(seed),
-            seed: Some(seed),
             sigma,
             xi: 0,
         }
@@ -108,15 +127,21 @@ impl SDPathRandomBurstyAdversary {
 }
 
 impl AdversaryTrait for SDPathRandomBurstyAdversary {
-    fn get_next_packets(&mut self, network: &Network, rd: usize) -> Vec<Packet> {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
         // Possible numbers of packets to inject are 0..(sigma-xi+1). Choose uniformly from these
         // options.
-        let num_to_inject = self.rng.rand_int(self.sigma - self.xi + 2);
+        let num_to_inject = self.rng.rand_int(burst_choices(self.sigma, self.xi));
         let mut next_packets = Vec::new();
         for _ in 0..num_to_inject {
             let dest_id: NodeID = network.get_num_nodes() - 1;
             let src_id = self.rng.rand_int(dest_id - 1);
-            next_packets.push(self.factory.create_packet((0..dest_id + 1).collect(), rd, src_id));
+            let path = factory.path_from(0..dest_id + 1);
+            next_packets.push(factory.create_packet(path, rd, src_id));
         }
         // Update xi.
         if self.xi + num_to_inject == 0 {
@@ -130,6 +155,18 @@ impl AdversaryTrait for SDPathRandomBurstyAdversary {
 
 const SIGMA_NAME_KEY: &str = "sigma";
 
+impl Describable for SDPathRandomBurstyAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            SD_PATH_RANDOM_BURSTY_NAME,
+            "Injects random bursts of packets to the end of the path, within a burst budget.",
+        )
+        .required(SIGMA_NAME_KEY, "The burstiness.", json!(2))
+        .optional(SEED_NAME_KEY, "The random seed.", json!(0))
+    }
+}
+
 impl Configurable for SDPathRandomBurstyAdversary {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
         let map = config.as_object().unwrap();
@@ -159,9 +196,348 @@ impl Configurable for SDPathRandomBurstyAdversary {
             SIGMA_NAME_KEY.to_string(),
             Value::Number(Number::from(self.sigma)),
         );
-        if let Some(seed) = self.seed {
-            map.insert(SEED_NAME_KEY.to_string(), Value::Number(Number::from(seed)));
+        map.insert(
+            SEED_NAME_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+/// A multi-destination path random adversary, which injects packets between random (source, sink)
+/// pairs on the path. Each packet's sink is drawn uniformly from `sinks` (every node after the
+/// first if none are given) and its source uniformly from the nodes before its sink. If `sigma` is
+/// given, the adversary is bursty like `SDPathRandomBurstyAdversary`, injecting between 0 and
+/// `sigma-xi+1` packets each round; otherwise, it injects one packet per round.
+#[derive(Clone)]
+pub struct MultiSinkPathRandomAdversary {
+    rng: SimRng,
+    sinks: Option<Vec<NodeID>>,
+    sigma: Option<usize>,
+    xi: usize,
+}
+
+impl MultiSinkPathRandomAdversary {
+    /// Get a new `MultiSinkPathRandomAdversary` with the given seed, or a random one if none is
+    /// given.
+    pub fn new(sinks: Option<Vec<NodeID>>, sigma: Option<usize>, seed: Option<u64>) -> Self {
+        MultiSinkPathRandomAdversary {
+            rng: match seed {
+                Some(seed) => SimRng::from_seed(seed),
+                None => SimRng::new(),
+            },
+            sinks,
+            sigma,
+            xi: 0,
         }
+    }
+
+    /// Create a packet from a random source to a random sink. Like the single-destination
+    /// adversaries, its path covers the whole path network up to its sink, starting at its source.
+    fn create_packet(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Packet {
+        let num_nodes = network.get_num_nodes();
+        let sink_id = match &self.sinks {
+            Some(sinks) => sinks[self.rng.rand_int(sinks.len())],
+            None => 1 + self.rng.rand_int(num_nodes - 1),
+        };
+        assert!(
+            0 < sink_id && sink_id < num_nodes,
+            "Sink {} is not a node after the first on the path.",
+            sink_id
+        );
+        let src_id = self.rng.rand_int(sink_id);
+        let path = factory.path_from(0..sink_id + 1);
+        factory.create_packet(path, rd, src_id)
+    }
+}
+
+impl AdversaryTrait for MultiSinkPathRandomAdversary {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        let num_to_inject = match self.sigma {
+            Some(sigma) => self.rng.rand_int(burst_choices(sigma, self.xi)),
+            None => 1,
+        };
+        let next_packets = (0..num_to_inject)
+            .map(|_| self.create_packet(network, rd, factory))
+            .collect();
+        // Update xi.
+        self.xi = (self.xi + num_to_inject).saturating_sub(1);
+        next_packets
+    }
+}
+
+const SINKS_NAME_KEY: &str = "sinks";
+
+impl Describable for MultiSinkPathRandomAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            MULTI_SINK_PATH_RANDOM_NAME,
+            "Injects packets between random (source, sink) pairs on the path.",
+        )
+        .optional(
+            SINKS_NAME_KEY,
+            "The possible sinks (every node after the first by default).",
+            json!([2, 3]),
+        )
+        .optional(
+            SIGMA_NAME_KEY,
+            "The burstiness; one packet is injected per round if unset.",
+            json!(2),
+        )
+        .optional(SEED_NAME_KEY, "The random seed.", json!(0))
+    }
+}
+
+impl Configurable for MultiSinkPathRandomAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let seed = config.get(SEED_NAME_KEY).and_then(Value::as_u64);
+        let sigma = match config.get(SIGMA_NAME_KEY) {
+            Some(sigma) => match sigma.as_u64() {
+                Some(sigma) => Ok(Some(sigma as usize)),
+                None => Err(String::from("Sigma must be a nonnegative integer.")),
+            },
+            None => Ok(None),
+        }?;
+        let sinks = match config.get(SINKS_NAME_KEY) {
+            Some(Value::Array(sinks)) if !sinks.is_empty() => sinks
+                .iter()
+                .map(|sink| match sink.as_u64() {
+                    Some(sink) if sink > 0 => Ok(sink as NodeID),
+                    _ => Err(format!("Sink {} must be a node after the first.", sink)),
+                })
+                .collect::<Result<Vec<NodeID>, CfgErrorMsg>>()
+                .map(Some),
+            Some(_) => Err(String::from("Sinks must be a nonempty array of node ids.")),
+            None => Ok(None),
+        }?;
+        Ok(Self::new(sinks, sigma, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(MULTI_SINK_PATH_RANDOM_NAME.to_string()),
+        );
+        if let Some(sinks) = &self.sinks {
+            let sinks = sinks.iter().map(|sink| Value::Number(Number::from(*sink)));
+            map.insert(SINKS_NAME_KEY.to_string(), Value::Array(sinks.collect()));
+        }
+        if let Some(sigma) = self.sigma {
+            map.insert(
+                SIGMA_NAME_KEY.to_string(),
+                Value::Number(Number::from(sigma)),
+            );
+        }
+        map.insert(
+            SEED_NAME_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
         Value::Object(map)
     }
 }
+
+/// Which of its two candidate buffers a `PathPowerOfTwoAdversary` injects into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoadChoice {
+    /// The less loaded candidate, balancing load like the power of two choices.
+    LessLoaded,
+    /// The more loaded candidate, piling load onto already congested buffers.
+    MoreLoaded,
+}
+
+const LESS_LOADED_NAME: &str = "less_loaded";
+const MORE_LOADED_NAME: &str = "more_loaded";
+
+impl Configurable for LoadChoice {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(LESS_LOADED_NAME) => Ok(Self::LessLoaded),
+            Some(MORE_LOADED_NAME) => Ok(Self::MoreLoaded),
+            _ => Err(format!("No load choice {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::LessLoaded => Value::String(LESS_LOADED_NAME.to_string()),
+            Self::MoreLoaded => Value::String(MORE_LOADED_NAME.to_string()),
+        }
+    }
+}
+
+/// A single-destination path adversary using the power of two choices: each round, it samples two
+/// source buffers uniformly at random and injects one packet, destined for the end of the path,
+/// into the less loaded of the two (or, as an adversarial variant, the more loaded). Ties go to
+/// the first sample. This sits between the oblivious random adversaries and fully adaptive ones.
+#[derive(Clone)]
+pub struct PathPowerOfTwoAdversary {
+    rng: SimRng,
+    choice: LoadChoice,
+}
+
+impl PathPowerOfTwoAdversary {
+    /// Get a new `PathPowerOfTwoAdversary` with the given seed, or a random one if none is given.
+    pub fn new(choice: LoadChoice, seed: Option<u64>) -> Self {
+        PathPowerOfTwoAdversary {
+            rng: seed.map_or_else(SimRng::new, SimRng::from_seed),
+            choice,
+        }
+    }
+
+    /// Get which of its two candidate buffers this adversary injects into.
+    pub fn choice(&self) -> LoadChoice {
+        self.choice
+    }
+}
+
+impl AdversaryTrait for PathPowerOfTwoAdversary {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        let dest_id: NodeID = network.get_num_nodes() - 1;
+        let first_id = self.rng.rand_int(dest_id);
+        let second_id = self.rng.rand_int(dest_id);
+        let load = |src_id: NodeID| {
+            network
+                .get_edgebuffer(src_id, src_id + 1)
+                .map_or(0, |eb| eb.buffer.len())
+        };
+        let src_id = match self.choice {
+            LoadChoice::LessLoaded if load(second_id) < load(first_id) => second_id,
+            LoadChoice::MoreLoaded if load(second_id) > load(first_id) => second_id,
+            _ => first_id,
+        };
+        let path = factory.path_from(0..dest_id + 1);
+        vec![factory.create_packet(path, rd, src_id)]
+    }
+}
+
+const CHOICE_NAME_KEY: &str = "choice";
+
+impl Describable for PathPowerOfTwoAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            PATH_POWER_OF_TWO_NAME,
+            "Injects one packet per round to the end of the path, into the less (or more) loaded \
+             of two random source buffers.",
+        )
+        .optional(
+            CHOICE_NAME_KEY,
+            "\"less_loaded\" (the default) or \"more_loaded\".",
+            json!("more_loaded"),
+        )
+        .optional(SEED_NAME_KEY, "The random seed.", json!(0))
+    }
+}
+
+impl Configurable for PathPowerOfTwoAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let seed = config.get(SEED_NAME_KEY).and_then(Value::as_u64);
+        let choice = match config.get(CHOICE_NAME_KEY) {
+            Some(choice) => LoadChoice::from_config(choice.clone())?,
+            None => LoadChoice::LessLoaded,
+        };
+        Ok(Self::new(choice, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(PATH_POWER_OF_TWO_NAME.to_string()),
+        );
+        map.insert(CHOICE_NAME_KEY.to_string(), self.choice.to_config());
+        map.insert(
+            SEED_NAME_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+
+    #[test]
+    fn test_burst_choices_never_underflow() {
+        assert_eq!(burst_choices(3, 0), 5);
+        assert_eq!(burst_choices(3, 3), 2);
+        assert_eq!(burst_choices(3, 4), 1);
+        assert_eq!(burst_choices(3, usize::MAX), 1);
+    }
+
+    #[test]
+    fn test_multi_sink_pairs() {
+        let network = construct_path(6);
+        let mut factory = PacketFactory::new();
+        let mut adversary = MultiSinkPathRandomAdversary::new(Some(vec![2, 5]), Some(2), Some(0));
+        let mut sinks_seen = Vec::new();
+        for rd in 1..=50 {
+            for p in adversary.get_next_packets(&network, rd, &mut factory) {
+                let sink_id = *p.get_path().last().unwrap();
+                assert!(sink_id == 2 || sink_id == 5);
+                assert!(p.cur_node().unwrap() < sink_id);
+                sinks_seen.push(sink_id);
+            }
+        }
+        assert!(sinks_seen.contains(&2) && sinks_seen.contains(&5));
+
+        let config = adversary.to_config();
+        assert_eq!(
+            MultiSinkPathRandomAdversary::from_config(config.clone())
+                .unwrap()
+                .to_config(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_power_of_two_choices() {
+        // Inject without forwarding, so each adversary sees the load it built up.
+        let max_load = |choice| {
+            let mut network = construct_path(9);
+            let mut factory = PacketFactory::new();
+            let mut adversary = PathPowerOfTwoAdversary::new(choice, Some(3));
+            for rd in 1..=400 {
+                for p in adversary.get_next_packets(&network, rd, &mut factory) {
+                    let src_id = p.cur_node().unwrap();
+                    network.add_packet(p, src_id, src_id + 1);
+                }
+            }
+            let loads = network
+                .get_edgebuffers()
+                .into_iter()
+                .map(|(from_id, to_id)| {
+                    network.get_edgebuffer(from_id, to_id).unwrap().buffer.len()
+                });
+            loads.max().unwrap()
+        };
+        // Balancing keeps every buffer within a packet or two of the average of 50.
+        assert!(max_load(LoadChoice::LessLoaded) <= 52);
+        // Piling onto the more loaded buffer skews the load well beyond it.
+        assert!(max_load(LoadChoice::MoreLoaded) > 80);
+
+        let config =
+            json!({ "adversary_name": "path_power_of_two", "choice": "more_loaded", "seed": 3 });
+        let adversary = PathPowerOfTwoAdversary::from_config(config.clone()).unwrap();
+        assert_eq!(adversary.to_config(), config);
+    }
+}
@@ -0,0 +1,294 @@
+//! This module contains adversaries which inject packets along preset paths, either on a fixed
+//! schedule or chosen at random.
+
+use super::{AdversaryTrait, ADVERSARY_NAME_KEY, PRESET_NAME, WEIGHTED_PATHS_NAME};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::Network;
+use crate::packet::{Packet, PacketFactory, PacketPath};
+use crate::simulation::random::SimRng;
+use serde_json::{json, Map, Number, Value};
+
+/// An adversary which injects packets according to a preset schedule: in the `i`th round in which
+/// it is asked for packets (counting from 0), it injects one packet (at the start of its path) for
+/// each path in `schedule[i]`. Once the schedule is exhausted, the adversary injects nothing and
+/// reports that it is done.
+#[derive(Clone)]
+pub struct PresetAdversary {
+    schedule: Vec<Vec<PacketPath>>,
+    num_rds: usize,
+}
+
+impl PresetAdversary {
+    /// Get a new `PresetAdversary` with the given schedule.
+    pub fn new(schedule: Vec<Vec<PacketPath>>) -> Self {
+        PresetAdversary {
+            schedule,
+            num_rds: 0,
+        }
+    }
+
+    /// Get the number of rounds in this adversary's schedule.
+    pub fn rds(&self) -> usize {
+        self.schedule.len()
+    }
+}
+
+impl AdversaryTrait for PresetAdversary {
+    fn get_next_packets(
+        &mut self,
+        _network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        self.num_rds += 1;
+        match self.schedule.get(self.num_rds - 1) {
+            Some(paths) => paths
+                .iter()
+                .map(|path| {
+                    let path = factory.path_from(path.iter().copied());
+                    factory.create_packet(path, rd, 0)
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.num_rds >= self.schedule.len()
+    }
+}
+
+const SCHEDULE_KEY: &str = "schedule";
+
+impl Describable for PresetAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            PRESET_NAME,
+            "Injects a fixed schedule of packets, then reports that it is done.",
+        )
+        .required(
+            SCHEDULE_KEY,
+            "For each round, the paths of the packets to inject.",
+            json!([[[0, 1, 2]], [], [[1, 2]]]),
+        )
+    }
+}
+
+impl Configurable for PresetAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let rd_cfgs = match config.get(SCHEDULE_KEY) {
+            Some(Value::Array(rd_cfgs)) => Ok(rd_cfgs),
+            _ => Err(String::from("No schedule provided.")),
+        }?;
+
+        let mut schedule = Vec::new();
+        for rd_cfg in rd_cfgs {
+            let path_cfgs = rd_cfg
+                .as_array()
+                .ok_or("Each round of the schedule must be an array of paths.")?;
+            let paths = path_cfgs
+                .iter()
+                .map(path_from_config)
+                .collect::<Result<Vec<PacketPath>, CfgErrorMsg>>()?;
+            schedule.push(paths);
+        }
+        Ok(Self::new(schedule))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(PRESET_NAME.to_string()),
+        );
+        let schedule_cfg = self
+            .schedule
+            .iter()
+            .map(|paths| Value::Array(paths.iter().map(path_to_config).collect()))
+            .collect();
+        map.insert(SCHEDULE_KEY.to_string(), Value::Array(schedule_cfg));
+        Value::Object(map)
+    }
+}
+
+fn path_from_config(config: &Value) -> Result<PacketPath, CfgErrorMsg> {
+    let path: Option<PacketPath> = config.as_array().and_then(|ids| {
+        ids.iter()
+            .map(|id| id.as_u64().map(|id| id as usize))
+            .collect()
+    });
+    path.ok_or_else(|| String::from("Each path must be an array of node ids."))
+}
+
+fn path_to_config(path: &PacketPath) -> Value {
+    Value::Array(
+        path.iter()
+            .map(|id| Value::Number(Number::from(*id)))
+            .collect(),
+    )
+}
+
+/// An adversary which injects one packet per round, along a path chosen at random from a fixed
+/// list of paths, each with probability proportional to its weight (all equal by default).
+#[derive(Clone)]
+pub struct WeightedPathAdversary {
+    paths: Vec<PacketPath>,
+    weights: Vec<f64>,
+    rng: SimRng,
+}
+
+impl WeightedPathAdversary {
+    /// Get a new `WeightedPathAdversary` with the given paths and positive weights, and the given
+    /// seed, or a random one if none is given.
+    pub fn new(paths: Vec<PacketPath>, weights: Vec<f64>, seed: Option<u64>) -> Self {
+        assert!(!paths.is_empty(), "There must be at least one path.");
+        assert_eq!(
+            paths.len(),
+            weights.len(),
+            "There must be one weight per path."
+        );
+        assert!(
+            weights.iter().all(|weight| *weight > 0.0),
+            "Path weights must be positive."
+        );
+        WeightedPathAdversary {
+            paths,
+            weights,
+            rng: seed.map_or_else(SimRng::new, SimRng::from_seed),
+        }
+    }
+}
+
+impl AdversaryTrait for WeightedPathAdversary {
+    fn get_next_packets(
+        &mut self,
+        _network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        let path = &self.paths[self.rng.rand_weighted(&self.weights)];
+        let path = factory.path_from(path.iter().copied());
+        vec![factory.create_packet(path, rd, 0)]
+    }
+}
+
+const PATHS_KEY: &str = "paths";
+const WEIGHTS_KEY: &str = "weights";
+const SEED_KEY: &str = "seed";
+
+impl Describable for WeightedPathAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            WEIGHTED_PATHS_NAME,
+            "Injects one packet per round along a path chosen at random from a weighted list.",
+        )
+        .required(
+            PATHS_KEY,
+            "The paths to choose from, each an array of node ids.",
+            json!([[0, 1, 2], [1, 2]]),
+        )
+        .optional(
+            WEIGHTS_KEY,
+            "The positive weight of each path (all equal by default).",
+            json!([2.0, 1.0]),
+        )
+        .optional(SEED_KEY, "The random seed.", json!(0))
+    }
+}
+
+impl Configurable for WeightedPathAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let paths = match config.get(PATHS_KEY) {
+            Some(Value::Array(path_cfgs)) if !path_cfgs.is_empty() => path_cfgs
+                .iter()
+                .map(path_from_config)
+                .collect::<Result<Vec<PacketPath>, CfgErrorMsg>>(),
+            _ => Err(String::from("Paths must be a nonempty array of paths.")),
+        }?;
+        let weights = match config.get(WEIGHTS_KEY) {
+            Some(Value::Array(weight_cfgs)) if weight_cfgs.len() == paths.len() => weight_cfgs
+                .iter()
+                .map(|weight| match weight.as_f64() {
+                    Some(weight) if weight > 0.0 => Ok(weight),
+                    _ => Err(format!("Path weight {} must be a positive number.", weight)),
+                })
+                .collect::<Result<Vec<f64>, CfgErrorMsg>>(),
+            Some(_) => Err(String::from("There must be one weight per path.")),
+            None => Ok(vec![1.0; paths.len()]),
+        }?;
+        let seed = config.get(SEED_KEY).and_then(Value::as_u64);
+        Ok(Self::new(paths, weights, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(WEIGHTED_PATHS_NAME.to_string()),
+        );
+        map.insert(
+            PATHS_KEY.to_string(),
+            Value::Array(self.paths.iter().map(path_to_config).collect()),
+        );
+        map.insert(WEIGHTS_KEY.to_string(), json!(self.weights));
+        map.insert(
+            SEED_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+
+    #[test]
+    fn test_preset_schedule_exhaustion() {
+        let network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut adversary = PresetAdversary::new(vec![vec![vec![0, 1, 2], vec![1, 2]], vec![]]);
+        assert!(!adversary.is_done());
+
+        assert_eq!(
+            adversary.get_next_packets(&network, 1, &mut factory).len(),
+            2
+        );
+        assert!(!adversary.is_done());
+        assert!(adversary
+            .get_next_packets(&network, 2, &mut factory)
+            .is_empty());
+        assert!(adversary.is_done());
+
+        // Past the end of the schedule, nothing is injected rather than panicking.
+        assert!(adversary
+            .get_next_packets(&network, 3, &mut factory)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_weighted_paths_follow_weights() {
+        let network = construct_path(4);
+        let mut factory = PacketFactory::new();
+        let config = json!({
+            "adversary_name": "weighted_paths",
+            "paths": [[0, 1, 2, 3], [2, 3]],
+            "weights": [3.0, 1.0],
+            "seed": 5,
+        });
+        let mut adversary = WeightedPathAdversary::from_config(config).unwrap();
+        let mut num_long = 0;
+        for rd in 1..=1000 {
+            let packets = adversary.get_next_packets(&network, rd, &mut factory);
+            assert_eq!(packets.len(), 1);
+            if packets[0].get_path().len() == 4 {
+                num_long += 1;
+            }
+        }
+        assert!((700..800).contains(&num_long));
+        assert_eq!(adversary.to_config()["seed"], 5);
+    }
+}
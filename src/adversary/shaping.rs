@@ -0,0 +1,378 @@
+//! This module contains adversaries which wrap another adversary and reshape its output.
+
+use super::path_random::SDPathRandomAdversary;
+use super::{
+    Adversary, AdversaryTrait, ADVERSARY_NAME_KEY, INNER_ADVERSARY_KEY, SCHEDULED_NAME,
+    TOKEN_BUCKET_NAME,
+};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory};
+use hashbrown::HashMap;
+use serde_json::{json, Map, Number, Value};
+
+/// Shapes the output of an inner adversary through a (rho, sigma) token bucket on every edge, so
+/// that the resulting injections are (rho, sigma)-admissible: over any interval of `T` rounds, at
+/// most `rho * T + sigma` injected packets have a given edge on their path.
+///
+/// Each edge's bucket holds at most `sigma` tokens at the end of a round and gains `rho` tokens at
+/// the start of the next. A packet is injected only if every edge on its remaining path has a
+/// whole token available, in which case one token is taken from each. Packets which cannot be
+/// injected are deferred, and are retried (oldest first, before any new packets) in later rounds;
+/// their injection round is set to the round in which they are actually injected.
+#[derive(Clone)]
+pub struct TokenBucketAdversary {
+    inner: Box<Adversary>,
+    rho: f64,
+    sigma: f64,
+    tokens: HashMap<(NodeID, NodeID), f64>,
+    deferred: Vec<Packet>,
+}
+
+impl TokenBucketAdversary {
+    /// Get a new `TokenBucketAdversary` shaping the given adversary.
+    pub fn new(inner: Adversary, rho: f64, sigma: f64) -> Self {
+        TokenBucketAdversary {
+            inner: Box::new(inner),
+            rho,
+            sigma,
+            tokens: HashMap::new(),
+            deferred: Vec::new(),
+        }
+    }
+
+    /// Get the number of packets which are currently being held back.
+    pub fn num_deferred(&self) -> usize {
+        self.deferred.len()
+    }
+
+    fn refill(&mut self, network: &Network) {
+        for edge in network.edges() {
+            let level = self.tokens.entry(edge.ids()).or_insert(self.sigma);
+            *level = level.min(self.sigma) + self.rho;
+        }
+    }
+
+    fn remaining_edges(p: &Packet) -> impl Iterator<Item = (NodeID, NodeID)> + '_ {
+        let path = &p.get_path()[p.get_path_idx()..];
+        path.windows(2).map(|w| (w[0], w[1]))
+    }
+
+    /// Take a token from every edge on the packet's remaining path if all of them have one.
+    fn try_take_tokens(&mut self, p: &Packet) -> bool {
+        let admissible =
+            Self::remaining_edges(p).all(|e| self.tokens.get(&e).copied().unwrap_or(0.0) >= 1.0);
+        if admissible {
+            for e in Self::remaining_edges(p) {
+                *self.tokens.get_mut(&e).unwrap() -= 1.0;
+            }
+        }
+        admissible
+    }
+}
+
+impl AdversaryTrait for TokenBucketAdversary {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        self.refill(network);
+
+        let mut candidates = std::mem::take(&mut self.deferred);
+        candidates.append(&mut self.inner.get_next_packets(network, rd, factory));
+
+        let mut next_packets = Vec::new();
+        for mut p in candidates {
+            if self.try_take_tokens(&p) {
+                p.set_injection_rd(rd);
+                next_packets.push(p);
+            } else {
+                self.deferred.push(p);
+            }
+        }
+        next_packets
+    }
+
+    fn is_done(&self) -> bool {
+        self.inner.is_done() && self.deferred.is_empty()
+    }
+
+    fn phase(&self) -> Option<&str> {
+        self.inner.phase()
+    }
+}
+
+const RHO_KEY: &str = "rho";
+const SIGMA_KEY: &str = "sigma";
+
+impl Describable for TokenBucketAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            TOKEN_BUCKET_NAME,
+            "Shapes an inner adversary's output to be (rho, sigma)-admissible, deferring packets.",
+        )
+        .required(RHO_KEY, "The rate of each edge's token bucket.", json!(0.5))
+        .required(
+            SIGMA_KEY,
+            "The size of each edge's token bucket.",
+            json!(2.0),
+        )
+        .required(
+            INNER_ADVERSARY_KEY,
+            "The config of the adversary to shape.",
+            SDPathRandomAdversary::describe().example_config(),
+        )
+    }
+}
+
+impl Configurable for TokenBucketAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let map = config.as_object().unwrap();
+        let rho = match map.get(RHO_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_f64().unwrap()),
+            _ => Err(String::from("No rho value provided.")),
+        }?;
+        let sigma = match map.get(SIGMA_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_f64().unwrap()),
+            _ => Err(String::from("No sigma value provided.")),
+        }?;
+        let inner = match map.get(INNER_ADVERSARY_KEY) {
+            Some(inner_cfg) => Adversary::from_config(inner_cfg.clone()),
+            None => Err(String::from("No inner adversary provided.")),
+        }?;
+        Ok(Self::new(inner, rho, sigma))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(TOKEN_BUCKET_NAME.to_string()),
+        );
+        map.insert(
+            RHO_KEY.to_string(),
+            Value::Number(Number::from_f64(self.rho).unwrap()),
+        );
+        map.insert(
+            SIGMA_KEY.to_string(),
+            Value::Number(Number::from_f64(self.sigma).unwrap()),
+        );
+        map.insert(INNER_ADVERSARY_KEY.to_string(), self.inner.to_config());
+        Value::Object(map)
+    }
+}
+
+/// Runs an inner adversary only during a schedule of round windows, pausing it in between: in a
+/// paused round, the inner adversary isn't asked for packets at all, so it resumes where it left
+/// off. Each window `[start, end]` is an inclusive range of rounds. With a `period`, the windows
+/// repeat every `period` rounds, so that round `rd` is active if `(rd - 1) % period + 1` is in one.
+#[derive(Clone)]
+pub struct ScheduledAdversary {
+    inner: Box<Adversary>,
+    windows: Vec<(usize, usize)>,
+    period: Option<usize>,
+    // The last round for which packets were requested.
+    last_rd: usize,
+}
+
+impl ScheduledAdversary {
+    /// Get a new `ScheduledAdversary` running the given adversary during the given windows,
+    /// repeated every `period` rounds if given.
+    pub fn new(inner: Adversary, windows: Vec<(usize, usize)>, period: Option<usize>) -> Self {
+        ScheduledAdversary {
+            inner: Box::new(inner),
+            windows,
+            period,
+            last_rd: 0,
+        }
+    }
+
+    /// Check whether the inner adversary runs in the given round.
+    pub fn is_active(&self, rd: usize) -> bool {
+        let rd = match self.period {
+            Some(period) => (rd.max(1) - 1) % period + 1,
+            None => rd,
+        };
+        self.windows
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&rd))
+    }
+}
+
+impl AdversaryTrait for ScheduledAdversary {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        self.last_rd = rd;
+        match self.is_active(rd) {
+            true => self.inner.get_next_packets(network, rd, factory),
+            false => Vec::new(),
+        }
+    }
+
+    /// Done once the inner adversary is, or without a period, once the last window has passed.
+    fn is_done(&self) -> bool {
+        let windows_passed =
+            self.period.is_none() && self.windows.iter().all(|(_, end)| *end <= self.last_rd);
+        self.inner.is_done() || windows_passed
+    }
+
+    /// `"active"` in the windows and `"paused"` between them, once packets have been requested.
+    fn phase(&self) -> Option<&str> {
+        match self.last_rd {
+            0 => None,
+            rd if self.is_active(rd) => Some(ACTIVE_PHASE),
+            _ => Some(PAUSED_PHASE),
+        }
+    }
+}
+
+const WINDOWS_KEY: &str = "windows";
+const ACTIVE_PHASE: &str = "active";
+const PAUSED_PHASE: &str = "paused";
+const PERIOD_KEY: &str = "period";
+
+impl Describable for ScheduledAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            SCHEDULED_NAME,
+            "Runs an inner adversary only during given round windows, pausing it in between.",
+        )
+        .required(
+            WINDOWS_KEY,
+            "The inclusive [start, end] round ranges in which the inner adversary runs.",
+            json!([[1, 10], [21, 30]]),
+        )
+        .optional(
+            PERIOD_KEY,
+            "The number of rounds after which the windows repeat.",
+            json!(40),
+        )
+        .required(
+            INNER_ADVERSARY_KEY,
+            "The config of the adversary to schedule.",
+            SDPathRandomAdversary::describe().example_config(),
+        )
+    }
+}
+
+impl Configurable for ScheduledAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let window_cfgs = match config.get(WINDOWS_KEY) {
+            Some(Value::Array(window_cfgs)) => Ok(window_cfgs),
+            _ => Err(String::from("No windows provided.")),
+        }?;
+        let mut windows = Vec::new();
+        for window_cfg in window_cfgs {
+            let bounds: Option<Vec<u64>> = window_cfg
+                .as_array()
+                .and_then(|bounds| bounds.iter().map(Value::as_u64).collect());
+            match bounds.as_deref() {
+                Some([start, end]) if 0 < *start && start <= end => {
+                    windows.push((*start as usize, *end as usize))
+                }
+                _ => {
+                    return Err(format!(
+                        "Window {} must be an array [start, end] of rounds with 0 < start <= end.",
+                        window_cfg
+                    ))
+                }
+            }
+        }
+        let period = match config.get(PERIOD_KEY) {
+            Some(period_cfg) => match period_cfg.as_u64() {
+                Some(period) if period > 0 => Ok(Some(period as usize)),
+                _ => Err(String::from("Period must be a positive integer.")),
+            },
+            None => Ok(None),
+        }?;
+        let inner = match config.get(INNER_ADVERSARY_KEY) {
+            Some(inner_cfg) => Adversary::from_config(inner_cfg.clone()),
+            None => Err(String::from("No inner adversary provided.")),
+        }?;
+        Ok(Self::new(inner, windows, period))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(SCHEDULED_NAME.to_string()),
+        );
+        let windows = self
+            .windows
+            .iter()
+            .map(|(start, end)| json!([start, end]))
+            .collect();
+        map.insert(WINDOWS_KEY.to_string(), Value::Array(windows));
+        if let Some(period) = self.period {
+            map.insert(PERIOD_KEY.to_string(), Value::Number(Number::from(period)));
+        }
+        map.insert(INNER_ADVERSARY_KEY.to_string(), self.inner.to_config());
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::SDPathRandomAdversary;
+    use crate::network::presets::construct_path;
+
+    #[test]
+    fn test_token_bucket_shaping() {
+        // On a 3-node path, the inner adversary always injects one packet at node 0.
+        let network = construct_path(3);
+        let inner = Adversary::new(SDPathRandomAdversary::from_seed(0));
+        let mut adversary = TokenBucketAdversary::new(inner, 0.5, 1.0);
+        let mut factory = PacketFactory::new();
+
+        let num_injected: Vec<usize> = (1..=6)
+            .map(|rd| adversary.get_next_packets(&network, rd, &mut factory).len())
+            .collect();
+        assert_eq!(num_injected, vec![1, 1, 0, 1, 0, 1]);
+        assert_eq!(adversary.num_deferred(), 2);
+
+        // Deferred packets are injected with the round they actually enter the network.
+        assert!(adversary
+            .get_next_packets(&network, 7, &mut factory)
+            .is_empty());
+        let packets = adversary.get_next_packets(&network, 8, &mut factory);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].injection_rd(), 8);
+    }
+
+    #[test]
+    fn test_scheduled_windows_pause_and_resume() {
+        let network = construct_path(3);
+        let config = serde_json::json!({
+            "adversary_name": "scheduled",
+            "windows": [[2, 3]],
+            "period": 4,
+            "adversary": { "adversary_name": "preset", "schedule": [[[0, 1]], [[0, 1, 2]], [[1, 2]]] },
+        });
+        let mut adversary = ScheduledAdversary::from_config(config.clone()).unwrap();
+        assert_eq!(adversary.to_config(), config);
+        let mut factory = PacketFactory::new();
+
+        // The inner preset schedule resumes where it paused, in the next window.
+        let path_lens: Vec<Vec<usize>> = (1..=7)
+            .map(|rd| {
+                let packets = adversary.get_next_packets(&network, rd, &mut factory);
+                packets.iter().map(|p| p.get_path().len()).collect()
+            })
+            .collect();
+        assert_eq!(
+            path_lens,
+            vec![vec![], vec![2], vec![3], vec![], vec![], vec![2], vec![]]
+        );
+        assert!(adversary.is_done());
+    }
+}
@@ -0,0 +1,177 @@
+//! This module contains stochastic adversaries which route packets along weighted shortest paths,
+//! and so work on any network.
+
+use super::{AdversaryTrait, ADVERSARY_NAME_KEY, SHORTEST_PATH_RANDOM_NAME};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory, PacketPath};
+use crate::simulation::random::SimRng;
+use serde_json::{json, Map, Number, Value};
+
+const SEED_NAME_KEY: &str = "seed";
+const DESTINATIONS_NAME_KEY: &str = "destinations";
+
+/// A multi-destination random adversary, which injects one packet per round between a random
+/// (source, destination) pair, routed along a minimum-weight path (see
+/// `Network::shortest_path`). The destination is drawn uniformly from `destinations` (every node
+/// by default) which can be reached from some other node, and the source uniformly from the nodes
+/// which can reach it.
+#[derive(Clone)]
+pub struct ShortestPathRandomAdversary {
+    rng: SimRng,
+    destinations: Option<Vec<NodeID>>,
+    // The shortest paths to each reachable destination, one per source which can reach it.
+    // Computed on the first round, since the network's edges and weights are fixed.
+    routes: Option<Vec<Vec<PacketPath>>>,
+}
+
+impl ShortestPathRandomAdversary {
+    /// Get a new `ShortestPathRandomAdversary` with the given seed, or a random one if none is
+    /// given.
+    pub fn new(destinations: Option<Vec<NodeID>>, seed: Option<u64>) -> Self {
+        ShortestPathRandomAdversary {
+            rng: match seed {
+                Some(seed) => SimRng::from_seed(seed),
+                None => SimRng::new(),
+            },
+            destinations,
+            routes: None,
+        }
+    }
+
+    fn compute_routes(&self, network: &Network) -> Vec<Vec<PacketPath>> {
+        let destinations = match &self.destinations {
+            Some(destinations) => destinations.clone(),
+            None => network.get_nodes(),
+        };
+        let routes: Vec<Vec<PacketPath>> = destinations
+            .iter()
+            .map(|dest_id| {
+                network
+                    .get_nodes()
+                    .into_iter()
+                    .filter(|src_id| src_id != dest_id)
+                    .filter_map(|src_id| network.shortest_path(src_id, *dest_id))
+                    .collect()
+            })
+            .filter(|dest_routes: &Vec<PacketPath>| !dest_routes.is_empty())
+            .collect();
+        assert!(
+            !routes.is_empty(),
+            "No destination of the shortest path random adversary is reachable."
+        );
+        routes
+    }
+}
+
+impl AdversaryTrait for ShortestPathRandomAdversary {
+    fn get_next_packets(
+        &mut self,
+        network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        if self.routes.is_none() {
+            self.routes = Some(self.compute_routes(network));
+        }
+        let routes = self.routes.as_ref().unwrap();
+        let dest_routes = &routes[self.rng.rand_int(routes.len())];
+        let route = &dest_routes[self.rng.rand_int(dest_routes.len())];
+        let path = factory.path_from(route.iter().copied());
+        vec![factory.create_packet(path, rd, 0)]
+    }
+}
+
+impl Describable for ShortestPathRandomAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            SHORTEST_PATH_RANDOM_NAME,
+            "Injects one packet per round between a random pair along a minimum-weight path.",
+        )
+        .optional(
+            DESTINATIONS_NAME_KEY,
+            "The possible destinations (every node by default).",
+            json!([2, 3]),
+        )
+        .optional(SEED_NAME_KEY, "The random seed.", json!(0))
+    }
+}
+
+impl Configurable for ShortestPathRandomAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let seed = config.get(SEED_NAME_KEY).and_then(Value::as_u64);
+        let destinations = match config.get(DESTINATIONS_NAME_KEY) {
+            Some(Value::Array(destinations)) if !destinations.is_empty() => destinations
+                .iter()
+                .map(|dest| match dest.as_u64() {
+                    Some(dest) => Ok(dest as NodeID),
+                    None => Err(format!("Destination {} must be a node id.", dest)),
+                })
+                .collect::<Result<Vec<NodeID>, CfgErrorMsg>>()
+                .map(Some),
+            Some(_) => Err(String::from(
+                "Destinations must be a nonempty array of node ids.",
+            )),
+            None => Ok(None),
+        }?;
+        Ok(Self::new(destinations, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(SHORTEST_PATH_RANDOM_NAME.to_string()),
+        );
+        if let Some(destinations) = &self.destinations {
+            let destinations = destinations
+                .iter()
+                .map(|dest| Value::Number(Number::from(*dest)));
+            map.insert(
+                DESTINATIONS_NAME_KEY.to_string(),
+                Value::Array(destinations.collect()),
+            );
+        }
+        map.insert(
+            SEED_NAME_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_routes_follow_weights() {
+        // Node 3 is reached from 0 via the light edges through 2, never through 1.
+        let network = Network::from_config(json!([
+            [{"to": 1, "weight": 4}, 2],
+            [3],
+            [{"to": 3, "weight": 2}],
+            []
+        ]))
+        .unwrap();
+        let mut factory = PacketFactory::new();
+        let mut adversary = ShortestPathRandomAdversary::from_config(json!({
+            "adversary_name": "shortest_path_random",
+            "destinations": [3],
+            "seed": 1,
+        }))
+        .unwrap();
+        for rd in 1..=30 {
+            for p in adversary.get_next_packets(&network, rd, &mut factory) {
+                let path = p.get_path().to_vec();
+                assert_eq!(*path.last().unwrap(), 3);
+                assert_eq!(Some(path.clone()), network.shortest_path(path[0], 3));
+                if path[0] == 0 {
+                    assert_eq!(path, vec![0, 2, 3]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! This module contains adversaries which load edges at target rates.
+
+use super::{AdversaryTrait, ADVERSARY_NAME_KEY, UTILIZATION_NAME};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory};
+use serde_json::{json, Map, Number, Value};
+
+// An edge to load, with its target utilization, the fractional demand carried over to the next
+// round, and the number of packets injected onto it so far.
+#[derive(Clone)]
+struct EdgeTarget {
+    edge: (NodeID, NodeID),
+    utilization: f64,
+    credit: f64,
+    num_injected: u64,
+}
+
+// Slack for rounding error in accrued demand, so that e.g. ten rounds at 0.1 give a packet.
+const CREDIT_TOLERANCE: f64 = 1e-9;
+
+/// Injects single-hop packets onto a set of edges so that each edge's long-run demand is its
+/// target utilization: a fraction of the edge's capacity, the number of packets it can forward
+/// per round. Each round, every edge accrues `utilization * capacity` packets of demand, and as
+/// many whole packets as it has accrued are injected onto it, so that the demand injected by round
+/// `T` is always within one packet of `utilization * capacity * T`.
+#[derive(Clone)]
+pub struct UtilizationAdversary {
+    capacity: usize,
+    targets: Vec<EdgeTarget>,
+    num_rds: u64,
+}
+
+impl UtilizationAdversary {
+    /// Get a new `UtilizationAdversary` loading each of the given edges at its given positive
+    /// target utilization of the given capacity.
+    pub fn new(capacity: usize, targets: Vec<((NodeID, NodeID), f64)>) -> Self {
+        assert!(
+            targets.iter().all(|(_, utilization)| *utilization > 0.0),
+            "Target utilizations must be positive."
+        );
+        UtilizationAdversary {
+            capacity,
+            targets: targets
+                .into_iter()
+                .map(|(edge, utilization)| EdgeTarget {
+                    edge,
+                    utilization,
+                    credit: 0.0,
+                    num_injected: 0,
+                })
+                .collect(),
+            num_rds: 0,
+        }
+    }
+
+    /// Get each target edge with the utilization its injected demand has achieved so far: the
+    /// packets injected onto it per round, as a fraction of its capacity.
+    pub fn achieved_utilizations(&self) -> Vec<((NodeID, NodeID), f64)> {
+        self.targets
+            .iter()
+            .map(|target| {
+                let demand = target.num_injected as f64 / self.num_rds.max(1) as f64;
+                (target.edge, demand / self.capacity as f64)
+            })
+            .collect()
+    }
+}
+
+impl AdversaryTrait for UtilizationAdversary {
+    fn get_next_packets(
+        &mut self,
+        _network: &Network,
+        rd: usize,
+        factory: &mut PacketFactory,
+    ) -> Vec<Packet> {
+        self.num_rds += 1;
+        let mut packets = Vec::new();
+        for target in &mut self.targets {
+            target.credit += target.utilization * self.capacity as f64;
+            let num_packets = (target.credit + CREDIT_TOLERANCE).floor();
+            target.credit = (target.credit - num_packets).max(0.0);
+            target.num_injected += num_packets as u64;
+            let (from_id, to_id) = target.edge;
+            for _ in 0..num_packets as usize {
+                let path = factory.path_from([from_id, to_id]);
+                packets.push(factory.create_packet(path, rd, 0));
+            }
+        }
+        packets
+    }
+
+    fn report(&self) -> Option<Value> {
+        let edges: Vec<Value> = self
+            .targets
+            .iter()
+            .zip(self.achieved_utilizations())
+            .map(|(target, (edge, achieved))| {
+                json!({
+                    EDGE_KEY: [edge.0, edge.1],
+                    UTILIZATION_KEY: target.utilization,
+                    "achieved": achieved,
+                })
+            })
+            .collect();
+        Some(json!({ TARGETS_KEY: edges }))
+    }
+}
+
+const CAPACITY_KEY: &str = "capacity";
+const TARGETS_KEY: &str = "targets";
+const EDGE_KEY: &str = "edge";
+const UTILIZATION_KEY: &str = "utilization";
+
+impl Describable for UtilizationAdversary {
+    fn describe() -> Description {
+        Description::new(
+            ADVERSARY_NAME_KEY,
+            UTILIZATION_NAME,
+            "Injects single-hop packets so that each target edge is loaded at its utilization.",
+        )
+        .required(
+            TARGETS_KEY,
+            "The edges to load, each with its target utilization (a fraction of capacity).",
+            json!([{ EDGE_KEY: [0, 1], UTILIZATION_KEY: 0.5 }]),
+        )
+        .optional(
+            CAPACITY_KEY,
+            "The packets each edge can forward per round (1 by default).",
+            json!(2),
+        )
+    }
+}
+
+impl Configurable for UtilizationAdversary {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let capacity = match config.get(CAPACITY_KEY) {
+            Some(Value::Number(num)) => match num.as_u64() {
+                Some(capacity) if capacity > 0 => Ok(capacity as usize),
+                _ => Err(String::from("Capacity must be a positive integer.")),
+            },
+            Some(_) => Err(String::from("Capacity must be a positive integer.")),
+            None => Ok(1),
+        }?;
+        let target_cfgs = match config.get(TARGETS_KEY) {
+            Some(Value::Array(target_cfgs)) => Ok(target_cfgs),
+            _ => Err(String::from("No utilization targets provided.")),
+        }?;
+        let mut targets = Vec::new();
+        for target_cfg in target_cfgs {
+            let edge = match target_cfg.get(EDGE_KEY).and_then(Value::as_array) {
+                Some(ids) if ids.len() == 2 => match (ids[0].as_u64(), ids[1].as_u64()) {
+                    (Some(from_id), Some(to_id)) => Ok((from_id as usize, to_id as usize)),
+                    _ => Err(String::from("Edge node ids must be integers.")),
+                },
+                _ => Err(String::from(
+                    "Each target's edge must be an array of two node ids.",
+                )),
+            }?;
+            let utilization = match target_cfg.get(UTILIZATION_KEY).and_then(Value::as_f64) {
+                Some(utilization) if utilization > 0.0 => Ok(utilization),
+                _ => Err(String::from(
+                    "Each target's utilization must be a positive number.",
+                )),
+            }?;
+            targets.push((edge, utilization));
+        }
+        Ok(Self::new(capacity, targets))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADVERSARY_NAME_KEY.to_string(),
+            Value::String(UTILIZATION_NAME.to_string()),
+        );
+        map.insert(
+            CAPACITY_KEY.to_string(),
+            Value::Number(Number::from(self.capacity)),
+        );
+        let targets: Vec<Value> = self
+            .targets
+            .iter()
+            .map(|target| {
+                json!({
+                    EDGE_KEY: [target.edge.0, target.edge.1],
+                    UTILIZATION_KEY: target.utilization,
+                })
+            })
+            .collect();
+        map.insert(TARGETS_KEY.to_string(), Value::Array(targets));
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+
+    #[test]
+    fn test_utilization_targets_are_met() {
+        let network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut adversary = UtilizationAdversary::new(2, vec![((0, 1), 0.3), ((1, 2), 0.75)]);
+
+        let mut num_injected = [0, 0];
+        for rd in 1..=100 {
+            for p in adversary.get_next_packets(&network, rd, &mut factory) {
+                assert_eq!(p.get_path().len(), 2);
+                num_injected[p.get_path()[0]] += 1;
+            }
+        }
+        // Demand stays within a packet of the target, so it matches exactly at round 100.
+        assert_eq!(num_injected, [60, 150]);
+        assert_eq!(
+            adversary.achieved_utilizations(),
+            vec![((0, 1), 0.3), ((1, 2), 0.75)]
+        );
+        let report = adversary.report().unwrap();
+        assert_eq!(report["targets"][1]["achieved"], 0.75);
+    }
+}
@@ -1,8 +1,21 @@
+use crate::simulation::experiment::Experiment;
+use crate::simulation::layout::{self, OutputLayout};
+use crate::simulation::random::SimRng;
+use serde::Serialize;
 use serde_json::{Map, Value};
+use std::fmt;
+use std::ops::Range;
+use std::path::{PathBuf, MAIN_SEPARATOR_STR};
 
 /// String containing a configuration error message.
 pub type CfgErrorMsg = String;
 
+/// Convert a path from a config to a `PathBuf`, accepting both `/` and `\` as separators so that
+/// configs written on any platform work on every platform.
+pub fn path_from_config(path: &str) -> PathBuf {
+    PathBuf::from(path.replace(['/', '\\'], MAIN_SEPARATOR_STR))
+}
+
 /// Trait which structs which we can dump to/load from a json config.
 pub trait Configurable {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg>
@@ -11,7 +24,80 @@ pub trait Configurable {
     fn to_config(&self) -> Value;
 }
 
-/// Configuration for a `Simulation` run.
+/// Documentation of one config key of a component.
+#[derive(Clone, Debug, Serialize)]
+pub struct KeyDescription {
+    pub key: &'static str,
+    pub required: bool,
+    pub doc: &'static str,
+    /// A valid value for the key, used when generating example configs.
+    pub example: Value,
+}
+
+/// Structured documentation of a component's config, so that the CLI can list components and
+/// generate example configs without hard-coding them.
+#[derive(Clone, Debug, Serialize)]
+pub struct Description {
+    /// The key under which the component's name is given, e.g. `"protocol_name"`.
+    pub name_key: &'static str,
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub keys: Vec<KeyDescription>,
+}
+
+impl Description {
+    /// Get a new `Description` of a component with no config keys besides its name.
+    pub fn new(name_key: &'static str, name: &'static str, doc: &'static str) -> Self {
+        Description {
+            name_key,
+            name,
+            doc,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Add a required key, with an example value.
+    pub fn required(mut self, key: &'static str, doc: &'static str, example: Value) -> Self {
+        self.keys.push(KeyDescription {
+            key,
+            required: true,
+            doc,
+            example,
+        });
+        self
+    }
+
+    /// Add an optional key, with an example value.
+    pub fn optional(mut self, key: &'static str, doc: &'static str, example: Value) -> Self {
+        self.keys.push(KeyDescription {
+            key,
+            required: false,
+            doc,
+            example,
+        });
+        self
+    }
+
+    /// Get an example config of the component, with its name and the example value of every
+    /// required key.
+    pub fn example_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(self.name_key.to_string(), Value::from(self.name));
+        for key in self.keys.iter().filter(|k| k.required) {
+            map.insert(key.key.to_string(), key.example.clone());
+        }
+        Value::Object(map)
+    }
+}
+
+/// Trait for components which describe their config (see `Description`).
+pub trait Describable {
+    fn describe() -> Description;
+}
+
+/// Configuration for a `Simulation` run. The adversary config may be either a single adversary
+/// object or an array of adversary objects, all of which inject each round.
+#[derive(Clone)]
 pub struct SimConfig {
     pub graph_adjacency: Value,
     pub protocol_cfg: Value,
@@ -19,6 +105,21 @@ pub struct SimConfig {
     pub threshold_cfg: Value,
     pub recorder_cfgs: Value,
     pub output_path: String,
+    pub admission_cfg: Option<Value>,
+    pub end_of_injection_cfg: Option<Value>,
+    pub absorption_cfg: Option<Value>,
+    pub initial_backlog_cfg: Option<Value>,
+    pub node_capacities_cfg: Option<Value>,
+    pub random_edge_order_cfg: Option<Value>,
+    pub round_steps_cfg: Option<Value>,
+    pub max_injections_per_rd_cfg: Option<Value>,
+    pub excess_injections_cfg: Option<Value>,
+    pub seed_cfg: Option<Value>,
+    pub stall_rds_cfg: Option<Value>,
+    pub warm_up_rds_cfg: Option<Value>,
+    pub stop_on_write_error_cfg: Option<Value>,
+    pub metrics_only_cfg: Option<Value>,
+    pub metadata_columns_cfg: Option<Value>,
 }
 
 pub const ADJACENCY_KEY: &str = "graph_adjacency";
@@ -27,6 +128,21 @@ pub const ADVERSARY_KEY: &str = "adversary";
 pub const THRESHOLD_KEY: &str = "threshold";
 pub const RECORDERS_KEY: &str = "recorders";
 pub const OUTPUT_PATH_KEY: &str = "output_path";
+pub const ADMISSION_KEY: &str = "admission";
+pub const END_OF_INJECTION_KEY: &str = "end_of_injection";
+pub const ABSORPTION_KEY: &str = "absorption";
+pub const INITIAL_BACKLOG_KEY: &str = "initial_backlog";
+pub const NODE_CAPACITIES_KEY: &str = "node_capacities";
+pub const RANDOM_EDGE_ORDER_KEY: &str = "random_edge_order";
+pub const ROUND_STEPS_KEY: &str = "round_steps";
+pub const MAX_INJECTIONS_PER_RD_KEY: &str = "max_injections_per_rd";
+pub const EXCESS_INJECTIONS_KEY: &str = "excess_injections";
+pub const SEED_KEY: &str = "seed";
+pub const STALL_RDS_KEY: &str = "stall_rds";
+pub const WARM_UP_RDS_KEY: &str = "warm_up_rds";
+pub const STOP_ON_WRITE_ERROR_KEY: &str = "stop_on_write_error";
+pub const METRICS_ONLY_KEY: &str = "metrics_only";
+pub const METADATA_COLUMNS_KEY: &str = "metadata_columns";
 
 impl SimConfig {
     fn get_key(
@@ -57,6 +173,21 @@ impl SimConfig {
             Some(Value::String(path)) => Ok(path),
             _ => Err("No output path string found."),
         }?;
+        let admission_cfg = obj.remove(ADMISSION_KEY);
+        let end_of_injection_cfg = obj.remove(END_OF_INJECTION_KEY);
+        let absorption_cfg = obj.remove(ABSORPTION_KEY);
+        let initial_backlog_cfg = obj.remove(INITIAL_BACKLOG_KEY);
+        let node_capacities_cfg = obj.remove(NODE_CAPACITIES_KEY);
+        let random_edge_order_cfg = obj.remove(RANDOM_EDGE_ORDER_KEY);
+        let round_steps_cfg = obj.remove(ROUND_STEPS_KEY);
+        let max_injections_per_rd_cfg = obj.remove(MAX_INJECTIONS_PER_RD_KEY);
+        let excess_injections_cfg = obj.remove(EXCESS_INJECTIONS_KEY);
+        let seed_cfg = obj.remove(SEED_KEY);
+        let stall_rds_cfg = obj.remove(STALL_RDS_KEY);
+        let warm_up_rds_cfg = obj.remove(WARM_UP_RDS_KEY);
+        let stop_on_write_error_cfg = obj.remove(STOP_ON_WRITE_ERROR_KEY);
+        let metrics_only_cfg = obj.remove(METRICS_ONLY_KEY);
+        let metadata_columns_cfg = obj.remove(METADATA_COLUMNS_KEY);
 
         Ok(Self {
             graph_adjacency,
@@ -65,6 +196,21 @@ impl SimConfig {
             threshold_cfg,
             recorder_cfgs,
             output_path,
+            admission_cfg,
+            end_of_injection_cfg,
+            absorption_cfg,
+            initial_backlog_cfg,
+            node_capacities_cfg,
+            random_edge_order_cfg,
+            round_steps_cfg,
+            max_injections_per_rd_cfg,
+            excess_injections_cfg,
+            seed_cfg,
+            stall_rds_cfg,
+            warm_up_rds_cfg,
+            stop_on_write_error_cfg,
+            metrics_only_cfg,
+            metadata_columns_cfg,
         })
     }
 
@@ -80,18 +226,113 @@ impl SimConfig {
             OUTPUT_PATH_KEY.to_string(),
             Value::String(self.output_path.clone()),
         );
+        if let Some(admission_cfg) = &self.admission_cfg {
+            map.insert(ADMISSION_KEY.to_string(), admission_cfg.clone());
+        }
+        if let Some(end_of_injection_cfg) = &self.end_of_injection_cfg {
+            map.insert(
+                END_OF_INJECTION_KEY.to_string(),
+                end_of_injection_cfg.clone(),
+            );
+        }
+        if let Some(absorption_cfg) = &self.absorption_cfg {
+            map.insert(ABSORPTION_KEY.to_string(), absorption_cfg.clone());
+        }
+        if let Some(initial_backlog_cfg) = &self.initial_backlog_cfg {
+            map.insert(INITIAL_BACKLOG_KEY.to_string(), initial_backlog_cfg.clone());
+        }
+        if let Some(node_capacities_cfg) = &self.node_capacities_cfg {
+            map.insert(NODE_CAPACITIES_KEY.to_string(), node_capacities_cfg.clone());
+        }
+        if let Some(random_edge_order_cfg) = &self.random_edge_order_cfg {
+            map.insert(
+                RANDOM_EDGE_ORDER_KEY.to_string(),
+                random_edge_order_cfg.clone(),
+            );
+        }
+        if let Some(round_steps_cfg) = &self.round_steps_cfg {
+            map.insert(ROUND_STEPS_KEY.to_string(), round_steps_cfg.clone());
+        }
+        if let Some(max_injections_per_rd_cfg) = &self.max_injections_per_rd_cfg {
+            map.insert(
+                MAX_INJECTIONS_PER_RD_KEY.to_string(),
+                max_injections_per_rd_cfg.clone(),
+            );
+        }
+        if let Some(excess_injections_cfg) = &self.excess_injections_cfg {
+            map.insert(
+                EXCESS_INJECTIONS_KEY.to_string(),
+                excess_injections_cfg.clone(),
+            );
+        }
+        if let Some(seed_cfg) = &self.seed_cfg {
+            map.insert(SEED_KEY.to_string(), seed_cfg.clone());
+        }
+        if let Some(stall_rds_cfg) = &self.stall_rds_cfg {
+            map.insert(STALL_RDS_KEY.to_string(), stall_rds_cfg.clone());
+        }
+        if let Some(warm_up_rds_cfg) = &self.warm_up_rds_cfg {
+            map.insert(WARM_UP_RDS_KEY.to_string(), warm_up_rds_cfg.clone());
+        }
+        if let Some(stop_on_write_error_cfg) = &self.stop_on_write_error_cfg {
+            map.insert(
+                STOP_ON_WRITE_ERROR_KEY.to_string(),
+                stop_on_write_error_cfg.clone(),
+            );
+        }
+        if let Some(metrics_only_cfg) = &self.metrics_only_cfg {
+            map.insert(METRICS_ONLY_KEY.to_string(), metrics_only_cfg.clone());
+        }
+        if let Some(metadata_columns_cfg) = &self.metadata_columns_cfg {
+            map.insert(
+                METADATA_COLUMNS_KEY.to_string(),
+                metadata_columns_cfg.clone(),
+            );
+        }
         Value::Object(map)
     }
 }
 
+/// The runs into which a simulation config with `"replications"` was expanded.
+#[derive(Clone)]
+pub struct ReplicationGroup {
+    /// The config's output path, under which each replication writes to `rep_i`.
+    pub output_path: String,
+    /// The indices of the replications in `Config::sim_configs`.
+    pub run_ids: Range<usize>,
+    pub metrics_only: bool,
+}
+
 /// Config for the entire program.
 pub struct Config {
     pub sim_configs: Vec<SimConfig>,
+    pub replication_groups: Vec<ReplicationGroup>,
+    // The indices in `sim_configs` of the runs of each entry of `"simulations"`.
+    pub sim_run_ids: Vec<Range<usize>>,
+    // If set, the simulations are this experiment's points, to be analyzed once they finish.
+    pub experiment: Option<Experiment>,
+    // If set, every simulation's output is placed under this layout's root.
+    pub output_layout: Option<OutputLayout>,
     pub parallel: bool,
+    // If set, every simulation's recorders write to merged files in this directory.
+    pub merged_output_path: Option<String>,
+    // If set, a parallel batch prints a status table of its runs every this many seconds.
+    pub status_interval_secs: Option<u64>,
+    // The number of times a simulation which panics is rerun before it's marked as failed.
+    pub max_retries: usize,
 }
 
 const SIMS_KEY: &str = "simulations";
+const EXPERIMENT_KEY: &str = "experiment";
+const REPLICATIONS_KEY: &str = "replications";
+const RESAMPLE_TOPOLOGY_KEY: &str = "resample_topology";
+const OUTPUT_ROOT_KEY: &str = "output_root";
+/// The prefix of the directory of each replication, under its simulation's output path.
+pub const REPLICATION_DIR_PREFIX: &str = "rep_";
 const PARALLEL_KEY: &str = "parallel";
+const MERGED_OUTPUT_PATH_KEY: &str = "merged_output_path";
+const STATUS_INTERVAL_KEY: &str = "status_interval_secs";
+const MAX_RETRIES_KEY: &str = "max_retries";
 const COMMENT_PREFIX: &str = "//";
 
 impl Config {
@@ -109,22 +350,194 @@ impl Config {
             )),
         }?;
 
-        let sim_cfgs: Vec<SimConfig> = match map.get(SIMS_KEY) {
-            Some(Value::Array(cfgs)) => Ok(cfgs
-                .iter()
-                .map(|x| SimConfig::from_val(x.clone()).unwrap())
-                .collect()),
+        let experiment = match map.remove(EXPERIMENT_KEY) {
+            Some(experiment_cfg) => Some(Experiment::from_config(experiment_cfg)?),
+            None => None,
+        };
+        if let Some(experiment) = &experiment {
+            if map.contains_key(SIMS_KEY) || map.contains_key(OUTPUT_ROOT_KEY) {
+                return Err(String::from(
+                    "An experiment sets its own simulations and output root.",
+                ));
+            }
+            map.insert(
+                SIMS_KEY.to_string(),
+                Value::Array(experiment.sim_configs()?),
+            );
+            map.insert(
+                OUTPUT_ROOT_KEY.to_string(),
+                Value::String(experiment.output_root().to_string()),
+            );
+        }
+
+        let mut output_layout = match map.remove(OUTPUT_ROOT_KEY) {
+            Some(Value::String(root)) => Ok(Some(OutputLayout::new(root))),
+            Some(_) => Err(String::from("Output root must be a string.")),
+            None => Ok(None),
+        }?;
+
+        let mut sim_cfgs: Vec<SimConfig> = Vec::new();
+        let mut replication_groups = Vec::new();
+        let mut sim_run_ids = Vec::new();
+        match map.get(SIMS_KEY) {
+            Some(Value::Array(cfgs)) => {
+                for (index, cfg) in cfgs.iter().enumerate() {
+                    let mut cfg = cfg.clone();
+                    let replications =
+                        match cfg.as_object_mut().and_then(|o| o.remove(REPLICATIONS_KEY)) {
+                            Some(num) => match num.as_u64() {
+                                Some(num) if num > 0 => Some(num as usize),
+                                _ => {
+                                    return Err(String::from(
+                                        "Replications must be a positive integer.",
+                                    ))
+                                }
+                            },
+                            None => None,
+                        };
+                    let resample_topology = match cfg
+                        .as_object_mut()
+                        .and_then(|o| o.remove(RESAMPLE_TOPOLOGY_KEY))
+                    {
+                        Some(Value::Bool(_)) if replications.is_none() => {
+                            return Err(String::from(
+                                "Resampling the topology requires replications.",
+                            ))
+                        }
+                        Some(Value::Bool(resample)) => resample,
+                        Some(_) => {
+                            return Err(String::from("Resample topology must be a boolean."))
+                        }
+                        None => false,
+                    };
+                    let dir = layout::expand_output_path(&mut cfg, index, output_layout.as_ref())?;
+                    let sim_cfg = SimConfig::from_val(cfg)?;
+                    let first_run_id = sim_cfgs.len();
+                    match replications {
+                        Some(replications) => {
+                            let (group, rep_cfgs) = Self::replicate(
+                                &sim_cfg,
+                                replications,
+                                resample_topology,
+                                first_run_id,
+                            );
+                            sim_cfgs.extend(rep_cfgs);
+                            replication_groups.push(group);
+                        }
+                        None => sim_cfgs.push(sim_cfg),
+                    }
+                    sim_run_ids.push(first_run_id..sim_cfgs.len());
+                    if let Some(output_layout) = &mut output_layout {
+                        output_layout.add_entry(
+                            dir,
+                            first_run_id..sim_cfgs.len(),
+                            replications.is_some(),
+                        );
+                    }
+                }
+                Ok(())
+            }
             _ => Err(String::from(
                 "Must provide \"parallel\" boolean field in config.",
             )),
         }?;
 
+        let merged_output_path = match map.remove(MERGED_OUTPUT_PATH_KEY) {
+            Some(Value::String(path)) => Ok(Some(path)),
+            Some(_) => Err(String::from("Merged output path must be a string.")),
+            None => Ok(None),
+        }?;
+
+        let status_interval_secs = match map.remove(STATUS_INTERVAL_KEY) {
+            Some(Value::Number(secs)) => match secs.as_u64() {
+                Some(secs) if secs > 0 => Ok(Some(secs)),
+                _ => Err(String::from(
+                    "Status interval must be a positive integer number of seconds.",
+                )),
+            },
+            Some(_) => Err(String::from(
+                "Status interval must be a positive integer number of seconds.",
+            )),
+            None => Ok(None),
+        }?;
+
+        let max_retries = match map.remove(MAX_RETRIES_KEY) {
+            Some(Value::Number(retries)) => match retries.as_u64() {
+                Some(retries) => Ok(retries as usize),
+                None => Err(String::from("Max retries must be a nonnegative integer.")),
+            },
+            Some(_) => Err(String::from("Max retries must be a nonnegative integer.")),
+            None => Ok(0),
+        }?;
+
         Ok(Self {
             sim_configs: sim_cfgs,
+            replication_groups,
+            sim_run_ids,
+            experiment,
+            output_layout,
             parallel,
+            merged_output_path,
+            status_interval_secs,
+            max_retries,
         })
     }
 
+    /// Expand the given config into the given number of replications, to be run as the simulations
+    /// starting at `first_run_id`. Replication `i` writes to `rep_i` under the config's output
+    /// path, and if the config sets a base seed, uses the `"replication_i"` stream of it (see
+    /// `SimRng::stream_seed`) as its own.
+    ///
+    /// A random network preset is the same in every replication unless `resample_topology` is
+    /// set: its seed is then the `"replication_i"` stream of its own seed if it has one, or else
+    /// comes from the replication's base seed (see `Simulation::from_config`) if there is one.
+    fn replicate(
+        sim_cfg: &SimConfig,
+        replications: usize,
+        resample_topology: bool,
+        first_run_id: usize,
+    ) -> (ReplicationGroup, Vec<SimConfig>) {
+        let base_seed = sim_cfg.seed_cfg.as_ref().and_then(Value::as_u64);
+        let topology_seed = match &sim_cfg.graph_adjacency {
+            Value::Object(preset) => match preset.get(SEED_KEY).and_then(Value::as_u64) {
+                Some(seed) => Some(seed),
+                None if resample_topology => None,
+                None => Some(match base_seed {
+                    Some(base_seed) => SimRng::stream_seed(base_seed, "topology"),
+                    None => SimRng::new().seed(),
+                }),
+            },
+            _ => None,
+        };
+        let sim_cfgs = (0..replications)
+            .map(|i| {
+                let mut rep_cfg = sim_cfg.clone();
+                if let Some(topology_seed) = topology_seed {
+                    let seed = match resample_topology {
+                        true => SimRng::stream_seed(topology_seed, &format!("replication_{}", i)),
+                        false => topology_seed,
+                    };
+                    rep_cfg.graph_adjacency[SEED_KEY] = Value::from(seed);
+                }
+                rep_cfg.output_path = path_from_config(&sim_cfg.output_path)
+                    .join(format!("{}{}", REPLICATION_DIR_PREFIX, i))
+                    .to_string_lossy()
+                    .to_string();
+                if let Some(base_seed) = base_seed {
+                    let seed = SimRng::stream_seed(base_seed, &format!("replication_{}", i));
+                    rep_cfg.seed_cfg = Some(Value::from(seed));
+                }
+                rep_cfg
+            })
+            .collect();
+        let group = ReplicationGroup {
+            output_path: sim_cfg.output_path.clone(),
+            run_ids: first_run_id..first_run_id + replications,
+            metrics_only: sim_cfg.metrics_only_cfg == Some(Value::Bool(true)),
+        };
+        (group, sim_cfgs)
+    }
+
     fn remove_commented_lines(config_str: String) -> String {
         let lines = config_str.lines();
         let uncommented_lines: Vec<String> = lines
@@ -133,9 +546,11 @@ impl Config {
             .collect();
         uncommented_lines.concat()
     }
+}
 
+impl fmt::Display for Config {
     /// Dump this `Config` into a json string.
-    pub fn to_string(&self) -> String {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut map = Map::new();
         map.insert(PARALLEL_KEY.to_string(), Value::Bool(self.parallel));
         let mut sims_arr = Vec::new();
@@ -143,7 +558,69 @@ impl Config {
             sims_arr.push(sim_cfg.to_val())
         }
         map.insert(SIMS_KEY.to_string(), Value::Array(sims_arr));
+        if let Some(path) = &self.merged_output_path {
+            map.insert(
+                MERGED_OUTPUT_PATH_KEY.to_string(),
+                Value::String(path.clone()),
+            );
+        }
+        if let Some(secs) = self.status_interval_secs {
+            map.insert(STATUS_INTERVAL_KEY.to_string(), Value::from(secs));
+        }
+        if self.max_retries > 0 {
+            map.insert(MAX_RETRIES_KEY.to_string(), Value::from(self.max_retries));
+        }
         let obj = Value::Object(map);
-        serde_json::to_string(&obj).unwrap()
+        write!(f, "{}", serde_json::to_string(&obj).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+    use serde_json::json;
+
+    #[test]
+    fn test_topology_resampling_across_replications() {
+        let config = |resample_topology: bool| {
+            let sim = json!({
+                "graph_adjacency": { "preset": "random_dag", "num_nodes": 8, "edge_prob": 0.5 },
+                "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "adversary": { "adversary_name": "shortest_path_random" },
+                "threshold": { "threshold_name": "timed", "max_rds": 10 },
+                "recorders": [],
+                "output_path": "out",
+                "seed": 7,
+                "replications": 4,
+                "resample_topology": resample_topology,
+            });
+            json!({ "parallel": false, "simulations": [sim] }).to_string()
+        };
+        let topologies = |resample_topology: bool| -> Vec<Vec<(usize, usize)>> {
+            let config = Config::from_string(config(resample_topology)).unwrap();
+            config
+                .sim_configs
+                .into_iter()
+                .map(|c| {
+                    // Fill the network's seed from the replication's base seed, as a simulation
+                    // does.
+                    let mut graph = c.graph_adjacency;
+                    if let Some(seed) = c.seed_cfg.and_then(|s| s.as_u64()) {
+                        SimRng::fill_seed(&mut graph, SimRng::stream_seed(seed, "topology"));
+                    }
+                    let mut edges = Network::from_config(graph).unwrap().get_edgebuffers();
+                    edges.sort_unstable();
+                    edges
+                })
+                .collect()
+        };
+
+        let fixed = topologies(false);
+        assert!(fixed.iter().all(|t| *t == fixed[0]));
+        assert_eq!(topologies(false), fixed);
+        let resampled = topologies(true);
+        assert!(resampled.iter().any(|t| *t != resampled[0]));
+        assert_eq!(topologies(true), resampled);
     }
 }
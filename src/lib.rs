@@ -1,6 +1,15 @@
+//! A simulator for the adversarial queueing theory (AQT) model. Programs embedding it should start
+//! from `prelude`, which re-exports the types needed to build, run, and extend simulations. The
+//! command-line program is built with the `cli` feature, which is on by default; depend on the
+//! crate with `default-features = false` to leave it out.
+
 pub mod adversary;
 pub mod config;
 pub mod network;
 pub mod packet;
+pub mod prelude;
 pub mod protocol;
 pub mod simulation;
+pub mod testing;
+#[cfg(feature = "testkit")]
+pub mod testkit;
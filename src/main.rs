@@ -1,47 +1,566 @@
-use aqt_sim::config::Config;
+use aqt_sim::adversary::Adversary;
+use aqt_sim::config::{self, Config, Configurable, Description, ReplicationGroup, SimConfig};
+use aqt_sim::network::presets::construct_path;
+use aqt_sim::protocol::Protocol;
+use aqt_sim::simulation::aggregate::{Aggregate, AGGREGATE_FILENAME};
+use aqt_sim::simulation::bundle::Bundle;
+use aqt_sim::simulation::cosim::CoSimulation;
+use aqt_sim::simulation::debugger::Debugger;
+use aqt_sim::simulation::dry_run::{self, OutputEstimate, DEFAULT_PROBE_RDS};
+use aqt_sim::simulation::experiment::Experiment;
+use aqt_sim::simulation::layout::OutputLayout;
+use aqt_sim::simulation::metrics::SimMetrics;
+use aqt_sim::simulation::recorder::MergedOutput;
+use aqt_sim::simulation::search::Search;
+use aqt_sim::simulation::status::{panic_message, RunState, StatusBoard};
+use aqt_sim::simulation::threshold::Threshold;
+use aqt_sim::simulation::trace;
 use aqt_sim::simulation::Simulation;
+use serde_json::json;
 use std::env;
 use std::fs;
+use std::io;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-const USAGE_MSG: &str = "USAGE: aqt_sim <config_filepath>";
+const USAGE_MSG: &str = "USAGE: aqt_sim <config_filepath> [--force | --dry-run]
+       aqt_sim replay <trace_filepath> <rd> [prime]
+       aqt_sim debug <config_filepath> [sim_idx]
+       aqt_sim cosim <config_filepath> <sim_idx_a> <sim_idx_b> <output_path>
+       aqt_sim search <search_config_filepath> <output_path>
+       aqt_sim list
+       aqt_sim generate-config <protocol_name> <adversary_name>
+       aqt_sim export <run_output_path> <bundle_path>
+       aqt_sim import <bundle_path> <run_output_path>
+       aqt_sim inspect <bundle_path>";
+const REPLAY_CMD: &str = "replay";
+const DEBUG_CMD: &str = "debug";
+const COSIM_CMD: &str = "cosim";
+const SEARCH_CMD: &str = "search";
+const LIST_CMD: &str = "list";
+const GENERATE_CONFIG_CMD: &str = "generate-config";
+const EXPORT_CMD: &str = "export";
+const IMPORT_CMD: &str = "import";
+const INSPECT_CMD: &str = "inspect";
+const PRIME_ARG: &str = "prime";
+const FORCE_FLAG: &str = "--force";
+const DRY_RUN_FLAG: &str = "--dry-run";
+// Dry runs warn about batches projected to write more than this many bytes.
+const LARGE_OUTPUT_BYTES: u64 = 1 << 30;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
         println!("{}", USAGE_MSG);
+    } else if args[1] == REPLAY_CMD {
+        replay(&args[2..]);
+    } else if args[1] == DEBUG_CMD {
+        debug(&args[2..]);
+    } else if args[1] == COSIM_CMD {
+        cosim(&args[2..]);
+    } else if args[1] == SEARCH_CMD {
+        search(&args[2..]);
+    } else if args[1] == LIST_CMD {
+        print_descriptions("Protocols", &Protocol::descriptions());
+        print_descriptions("Adversaries", &Adversary::descriptions());
+    } else if args[1] == GENERATE_CONFIG_CMD {
+        generate_config(&args[2..]);
+    } else if args[1] == EXPORT_CMD {
+        export(&args[2..]);
+    } else if args[1] == IMPORT_CMD {
+        import(&args[2..]);
+    } else if args[1] == INSPECT_CMD {
+        inspect(&args[2..]);
     } else {
         let now = Instant::now();
         let json = fs::read_to_string(&args[1]).unwrap();
         let config = Config::from_string(json).unwrap();
-        if config.parallel {
-            run_parallel(config)
-        } else {
-            run_sequential(config)
+        if args[2..].iter().any(|arg| arg == DRY_RUN_FLAG) {
+            return dry_run(&config);
         }
+        let force = args[2..].iter().any(|arg| arg == FORCE_FLAG);
+        let failed = if config.parallel {
+            run_parallel(config, force)
+        } else {
+            run_sequential(config, force)
+        };
         let elapsed = now.elapsed();
         println!("Elapsed: {:.2?}", elapsed);
+        if failed {
+            process::exit(1);
+        }
+    }
+}
+
+/// The state shared by every run in a batch.
+struct Batch {
+    force: bool,
+    max_retries: usize,
+    merged_output: Option<Arc<MergedOutput>>,
+    status_board: Arc<StatusBoard>,
+    // The metrics of each run which finished or was skipped as complete, for aggregation.
+    metrics: Mutex<Vec<Option<SimMetrics>>>,
+}
+
+impl Batch {
+    fn new(config: &Config, force: bool) -> Self {
+        Batch {
+            force,
+            max_retries: config.max_retries,
+            merged_output: config
+                .merged_output_path
+                .clone()
+                .map(|path| Arc::new(MergedOutput::new(config::path_from_config(&path)))),
+            status_board: Arc::new(StatusBoard::new(config.sim_configs.len())),
+            metrics: Mutex::new(vec![None; config.sim_configs.len()]),
+        }
+    }
+
+    /// Run the simulation with the given config, isolating any panic so that the rest of the batch
+    /// continues. A run which panics or fails to write its output is rebuilt from its config and
+    /// rerun up to `max_retries` times, and is marked as failed if every attempt fails.
+    fn run(&self, run_id: usize, sim_config: SimConfig) {
+        for attempt in 0..=self.max_retries {
+            let sim_config = sim_config.clone();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| self.attempt(run_id, sim_config)))
+                .unwrap_or_else(|payload| Err(panic_message(payload.as_ref())));
+            if let Err(msg) = result {
+                if attempt < self.max_retries {
+                    eprintln!("Simulation {} failed ({}); retrying.", run_id, msg);
+                } else {
+                    self.status_board.set_failed(run_id, msg);
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Run the simulation once. Returns an error if some of its output couldn't be written.
+    fn attempt(&self, run_id: usize, sim_config: SimConfig) -> Result<(), String> {
+        let mut simulation = Simulation::from_config(sim_config);
+        if self.skip_complete(run_id, &simulation) {
+            self.status_board.set_state(run_id, RunState::Skipped);
+            self.metrics.lock().unwrap()[run_id] = simulation.saved_metrics();
+            return Ok(());
+        }
+        // Metrics-only runs print their metrics instead of writing any output.
+        if let Some(merged_output) = self
+            .merged_output
+            .as_ref()
+            .filter(|_| !simulation.metrics_only())
+        {
+            simulation.set_merged_output(run_id, merged_output.clone());
+        }
+        simulation.set_status_board(run_id, self.status_board.clone());
+        let metrics = simulation.run();
+        if simulation.metrics_only() {
+            println!("{}", json!({ "sim": run_id, "metrics": metrics }));
+        }
+        match simulation.write_error() {
+            Some(msg) => Err(msg.clone()),
+            None => {
+                self.metrics.lock().unwrap()[run_id] = Some(metrics);
+                Ok(())
+            }
+        }
+    }
+
+    /// Aggregate the metrics of each group of replications which has any successful runs, and
+    /// write them to `aggregate.json` in the group's output path (or print them, for metrics-only
+    /// runs).
+    fn save_aggregates(&self, replication_groups: &[ReplicationGroup]) {
+        let metrics = self.metrics.lock().unwrap();
+        for group in replication_groups {
+            let replications: Vec<SimMetrics> = metrics[group.run_ids.clone()]
+                .iter()
+                .flatten()
+                .copied()
+                .collect();
+            if replications.is_empty() {
+                continue;
+            }
+            let aggregate = Aggregate::new(&replications);
+            if group.metrics_only {
+                println!(
+                    "{}",
+                    json!({ "output_path": group.output_path, "aggregate": aggregate })
+                );
+                continue;
+            }
+            let output_path = config::path_from_config(&group.output_path);
+            if aggregate.save(&output_path).is_err() {
+                eprintln!(
+                    "Failed to save {} to {}",
+                    AGGREGATE_FILENAME,
+                    output_path.display()
+                );
+            }
+        }
+    }
+
+    /// Run the analysis of the batch's experiment, if it has one, on the batch's metrics, given the
+    /// run ids of each of its points.
+    fn save_experiment(&self, experiment: Option<&Experiment>, point_run_ids: &[Range<usize>]) {
+        let experiment = match experiment {
+            Some(experiment) => experiment,
+            None => return,
+        };
+        let metrics = self.metrics.lock().unwrap();
+        if let Err(err) = experiment.analyze(point_run_ids, &metrics) {
+            eprintln!(
+                "Failed to save the experiment's analysis to {}: {}",
+                experiment.output_root(),
+                err
+            );
+        }
+    }
+
+    /// Returns whether the given simulation should be skipped because its output is already
+    /// complete. Runs writing to merged output are never skipped, since the merged files are
+    /// rewritten by every batch.
+    fn skip_complete(&self, run_id: usize, simulation: &Simulation) -> bool {
+        let skip = !self.force && self.merged_output.is_none() && simulation.is_complete();
+        if skip {
+            println!(
+                "Skipping simulation {}: its output is already complete.",
+                run_id
+            );
+        }
+        skip
+    }
+
+    /// Print every failed run with its error message. Returns whether there were any.
+    fn report_failures(&self) -> bool {
+        let failures = self.status_board.failures();
+        if failures.is_empty() {
+            return false;
+        }
+        eprintln!("{} simulation(s) failed:", failures.len());
+        for (run_id, msg) in failures {
+            eprintln!("  simulation {}: {}", run_id, msg);
+        }
+        true
     }
 }
 
-fn run_parallel(config: Config) {
+/// Run every simulation in its own thread. Returns whether any of them failed.
+fn run_parallel(config: Config, force: bool) -> bool {
+    let batch = Arc::new(Batch::new(&config, force));
+    let replication_groups = config.replication_groups.clone();
+    let output_layout = config.output_layout.clone();
     let mut handles = Vec::new();
-    for sim_config in config.sim_configs {
-        handles.push(thread::spawn(move || {
-            let mut simulation = Simulation::from_config(sim_config);
-            simulation.run();
-        }));
+    for (run_id, sim_config) in config.sim_configs.into_iter().enumerate() {
+        let batch = batch.clone();
+        handles.push(thread::spawn(move || batch.run(run_id, sim_config)));
     }
+    let reporter = spawn_reporter(&batch, config.status_interval_secs);
 
     for handle in handles {
         handle.join().unwrap()
     }
+    if let Some(reporter) = reporter {
+        reporter.join().unwrap();
+    }
+    batch.save_aggregates(&replication_groups);
+    save_indexes(output_layout.as_ref());
+    batch.save_experiment(config.experiment.as_ref(), &config.sim_run_ids);
+    batch.report_failures()
 }
 
-fn run_sequential(config: Config) {
-    for sim_config in config.sim_configs {
-        let mut simulation = Simulation::from_config(sim_config);
-        simulation.run();
+/// Spawn a thread printing the batch's status table every `interval_secs`, if it's set.
+fn spawn_reporter(batch: &Batch, interval_secs: Option<u64>) -> Option<thread::JoinHandle<()>> {
+    interval_secs.map(|secs| {
+        let status_board = batch.status_board.clone();
+        thread::spawn(move || print_status(&status_board, Duration::from_secs(secs)))
+    })
+}
+
+/// Print the status table every `interval` until it shows every run as done.
+fn print_status(status_board: &StatusBoard, interval: Duration) {
+    loop {
+        thread::sleep(interval);
+        let all_done = status_board.all_done();
+        print!("{}", status_board.render());
+        if all_done {
+            break;
+        }
+    }
+}
+
+/// Run every simulation in turn. Returns whether any of them failed.
+fn run_sequential(config: Config, force: bool) -> bool {
+    let batch = Batch::new(&config, force);
+    let reporter = spawn_reporter(&batch, config.status_interval_secs);
+    for (run_id, sim_config) in config.sim_configs.into_iter().enumerate() {
+        batch.run(run_id, sim_config);
+    }
+    if let Some(reporter) = reporter {
+        reporter.join().unwrap();
+    }
+    batch.save_aggregates(&config.replication_groups);
+    save_indexes(config.output_layout.as_ref());
+    batch.save_experiment(config.experiment.as_ref(), &config.sim_run_ids);
+    batch.report_failures()
+}
+
+/// Write the index files of the batch's output layout, if it has one.
+fn save_indexes(output_layout: Option<&OutputLayout>) {
+    if let Some(output_layout) = output_layout {
+        if let Err(err) = output_layout.write_indexes() {
+            eprintln!("Failed to save the output layout's index files: {}", err);
+        }
+    }
+}
+
+/// Estimate each simulation's output from short probe runs, and print the estimates without
+/// launching the batch, warning about unbounded or very large output.
+fn dry_run(config: &Config) {
+    let scratch_path = env::temp_dir().join(format!("aqt_sim_dry_run_{}", process::id()));
+    let mut total_bytes = 0;
+    let mut unbounded = Vec::new();
+    println!("sim\tper round\ttotal\toutput_path");
+    for (run_id, sim_config) in config.sim_configs.iter().enumerate() {
+        let estimate =
+            match OutputEstimate::from_probe(sim_config, DEFAULT_PROBE_RDS, &scratch_path) {
+                Ok(estimate) => estimate,
+                Err(err) => return eprintln!("Failed to probe simulation {}: {}", run_id, err),
+            };
+        let total = match estimate.total_bytes() {
+            Some(bytes) => {
+                total_bytes += bytes;
+                dry_run::format_bytes(bytes as f64)
+            }
+            None => {
+                unbounded.push(run_id);
+                String::from("unbounded")
+            }
+        };
+        println!(
+            "{}\t{}\t{}\t{}",
+            run_id,
+            dry_run::format_bytes(estimate.bytes_per_rd()),
+            total,
+            sim_config.output_path
+        );
+    }
+    println!("Total: {}", dry_run::format_bytes(total_bytes as f64));
+    if !unbounded.is_empty() {
+        println!(
+            "Warning: simulations {:?} have no fixed length, so their output is unbounded.",
+            unbounded
+        );
+    }
+    if total_bytes > LARGE_OUTPUT_BYTES {
+        println!(
+            "Warning: the batch is projected to write {}.",
+            dry_run::format_bytes(total_bytes as f64)
+        );
+    }
+}
+
+/// Run the `sim_idx`th simulation in the config (the first by default) in the debugger.
+fn debug(args: &[String]) {
+    let (config_path, sim_idx) = match args {
+        [config_path] => (config_path, 0),
+        [config_path, sim_idx] => match sim_idx.parse::<usize>() {
+            Ok(sim_idx) => (config_path, sim_idx),
+            Err(_) => return println!("{}", USAGE_MSG),
+        },
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let json = fs::read_to_string(config_path).unwrap();
+    let config = Config::from_string(json).unwrap();
+    let sim_config = match config.sim_configs.into_iter().nth(sim_idx) {
+        Some(sim_config) => sim_config,
+        None => return eprintln!("No simulation {} in {}.", sim_idx, config_path),
+    };
+    let mut debugger = Debugger::new(Simulation::from_config(sim_config));
+    debugger.run(io::stdin().lock(), &mut io::stdout());
+}
+
+/// Run two simulations in the config in lockstep, writing their per-round comparison to
+/// `cosim.csv` in the given output path.
+fn cosim(args: &[String]) {
+    let (config_path, sim_idxs, output_path) = match args {
+        [config_path, a, b, output_path] => match (a.parse::<usize>(), b.parse::<usize>()) {
+            (Ok(a), Ok(b)) => (config_path, [a, b], output_path),
+            _ => return println!("{}", USAGE_MSG),
+        },
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let json = fs::read_to_string(config_path).unwrap();
+    let config = Config::from_string(json).unwrap();
+    let mut simulations = Vec::new();
+    for sim_idx in sim_idxs {
+        match config.sim_configs.get(sim_idx) {
+            Some(sim_config) => simulations.push(Simulation::from_config(sim_config.clone())),
+            None => return eprintln!("No simulation {} in {}.", sim_idx, config_path),
+        }
+    }
+    let b = simulations.pop().unwrap();
+    let a = simulations.pop().unwrap();
+    let mut cosim = match CoSimulation::new(a, b) {
+        Ok(cosim) => cosim,
+        Err(msg) => return eprintln!("{}", msg),
+    };
+    if let Err(err) = cosim.run(&config::path_from_config(output_path)) {
+        eprintln!(
+            "Failed to save the co-simulation to {}: {}",
+            output_path, err
+        );
+    }
+}
+
+/// Run an annealing search for the adversary parameters maximizing the max load, writing its
+/// iterations and worst-case configuration to the given output path.
+fn search(args: &[String]) {
+    let (config_path, output_path) = match args {
+        [config_path, output_path] => (config_path, output_path),
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let json = fs::read_to_string(config_path).unwrap();
+    let mut search = match Search::from_config(serde_json::from_str(&json).unwrap()) {
+        Ok(search) => search,
+        Err(msg) => return eprintln!("{}", msg),
+    };
+    let result = match search.run() {
+        Ok(result) => result,
+        Err(msg) => return eprintln!("{}", msg),
+    };
+    println!(
+        "Worst case found: max load {} with {}",
+        result.best_max_load,
+        json!(result.best_config[config::ADVERSARY_KEY])
+    );
+    if let Err(err) = result.save(&config::path_from_config(output_path)) {
+        eprintln!("Failed to save the search to {}: {}", output_path, err);
+    }
+}
+
+/// Print the name, documentation, and config keys of each of the given components.
+fn print_descriptions(title: &str, descriptions: &[Description]) {
+    println!("{}:", title);
+    for description in descriptions {
+        println!("  {}: {}", description.name, description.doc);
+        for key in &description.keys {
+            let required = if key.required { " (required)" } else { "" };
+            println!(
+                "    {}{}: {} e.g. {}",
+                key.key, required, key.doc, key.example
+            );
+        }
+    }
+}
+
+/// Print an example config running the given protocol against the given adversary on a path,
+/// with every required key of both set to an example value.
+fn generate_config(args: &[String]) {
+    let (protocol_name, adversary_name) = match args {
+        [protocol_name, adversary_name] => (protocol_name, adversary_name),
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let find = |descriptions: Vec<Description>, name: &str| {
+        descriptions.into_iter().find(|d| d.name == name)
+    };
+    let protocol = match find(Protocol::descriptions(), protocol_name) {
+        Some(description) => description.example_config(),
+        None => return eprintln!("No protocol with name {}.", protocol_name),
+    };
+    let adversary = match find(Adversary::descriptions(), adversary_name) {
+        Some(description) => description.example_config(),
+        None => return eprintln!("No adversary with name {}.", adversary_name),
+    };
+    let config = json!({
+        "parallel": false,
+        "simulations": [{
+            "graph_adjacency": construct_path(5).to_config(),
+            "protocol": protocol,
+            "adversary": adversary,
+            "threshold": Threshold::timed_from_rds(100).to_config(),
+            "recorders": [],
+            "output_path": format!("output/{}_{}", protocol_name, adversary_name),
+        }],
+    });
+    println!("{}", serde_json::to_string_pretty(&config).unwrap());
+}
+
+/// Print the network state at the given round, reconstructed from an event trace.
+fn replay(args: &[String]) {
+    let (trace_path, rd) = match args {
+        [trace_path, rd, ..] => match rd.parse::<usize>() {
+            Ok(rd) => (trace_path, rd),
+            Err(_) => return println!("{}", USAGE_MSG),
+        },
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let prime = args.get(2).map(|arg| arg == PRIME_ARG).unwrap_or(false);
+    let data = fs::read_to_string(trace_path).unwrap();
+    match trace::replay(&data, rd, prime) {
+        Ok(network) => print!("{}", network),
+        Err(msg) => eprintln!("{}", msg),
+    }
+}
+
+/// Pack a run's output directory into a single bundle file.
+fn export(args: &[String]) {
+    let (run_path, bundle_path) = match args {
+        [run_path, bundle_path] => (run_path, bundle_path),
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let bundle_path = config::path_from_config(bundle_path);
+    let result = Bundle::from_run(&config::path_from_config(run_path))
+        .and_then(|bundle| bundle.write(&bundle_path).map(|_| bundle));
+    match result {
+        Ok(bundle) => println!(
+            "Exported {} files to {}.",
+            bundle.files().len(),
+            bundle_path.display()
+        ),
+        Err(err) => eprintln!("Failed to export {}: {}", run_path, err),
+    }
+}
+
+/// Unpack a bundle into a new run output directory.
+fn import(args: &[String]) {
+    let (bundle_path, run_path) = match args {
+        [bundle_path, run_path] => (bundle_path, run_path),
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let result = Bundle::read(&config::path_from_config(bundle_path))
+        .and_then(|bundle| bundle.extract(&config::path_from_config(run_path)));
+    if let Err(err) = result {
+        eprintln!("Failed to import {}: {}", bundle_path, err);
+    }
+}
+
+/// Print a bundle's run (its protocol, adversary, and status) and its files.
+fn inspect(args: &[String]) {
+    let bundle_path = match args {
+        [bundle_path] => bundle_path,
+        _ => return println!("{}", USAGE_MSG),
+    };
+    let bundle = match Bundle::read(&config::path_from_config(bundle_path)) {
+        Ok(bundle) => bundle,
+        Err(err) => return eprintln!("Failed to read {}: {}", bundle_path, err),
+    };
+    let config = bundle.config().unwrap_or_default();
+    println!("Protocol: {}", config[config::PROTOCOL_KEY]);
+    println!("Adversary: {}", config[config::ADVERSARY_KEY]);
+    match bundle.summary() {
+        Some(summary) => println!(
+            "Status: {} after round {}",
+            summary["status"].as_str().unwrap_or("unknown"),
+            summary["last_rd"]
+        ),
+        None => println!("Status: unfinished"),
+    }
+    println!("Files:");
+    for (path, num_bytes) in bundle.files() {
+        println!("  {} ({} bytes)", path, num_bytes);
     }
 }
@@ -5,8 +5,12 @@
 
 use crate::config::{CfgErrorMsg, Configurable};
 use crate::packet::Packet;
-use hashbrown::HashMap;
-use serde_json::{Number, Value};
+use crate::simulation::random::SimRng;
+use hashbrown::{HashMap, HashSet};
+use serde_json::{json, Number, Value};
+use std::cell::Cell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
 use std::fmt;
 
 /// The `Network` struct wraps the underlying graph data structure and manages the buffers of
@@ -14,38 +18,288 @@ use std::fmt;
 ///
 /// Construction
 /// - Create a new network:
-///     `Network::new()`,
+///   `Network::new()`,
 /// - Add a new `Node` to the network:
-///     `let node_id = network.add_node()`,
+///   `let node_id = network.add_node()`,
 /// - Add an new `EdgeBuffer` to the network:
-///     `network.add_edgebuffer(from_id, to_id, capacity)`.
+///   `network.add_edgebuffer(from_id, to_id, capacity)`,
+/// - Set the weight of an edge (1 by default), used for shortest-path routing:
+///   `network.set_edge_weight(from_id, to_id, weight)`,
+/// - Limit how many packets each node may send and receive per round:
+///   `network.set_node_capacities(node_capacities)`.
 ///
 /// Access
 /// - Get vector of neighbor IDs of a node:
-///     `network.get_neighbors(node_id)``,
+///   `network.get_neighbors(node_id)``,
 /// - Get a vector of the graph's nodes' IDs:
-///     `network.get_nodes()`,
+///   `network.get_nodes()`,
 /// - Get a vector of the graph's edgebuffers' ID pairs:
-///     `network.get_edgebuffers()`.
+///   `network.get_edgebuffers()`,
+/// - Get a vector of the graph's edgebuffers' ID pairs in the order to forward them this round:
+///   `network.forwarding_order()`,
+/// - Get a vector of read-only `EdgeRef` handles to the graph's edgebuffers:
+///   `network.edges()`,
+/// - Get a read-only `EdgeRef` handle from the given edgebuffer ID pair:
+///   `network.edge(from_id, to_id)`,
+/// - Fill a reusable buffer with every edgebuffer's ID pair and load, in sorted order:
+///   `network.load_snapshot(&mut loads)`,
+/// - Get the topological level of every node, if the graph is acyclic:
+///   `network.topological_levels()`,
+/// - Get the weight of an edge:
+///   `network.edge_weight(from_id, to_id)`,
+/// - Get a minimum-weight path between two nodes, if there is one:
+///   `network.shortest_path(src_id, dest_id)`,
+/// - Get the number of rounds since a buffer was last served, if service is tracked:
+///   `network.rds_since_service(from_id, to_id)`.
 ///
 /// Buffer Access/Modification
+/// - Set the current round, with which packets added to buffers are stamped:
+///   `network.set_cur_rd(rd)`,
 /// - Add a given `Packet` into an `EdgeBuffer` from the given edgebuffer ID pair:
-///     `network.add_packet(packet, from_id, to_id)`,
+///   `network.add_packet(packet, from_id, to_id)`,
+/// - Put `Packet`s taken out of their buffers this round back at the front, in their old order:
+///   `network.restore_packets(packets)`,
 /// - Get an immutable reference to a `Buffer` from the given edgebuffer ID pair:
-///     `network.get_buffer(from_id, to_id)`,
+///   `network.get_buffer(from_id, to_id)`,
 /// - Get a mutable reference to a `Buffer`` from the given edgebuffer ID pair:
-///     `network.get_buffer_mut(from_id, to_id)`,
+///   `network.get_buffer_mut(from_id, to_id)`,
 /// - Get and take a `Buffer` and replace it with a new empty `Buffer`:
-///     `network.take_buffer(from_id, to_id)`.
+///   `network.take_buffer(from_id, to_id)`,
+/// - Move a `Packet` by index to an adjacent edge on its path:
+///   `network.move_packet((from_id, to_id), (next_from_id, next_to_id), index)`,
+/// - Remove the first `Packet` in a `Buffer` matching a predicate:
+///   `network.remove_packet((from_id, to_id), |p| ...)`,
+/// - Drop the first `Packet` in a `Buffer` matching a predicate, counting it as dropped:
+///   `network.drop_packet((from_id, to_id), |p| ...)`,
+/// - Get an `EdgeBuffer` to forward from along with how many of its packets may be forwarded
+///   this round, given a per-edge capacity and any node capacities:
+///   `network.edgebuffer_to_forward(from_id, to_id, capacity)`.
+///
+/// Injection Queues
+/// - Add a given `Packet` to the injection queue of its current node:
+///   `network.add_to_injection_queue(packet)`,
+/// - Get an immutable reference to a node's injection queue:
+///   `network.get_injection_queue(node_id)`,
+/// - Get and take a node's injection queue and replace it with a new empty `Buffer`:
+///   `network.take_injection_queue(node_id)`.
 #[derive(Clone)]
 pub struct Network {
     nodes: Vec<Node>,
+    injection_queues: Vec<Buffer>,
+    // Counts of structural accesses, if instrumentation is enabled. A `Cell` so that read-only
+    // accesses can be counted too.
+    access_counts: Option<Cell<AccessCounts>>,
+    // The number of packets dropped from the network so far.
+    num_dropped: u64,
+    // The current round, with which packets are stamped when they enter a buffer.
+    cur_rd: usize,
+    // Per-node send and receive limits, if any, and the number of packets each node has sent and
+    // received in the current forwarding step.
+    node_capacities: Option<NodeCapacities>,
+    ports_used: Vec<(usize, usize)>,
+    // If service is tracked, the last round in which each buffer forwarded a packet or was empty
+    // after forwarding (see `record_service`). Buffers not yet served map to round 0.
+    last_service: Option<HashMap<(NodeID, NodeID), usize>>,
+    // If set, shuffles the order in which protocols forward from buffers each round.
+    edge_order_rng: Option<SimRng>,
+}
+
+/// Cumulative counts of a `Network`'s structural accesses, for performance debugging. The counts
+/// are `u64`, since they grow with every round of a long run.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct AccessCounts {
+    /// Lookups of an `EdgeBuffer` by its ID pair.
+    pub edgebuffer_lookups: u64,
+    /// Packets placed into an `EdgeBuffer`.
+    pub packet_moves: u64,
+    /// Scans over the packets in a `Buffer`.
+    pub buffer_scans: u64,
+}
+
+/// A per-node limit: either the same for every node, or given for each node by `NodeID`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NodeLimit {
+    Uniform(usize),
+    PerNode(Vec<usize>),
+}
+
+impl NodeLimit {
+    /// Get the limit of the given node. Nodes past the end of a per-node list are unlimited.
+    pub fn get(&self, node_id: NodeID) -> usize {
+        match self {
+            Self::Uniform(limit) => *limit,
+            Self::PerNode(limits) => limits.get(node_id).copied().unwrap_or(usize::MAX),
+        }
+    }
+}
+
+impl Configurable for NodeLimit {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let to_limit = |val: &Value| match val.as_u64() {
+            Some(limit) => Ok(limit as usize),
+            None => Err(format!("Node limit {} must be a nonnegative integer.", val)),
+        };
+        match &config {
+            Value::Array(limits) => Ok(Self::PerNode(
+                limits.iter().map(to_limit).collect::<Result<_, _>>()?,
+            )),
+            limit => Ok(Self::Uniform(to_limit(limit)?)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Uniform(limit) => json!(limit),
+            Self::PerNode(limits) => json!(limits),
+        }
+    }
+}
+
+/// Limits on how many packets each node may send (forward out along any of its edges) and
+/// receive (have forwarded in along any of its in-edges) per round, modeling router port limits,
+/// and absorb as their destination per round, modeling finite sink service rates. These apply on
+/// top of each protocol's per-edge capacity. Packets a node can't absorb yet wait in the last
+/// buffer of their path.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct NodeCapacities {
+    pub send: Option<NodeLimit>,
+    pub receive: Option<NodeLimit>,
+    pub absorb: Option<NodeLimit>,
+}
+
+const SEND_KEY: &str = "send";
+const RECEIVE_KEY: &str = "receive";
+const ABSORB_KEY: &str = "absorb";
+
+impl Configurable for NodeCapacities {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let get_limit = |key: &str| config.get(key).cloned().map(NodeLimit::from_config);
+        match config {
+            Value::Object(_) => Ok(NodeCapacities {
+                send: get_limit(SEND_KEY).transpose()?,
+                receive: get_limit(RECEIVE_KEY).transpose()?,
+                absorb: get_limit(ABSORB_KEY).transpose()?,
+            }),
+            _ => Err(String::from("Node capacities must be an object.")),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = serde_json::Map::new();
+        if let Some(send) = &self.send {
+            map.insert(SEND_KEY.to_string(), send.to_config());
+        }
+        if let Some(receive) = &self.receive {
+            map.insert(RECEIVE_KEY.to_string(), receive.to_config());
+        }
+        if let Some(absorb) = &self.absorb {
+            map.insert(ABSORB_KEY.to_string(), absorb.to_config());
+        }
+        Value::Object(map)
+    }
 }
 
 impl Network {
     /// Get a new empty `Network`.
     pub fn new() -> Self {
-        Network { nodes: Vec::new() }
+        Network {
+            nodes: Vec::new(),
+            injection_queues: Vec::new(),
+            access_counts: None,
+            num_dropped: 0,
+            cur_rd: 0,
+            node_capacities: None,
+            ports_used: Vec::new(),
+            last_service: None,
+            edge_order_rng: None,
+        }
+    }
+
+    /// Enable or disable counting of structural accesses. Disabling discards the counts.
+    pub fn set_instrumented(&mut self, instrumented: bool) {
+        self.access_counts = match instrumented {
+            true => Some(Cell::new(AccessCounts::default())),
+            false => None,
+        };
+    }
+
+    /// Get the access counts so far, or `None` if the network is not instrumented.
+    pub fn access_counts(&self) -> Option<AccessCounts> {
+        self.access_counts.as_ref().map(|counts| counts.get())
+    }
+
+    fn count_access(&self, update: impl FnOnce(&mut AccessCounts)) {
+        if let Some(cell) = &self.access_counts {
+            let mut counts = cell.get();
+            update(&mut counts);
+            cell.set(counts);
+        }
+    }
+
+    /// Count a packet being placed into a buffer, for protocols which push into buffers directly
+    /// rather than through `add_packet`. Does nothing if the network is not instrumented.
+    pub fn count_packet_move(&self) {
+        self.count_access(|counts| counts.packet_moves += 1);
+    }
+
+    /// Count a scan over a buffer's packets, for protocols which iterate over buffers directly.
+    /// Does nothing if the network is not instrumented.
+    pub fn count_buffer_scan(&self) {
+        self.count_access(|counts| counts.buffer_scans += 1);
+    }
+
+    /// Enable or disable tracking when each buffer was last served. Disabling discards the
+    /// history.
+    pub fn set_service_tracked(&mut self, tracked: bool) {
+        self.last_service = tracked.then(HashMap::new);
+    }
+
+    /// Check whether this network tracks when each buffer was last served.
+    pub fn tracks_service(&self) -> bool {
+        self.last_service.is_some()
+    }
+
+    /// Record the given round's forwarding step, in which the given buffers forwarded at least
+    /// one packet (forward along its path, or out of the network). Those buffers, and every buffer
+    /// which is empty, count as served in this round. Does nothing if service isn't tracked.
+    pub fn record_service(&mut self, rd: usize, forwarded: &HashSet<(NodeID, NodeID)>) {
+        let served: Vec<(NodeID, NodeID)> = self
+            .edges()
+            .into_iter()
+            .filter(|edge| edge.load() == 0 || forwarded.contains(&edge.ids()))
+            .map(|edge| edge.ids())
+            .collect();
+        if let Some(last_service) = &mut self.last_service {
+            for edge in served {
+                last_service.insert(edge, rd);
+            }
+        }
+    }
+
+    /// Get the number of rounds since the given buffer last forwarded a packet or was empty, as
+    /// of the current round, or `None` if service isn't tracked.
+    pub fn rds_since_service(&self, from_id: NodeID, to_id: NodeID) -> Option<usize> {
+        let last_service = self.last_service.as_ref()?;
+        let last_rd = last_service.get(&(from_id, to_id)).copied().unwrap_or(0);
+        Some(self.cur_rd.saturating_sub(last_rd))
+    }
+
+    /// Set the current round. Packets which enter a buffer are stamped with it as their
+    /// `last_moved_rd`. The `Simulation` sets it at the start of every round; it is 0 before the
+    /// first round.
+    pub fn set_cur_rd(&mut self, rd: usize) {
+        self.cur_rd = rd;
+    }
+
+    /// Get the current round.
+    pub fn cur_rd(&self) -> usize {
+        self.cur_rd
+    }
+
+    /// Stamp a packet with the current round as the round it entered its current buffer, for
+    /// protocols which push into buffers directly rather than through `add_packet`.
+    pub fn stamp_arrival(&self, p: &mut Packet) {
+        p.set_last_moved_rd(self.cur_rd);
     }
 
     /// Create a `Network` from the given adjacency lists.
@@ -54,10 +308,9 @@ impl Network {
         for _ in 0..adj_lists.len() {
             network.add_node();
         }
-        for node_id in 0..adj_lists.len() {
-            let neighbors = &adj_lists[node_id];
+        for (node_id, neighbors) in adj_lists.iter().enumerate() {
             let _: Vec<()> = neighbors
-                .into_iter()
+                .iter()
                 .map(|to_id| network.add_edgebuffer(node_id, *to_id))
                 .collect();
         }
@@ -86,6 +339,7 @@ impl Network {
     pub fn add_node(&mut self) -> NodeID {
         let node_id = self.nodes.len();
         self.nodes.push(Node::new());
+        self.injection_queues.push(Vec::new());
         node_id
     }
 
@@ -112,11 +366,110 @@ impl Network {
         let node = &self.nodes[node_id];
         let mut result = Vec::new();
         for neighbor_id in node.keys() {
-            result.push(neighbor_id.clone());
+            result.push(*neighbor_id);
         }
         result
     }
 
+    /// Get the level of every node in the graph, indexed by `NodeID`, or `None` if the graph has a
+    /// cycle. Nodes with no in-edges are at level 0, and every other node is one level past the
+    /// highest of its in-neighbors, so every edge goes from a lower to a higher level.
+    pub fn topological_levels(&self) -> Option<Vec<usize>> {
+        let num_nodes = self.nodes.len();
+        let mut in_degrees = vec![0; num_nodes];
+        for node in &self.nodes {
+            for to_id in node.keys() {
+                in_degrees[*to_id] += 1;
+            }
+        }
+
+        let mut levels = vec![0; num_nodes];
+        let mut ready: Vec<NodeID> = (0..num_nodes).filter(|id| in_degrees[*id] == 0).collect();
+        let mut num_visited = 0;
+        while let Some(node_id) = ready.pop() {
+            num_visited += 1;
+            for to_id in self.nodes[node_id].keys() {
+                levels[*to_id] = levels[*to_id].max(levels[node_id] + 1);
+                in_degrees[*to_id] -= 1;
+                if in_degrees[*to_id] == 0 {
+                    ready.push(*to_id);
+                }
+            }
+        }
+        (num_visited == num_nodes).then_some(levels)
+    }
+
+    /// Set the weight of the edge between the given nodes. Panics if there is no such edge, or if
+    /// the weight is not positive and finite.
+    pub fn set_edge_weight(&mut self, from_id: NodeID, to_id: NodeID, weight: f64) {
+        assert!(
+            weight > 0.0 && weight.is_finite(),
+            "Edge weights must be positive and finite, got {}.",
+            weight
+        );
+        match self.get_edgebuffer_mut(from_id, to_id) {
+            Some(edgebuffer) => edgebuffer.weight = weight,
+            None => panic!(
+                "There is no EdgeBuffer between nodes {} and {}",
+                from_id, to_id
+            ),
+        }
+    }
+
+    /// Get the weight of the edge between the given nodes, or `None` if there is no such edge.
+    pub fn edge_weight(&self, from_id: NodeID, to_id: NodeID) -> Option<f64> {
+        self.get_edgebuffer(from_id, to_id)
+            .map(|edgebuffer| edgebuffer.weight)
+    }
+
+    /// Get a minimum-weight path from `src_id` to `dest_id`, as the sequence of `NodeID`s it
+    /// visits, or `None` if `dest_id` is unreachable. Ties between equal-weight paths are broken
+    /// towards lower `NodeID`s, so the result doesn't depend on the order edges are stored in.
+    pub fn shortest_path(&self, src_id: NodeID, dest_id: NodeID) -> Option<Vec<NodeID>> {
+        self.check_node_id(src_id);
+        self.check_node_id(dest_id);
+        let num_nodes = self.nodes.len();
+        let mut dists: Vec<Option<f64>> = vec![None; num_nodes];
+        let mut preds: Vec<Option<NodeID>> = vec![None; num_nodes];
+        let mut done = vec![false; num_nodes];
+        // Min-heap on (distance, node id).
+        let mut heap = BinaryHeap::new();
+        dists[src_id] = Some(0.0);
+        heap.push(Reverse((Dist(0.0), src_id)));
+        while let Some(Reverse((Dist(dist), node_id))) = heap.pop() {
+            if done[node_id] {
+                continue;
+            }
+            done[node_id] = true;
+            if node_id == dest_id {
+                break;
+            }
+            for (to_id, edgebuffer) in &self.nodes[node_id] {
+                let new_dist = dist + edgebuffer.weight;
+                let is_better = match (dists[*to_id], preds[*to_id]) {
+                    (None, _) => true,
+                    (Some(old_dist), pred) => {
+                        new_dist < old_dist
+                            || (new_dist == old_dist && pred.is_some_and(|pred| node_id < pred))
+                    }
+                };
+                if !done[*to_id] && is_better {
+                    dists[*to_id] = Some(new_dist);
+                    preds[*to_id] = Some(node_id);
+                    heap.push(Reverse((Dist(new_dist), *to_id)));
+                }
+            }
+        }
+
+        dists[dest_id]?;
+        let mut path = vec![dest_id];
+        while let Some(pred) = preds[*path.last().unwrap()] {
+            path.push(pred);
+        }
+        path.reverse();
+        Some(path)
+    }
+
     /// Get a vector of all nodes' node ids in sorted order.
     pub fn get_nodes(&self) -> Vec<NodeID> {
         (0..self.nodes.len()).collect()
@@ -138,22 +491,117 @@ impl Network {
         result
     }
 
+    /// Get every edgebuffer ID pair in the order protocols forward from them in this round: that of
+    /// `get_edgebuffers`, or a new random permutation of the sorted pairs each call if random edge
+    /// order is set (see `set_random_edge_order`).
+    pub fn forwarding_order(&mut self) -> Vec<(NodeID, NodeID)> {
+        let mut eb_ids = self.get_edgebuffers();
+        if let Some(rng) = &mut self.edge_order_rng {
+            eb_ids.sort_unstable();
+            rng.shuffle(&mut eb_ids);
+        }
+        eb_ids
+    }
+
+    /// Make protocols forward from buffers in a random order each round, drawn from the given
+    /// seed (a random one if `None`), to expose results which depend on the processing order.
+    pub fn set_random_edge_order(&mut self, seed: Option<u64>) {
+        self.edge_order_rng = Some(seed.map_or_else(SimRng::new, SimRng::from_seed));
+    }
+
+    /// Get the seed of the random edge order, if it is set.
+    pub fn random_edge_order_seed(&self) -> Option<u64> {
+        self.edge_order_rng.as_ref().map(SimRng::seed)
+    }
+
+    /// Get an `EdgeRef` for every `EdgeBuffer`, in the same order as `get_edgebuffers`.
+    pub fn edges(&self) -> Vec<EdgeRef<'_>> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(from_id, node)| {
+                node.iter().map(move |(to_id, edgebuffer)| EdgeRef {
+                    from_id,
+                    to_id: *to_id,
+                    edgebuffer,
+                    network: self,
+                })
+            })
+            .collect()
+    }
+
+    /// Get an `EdgeRef` for the specified `EdgeBuffer`. Returns `None` if there is no
+    /// `EdgeBuffer` corresponding to the given from- and to-IDs.
+    pub fn edge(&self, from_id: NodeID, to_id: NodeID) -> Option<EdgeRef<'_>> {
+        self.get_edgebuffer(from_id, to_id)
+            .map(|edgebuffer| EdgeRef {
+                from_id,
+                to_id,
+                edgebuffer,
+                network: self,
+            })
+    }
+
+    /// Clear the given buffer and fill it with the ID pair and load of every `EdgeBuffer`, sorted by
+    /// ID pair. Reusing the buffer across calls avoids allocating every time loads are read.
+    pub fn load_snapshot(&self, loads: &mut Vec<(NodeID, NodeID, usize)>) {
+        loads.clear();
+        for (from_id, node) in self.nodes.iter().enumerate() {
+            let start = loads.len();
+            loads.extend(
+                node.iter()
+                    .map(|(to_id, edgebuffer)| (from_id, *to_id, edgebuffer.buffer.len())),
+            );
+            loads[start..].sort_unstable_by_key(|(_, to_id, _)| *to_id);
+        }
+    }
+
+    /// Get the total number of packets in all `EdgeBuffer`s of the network (not including packets
+    /// waiting in injection queues).
+    pub fn total_load(&self) -> usize {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.values())
+            .map(|edgebuffer| edgebuffer.buffer.len())
+            .sum()
+    }
+
     /// Add the given `Packet` to the specified `Buffer`. Returns `None` if there is no
     /// `EdgeBuffer` corresponding to the given from- and to-IDs.
     pub fn add_packet(&mut self, p: Packet, from_id: NodeID, to_id: NodeID) {
+        let mut p = p;
+        self.stamp_arrival(&mut p);
+        self.count_packet_move();
         match self.get_edgebuffer_mut(from_id, to_id) {
             Some(eb) => eb.buffer.push(p),
             None => panic!("No EdgeBuffer between Nodes {} and {}.", from_id, to_id),
         }
     }
 
+    /// Put the given packets back at the front of the buffers of their current edges, in the given
+    /// order and keeping their arrival rounds, as if they had never been taken out. Panics if a
+    /// packet's current edge does not exist.
+    pub fn restore_packets(&mut self, packets: Vec<Packet>) {
+        let mut num_restored: HashMap<(NodeID, NodeID), usize> = HashMap::new();
+        for p in packets {
+            let (from_id, to_id) = (p.cur_node().unwrap(), p.next_node().unwrap());
+            let idx = num_restored.entry((from_id, to_id)).or_insert(0);
+            match self.get_edgebuffer_mut(from_id, to_id) {
+                Some(eb) => eb.buffer.insert(*idx, p),
+                None => panic!("No EdgeBuffer between Nodes {} and {}.", from_id, to_id),
+            }
+            *idx += 1;
+        }
+    }
+
     /// Get an immutable reference to the specified `Buffer`. Returns `None` if there is no
     /// `EdgeBuffer` corresponding to the given from- and to-IDs.
     pub fn get_edgebuffer(&self, from_id: NodeID, to_id: NodeID) -> Option<&EdgeBuffer> {
+        self.count_access(|counts| counts.edgebuffer_lookups += 1);
         self.check_node_id(from_id);
         self.check_node_id(to_id);
         match self.nodes[from_id].get(&to_id) {
-            Some(eb) => Some(&eb),
+            Some(eb) => Some(eb),
             None => None,
         }
     }
@@ -165,6 +613,7 @@ impl Network {
         from_id: NodeID,
         to_id: NodeID,
     ) -> Option<&mut EdgeBuffer> {
+        self.count_access(|counts| counts.edgebuffer_lookups += 1);
         self.check_node_id(from_id);
         self.check_node_id(to_id);
         match self.nodes[from_id].get_mut(&to_id) {
@@ -176,6 +625,7 @@ impl Network {
     /// Get (and take ownership of) the specified `Buffer`. Returns `None` if there is no
     /// `EdgeBuffer` corresponding to the given from- and to-IDs.
     pub fn take_buffer(&mut self, from_id: NodeID, to_id: NodeID) -> Option<Buffer> {
+        self.count_access(|counts| counts.edgebuffer_lookups += 1);
         self.check_node_id(from_id);
         self.check_node_id(to_id);
         match self.nodes[from_id].get_mut(&to_id) {
@@ -188,6 +638,185 @@ impl Network {
         }
     }
 
+    /// Move the packet at `index` in the `from_edge` buffer to the end of the `to_edge` buffer,
+    /// updating its path index. `to_edge` must be the edge after (forward) or before (backward)
+    /// the packet's current edge on its path, or the current edge itself (which moves the packet to
+    /// the back of its buffer). Panics if either edge does not exist, the index is out of range, or
+    /// the move does not follow the packet's path.
+    pub fn move_packet(
+        &mut self,
+        from_edge: (NodeID, NodeID),
+        to_edge: (NodeID, NodeID),
+        index: usize,
+    ) {
+        let (from_id, to_id) = from_edge;
+        if self.get_edgebuffer(to_edge.0, to_edge.1).is_none() {
+            panic!(
+                "No EdgeBuffer between Nodes {} and {}.",
+                to_edge.0, to_edge.1
+            );
+        }
+        let buffer = match self.get_edgebuffer_mut(from_id, to_id) {
+            Some(eb) => &mut eb.buffer,
+            None => panic!("No EdgeBuffer between Nodes {} and {}.", from_id, to_id),
+        };
+        if index >= buffer.len() {
+            panic!(
+                "No packet at index {} in the buffer between Nodes {} and {} (load {}).",
+                index,
+                from_id,
+                to_id,
+                buffer.len()
+            );
+        }
+
+        let mut p = buffer.remove(index);
+        let path_idx = p.get_path_idx();
+        let edge_at = |idx: usize| -> Option<(NodeID, NodeID)> {
+            let path = p.get_path();
+            Some((*path.get(idx)?, *path.get(idx + 1)?))
+        };
+        if edge_at(path_idx + 1) == Some(to_edge) {
+            p.increment_path_idx();
+        } else if path_idx > 0 && edge_at(path_idx - 1) == Some(to_edge) {
+            p.decrement_path_idx();
+        } else if edge_at(path_idx) != Some(to_edge) {
+            panic!(
+                "Packet {} cannot move from buffer ({}, {}) to ({}, {}) along its path.",
+                p.id(),
+                from_id,
+                to_id,
+                to_edge.0,
+                to_edge.1
+            );
+        }
+        self.add_packet(p, to_edge.0, to_edge.1);
+    }
+
+    /// Remove and return the first packet in the `from_edge` buffer which satisfies the given
+    /// predicate, keeping the order of the remaining packets. Returns `None` if no packet does.
+    /// Panics if the edge does not exist.
+    pub fn remove_packet<F>(&mut self, from_edge: (NodeID, NodeID), predicate: F) -> Option<Packet>
+    where
+        F: Fn(&Packet) -> bool,
+    {
+        let (from_id, to_id) = from_edge;
+        let buffer = match self.get_edgebuffer_mut(from_id, to_id) {
+            Some(eb) => &mut eb.buffer,
+            None => panic!("No EdgeBuffer between Nodes {} and {}.", from_id, to_id),
+        };
+        let index = buffer.iter().position(predicate)?;
+        Some(buffer.remove(index))
+    }
+
+    /// Like `remove_packet`, but counts the removed packet as dropped, i.e. as leaving the network
+    /// for good without being absorbed. Protocols which drop packets should use this so that the
+    /// drop is accounted for (see `num_dropped`).
+    pub fn drop_packet<F>(&mut self, from_edge: (NodeID, NodeID), predicate: F) -> Option<Packet>
+    where
+        F: Fn(&Packet) -> bool,
+    {
+        let p = self.remove_packet(from_edge, predicate)?;
+        self.num_dropped += 1;
+        Some(p)
+    }
+
+    /// Limit how many packets each node may send, receive, and absorb per round. Protocols enforce
+    /// the send and receive limits by getting the buffers they forward from with
+    /// `edgebuffer_to_forward`, and the `Simulation` enforces the absorb limits.
+    pub fn set_node_capacities(&mut self, node_capacities: NodeCapacities) {
+        self.node_capacities = Some(node_capacities);
+    }
+
+    /// Get the per-node send and receive limits, if any.
+    pub fn node_capacities(&self) -> Option<&NodeCapacities> {
+        self.node_capacities.as_ref()
+    }
+
+    /// Start a new forwarding step, so that every node may again send and receive up to its
+    /// capacity. Called by `Protocol::forward_packets`.
+    pub fn reset_ports(&mut self) {
+        self.ports_used.clear();
+    }
+
+    /// Get the given `EdgeBuffer` to forward packets from, along with how many of its packets may
+    /// be forwarded in this forwarding step given the protocol's per-edge capacity: the smallest
+    /// of the capacity, the buffer's load, and the number of packets its from-node may still send
+    /// and its to-node may still receive. The node ports for that many packets are reserved, so
+    /// the protocol must forward exactly that many. Panics if there is no such `EdgeBuffer`.
+    pub fn edgebuffer_to_forward(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        capacity: usize,
+    ) -> (&mut EdgeBuffer, usize) {
+        self.count_access(|counts| counts.edgebuffer_lookups += 1);
+        self.check_node_id(from_id);
+        self.check_node_id(to_id);
+        if self.node_capacities.is_some() && self.ports_used.is_empty() {
+            self.ports_used = vec![(0, 0); self.nodes.len()];
+        }
+        let eb = match self.nodes[from_id].get_mut(&to_id) {
+            Some(eb) => eb,
+            None => panic!("No EdgeBuffer between Nodes {} and {}.", from_id, to_id),
+        };
+        let mut n = capacity.min(eb.buffer.len());
+        if let Some(node_capacities) = &self.node_capacities {
+            let left = |limit: &Option<NodeLimit>, node_id: NodeID, used: usize| {
+                limit
+                    .as_ref()
+                    .map_or(usize::MAX, |limit| limit.get(node_id).saturating_sub(used))
+            };
+            n = n
+                .min(left(
+                    &node_capacities.send,
+                    from_id,
+                    self.ports_used[from_id].0,
+                ))
+                .min(left(
+                    &node_capacities.receive,
+                    to_id,
+                    self.ports_used[to_id].1,
+                ));
+            self.ports_used[from_id].0 += n;
+            self.ports_used[to_id].1 += n;
+        }
+        (eb, n)
+    }
+
+    /// Get the number of packets dropped via `drop_packet` so far.
+    pub fn num_dropped(&self) -> u64 {
+        self.num_dropped
+    }
+
+    /// Add the given `Packet` to the injection queue of the node it currently occupies. Packets in
+    /// injection queues are not yet in any `EdgeBuffer`; they are moved into the network by an
+    /// admission step.
+    pub fn add_to_injection_queue(&mut self, p: Packet) {
+        let node_id = p
+            .cur_node()
+            .expect("Cannot queue an absorbed packet for injection.");
+        self.check_node_id(node_id);
+        self.injection_queues[node_id].push(p);
+    }
+
+    /// Get an immutable reference to the injection queue of the given node.
+    pub fn get_injection_queue(&self, node_id: NodeID) -> &Buffer {
+        self.check_node_id(node_id);
+        &self.injection_queues[node_id]
+    }
+
+    /// Get (and take ownership of) the injection queue of the given node, leaving it empty.
+    pub fn take_injection_queue(&mut self, node_id: NodeID) -> Buffer {
+        self.check_node_id(node_id);
+        std::mem::take(&mut self.injection_queues[node_id])
+    }
+
+    /// Get the total number of packets waiting in injection queues.
+    pub fn injection_queue_load(&self) -> usize {
+        self.injection_queues.iter().map(|q| q.len()).sum()
+    }
+
     fn check_node_id(&self, node_id: NodeID) {
         if node_id >= self.nodes.len() {
             panic!("No Node with ID {} in this network.", node_id);
@@ -195,43 +824,153 @@ impl Network {
     }
 }
 
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl fmt::Display for Network {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut result = String::new();
-        let edgebuffer_ids = self.get_edgebuffers();
-        for (from_id, to_id) in edgebuffer_ids {
-            let buffer = &self.get_edgebuffer(from_id, to_id).unwrap().buffer;
-            result.push_str(&format!("{}, {}: {:?}\n", from_id, to_id, buffer));
+        for edge in self.edges() {
+            result.push_str(&format!(
+                "{}, {}: {:?}\n",
+                edge.from(),
+                edge.to(),
+                edge.buffer()
+            ));
         }
         write!(f, "{}", result)
     }
 }
 
+// A distance in `Network::shortest_path`, ordered totally so that it can be kept in a heap.
+#[derive(Clone, Copy, PartialEq)]
+struct Dist(f64);
+
+impl Eq for Dist {}
+
+impl PartialOrd for Dist {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Dist {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+const TO_KEY: &str = "to";
+const WEIGHT_KEY: &str = "weight";
+const PRESET_KEY: &str = "preset";
+const PATH_PRESET_NAME: &str = "path";
+const LAYERED_PRESET_NAME: &str = "layered";
+const RANDOM_DAG_PRESET_NAME: &str = "random_dag";
+const NUM_NODES_KEY: &str = "num_nodes";
+const WIDTH_KEY: &str = "width";
+const DEPTH_KEY: &str = "depth";
+const EDGE_PROB_KEY: &str = "edge_prob";
+const SEED_KEY: &str = "seed";
+
+impl Network {
+    /// Construct a preset network from a config object such as
+    /// `{"preset": "layered", "width": 3, "depth": 4}`. Random presets take an optional `"seed"`,
+    /// and draw one if none is given.
+    fn from_preset_config(config: &Value) -> Result<Self, CfgErrorMsg> {
+        let get_usize = |key: &str| match config.get(key).and_then(Value::as_u64) {
+            Some(val) if val > 0 => Ok(val as usize),
+            _ => Err(format!("Preset {} must be a positive integer.", key)),
+        };
+        match config.get(PRESET_KEY).and_then(Value::as_str) {
+            Some(PATH_PRESET_NAME) => Ok(presets::construct_path(get_usize(NUM_NODES_KEY)?)),
+            Some(LAYERED_PRESET_NAME) => Ok(presets::construct_layered(
+                get_usize(WIDTH_KEY)?,
+                get_usize(DEPTH_KEY)?,
+            )),
+            Some(RANDOM_DAG_PRESET_NAME) => {
+                let edge_prob = match config.get(EDGE_PROB_KEY).and_then(Value::as_f64) {
+                    Some(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                    _ => Err(String::from("Preset edge_prob must be between 0 and 1.")),
+                }?;
+                let mut rng = match config.get(SEED_KEY) {
+                    Some(seed) => match seed.as_u64() {
+                        Some(seed) => SimRng::from_seed(seed),
+                        None => return Err(String::from("Seed must be a nonnegative integer.")),
+                    },
+                    None => SimRng::new(),
+                };
+                Ok(presets::construct_random_dag(
+                    get_usize(NUM_NODES_KEY)?,
+                    edge_prob,
+                    &mut rng,
+                ))
+            }
+            _ => Err(format!("No network preset {}.", config)),
+        }
+    }
+}
+
 impl Configurable for Network {
+    /// Construct a `Network` from its adjacency lists, or from a preset config object. A preset
+    /// network is dumped by `to_config` as its adjacency lists. Each entry of an adjacency list is
+    /// either a neighbor's `NodeID`, or an object `{"to": id, "weight": w}` giving the edge's
+    /// weight.
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        if config.is_object() {
+            return Self::from_preset_config(&config);
+        }
         // TODO: correct error message here
         let adj_list_cfgs = config.as_array().unwrap().clone();
         let mut adj_lists = Vec::new();
-        for adj_list_val in &adj_list_cfgs {
+        let mut weights = Vec::new();
+        for (from_id, adj_list_val) in adj_list_cfgs.iter().enumerate() {
             let adj_list_cfg: Vec<Value> = adj_list_val.as_array().unwrap().clone();
-            adj_lists.push(
-                adj_list_cfg
-                    .iter()
-                    .map(|x| x.as_u64().unwrap() as usize)
-                    .collect(),
-            );
+            let mut adj_list = Vec::new();
+            for entry in &adj_list_cfg {
+                match entry.get(TO_KEY) {
+                    Some(to_id) => {
+                        let to_id = to_id.as_u64().unwrap() as usize;
+                        if let Some(weight) = entry.get(WEIGHT_KEY) {
+                            match weight.as_f64() {
+                                Some(weight) if weight > 0.0 && weight.is_finite() => {
+                                    weights.push((from_id, to_id, weight))
+                                }
+                                _ => {
+                                    return Err(format!(
+                                        "Weight of edge ({}, {}) must be a positive number.",
+                                        from_id, to_id
+                                    ))
+                                }
+                            }
+                        }
+                        adj_list.push(to_id);
+                    }
+                    None => adj_list.push(entry.as_u64().unwrap() as usize),
+                }
+            }
+            adj_lists.push(adj_list);
         }
 
-        Ok(Self::from_adj_lists(adj_lists))
+        let mut network = Self::from_adj_lists(adj_lists);
+        for (from_id, to_id, weight) in weights {
+            network.set_edge_weight(from_id, to_id, weight);
+        }
+        Ok(network)
     }
 
     fn to_config(&self) -> Value {
         let adj_lists = self.adj_lists();
         let mut adj_list_cfgs = Vec::new();
-        for adj_list in adj_lists {
+        for (from_id, adj_list) in adj_lists.iter().enumerate() {
             let adj_list_cfg = adj_list
                 .iter()
-                .map(|l| Value::Number(Number::from(*l)))
+                .map(|l| match self.edge_weight(from_id, *l) {
+                    Some(weight) if weight != 1.0 => json!({ TO_KEY: *l, WEIGHT_KEY: weight }),
+                    _ => Value::Number(Number::from(*l)),
+                })
                 .collect();
             adj_list_cfgs.push(Value::Array(adj_list_cfg));
         }
@@ -240,20 +979,120 @@ impl Configurable for Network {
     }
 }
 
+/// A read-only handle to one of a `Network`'s `EdgeBuffer`s, along with the IDs of the nodes it
+/// connects.
+#[derive(Clone, Copy)]
+pub struct EdgeRef<'a> {
+    from_id: NodeID,
+    to_id: NodeID,
+    edgebuffer: &'a EdgeBuffer,
+    network: &'a Network,
+}
+
+impl<'a> EdgeRef<'a> {
+    /// Get the ID of the node this edge leaves.
+    pub fn from(&self) -> NodeID {
+        self.from_id
+    }
+
+    /// Get the ID of the node this edge enters.
+    pub fn to(&self) -> NodeID {
+        self.to_id
+    }
+
+    /// Get the (from-ID, to-ID) pair of this edge.
+    pub fn ids(&self) -> (NodeID, NodeID) {
+        (self.from_id, self.to_id)
+    }
+
+    /// Get the number of packets in this edge's buffer.
+    pub fn load(&self) -> usize {
+        self.edgebuffer.buffer.len()
+    }
+
+    /// Iterate over the packets in this edge's buffer, in buffer order.
+    pub fn iter_packets(&self) -> std::slice::Iter<'a, Packet> {
+        self.network.count_buffer_scan();
+        self.edgebuffer.buffer.iter()
+    }
+
+    /// Get this edge's buffer.
+    pub fn buffer(&self) -> &'a Buffer {
+        &self.edgebuffer.buffer
+    }
+
+    /// Get this edge's weight.
+    pub fn weight(&self) -> f64 {
+        self.edgebuffer.weight
+    }
+}
+
 /// Just a map of outgoing `EdgeBuffer`s.
 pub type Node = HashMap<NodeID, EdgeBuffer>;
 
 /// An `EdgeBuffer` represents an edge in the graph with an associated `Buffer` (just a vector of
-/// `Packet`s).
+/// `Packet`s) and a weight, used for shortest-path routing.
 #[derive(Clone)]
 pub struct EdgeBuffer {
     pub buffer: Buffer,
+    weight: f64,
 }
 
 impl EdgeBuffer {
-    /// Get a new empty `EdgeBuffer`.
+    /// Get a new empty `EdgeBuffer` of weight 1.
     pub fn new() -> Self {
-        EdgeBuffer { buffer: Vec::new() }
+        EdgeBuffer {
+            buffer: Vec::new(),
+            weight: 1.0,
+        }
+    }
+
+    /// Add a `Packet` to a buffer which is kept as a binary heap under the given priority
+    /// comparator (`Ordering::Less` meaning higher priority), so that the highest priority packet
+    /// is always at index 0. Heap buffers must only be modified via `heap_push` and `heap_pop`.
+    pub fn heap_push(&mut self, p: Packet, cmp: PacketComparator) {
+        self.buffer.push(p);
+        let mut idx = self.buffer.len() - 1;
+        while idx > 0 {
+            let parent = (idx - 1) / 2;
+            if cmp(&self.buffer[idx], &self.buffer[parent]) != Ordering::Less {
+                break;
+            }
+            self.buffer.swap(idx, parent);
+            idx = parent;
+        }
+    }
+
+    /// Remove and return the highest priority `Packet` from a heap buffer (see `heap_push`).
+    pub fn heap_pop(&mut self, cmp: PacketComparator) -> Option<Packet> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let top = self.buffer.swap_remove(0);
+        let len = self.buffer.len();
+        let mut idx = 0;
+        loop {
+            let (left, right) = (2 * idx + 1, 2 * idx + 2);
+            let mut best = idx;
+            if left < len && cmp(&self.buffer[left], &self.buffer[best]) == Ordering::Less {
+                best = left;
+            }
+            if right < len && cmp(&self.buffer[right], &self.buffer[best]) == Ordering::Less {
+                best = right;
+            }
+            if best == idx {
+                break;
+            }
+            self.buffer.swap(idx, best);
+            idx = best;
+        }
+        Some(top)
+    }
+}
+
+impl Default for EdgeBuffer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -264,9 +1103,13 @@ pub type NodeID = usize;
 /// Just a vector of `Packet`s.
 pub type Buffer = Vec<Packet>;
 
+/// Compares two `Packet`s by priority; `Ordering::Less` means the first has higher priority.
+pub type PacketComparator = fn(&Packet, &Packet) -> Ordering;
+
 pub mod presets {
     //! This module contains functions to create preset network structures.
     use super::Network;
+    use crate::simulation::random::SimRng;
 
     /// Construct a path network with the given number of buffers.
     pub fn construct_path(num_buffers: usize) -> Network {
@@ -281,6 +1124,40 @@ pub mod presets {
 
         network
     }
+
+    /// Construct a layered network with `depth` layers of `width` nodes each, where every node in
+    /// a layer has an edge to every node in the next layer. Node `i` of layer `l` has `NodeID`
+    /// `l * width + i`.
+    pub fn construct_layered(width: usize, depth: usize) -> Network {
+        let mut network = Network::new();
+        for _ in 0..width * depth {
+            network.add_node();
+        }
+
+        for layer in 0..depth.saturating_sub(1) {
+            for i in 0..width {
+                for j in 0..width {
+                    network.add_edgebuffer(layer * width + i, (layer + 1) * width + j);
+                }
+            }
+        }
+
+        network
+    }
+
+    /// Construct a random DAG on `num_nodes` nodes: the path through every node in order, plus
+    /// each other edge from a node to a later one independently with probability `edge_prob`.
+    pub fn construct_random_dag(num_nodes: usize, edge_prob: f64, rng: &mut SimRng) -> Network {
+        let mut network = construct_path(num_nodes);
+        for from_id in 0..num_nodes {
+            for to_id in from_id + 2..num_nodes {
+                if rng.rand_bool(edge_prob) {
+                    network.add_edgebuffer(from_id, to_id);
+                }
+            }
+        }
+        network
+    }
 }
 
 #[cfg(test)]
@@ -317,9 +1194,9 @@ mod tests {
         let c_neighbors = network.get_neighbors(c_id);
         let d_neighbors = network.get_neighbors(d_id);
 
-        let expect_a_neighbors = vec![b_id, c_id, d_id];
-        let expect_b_neighbors = vec![c_id, d_id];
-        let expect_c_neighbors = vec![b_id];
+        let expect_a_neighbors = [b_id, c_id, d_id];
+        let expect_b_neighbors = [c_id, d_id];
+        let expect_c_neighbors = [b_id];
         let expect_d_neighbors: Vec<NodeID> = vec![];
 
         assert!(a_neighbors
@@ -347,7 +1224,7 @@ mod tests {
     fn test_get_nodes() {
         let network = setup_test_graph();
         let node_ids = network.get_nodes();
-        let expect_node_ids = vec![0, 1, 2, 3];
+        let expect_node_ids = [0, 1, 2, 3];
         assert!(node_ids
             .into_iter()
             .all(|node_id| expect_node_ids.contains(&node_id)));
@@ -358,7 +1235,7 @@ mod tests {
         let network = setup_test_graph();
         let (a_id, b_id, c_id, d_id) = (0, 1, 2, 3);
         let eb_ids = network.get_edgebuffers();
-        let expect_eb_ids = vec![
+        let expect_eb_ids = [
             (a_id, b_id),
             (a_id, c_id),
             (c_id, b_id),
@@ -371,6 +1248,29 @@ mod tests {
             .all(|eb_id_pair| expect_eb_ids.contains(&eb_id_pair)))
     }
 
+    #[test]
+    fn test_load_snapshot() {
+        let mut network = setup_test_graph();
+        let mut factory = PacketFactory::new();
+        network.add_packet(factory.create_packet(vec![1, 2], 1, 0), 1, 2);
+        network.add_packet(factory.create_packet(vec![0, 3], 1, 0), 0, 3);
+        network.add_packet(factory.create_packet(vec![0, 3], 1, 0), 0, 3);
+        let mut loads = vec![(9, 9, 9)];
+        network.load_snapshot(&mut loads);
+        assert_eq!(
+            loads,
+            vec![
+                (0, 1, 0),
+                (0, 2, 0),
+                (0, 3, 2),
+                (1, 2, 1),
+                (1, 3, 0),
+                (2, 1, 0)
+            ]
+        );
+        assert_eq!(network.total_load(), 3);
+    }
+
     #[test]
     fn test_add_packet_and_get_edgebuffer() {
         let mut network = setup_test_graph();
@@ -402,6 +1302,129 @@ mod tests {
         assert!(buff.contains(&p2));
 
         let new_eb = network.get_edgebuffer(b_id, d_id).unwrap();
-        assert!(new_eb.buffer.len() == 0);
+        assert!(new_eb.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_heap_buffer() {
+        let mut eb = EdgeBuffer::new();
+        let mut factory = PacketFactory::new();
+        let packets: Vec<Packet> = (0..10)
+            .map(|_| factory.create_packet(Vec::new(), 0, 0))
+            .collect();
+        let by_id: PacketComparator = |p, q| p.id().cmp(&q.id());
+        for idx in [3, 7, 1, 9, 0, 4, 8, 2, 6, 5] {
+            eb.heap_push(packets[idx].clone(), by_id);
+        }
+        let popped: Vec<usize> = (0..10)
+            .map(|_| eb.heap_pop(by_id).unwrap().id().0)
+            .collect();
+        assert_eq!(popped, (0..10).collect::<Vec<usize>>());
+        assert!(eb.heap_pop(by_id).is_none());
+    }
+
+    #[test]
+    fn test_move_and_remove_packet() {
+        let mut network = presets::construct_path(4);
+        let mut factory = PacketFactory::new();
+        let p0 = factory.create_packet(vec![0, 1, 2, 3], 1, 1);
+        let p1 = factory.create_packet(vec![0, 1, 2, 3], 2, 1);
+        network.add_packet(p0, 1, 2);
+        network.add_packet(p1, 1, 2);
+
+        // Forward the packet at index 1, then send it back again.
+        network.move_packet((1, 2), (2, 3), 1);
+        assert_eq!(
+            network.get_edgebuffer(2, 3).unwrap().buffer[0].get_path_idx(),
+            2
+        );
+        network.move_packet((2, 3), (1, 2), 0);
+        let ids: Vec<usize> = network
+            .get_edgebuffer(1, 2)
+            .unwrap()
+            .buffer
+            .iter()
+            .map(|p| p.id().0)
+            .collect();
+        assert_eq!(ids, vec![0, 1]);
+
+        assert_eq!(
+            network
+                .remove_packet((1, 2), |p| p.id().0 == 1)
+                .unwrap()
+                .id()
+                .0,
+            1
+        );
+        assert!(network.remove_packet((1, 2), |p| p.id().0 == 1).is_none());
+        assert_eq!(network.get_edgebuffer(1, 2).unwrap().buffer.len(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_move_packet_off_path() {
+        let mut network = presets::construct_path(4);
+        let mut factory = PacketFactory::new();
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        // The packet's path ends at node 2, so it cannot move onto (2, 3).
+        network.move_packet((0, 1), (2, 3), 0);
+    }
+
+    #[test]
+    fn test_layered_levels() {
+        let network = presets::construct_layered(3, 4);
+        assert_eq!(network.get_num_nodes(), 12);
+        assert_eq!(network.get_edgebuffers().len(), 27);
+        let levels: Vec<usize> = (0..12).map(|id| id / 3).collect();
+        assert_eq!(network.topological_levels(), Some(levels));
+
+        let config = serde_json::json!({"preset": "layered", "width": 3, "depth": 4});
+        let from_config = Network::from_config(config).unwrap();
+        assert_eq!(from_config.adj_lists().len(), 12);
+
+        // Levels follow the longest path into each node, and cycles have none.
+        let network = Network::from_adj_lists(vec![vec![1, 2], vec![2], vec![]]);
+        assert_eq!(network.topological_levels(), Some(vec![0, 1, 2]));
+        assert_eq!(setup_test_graph().topological_levels(), None);
+    }
+
+    #[test]
+    fn test_weighted_shortest_path() {
+        // 0 -> 1 -> 3 is shorter by hops, but 0 -> 2 -> 3 is lighter.
+        let config =
+            serde_json::json!([[{"to": 1, "weight": 5.5}, 2], [3], [{"to": 3, "weight": 2.5}], []]);
+        let network = Network::from_config(config.clone()).unwrap();
+        assert_eq!(network.edge_weight(0, 1), Some(5.5));
+        assert_eq!(network.edge_weight(1, 3), Some(1.0));
+        assert_eq!(network.shortest_path(0, 3), Some(vec![0, 2, 3]));
+        assert_eq!(network.shortest_path(3, 0), None);
+        assert_eq!(network.shortest_path(1, 1), Some(vec![1]));
+        let network = Network::from_config(network.to_config()).unwrap();
+        assert_eq!(network.edge_weight(0, 1), Some(5.5));
+        assert_eq!(network.edge_weight(2, 3), Some(2.5));
+
+        // Equal-weight paths are broken towards lower ids.
+        let network = presets::construct_layered(3, 3);
+        assert_eq!(network.shortest_path(2, 8), Some(vec![2, 3, 8]));
+        assert!(Network::from_config(serde_json::json!([[{"to": 1, "weight": 0}], []])).is_err());
+    }
+
+    #[test]
+    fn test_access_counts() {
+        let mut network = presets::construct_path(3);
+        assert!(network.access_counts().is_none());
+        network.set_instrumented(true);
+
+        let mut factory = PacketFactory::new();
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        network.edge(0, 1).unwrap().iter_packets().count();
+        assert_eq!(
+            network.access_counts(),
+            Some(AccessCounts {
+                edgebuffer_lookups: 2,
+                packet_moves: 1,
+                buffer_scans: 1,
+            })
+        );
     }
 }
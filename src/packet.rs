@@ -1,30 +1,111 @@
 //! This module contains structs and types related to packets in the AQT model, including the
 //! Packet struct itself as well as a PacketFactory and PacketPath (which is just a vector of
 //! NodeIDs determining the route the Packet should follow).
+//!
+//! With the `compact_packets` feature, packets store their ids and rounds in 32 bits and their
+//! path indices, origins, and classes in 16 bits, and share interned paths, which halves the
+//! memory each packet takes. Packets whose fields don't fit panic when created or updated.
 
 use crate::network::NodeID;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "compact_packets")]
+use std::sync::Arc;
+
+// The integer types the fields of a `Packet` are stored in: ids and rounds, and the small fields
+// (path indices, origins, and classes).
+#[cfg(not(feature = "compact_packets"))]
+type StoredId = usize;
+#[cfg(not(feature = "compact_packets"))]
+type StoredSmall = usize;
+#[cfg(feature = "compact_packets")]
+type StoredId = u32;
+#[cfg(feature = "compact_packets")]
+type StoredSmall = u16;
+
+// How a `Packet` stores its path: owned, or as a handle to a path interned by its factory.
+#[cfg(not(feature = "compact_packets"))]
+type StoredPath = PacketPath;
+#[cfg(feature = "compact_packets")]
+type StoredPath = Arc<[NodeID]>;
+
+/// Conversions between `usize` and the integer types a `Packet` stores its fields in.
+trait Stored: Copy {
+    fn store(value: usize, field: &str) -> Self;
+    fn load(self) -> usize;
+}
+
+macro_rules! impl_stored {
+    ($($t:ty),*) => {
+        $(
+            impl Stored for $t {
+                fn store(value: usize, field: &str) -> Self {
+                    <$t>::try_from(value).unwrap_or_else(|_| {
+                        panic!("Packet {} {} doesn't fit in {} bits.", field, value, <$t>::BITS)
+                    })
+                }
+
+                fn load(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_stored!(usize, u32, u16);
+
+/// The unique id of a `Packet`. Ids are handed out in increasing order by the `PacketFactory`, so
+/// they also order packets by creation. Serialized as a plain number.
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct PacketId(pub usize);
+
+impl fmt::Display for PacketId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 /// The `Packet` struct represents a packet in AQT. It includes:
 /// - An id, which is unique,
 /// - A `PacketPath` for the packet to follow in the network,
-/// - An index into the packet's path so we know where the packet currently is, and
-/// - The packet's injection round.
+/// - An index into the packet's path so we know where the packet currently is,
+/// - The index into the packet's path at which it was created, i.e., its source,
+/// - The packet's injection round,
+/// - The round in which the packet entered its current buffer,
+/// - The index of the adversary which injected the packet,
+/// - An optional deadline round,
+/// - A class (0 by default), for class-based priorities, and
+/// - An optional group, shared by packets injected together as one fork/join job.
 ///
 /// We enforce the ID uniqueness by *only* allowing packets to be created via the `PacketFactory`
 /// struct.
 #[derive(Clone)]
 pub struct Packet {
-    id: usize,
-    path: PacketPath,
-    path_idx: usize,
-    injection_rd: usize,
+    id: StoredId,
+    path: StoredPath,
+    path_idx: StoredSmall,
+    src_idx: StoredSmall,
+    injection_rd: StoredId,
+    last_moved_rd: StoredId,
+    origin: StoredSmall,
+    deadline: Option<StoredId>,
+    class: StoredSmall,
+    // `NO_GROUP` if the packet isn't in a group, which keeps compact packets small.
+    group: StoredId,
 }
 
+const NO_GROUP: StoredId = StoredId::MAX;
+
 impl Packet {
     /// Get this `Packet`'s id.
-    pub fn id(&self) -> usize {
-        self.id
+    pub fn id(&self) -> PacketId {
+        PacketId(self.id.load())
     }
 
     /// Increment the index into the `PacketPath`. We need to keep this in sync with the packet's
@@ -45,56 +126,133 @@ impl Packet {
         self.path_idx -= 1;
     }
 
+    /// Set the index into the `PacketPath`, e.g. when reconstructing a packet's position from a
+    /// trace.
+    pub fn set_path_idx(&mut self, path_idx: usize) {
+        self.path_idx = Stored::store(path_idx, "path index");
+    }
+
     /// Check whether this packet is absorbed.
     pub fn is_absorbed(&self) -> bool {
-        self.path_idx == self.path.len()
+        self.get_path_idx() == self.path.len()
     }
 
     /// Check whether this packet should be absorbed the next time it is forwarded.
     pub fn should_be_absorbed(&self) -> bool {
-        self.path_idx == self.path.len() - 1
+        self.get_path_idx() == self.path.len() - 1
     }
 
     /// Get the injection round of this `Packet`.
     pub fn injection_rd(&self) -> usize {
-        self.injection_rd
+        self.injection_rd.load()
+    }
+
+    /// Set the injection round of this `Packet`. Used by adversaries which hold back packets they
+    /// have already created and inject them in a later round.
+    pub fn set_injection_rd(&mut self, injection_rd: usize) {
+        self.injection_rd = Stored::store(injection_rd, "injection round");
+    }
+
+    /// Get the round in which this `Packet` entered its current buffer. This is stamped by the
+    /// `Network` whenever the packet is added to a buffer (see `Network::stamp_arrival`).
+    pub fn last_moved_rd(&self) -> usize {
+        self.last_moved_rd.load()
+    }
+
+    /// Set the round in which this `Packet` entered its current buffer.
+    pub fn set_last_moved_rd(&mut self, last_moved_rd: usize) {
+        self.last_moved_rd = Stored::store(last_moved_rd, "round");
+    }
+
+    /// Get the round by which this `Packet` should be absorbed, if it has a deadline.
+    pub fn deadline(&self) -> Option<usize> {
+        self.deadline.map(Stored::load)
+    }
+
+    /// Set the round by which this `Packet` should be absorbed.
+    pub fn set_deadline(&mut self, deadline: Option<usize>) {
+        self.deadline = deadline.map(|deadline| Stored::store(deadline, "deadline"));
+    }
+
+    /// Get the class of this `Packet`. Lower classes have higher priority under class-based
+    /// priorities.
+    pub fn class(&self) -> usize {
+        self.class.load()
+    }
+
+    /// Set the class of this `Packet`.
+    pub fn set_class(&mut self, class: usize) {
+        self.class = Stored::store(class, "class");
+    }
+
+    /// Get the group of this `Packet`, if it was injected as part of one. A group's id is the id
+    /// of its first packet.
+    pub fn group(&self) -> Option<usize> {
+        (self.group != NO_GROUP).then(|| self.group.load())
+    }
+
+    /// Set the group of this `Packet`.
+    pub fn set_group(&mut self, group: Option<usize>) {
+        self.group = group.map_or(NO_GROUP, |group| Stored::store(group, "group"));
+    }
+
+    /// Get the index of the adversary (in the `Simulation`'s list of adversaries) which injected
+    /// this `Packet`.
+    pub fn origin(&self) -> usize {
+        self.origin.load()
     }
 
     /// Get the id of the current `Node` that this packet occupies. Returns `None` if the packet
     /// has been absorbed.
     pub fn cur_node(&self) -> Option<NodeID> {
-        match self.path.get(self.path_idx) {
-            Some(next_id) => Some(*next_id),
-            None => None,
-        }
+        self.path.get(self.get_path_idx()).copied()
     }
 
     /// Get the id of the next `Node` that this packet will occupy if forwarded in its path.
     /// Returns `None` if the packet has been absorbed or is about to be absorbed.
     pub fn next_node(&self) -> Option<NodeID> {
-        match self.path.get(self.path_idx + 1) {
-            Some(next_id) => Some(*next_id),
-            None => None,
-        }
+        self.path.get(self.get_path_idx() + 1).copied()
     }
 
     /// Get the number of steps that this packet needs to travel in the network in order to be
     /// absorbed, including the absorption step.
     pub fn dist_to_go(&self) -> usize {
-        self.path.len() - self.path_idx + 1
+        self.path.len() - self.get_path_idx() + 1
+    }
+
+    /// Get the id of the `Node` at which this packet was injected.
+    pub fn src_node(&self) -> NodeID {
+        self.path[self.src_idx.load()]
+    }
+
+    /// Get the id of the `Node` at which this packet is absorbed.
+    pub fn dest_node(&self) -> NodeID {
+        *self.path.last().unwrap()
+    }
+
+    /// Get the number of edges between this packet's source and destination.
+    pub fn path_len(&self) -> usize {
+        self.path.len() - 1 - self.src_idx.load()
+    }
+
+    /// Get the nodes of this packet's route, from its source to its destination.
+    pub fn route(&self) -> &[NodeID] {
+        &self.path[self.src_idx.load()..]
     }
 
     /// Get the current index into the `PacketPath`.
     pub fn get_path_idx(&self) -> usize {
-        self.path_idx
+        self.path_idx.load()
     }
 
-    /// Get a reference to this packet's path.
-    pub fn get_path(&self) -> &PacketPath {
+    /// Get this packet's path.
+    pub fn get_path(&self) -> &[NodeID] {
         &self.path
     }
 
-    /// Get a mutable reference to this packet's path.
+    /// Get a mutable reference to this packet's path. Compact packets share their paths, so
+    /// can't change them.
+    #[cfg(not(feature = "compact_packets"))]
     pub fn get_path_mut(&mut self) -> &mut PacketPath {
         &mut self.path
     }
@@ -103,48 +261,188 @@ impl Packet {
 impl fmt::Debug for Packet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Packet")
-            .field("id", &self.id)
+            .field("id", &self.id())
             .field("cur_node", &self.cur_node())
-            .field("injection_rd", &self.injection_rd)
+            .field("injection_rd", &self.injection_rd())
             .finish()
     }
 }
 
+/// Packets are identified by their ids, so two copies of a packet at different points in its path
+/// are equal, and packets are ordered by id.
 impl PartialEq for Packet {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
     }
 }
 
+impl Eq for Packet {}
+
+impl Hash for Packet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Packet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
 /// This struct allows for the creation of `Packet`s with unique ids. We thus require all `Packet`s
-/// to be created through a `PacketFactory`.
+/// to be created through a `PacketFactory`. A single factory is shared by all adversaries in a
+/// `Simulation`, so packet ids are unique across adversaries; the factory stamps each packet with
+/// the origin set via `set_origin`. With the `compact_packets` feature, the factory also interns
+/// the paths of the packets it creates, so that packets on the same path share it, and forgets
+/// paths no packet holds any more when recycling.
+///
+/// The factory also pools path buffers for reuse: the `Simulation` recycles absorbed packets into
+/// it, and `path_from` fills a pooled buffer rather than allocating one, so long runs with high
+/// injection rates don't allocate a path per packet.
 #[derive(Default, Clone)]
 pub struct PacketFactory {
     cur_id: usize,
+    origin: usize,
+    #[cfg(feature = "compact_packets")]
+    paths: hashbrown::HashSet<Arc<[NodeID]>>,
+    // The number of interned paths at which to next prune those no packet holds: twice the number
+    // left after the last prune, so that pruning takes amortized constant time per path.
+    #[cfg(feature = "compact_packets")]
+    prune_at: usize,
+    pool: Vec<PacketPath>,
 }
 
+// The most path buffers a `PacketFactory` pools for reuse.
+const MAX_POOLED_PATHS: usize = 1 << 12;
+
 impl PacketFactory {
     /// Create a new `PacketFactory`.
     pub fn new() -> Self {
-        PacketFactory { cur_id: 0 }
+        Self::default()
     }
 
-    /// Create a new `Packet`.
-    pub fn create_packet(
+    /// Get a path of the given nodes, in a pooled buffer if there is one.
+    pub fn path_from<I: IntoIterator<Item = NodeID>>(&mut self, nodes: I) -> PacketPath {
+        let mut path = self.pool.pop().unwrap_or_default();
+        path.extend(nodes);
+        path
+    }
+
+    /// Recycle the paths of the given packets, which have left the network, for reuse by
+    /// `path_from`. Compact packets share their paths, so are pooled when created instead, and
+    /// interned paths which no packet holds any more are dropped.
+    pub fn recycle<I: IntoIterator<Item = Packet>>(&mut self, packets: I) {
+        #[cfg(not(feature = "compact_packets"))]
+        for p in packets {
+            self.pool_path(p.path);
+        }
+        #[cfg(feature = "compact_packets")]
+        {
+            drop(packets);
+            if self.paths.len() >= self.prune_at {
+                self.paths.retain(|path| Arc::strong_count(path) > 1);
+                self.prune_at = 2 * self.paths.len();
+            }
+        }
+    }
+
+    /// Get the number of distinct paths interned for the packets created so far.
+    #[cfg(feature = "compact_packets")]
+    pub fn num_interned(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Get the number of path buffers pooled for reuse.
+    pub fn num_pooled(&self) -> usize {
+        self.pool.len()
+    }
+
+    fn pool_path(&mut self, mut path: PacketPath) {
+        if self.pool.len() < MAX_POOLED_PATHS {
+            path.clear();
+            self.pool.push(path);
+        }
+    }
+
+    #[cfg(not(feature = "compact_packets"))]
+    fn store_path(&mut self, path: PacketPath) -> StoredPath {
+        path
+    }
+
+    #[cfg(feature = "compact_packets")]
+    fn store_path(&mut self, path: PacketPath) -> StoredPath {
+        let interned = match self.paths.get(path.as_slice()) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Arc<[NodeID]> = path.as_slice().into();
+                self.paths.insert(interned.clone());
+                interned
+            }
+        };
+        self.pool_path(path);
+        interned
+    }
+
+    fn new_packet(
         &mut self,
+        id: PacketId,
         path: PacketPath,
         injection_rd: usize,
         path_idx: usize,
     ) -> Packet {
-        let p = Packet {
-            id: self.cur_id,
-            path,
+        let path_idx = Stored::store(path_idx, "path index");
+        let injection_rd = Stored::store(injection_rd, "injection round");
+        Packet {
+            id: Stored::store(id.0, "id"),
+            path: self.store_path(path),
             path_idx,
+            src_idx: path_idx,
             injection_rd,
-        };
+            last_moved_rd: injection_rd,
+            origin: Stored::store(self.origin, "origin"),
+            deadline: None,
+            class: Stored::store(0, "class"),
+            group: NO_GROUP,
+        }
+    }
+
+    /// Set the origin (adversary index) with which subsequently created `Packet`s are stamped.
+    pub fn set_origin(&mut self, origin: usize) {
+        self.origin = origin;
+    }
+
+    /// Create a new `Packet`.
+    pub fn create_packet(
+        &mut self,
+        path: PacketPath,
+        injection_rd: usize,
+        path_idx: usize,
+    ) -> Packet {
+        let p = self.new_packet(PacketId(self.cur_id), path, injection_rd, path_idx);
         self.cur_id += 1;
         p
     }
+
+    /// Recreate a `Packet` which was previously created with the given id, e.g. when
+    /// reconstructing a network from a trace. Subsequently created packets get larger ids, so
+    /// ids remain unique.
+    pub fn restore_packet(
+        &mut self,
+        id: PacketId,
+        path: PacketPath,
+        injection_rd: usize,
+        path_idx: usize,
+    ) -> Packet {
+        self.cur_id = self.cur_id.max(id.0 + 1);
+        self.new_packet(id, path, injection_rd, path_idx)
+    }
 }
 
 /// The path of `Node`s that a `Packet` will take through a `Network`.
@@ -185,4 +483,75 @@ mod tests {
         // Should panic here; we don't want to allow iteration if the packet is already absorbed.
         p.increment_path_idx();
     }
+
+    #[test]
+    fn test_shared_factory_origins() {
+        let mut packet_factory = PacketFactory::new();
+        let p = packet_factory.create_packet(vec![0, 1], 0, 0);
+        packet_factory.set_origin(1);
+        let q = packet_factory.create_packet(vec![0, 1], 0, 0);
+        assert_eq!(p.origin(), 0);
+        assert_eq!(q.origin(), 1);
+        assert_ne!(p.id(), q.id());
+    }
+
+    #[test]
+    fn test_packet_identity() {
+        let mut packet_factory = PacketFactory::new();
+        let p = packet_factory.create_packet(vec![0, 1, 2], 0, 0);
+        let q = packet_factory.create_packet(vec![0, 1], 0, 0);
+        let mut moved_p = p.clone();
+        moved_p.increment_path_idx();
+
+        let set: std::collections::BTreeSet<Packet> = [q.clone(), p.clone(), moved_p].into();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), [p.clone(), q.clone()]);
+        assert!(p < q);
+        assert_eq!(serde_json::json!(q.id()), serde_json::json!(1));
+        assert_eq!(q.id().to_string(), "1");
+    }
+
+    #[cfg(feature = "compact_packets")]
+    #[test]
+    fn test_compact_packets_share_paths() {
+        let mut packet_factory = PacketFactory::new();
+        let p = packet_factory.create_packet(vec![0, 1, 2], 3, 1);
+        let q = packet_factory.create_packet(vec![0, 1, 2], 4, 0);
+        assert!(std::ptr::eq(p.get_path(), q.get_path()));
+        assert_eq!((p.src_node(), p.injection_rd(), p.dist_to_go()), (1, 3, 3));
+        assert!(std::mem::size_of::<Packet>() <= 48);
+        let result = std::panic::catch_unwind(|| p.clone().set_class(1 << 16));
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "compact_packets")]
+    #[test]
+    fn test_unused_paths_are_pruned() {
+        let mut packet_factory = PacketFactory::new();
+        let packets: Vec<Packet> = (1..=4)
+            .map(|len| packet_factory.create_packet((0..=len).collect(), 1, 0))
+            .collect();
+        let kept = packets[0].clone();
+        assert_eq!(packet_factory.num_interned(), 4);
+        packet_factory.recycle(packets);
+        assert_eq!(packet_factory.num_interned(), 1);
+        // The path still held is shared with new packets on it.
+        let p = packet_factory.create_packet(vec![0, 1], 2, 0);
+        assert!(std::ptr::eq(p.get_path(), kept.get_path()));
+    }
+
+    #[test]
+    fn test_paths_are_pooled_for_reuse() {
+        let mut packet_factory = PacketFactory::new();
+        let path = packet_factory.path_from(0..4);
+        let p = packet_factory.create_packet(path, 1, 0);
+        assert_eq!(p.get_path(), [0, 1, 2, 3]);
+        packet_factory.recycle(vec![p]);
+        assert_eq!(packet_factory.num_pooled(), 1);
+        let path = packet_factory.path_from([5, 6]);
+        assert_eq!(
+            (path.as_slice(), packet_factory.num_pooled()),
+            ([5, 6].as_slice(), 0)
+        );
+        assert!(path.capacity() >= 4);
+    }
 }
@@ -0,0 +1,37 @@
+//! This module is the crate's library facade: the types most programs embedding the simulator
+//! need, re-exported in one place so they don't depend on where each is defined. It covers building
+//! a `Simulation` (from a config, or from a network, protocol, adversaries, threshold, and
+//! recorders), stepping it round by round, the traits for writing new components, and the network
+//! presets. Everything else stays public in its own module.
+//!
+//! For example, to run greedy FIFO on a path against a seeded random adversary for 100 rounds:
+//! ```
+//! use aqt_sim::prelude::*;
+//!
+//! let mut simulation = Simulation::new(
+//!     construct_path(5),
+//!     Protocol::new_greedy_fifo(1),
+//!     vec![Adversary::new(SDPathRandomAdversary::from_seed(1))],
+//!     Threshold::timed_from_rds(100),
+//!     Vec::new(),
+//!     String::from("unused"),
+//! );
+//! simulation.set_metrics_only(true);
+//! while simulation.step() {}
+//! assert_eq!(simulation.metrics().num_rds, 100);
+//! ```
+
+pub use crate::adversary::path_random::{SDPathRandomAdversary, SDPathRandomBurstyAdversary};
+pub use crate::adversary::{Adversary, AdversaryTrait};
+pub use crate::config::{CfgErrorMsg, Config, Configurable, Describable, Description, SimConfig};
+pub use crate::network::presets::{construct_layered, construct_path, construct_random_dag};
+pub use crate::network::{Network, NodeID};
+pub use crate::packet::{Packet, PacketFactory, PacketId, PacketPath};
+pub use crate::protocol::admission::{Admission, AdmissionTrait};
+pub use crate::protocol::{Protocol, ProtocolTrait};
+pub use crate::simulation::metrics::SimMetrics;
+pub use crate::simulation::random::SimRng;
+pub use crate::simulation::recorder::{RecordedStates, Recorder, RecorderTrait};
+pub use crate::simulation::threshold::{Threshold, ThresholdTrait};
+pub use crate::simulation::time::{Phase, SimTime};
+pub use crate::simulation::{SimStatus, Simulation};
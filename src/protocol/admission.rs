@@ -0,0 +1,182 @@
+//! This module contains admission protocols, which move packets waiting in per-node injection
+//! queues into the network's `EdgeBuffer`s. When a `Simulation` is configured with an admission
+//! protocol, adversaries inject into injection queues rather than directly into edge buffers, which
+//! separates the source backlog from network queueing.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::Network;
+use crate::protocol::Protocol;
+use serde_json::{Map, Number, Value};
+
+/// Enum for all admission protocols.
+#[derive(Clone)]
+pub enum Admission {
+    Unlimited(UnlimitedAdmission),
+    Capacity(CapacityAdmission),
+}
+
+impl Admission {
+    /// Get a new `CapacityAdmission` admission protocol.
+    pub fn new_capacity(capacity: usize) -> Self {
+        Self::Capacity(CapacityAdmission::new(capacity))
+    }
+
+    /// Admit packets from the injection queues via `AdmissionTrait`.
+    pub fn admit_packets(&mut self, network: &mut Network, protocol: &mut Protocol) {
+        match self {
+            Self::Unlimited(a) => a.admit_packets(network, protocol),
+            Self::Capacity(a) => a.admit_packets(network, protocol),
+        }
+    }
+}
+
+const ADMISSION_NAME_KEY: &str = "admission_name";
+const UNLIMITED_ADMISSION_NAME: &str = "unlimited";
+const CAPACITY_ADMISSION_NAME: &str = "capacity";
+const CAPACITY_KEY: &str = "capacity";
+
+impl Configurable for Admission {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let map: Map<String, Value> = match config.as_object() {
+            Some(map) => map.clone(),
+            None => return Err(String::from("Admission config must be a json object.")),
+        };
+        let admission_name = match map.get(ADMISSION_NAME_KEY) {
+            Some(Value::String(name)) => Ok(name),
+            _ => Err(String::from("No admission name found.")),
+        }?;
+
+        match &admission_name[..] {
+            UNLIMITED_ADMISSION_NAME => Ok(Self::Unlimited(UnlimitedAdmission)),
+            CAPACITY_ADMISSION_NAME => Ok(Self::Capacity(CapacityAdmission::from_config(config)?)),
+            _ => Err(format!(
+                "No admission protocol with name {}.",
+                admission_name
+            )),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Unlimited(a) => a.to_config(),
+            Self::Capacity(a) => a.to_config(),
+        }
+    }
+}
+
+/// Trait which all admission protocols must implement.
+pub trait AdmissionTrait {
+    /// Move packets from the `Network`'s injection queues into its `EdgeBuffer`s, adding each
+    /// packet via the given `Protocol`.
+    fn admit_packets(&mut self, network: &mut Network, protocol: &mut Protocol);
+}
+
+/// Admits every queued packet immediately.
+#[derive(Clone)]
+pub struct UnlimitedAdmission;
+
+impl AdmissionTrait for UnlimitedAdmission {
+    fn admit_packets(&mut self, network: &mut Network, protocol: &mut Protocol) {
+        for node_id in network.get_nodes() {
+            for p in network.take_injection_queue(node_id) {
+                protocol.add_packet(p, network);
+            }
+        }
+    }
+}
+
+impl Configurable for UnlimitedAdmission {
+    fn from_config(_config: Value) -> Result<Self, CfgErrorMsg> {
+        Ok(Self)
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADMISSION_NAME_KEY.to_string(),
+            Value::String(UNLIMITED_ADMISSION_NAME.to_string()),
+        );
+        Value::Object(map)
+    }
+}
+
+/// Admits at most `capacity` packets from each node's injection queue per round, in the order in
+/// which they were queued.
+#[derive(Clone)]
+pub struct CapacityAdmission {
+    capacity: usize,
+}
+
+impl CapacityAdmission {
+    /// Get a new `CapacityAdmission` with the given per-node capacity.
+    pub fn new(capacity: usize) -> Self {
+        CapacityAdmission { capacity }
+    }
+}
+
+impl AdmissionTrait for CapacityAdmission {
+    fn admit_packets(&mut self, network: &mut Network, protocol: &mut Protocol) {
+        for node_id in network.get_nodes() {
+            let mut queue = network.take_injection_queue(node_id);
+            let num_to_admit = self.capacity.min(queue.len());
+            let remaining = queue.split_off(num_to_admit);
+            for p in queue {
+                protocol.add_packet(p, network);
+            }
+            for p in remaining {
+                network.add_to_injection_queue(p);
+            }
+        }
+    }
+}
+
+impl Configurable for CapacityAdmission {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let capacity = match config.get(CAPACITY_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
+            _ => Err(String::from("No admission capacity provided.")),
+        }?;
+        Ok(Self::new(capacity))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            ADMISSION_NAME_KEY.to_string(),
+            Value::String(CAPACITY_ADMISSION_NAME.to_string()),
+        );
+        map.insert(
+            CAPACITY_KEY.to_string(),
+            Value::Number(Number::from(self.capacity)),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_capacity_admission() {
+        let mut network = construct_path(3);
+        let mut protocol = Protocol::new_greedy_fifo(1);
+        let mut factory = PacketFactory::new();
+        for _ in 0..3 {
+            network.add_to_injection_queue(factory.create_packet(vec![0, 1, 2], 1, 0));
+        }
+
+        let mut admission = Admission::new_capacity(2);
+        admission.admit_packets(&mut network, &mut protocol);
+        assert_eq!(network.get_edgebuffer(0, 1).unwrap().buffer.len(), 2);
+        assert_eq!(network.get_injection_queue(0).len(), 1);
+        // The oldest queued packet is admitted first.
+        assert_eq!(network.get_edgebuffer(0, 1).unwrap().buffer[0].id().0, 0);
+
+        admission.admit_packets(&mut network, &mut protocol);
+        assert_eq!(network.get_edgebuffer(0, 1).unwrap().buffer.len(), 3);
+        assert_eq!(network.injection_queue_load(), 0);
+    }
+}
@@ -0,0 +1,179 @@
+//! This module contains the backpressure (max-weight) protocol, the queueing-theory baseline
+//! which forwards packets toward less backlogged buffers, so that it needs the loads of the
+//! buffers downstream of each buffer it forwards from.
+
+use super::{ProtocolTrait, BACKPRESSURE_NAME, CAPACITY_KEY, PROTOCOL_NAME_KEY};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::Packet;
+use hashbrown::HashMap;
+use serde_json::{json, Map, Number, Value};
+
+/// The backpressure protocol forwards up to `capacity` packets from each buffer per round, but
+/// only packets whose differential backlog is positive: the load of the packet's buffer minus the
+/// load of the next buffer on its path (0 if the packet is absorbed next). Packets with the largest
+/// differential backlog go first (ties broken by LIS). Every buffer's load is taken at the start of
+/// the forwarding step, so the order buffers are visited in doesn't matter.
+#[derive(Clone)]
+pub struct Backpressure {
+    capacity: usize,
+}
+
+impl Backpressure {
+    /// Get a new `Backpressure` protocol with the given per-buffer capacity.
+    pub fn new(capacity: usize) -> Self {
+        Backpressure { capacity }
+    }
+}
+
+impl ProtocolTrait for Backpressure {
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        let mut snapshot = Vec::new();
+        network.load_snapshot(&mut snapshot);
+        let loads: HashMap<(NodeID, NodeID), usize> = snapshot
+            .into_iter()
+            .map(|(from_id, to_id, load)| ((from_id, to_id), load))
+            .collect();
+
+        let mut packets_to_fwd = Vec::new();
+        for (from_id, to_id) in network.forwarding_order() {
+            let tail_load = loads[&(from_id, to_id)];
+            if tail_load == 0 {
+                continue;
+            }
+            // The index of every packet with a positive differential backlog, highest first.
+            let buffer = &network.get_edgebuffer(from_id, to_id).unwrap().buffer;
+            let mut candidates: Vec<(usize, usize)> = buffer
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, p)| {
+                    let head_load = p
+                        .get_path()
+                        .get(p.get_path_idx() + 2)
+                        .map_or(0, |next_id| loads[&(to_id, *next_id)]);
+                    tail_load
+                        .checked_sub(head_load)
+                        .filter(|weight| *weight > 0)
+                        .map(|weight| (idx, weight))
+                })
+                .collect();
+            network.count_buffer_scan();
+            candidates.sort_by(|(i, i_weight), (j, j_weight)| {
+                j_weight
+                    .cmp(i_weight)
+                    .then(buffer[*i].injection_rd().cmp(&buffer[*j].injection_rd()))
+                    .then(buffer[*i].cmp(&buffer[*j]))
+            });
+
+            let capacity = self.capacity.min(candidates.len());
+            if capacity == 0 {
+                continue;
+            }
+            let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, capacity);
+            let mut idxs: Vec<usize> = candidates[..num_to_fwd]
+                .iter()
+                .map(|(idx, _)| *idx)
+                .collect();
+            idxs.sort_unstable_by(|i, j| j.cmp(i));
+            let start = packets_to_fwd.len();
+            for idx in idxs {
+                let mut p = eb.buffer.remove(idx);
+                p.increment_path_idx();
+                packets_to_fwd.push(p);
+            }
+            // Keep the forwarded packets in their buffer order.
+            packets_to_fwd[start..].reverse();
+        }
+
+        let mut absorbed = Vec::new();
+        for p in packets_to_fwd {
+            if !p.should_be_absorbed() {
+                self.add_packet(p, network);
+            } else {
+                absorbed.push(p);
+            }
+        }
+        absorbed
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Describable for Backpressure {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            BACKPRESSURE_NAME,
+            "Forwards packets toward less loaded buffers, largest differential backlog first.",
+        )
+        .required(
+            CAPACITY_KEY,
+            "The most packets forwarded from each buffer per round.",
+            json!(1),
+        )
+    }
+}
+
+impl Configurable for Backpressure {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.get(CAPACITY_KEY).and_then(Value::as_u64) {
+            Some(capacity) => Ok(Self::new(capacity as usize)),
+            None => Err(String::from("No capacity provided.")),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            PROTOCOL_NAME_KEY.to_string(),
+            Value::String(BACKPRESSURE_NAME.to_string()),
+        );
+        map.insert(
+            CAPACITY_KEY.to_string(),
+            Value::Number(Number::from(self.capacity)),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+    use crate::protocol::{conformance, Protocol};
+
+    #[test]
+    fn test_forwards_only_down_positive_differential_backlogs() {
+        let mut network = construct_path(4);
+        let mut factory = PacketFactory::new();
+        let mut protocol = Protocol::from_config(json!({
+            "protocol_name": "backpressure",
+            "capacity": 1,
+        }))
+        .unwrap();
+        // Buffer (0, 1) holds one packet and buffer (1, 2) two, all headed for node 3.
+        let p = factory.create_packet(vec![0, 1, 2, 3], 1, 0);
+        protocol.add_packet(p, &mut network);
+        for _ in 0..2 {
+            let p = factory.create_packet(vec![0, 1, 2, 3], 1, 1);
+            protocol.add_packet(p, &mut network);
+        }
+        let loads = |network: &Network| -> Vec<usize> {
+            network.edges().iter().map(|edge| edge.load()).collect()
+        };
+
+        // The packet at node 0 waits, since the buffer ahead of it is longer, and then packets
+        // only move toward shorter buffers.
+        protocol.forward_packets(&mut network);
+        assert_eq!(loads(&network), [1, 1, 1]);
+        let absorbed = protocol.forward_packets(&mut network);
+        assert_eq!((loads(&network), absorbed.len()), (vec![1, 1, 0], 1));
+        protocol.forward_packets(&mut network);
+        assert_eq!(loads(&network), [1, 0, 1]);
+
+        conformance::check_protocol(Backpressure::new).unwrap();
+    }
+}
@@ -0,0 +1,271 @@
+//! This module contains a conformance suite for protocols: standard scenarios which every
+//! `ProtocolTrait` implementation must handle, so that protocols written outside this crate can
+//! check themselves against the same expectations as the built-in ones. The suite assumes packets
+//! only move forward along their paths, so protocols which swap packets backward, like the OED
+//! protocols, are outside its scope.
+//!
+//! For example:
+//! ```ignore
+//! #[test]
+//! fn my_protocol_conforms() {
+//!     conformance::check_protocol(|capacity| MyProtocol::new(capacity)).unwrap();
+//! }
+//! ```
+
+use crate::network::presets::construct_path;
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory, PacketId};
+use crate::protocol::ProtocolTrait;
+use crate::simulation::random::SimRng;
+use hashbrown::HashMap;
+
+// The number of nodes on the path networks the scenarios run on.
+const NUM_NODES: usize = 5;
+// The most rounds a lone packet may take to cross the path network before it counts as stuck.
+const MAX_LONE_PACKET_RDS: usize = 10 * NUM_NODES;
+
+/// Run every scenario of the suite against protocols built by `new_protocol`, which is given the
+/// most packets the protocol may forward from a buffer per round. Returns a description of the
+/// first failure, if any. Protocols without a capacity may ignore it, but must then forward at most
+/// one packet per buffer per round.
+pub fn check_protocol<P, F>(mut new_protocol: F) -> Result<(), String>
+where
+    P: ProtocolTrait,
+    F: FnMut(usize) -> P,
+{
+    check_absorbs_at_path_end(&mut new_protocol(1))?;
+    check_capacity(&mut new_protocol(1), 1)?;
+    check_capacity(&mut new_protocol(2), 2)?;
+    check_conservation(&mut new_protocol(1))?;
+    check_empty_buffers(&mut new_protocol(1))
+}
+
+/// Check that a lone packet crossing a path network moves one edge at a time and is absorbed
+/// exactly once, at the end of its path.
+pub fn check_absorbs_at_path_end<P: ProtocolTrait>(protocol: &mut P) -> Result<(), String> {
+    let mut network = construct_path(NUM_NODES);
+    let mut factory = PacketFactory::new();
+    let p = factory.create_packet((0..NUM_NODES).collect(), 1, 0);
+    protocol.add_packet(p, &mut network);
+    for rd in 1..=MAX_LONE_PACKET_RDS {
+        let before = network.clone();
+        let absorbed = protocol.forward_packets(&mut network);
+        check_forward(&before, &network, &absorbed)
+            .map_err(|msg| format!("Absorption at path end, round {}: {}", rd, msg))?;
+        match absorbed.len() {
+            0 => (),
+            1 if network.total_load() == 0 => return Ok(()),
+            n => {
+                return Err(format!(
+                    "Absorption at path end, round {}: {} packets absorbed, {} left.",
+                    rd,
+                    n,
+                    network.total_load()
+                ))
+            }
+        }
+    }
+    Err(format!(
+        "Absorption at path end: the packet wasn't absorbed within {} rounds.",
+        MAX_LONE_PACKET_RDS
+    ))
+}
+
+/// Check that no more than `capacity` packets leave a buffer in a round.
+pub fn check_capacity<P: ProtocolTrait>(protocol: &mut P, capacity: usize) -> Result<(), String> {
+    let mut network = construct_path(NUM_NODES);
+    let mut factory = PacketFactory::new();
+    for _ in 0..capacity + 3 {
+        let p = factory.create_packet((0..NUM_NODES).collect(), 1, 0);
+        protocol.add_packet(p, &mut network);
+    }
+    for rd in 1..=NUM_NODES {
+        let before = network.clone();
+        let absorbed = protocol.forward_packets(&mut network);
+        check_forward(&before, &network, &absorbed)
+            .map_err(|msg| format!("Capacity {}, round {}: {}", capacity, rd, msg))?;
+        let positions = packet_positions(&network)?;
+        for edge in before.edges() {
+            let num_left = edge
+                .iter_packets()
+                .filter(|p| {
+                    positions
+                        .get(&p.id())
+                        .is_none_or(|(_, ids)| *ids != edge.ids())
+                })
+                .count();
+            if num_left > capacity {
+                return Err(format!(
+                    "Capacity {}, round {}: {} packets left buffer {:?}.",
+                    capacity,
+                    rd,
+                    num_left,
+                    edge.ids()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that under a seeded random workload, every packet stays where its path says it is, is
+/// never duplicated, lost, or created, and moves at most one edge per round.
+pub fn check_conservation<P: ProtocolTrait>(protocol: &mut P) -> Result<(), String> {
+    let mut network = construct_path(NUM_NODES);
+    let mut factory = PacketFactory::new();
+    let mut rng = SimRng::from_seed(0);
+    let mut num_injected = 0;
+    let mut num_absorbed = 0;
+    for rd in 1..=100 {
+        // Inject for the first half of the run, and let the network drain in the second.
+        if rd <= 50 {
+            for _ in 0..rng.rand_int(3) {
+                let src_id = rng.rand_int(NUM_NODES - 1);
+                let dest_id = src_id + 1 + rng.rand_int(NUM_NODES - 1 - src_id);
+                let p = factory.create_packet((src_id..=dest_id).collect(), rd, 0);
+                protocol.add_packet(p, &mut network);
+                num_injected += 1;
+            }
+        }
+        let before = network.clone();
+        let absorbed = protocol.forward_packets(&mut network);
+        check_forward(&before, &network, &absorbed)
+            .map_err(|msg| format!("Conservation, round {}: {}", rd, msg))?;
+        num_absorbed += absorbed.len();
+    }
+    match num_absorbed + network.total_load() == num_injected {
+        true => Ok(()),
+        false => Err(format!(
+            "Conservation: {} packets injected, but {} absorbed and {} left.",
+            num_injected,
+            num_absorbed,
+            network.total_load()
+        )),
+    }
+}
+
+/// Check that forwarding an empty network does nothing, and that packets only enter buffers
+/// downstream of their own.
+pub fn check_empty_buffers<P: ProtocolTrait>(protocol: &mut P) -> Result<(), String> {
+    let mut network = construct_path(NUM_NODES);
+    let absorbed = protocol.forward_packets(&mut network);
+    if !absorbed.is_empty() || network.total_load() > 0 {
+        return Err(String::from(
+            "Empty buffers: forwarding an empty network moved packets.",
+        ));
+    }
+    let mut factory = PacketFactory::new();
+    let p = factory.create_packet(vec![2, 3, 4], 1, 0);
+    protocol.add_packet(p, &mut network);
+    let before = network.clone();
+    let absorbed = protocol.forward_packets(&mut network);
+    check_forward(&before, &network, &absorbed).map_err(|msg| format!("Empty buffers: {}", msg))?;
+    for edge in network.edges() {
+        if edge.from() < 2 && edge.load() > 0 {
+            return Err(format!(
+                "Empty buffers: buffer {:?} gained packets upstream of every packet.",
+                edge.ids()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The packets in a network by id, along with the buffer they're in.
+type Positions = HashMap<PacketId, (Packet, (NodeID, NodeID))>;
+
+/// The packets in every buffer of the network, checking that each is where its path says it is.
+fn packet_positions(network: &Network) -> Result<Positions, String> {
+    let mut positions = HashMap::new();
+    for edge in network.edges() {
+        for p in edge.iter_packets() {
+            if p.cur_node() != Some(edge.from()) || p.next_node() != Some(edge.to()) {
+                return Err(format!(
+                    "Packet {} is in buffer {:?} but its path is at {:?}.",
+                    p.id(),
+                    edge.ids(),
+                    (p.cur_node(), p.next_node())
+                ));
+            }
+            if let Some((_, ids)) = positions.insert(p.id(), (p.clone(), edge.ids())) {
+                return Err(format!(
+                    "Packet {} is in both buffer {:?} and buffer {:?}.",
+                    p.id(),
+                    ids,
+                    edge.ids()
+                ));
+            }
+        }
+    }
+    Ok(positions)
+}
+
+/// Check that forwarding took the network from `before` to `after` while absorbing `absorbed`
+/// soundly: every packet sits in the buffer its path says it's in, no packet is in two buffers,
+/// every packet is conserved (it is either still in the network or absorbed), no new packets
+/// appear, and every packet moves at most one edge along its path.
+pub fn check_forward(before: &Network, after: &Network, absorbed: &[Packet]) -> Result<(), String> {
+    let before_positions = packet_positions(before)?;
+    let after_positions = packet_positions(after)?;
+
+    for p in absorbed {
+        if after_positions.contains_key(&p.id()) {
+            return Err(format!(
+                "Packet {} was absorbed but is still in the network.",
+                p.id()
+            ));
+        }
+        if !p.should_be_absorbed() {
+            return Err(format!(
+                "Packet {} was absorbed before reaching its sink.",
+                p.id()
+            ));
+        }
+    }
+    for (id, (p, _)) in &before_positions {
+        let moved_idx = match after_positions.get(id) {
+            Some((after_p, _)) => after_p.get_path_idx(),
+            None => match absorbed.iter().find(|absorbed_p| absorbed_p.id() == *id) {
+                Some(absorbed_p) => absorbed_p.get_path_idx(),
+                None => return Err(format!("Packet {} was lost.", id)),
+            },
+        };
+        if moved_idx != p.get_path_idx() && moved_idx != p.get_path_idx() + 1 {
+            return Err(format!(
+                "Packet {} teleported from path index {} to {}.",
+                id,
+                p.get_path_idx(),
+                moved_idx
+            ));
+        }
+    }
+    if let Some(id) = after_positions
+        .keys()
+        .find(|id| !before_positions.contains_key(*id))
+    {
+        return Err(format!("Packet {} appeared during forwarding.", id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::fair::{FlowKey, RoundRobin};
+    use crate::protocol::greedy::{Greedy, GreedyOrder};
+    use crate::protocol::priority::Priority;
+
+    #[test]
+    fn test_builtin_protocols_conform() {
+        check_protocol(Greedy::fifo).unwrap();
+        check_protocol(Greedy::lis).unwrap();
+        check_protocol(Greedy::buffer_fifo).unwrap();
+        check_protocol(|capacity| Greedy::random(capacity, Some(1))).unwrap();
+        check_protocol(|capacity| Greedy::new(capacity, GreedyOrder::Priority(Priority::NTG)))
+            .unwrap();
+        check_protocol(|capacity| RoundRobin::new(capacity, FlowKey::Source)).unwrap();
+        // A protocol which ignores its capacity fails.
+        let result = check_capacity(&mut Greedy::fifo(3), 2);
+        assert!(result.unwrap_err().starts_with("Capacity 2, round 1"));
+    }
+}
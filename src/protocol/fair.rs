@@ -0,0 +1,511 @@
+//! This module contains fairness-oriented protocols, which share each buffer's capacity between
+//! the flows whose packets are waiting in it. A packet's flow is determined by a `FlowKey`.
+
+use super::{CAPACITY_KEY, DEFICIT_ROUND_ROBIN_NAME, PROTOCOL_NAME_KEY, ROUND_ROBIN_NAME};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::Packet;
+use crate::protocol::ProtocolTrait;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Number, Value};
+
+/// How packets are grouped into flows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlowKey {
+    /// Packets with the same source node are in the same flow.
+    Source,
+    /// Packets with the same class are in the same flow.
+    Class,
+    /// Packets injected by the same adversary are in the same flow.
+    Adversary,
+}
+
+const SOURCE_FLOW_KEY_NAME: &str = "source";
+const CLASS_FLOW_KEY_NAME: &str = "class";
+const ADVERSARY_FLOW_KEY_NAME: &str = "adversary";
+const FLOW_KEY_KEY: &str = "flow_key";
+
+impl FlowKey {
+    /// Get the id of the given packet's flow.
+    pub fn flow_id(&self, p: &Packet) -> usize {
+        match self {
+            Self::Source => p.src_node(),
+            Self::Class => p.class(),
+            Self::Adversary => p.origin(),
+        }
+    }
+
+    /// Get the `FlowKey` under `FLOW_KEY_KEY` in the given protocol config, `Source` by default.
+    fn from_protocol_config(map: &Map<String, Value>) -> Result<Self, CfgErrorMsg> {
+        match map.get(FLOW_KEY_KEY) {
+            Some(flow_key) => Self::from_config(flow_key.clone()),
+            None => Ok(Self::Source),
+        }
+    }
+}
+
+impl Configurable for FlowKey {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(SOURCE_FLOW_KEY_NAME) => Ok(Self::Source),
+            Some(CLASS_FLOW_KEY_NAME) => Ok(Self::Class),
+            Some(ADVERSARY_FLOW_KEY_NAME) => Ok(Self::Adversary),
+            _ => Err(format!("No flow key {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Source => Value::String(SOURCE_FLOW_KEY_NAME.to_string()),
+            Self::Class => Value::String(CLASS_FLOW_KEY_NAME.to_string()),
+            Self::Adversary => Value::String(ADVERSARY_FLOW_KEY_NAME.to_string()),
+        }
+    }
+}
+
+/// The round-robin protocol forwards up to `capacity` packets from each buffer per round, taking
+/// each from the next flow (in increasing order of flow id, wrapping around) with a packet in the
+/// buffer after the flow served last. Within a flow, packets are forwarded in FIFO order.
+#[derive(Clone)]
+pub struct RoundRobin {
+    capacity: usize,
+    flow_key: FlowKey,
+    // The flow served last at each buffer.
+    last_served: HashMap<(NodeID, NodeID), usize>,
+}
+
+impl RoundRobin {
+    /// Get a new `RoundRobin` protocol with the given capacity and flows.
+    pub fn new(capacity: usize, flow_key: FlowKey) -> Self {
+        RoundRobin {
+            capacity,
+            flow_key,
+            last_served: HashMap::new(),
+        }
+    }
+
+    pub(super) fn get_buffer_packets_to_fwd(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        network: &mut Network,
+    ) -> Vec<Packet> {
+        let flow_key = self.flow_key;
+        let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, self.capacity);
+        let mut packets_to_fwd = Vec::new();
+        for _ in 0..num_to_fwd {
+            // The next flow is the one with the smallest id after the last served one, if any,
+            // and otherwise the one with the smallest id.
+            let last = self.last_served.get(&(from_id, to_id)).copied();
+            let next_idx = (0..eb.buffer.len())
+                .min_by_key(|idx| {
+                    let flow_id = flow_key.flow_id(&eb.buffer[*idx]);
+                    (last.is_some_and(|last| flow_id <= last), flow_id, *idx)
+                })
+                .unwrap();
+            let mut packet_to_fwd = eb.buffer.remove(next_idx);
+            self.last_served
+                .insert((from_id, to_id), flow_key.flow_id(&packet_to_fwd));
+            packet_to_fwd.increment_path_idx();
+            packets_to_fwd.push(packet_to_fwd);
+        }
+        packets_to_fwd
+    }
+}
+
+impl ProtocolTrait for RoundRobin {
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        let mut absorbed = Vec::new();
+        let mut packets_to_fwd = Vec::new();
+        for (from_id, to_id) in network.forwarding_order() {
+            packets_to_fwd.append(&mut self.get_buffer_packets_to_fwd(from_id, to_id, network));
+        }
+        for p in packets_to_fwd {
+            if !p.should_be_absorbed() {
+                self.add_packet(p, network)
+            } else {
+                absorbed.push(p);
+            }
+        }
+        absorbed
+    }
+
+    fn forwards_by_buffer(&self) -> bool {
+        true
+    }
+
+    fn buffer_packets_to_fwd(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        network: &mut Network,
+    ) -> Option<Vec<Packet>> {
+        Some(self.get_buffer_packets_to_fwd(from_id, to_id, network))
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The flow served last at each buffer, by edge.
+    fn save_state(&self) -> Option<Value> {
+        let mut last_served: Vec<((NodeID, NodeID), usize)> =
+            self.last_served.iter().map(|(k, v)| (*k, *v)).collect();
+        last_served.sort_unstable();
+        Some(serde_json::to_value(last_served).unwrap())
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), CfgErrorMsg> {
+        let last_served: Vec<((NodeID, NodeID), usize)> = serde_json::from_value(state)
+            .map_err(|err| format!("Invalid round robin state: {}", err))?;
+        self.last_served = last_served.into_iter().collect();
+        Ok(())
+    }
+}
+
+impl Describable for RoundRobin {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            ROUND_ROBIN_NAME,
+            "Forwards packets from each buffer taking turns between flows.",
+        )
+        .required(
+            CAPACITY_KEY,
+            "The most packets forwarded from each buffer per round.",
+            json!(1),
+        )
+        .optional(
+            FLOW_KEY_KEY,
+            "What packets' flows are keyed by: \"source\", \"class\", or \"adversary\".",
+            json!(SOURCE_FLOW_KEY_NAME),
+        )
+    }
+}
+
+impl Configurable for RoundRobin {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let map = config.as_object().unwrap();
+        let capacity = match map.get(CAPACITY_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
+            _ => Err(String::from("No capacity provided.")),
+        }?;
+        Ok(Self::new(capacity, FlowKey::from_protocol_config(map)?))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map: Map<String, Value> = Map::new();
+        map.insert(
+            PROTOCOL_NAME_KEY.to_string(),
+            Value::String(ROUND_ROBIN_NAME.to_string()),
+        );
+        map.insert(
+            CAPACITY_KEY.to_string(),
+            Value::Number(Number::from(self.capacity)),
+        );
+        map.insert(FLOW_KEY_KEY.to_string(), self.flow_key.to_config());
+        Value::Object(map)
+    }
+}
+
+/// The deficit round-robin protocol forwards up to `capacity` packets from each buffer per round,
+/// giving each flow a share of the buffer's capacity proportional to its weight. Flows with
+/// packets in a buffer take turns in increasing order of flow id. At the start of its turn, a
+/// flow's deficit counter grows by its weight, and the flow then forwards packets (in FIFO order)
+/// while its counter is at least 1, each costing 1. A flow's counter is reset when it runs out of
+/// packets, and a turn interrupted by the end of a round's capacity continues in the next round.
+#[derive(Clone)]
+pub struct DeficitRoundRobin {
+    capacity: usize,
+    flow_key: FlowKey,
+    // The weight of each flow, by flow id. Flows past the end have weight 1.
+    weights: Vec<f64>,
+    buffer_states: HashMap<(NodeID, NodeID), DRRBufferState>,
+}
+
+// The deficit round-robin state of a buffer.
+#[derive(Clone, Default)]
+struct DRRBufferState {
+    deficits: HashMap<usize, f64>,
+    // The flow whose turn it is or was last, and whether its turn is still in progress.
+    cur_flow: Option<usize>,
+    in_turn: bool,
+}
+
+// A buffer's deficit round-robin state as saved in a checkpoint, with its deficits by flow id.
+#[derive(Serialize, Deserialize)]
+struct SavedDRRBufferState {
+    edge: (NodeID, NodeID),
+    deficits: Vec<(usize, f64)>,
+    cur_flow: Option<usize>,
+    in_turn: bool,
+}
+
+impl DeficitRoundRobin {
+    /// Get a new `DeficitRoundRobin` protocol with the given capacity, flows, and flow weights.
+    /// Panics if a weight is not positive.
+    pub fn new(capacity: usize, flow_key: FlowKey, weights: Vec<f64>) -> Self {
+        assert!(
+            weights.iter().all(|weight| *weight > 0.0),
+            "Flow weights must be positive."
+        );
+        DeficitRoundRobin {
+            capacity,
+            flow_key,
+            weights,
+            buffer_states: HashMap::new(),
+        }
+    }
+
+    pub(super) fn get_buffer_packets_to_fwd(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        network: &mut Network,
+    ) -> Vec<Packet> {
+        let flow_key = self.flow_key;
+        let weights = &self.weights;
+        let weight = |flow_id: usize| weights.get(flow_id).copied().unwrap_or(1.0);
+        let state = self.buffer_states.entry((from_id, to_id)).or_default();
+        let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, self.capacity);
+        let flow_head = |buffer: &Vec<Packet>, flow_id: usize| {
+            buffer.iter().position(|p| flow_key.flow_id(p) == flow_id)
+        };
+
+        let mut packets_to_fwd = Vec::new();
+        while packets_to_fwd.len() < num_to_fwd {
+            let flow_id = match state.cur_flow {
+                Some(flow_id) if state.in_turn && flow_head(&eb.buffer, flow_id).is_some() => {
+                    flow_id
+                }
+                last => {
+                    // Start the turn of the flow with the smallest id after the last one, if any,
+                    // and otherwise the one with the smallest id.
+                    let flow_id = eb
+                        .buffer
+                        .iter()
+                        .map(|p| flow_key.flow_id(p))
+                        .min_by_key(|flow_id| (last.is_some_and(|last| *flow_id <= last), *flow_id))
+                        .unwrap();
+                    *state.deficits.entry(flow_id).or_default() += weight(flow_id);
+                    state.cur_flow = Some(flow_id);
+                    state.in_turn = true;
+                    flow_id
+                }
+            };
+
+            let deficit = state.deficits.get_mut(&flow_id).unwrap();
+            while packets_to_fwd.len() < num_to_fwd && *deficit >= 1.0 {
+                match flow_head(&eb.buffer, flow_id) {
+                    Some(idx) => {
+                        let mut packet_to_fwd = eb.buffer.remove(idx);
+                        packet_to_fwd.increment_path_idx();
+                        packets_to_fwd.push(packet_to_fwd);
+                        *deficit -= 1.0;
+                    }
+                    None => break,
+                }
+            }
+            if flow_head(&eb.buffer, flow_id).is_none() {
+                *deficit = 0.0;
+                state.in_turn = false;
+            } else if *deficit < 1.0 {
+                state.in_turn = false;
+            }
+        }
+        packets_to_fwd
+    }
+}
+
+impl ProtocolTrait for DeficitRoundRobin {
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        let mut absorbed = Vec::new();
+        let mut packets_to_fwd = Vec::new();
+        for (from_id, to_id) in network.forwarding_order() {
+            packets_to_fwd.append(&mut self.get_buffer_packets_to_fwd(from_id, to_id, network));
+        }
+        for p in packets_to_fwd {
+            if !p.should_be_absorbed() {
+                self.add_packet(p, network)
+            } else {
+                absorbed.push(p);
+            }
+        }
+        absorbed
+    }
+
+    fn forwards_by_buffer(&self) -> bool {
+        true
+    }
+
+    fn buffer_packets_to_fwd(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        network: &mut Network,
+    ) -> Option<Vec<Packet>> {
+        Some(self.get_buffer_packets_to_fwd(from_id, to_id, network))
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Each buffer's deficit counters and turn.
+    fn save_state(&self) -> Option<Value> {
+        let mut saved: Vec<SavedDRRBufferState> = self
+            .buffer_states
+            .iter()
+            .map(|(edge, state)| {
+                let mut deficits: Vec<(usize, f64)> =
+                    state.deficits.iter().map(|(k, v)| (*k, *v)).collect();
+                deficits.sort_unstable_by_key(|(flow, _)| *flow);
+                SavedDRRBufferState {
+                    edge: *edge,
+                    deficits,
+                    cur_flow: state.cur_flow,
+                    in_turn: state.in_turn,
+                }
+            })
+            .collect();
+        saved.sort_unstable_by_key(|state| state.edge);
+        Some(serde_json::to_value(saved).unwrap())
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), CfgErrorMsg> {
+        let saved: Vec<SavedDRRBufferState> = serde_json::from_value(state)
+            .map_err(|err| format!("Invalid deficit round robin state: {}", err))?;
+        self.buffer_states = saved
+            .into_iter()
+            .map(|state| {
+                let buffer_state = DRRBufferState {
+                    deficits: state.deficits.into_iter().collect(),
+                    cur_flow: state.cur_flow,
+                    in_turn: state.in_turn,
+                };
+                (state.edge, buffer_state)
+            })
+            .collect();
+        Ok(())
+    }
+}
+
+const WEIGHTS_KEY: &str = "weights";
+
+impl Describable for DeficitRoundRobin {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            DEFICIT_ROUND_ROBIN_NAME,
+            "Forwards packets from each buffer giving flows shares proportional to their weights.",
+        )
+        .required(
+            CAPACITY_KEY,
+            "The most packets forwarded from each buffer per round.",
+            json!(1),
+        )
+        .optional(
+            FLOW_KEY_KEY,
+            "What packets' flows are keyed by: \"source\", \"class\", or \"adversary\".",
+            json!(SOURCE_FLOW_KEY_NAME),
+        )
+        .optional(
+            WEIGHTS_KEY,
+            "The positive weight of each flow by flow id; flows past its end have weight 1.",
+            json!([1.0, 2.0]),
+        )
+    }
+}
+
+impl Configurable for DeficitRoundRobin {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let map = config.as_object().unwrap();
+        let capacity = match map.get(CAPACITY_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
+            _ => Err(String::from("No capacity provided.")),
+        }?;
+        let weights = match map.get(WEIGHTS_KEY) {
+            Some(Value::Array(weights)) => weights
+                .iter()
+                .map(|weight| match weight.as_f64() {
+                    Some(weight) if weight > 0.0 => Ok(weight),
+                    _ => Err(format!("Flow weight {} must be a positive number.", weight)),
+                })
+                .collect::<Result<Vec<f64>, CfgErrorMsg>>(),
+            Some(_) => Err(String::from("Flow weights must be an array.")),
+            None => Ok(Vec::new()),
+        }?;
+        Ok(Self::new(
+            capacity,
+            FlowKey::from_protocol_config(map)?,
+            weights,
+        ))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map: Map<String, Value> = Map::new();
+        map.insert(
+            PROTOCOL_NAME_KEY.to_string(),
+            Value::String(DEFICIT_ROUND_ROBIN_NAME.to_string()),
+        );
+        map.insert(
+            CAPACITY_KEY.to_string(),
+            Value::Number(Number::from(self.capacity)),
+        );
+        map.insert(FLOW_KEY_KEY.to_string(), self.flow_key.to_config());
+        let weights = self.weights.iter().map(|weight| Value::from(*weight));
+        map.insert(WEIGHTS_KEY.to_string(), Value::Array(weights.collect()));
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_round_robin_alternates_flows() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut protocol = RoundRobin::new(1, FlowKey::Source);
+        // Three packets from node 0 arrive at buffer (1, 2) ahead of two from node 1.
+        for _ in 0..3 {
+            let mut p = factory.create_packet(vec![0, 1, 2], 1, 0);
+            p.increment_path_idx();
+            protocol.add_packet(p, &mut network);
+        }
+        for _ in 0..2 {
+            protocol.add_packet(factory.create_packet(vec![0, 1, 2], 1, 1), &mut network);
+        }
+
+        let sources: Vec<NodeID> = (0..5)
+            .flat_map(|_| protocol.forward_packets(&mut network))
+            .map(|p| p.src_node())
+            .collect();
+        assert_eq!(sources, vec![0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_deficit_round_robin_shares_by_weight() {
+        let mut network = construct_path(2);
+        let mut factory = PacketFactory::new();
+        // Class 0 has twice the weight of class 1, and class 2 half of it.
+        let mut protocol = DeficitRoundRobin::new(1, FlowKey::Class, vec![1.0, 0.5, 0.25]);
+        for class in [0, 1, 2] {
+            for _ in 0..10 {
+                let mut p = factory.create_packet(vec![0, 1], 1, 0);
+                p.set_class(class);
+                protocol.add_packet(p, &mut network);
+            }
+        }
+
+        let classes: Vec<usize> = (0..14)
+            .flat_map(|_| protocol.forward_packets(&mut network))
+            .map(|p| p.class())
+            .collect();
+        assert_eq!(classes, vec![0, 0, 1, 0, 0, 1, 2, 0, 0, 1, 0, 0, 1, 2]);
+    }
+}
@@ -1,184 +1,975 @@
 //! This module contains implementations of greedy protocols.
 
-use super::{CAPACITY_KEY, GREEDY_FIFO_NAME, GREEDY_LIS_NAME, PROTOCOL_NAME_KEY};
-use crate::config::{CfgErrorMsg, Configurable};
-use crate::network::{Network, NodeID};
+use super::{
+    CAPACITY_KEY, GREEDY_BUFFER_FIFO_NAME, GREEDY_FIFO_NAME, GREEDY_LIS_NAME, GREEDY_NAME,
+    GREEDY_RANDOM_NAME, HEAP_KEY, PROTOCOL_NAME_KEY,
+};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Buffer, Network, NodeID, PacketComparator};
 use crate::packet::Packet;
+use crate::protocol::priority::Priority;
 use crate::protocol::ProtocolTrait;
-use crate::protocol::priority;
-use serde_json::{Map, Number, Value};
-use std::cmp::min;
+use crate::simulation::random::SimRng;
+use hashbrown::HashMap;
+use serde_json::{json, Map, Number, Value};
 
-/// The greedy FIFO protocol always forwards packets as many packets from a buffer as allowed by
-/// the protocol's capacity.
-#[derive(Clone)]
-pub struct GreedyFIFO {
-    capacity: usize,
+const EDGE_CAPACITIES_KEY: &str = "edge_capacities";
+const EDGE_KEY: &str = "edge";
+const EDGE_CAPACITIES_DOC: &str =
+    "Capacities overriding `capacity` on some buffers, each an edge with its capacity.";
+
+/// A greedy protocol's capacity on each buffer: the capacity given for the buffer's edge, if any,
+/// and otherwise the default capacity, so that links of different speeds can be modeled.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EdgeCapacities {
+    default: usize,
+    edges: HashMap<(NodeID, NodeID), usize>,
 }
 
-impl GreedyFIFO {
-    /// Get a new `GreedyFIFO` struct.
-    pub fn new(capacity: usize) -> Self {
-        GreedyFIFO { capacity }
+impl EdgeCapacities {
+    /// Get the same capacity on every buffer.
+    pub fn uniform(capacity: usize) -> Self {
+        EdgeCapacities {
+            default: capacity,
+            edges: HashMap::new(),
+        }
     }
-}
 
-impl ProtocolTrait for GreedyFIFO {
-    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
-        let mut absorbed = Vec::new();
-        let mut packets_to_fwd = Vec::new();
-
-        let eb_ids = network.get_edgebuffers();
-        for (from_id, to_id) in eb_ids {
-            let mut buffer_packets_to_fwd = self.get_buffer_packets_to_fwd(from_id, to_id, network);
-            packets_to_fwd.append(&mut buffer_packets_to_fwd);
-        }
-
-        let num_to_fwd = packets_to_fwd.len();
-        for _ in 0..num_to_fwd {
-            let p = packets_to_fwd.remove(0);
-            if !p.should_be_absorbed() {
-                self.add_packet(p, network)
-            } else {
-                absorbed.push(p);
-            }
+    /// Get the given capacities on their edges, and the default capacity on every other buffer.
+    pub fn new(default: usize, edges: Vec<((NodeID, NodeID), usize)>) -> Self {
+        EdgeCapacities {
+            default,
+            edges: edges.into_iter().collect(),
         }
-        absorbed
     }
-}
 
-impl GreedyFIFO {
-    fn get_buffer_packets_to_fwd(
-        &mut self,
-        from_id: NodeID,
-        to_id: NodeID,
-        network: &mut Network,
-    ) -> Vec<Packet> {
-        let eb = network.get_edgebuffer_mut(from_id, to_id).unwrap();
-        let num_to_fwd = min(self.capacity, eb.buffer.len());
-        let mut packets_to_fwd = Vec::new();
-        for _ in 0..num_to_fwd {
-            // NOTE: We need to remove from the front to enforce FIFO.
-            let mut packet_to_fwd = eb.buffer.remove(0);
-            packet_to_fwd.increment_path_idx();
-            packets_to_fwd.push(packet_to_fwd);
+    /// Get the capacity of the given buffer.
+    pub fn get(&self, from_id: NodeID, to_id: NodeID) -> usize {
+        self.edges
+            .get(&(from_id, to_id))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Get the largest capacity of any buffer.
+    pub fn max(&self) -> usize {
+        self.edges.values().copied().fold(self.default, usize::max)
+    }
+
+    /// Check that every edge given a capacity is in the network.
+    fn check_edges(&self, network: &Network) -> Result<(), CfgErrorMsg> {
+        let mut edges: Vec<_> = self.edges.keys().collect();
+        edges.sort_unstable();
+        match edges
+            .into_iter()
+            .find(|(from_id, to_id)| network.edge(*from_id, *to_id).is_none())
+        {
+            Some((from_id, to_id)) => Err(format!(
+                "Edge ({}, {}) is given a capacity but isn't in the network.",
+                from_id, to_id
+            )),
+            None => Ok(()),
         }
-        packets_to_fwd
     }
-}
 
-impl Configurable for GreedyFIFO {
-    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
-        let map = config.as_object().unwrap();
-        let capacity = match map.get(CAPACITY_KEY) {
+    /// Parse the capacities from a protocol's config: the required `capacity` and the optional
+    /// `edge_capacities`.
+    fn from_protocol_config(map: &Map<String, Value>) -> Result<Self, CfgErrorMsg> {
+        let default = match map.get(CAPACITY_KEY) {
             Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
             _ => Err(String::from("No capacity provided.")),
         }?;
-        Ok(Self { capacity })
+        let edge_cfgs = match map.get(EDGE_CAPACITIES_KEY) {
+            Some(Value::Array(edge_cfgs)) => Ok(edge_cfgs.clone()),
+            Some(_) => Err(String::from("Edge capacities must be an array.")),
+            None => Ok(Vec::new()),
+        }?;
+        let mut edges = Vec::new();
+        for edge_cfg in edge_cfgs {
+            let edge = match edge_cfg.get(EDGE_KEY).and_then(Value::as_array) {
+                Some(ids) if ids.len() == 2 => match (ids[0].as_u64(), ids[1].as_u64()) {
+                    (Some(from_id), Some(to_id)) => Ok((from_id as usize, to_id as usize)),
+                    _ => Err(String::from("Edge node ids must be integers.")),
+                },
+                _ => Err(String::from(
+                    "Each edge capacity's edge must be an array of two node ids.",
+                )),
+            }?;
+            let capacity = match edge_cfg.get(CAPACITY_KEY).and_then(Value::as_u64) {
+                Some(capacity) => Ok(capacity as usize),
+                None => Err(String::from("Each edge capacity needs a capacity.")),
+            }?;
+            edges.push((edge, capacity));
+        }
+        Ok(Self::new(default, edges))
     }
 
-    fn to_config(&self) -> Value {
-        let mut map: Map<String, Value> = Map::new();
-        map.insert(
-            PROTOCOL_NAME_KEY.to_string(),
-            Value::String(GREEDY_FIFO_NAME.to_string()),
-        );
+    /// Add the capacities to a protocol's config.
+    fn insert_into_config(&self, map: &mut Map<String, Value>) {
         map.insert(
             CAPACITY_KEY.to_string(),
-            Value::Number(Number::from(self.capacity)),
+            Value::Number(Number::from(self.default)),
         );
-        Value::Object(map)
+        if !self.edges.is_empty() {
+            let mut edges: Vec<_> = self.edges.iter().collect();
+            edges.sort_unstable();
+            let edges = edges
+                .into_iter()
+                .map(|((from_id, to_id), capacity)| {
+                    json!({ EDGE_KEY: [from_id, to_id], CAPACITY_KEY: capacity })
+                })
+                .collect();
+            map.insert(EDGE_CAPACITIES_KEY.to_string(), Value::Array(edges));
+        }
     }
 }
 
-/// The greedy LIS protocol always forwards packets as many of the oldest packets from a buffer as
-/// allowed by the protocol's capacity.
-#[derive(Clone)]
-pub struct GreedyLIS {
-    capacity: usize,
+/// What a `Greedy` buffer in FIFO order does with the packets at its head which the protocol's capacity
+/// would forward this round, but which node capacities hold back.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HeadOfLine {
+    /// The held-back packets keep their positions, so they're forwarded first next round (strict
+    /// head-of-line blocking).
+    #[default]
+    Block,
+    /// The held-back packets are moved to the tail of the buffer, behind the packets already
+    /// waiting in it but ahead of those forwarded into it in the same round.
+    Requeue,
 }
 
-impl GreedyLIS {
-    /// Get a new `GreedyLIS` struct.
-    pub fn new(capacity: usize) -> Self {
-        GreedyLIS { capacity }
+const HEAD_OF_LINE_KEY: &str = "head_of_line";
+const HEAD_OF_LINE_BLOCK_NAME: &str = "block";
+const HEAD_OF_LINE_REQUEUE_NAME: &str = "requeue";
+
+impl Configurable for HeadOfLine {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(HEAD_OF_LINE_BLOCK_NAME) => Ok(Self::Block),
+            Some(HEAD_OF_LINE_REQUEUE_NAME) => Ok(Self::Requeue),
+            _ => Err(format!("No head-of-line mode {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Block => Value::String(HEAD_OF_LINE_BLOCK_NAME.to_string()),
+            Self::Requeue => Value::String(HEAD_OF_LINE_REQUEUE_NAME.to_string()),
+        }
     }
 }
 
-impl ProtocolTrait for GreedyLIS {
-    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
-        let mut absorbed = Vec::new();
-        let mut packets_to_fwd = Vec::new();
-
-        let eb_ids = network.get_edgebuffers();
-        for (from_id, to_id) in eb_ids {
-            let mut buffer_packets_to_fwd = self.get_buffer_packets_to_fwd(from_id, to_id, network);
-            packets_to_fwd.append(&mut buffer_packets_to_fwd);
-        }
-
-        let num_to_fwd = packets_to_fwd.len();
-        for _ in 0..num_to_fwd {
-            let p = packets_to_fwd.remove(0);
-            if !p.should_be_absorbed() {
-                self.add_packet(p, network)
-            } else {
-                absorbed.push(p);
-            }
+/// The order in which a `Greedy` protocol forwards the packets in a buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GreedyOrder {
+    /// The order in which the packets were added to the buffer.
+    FIFO,
+    /// Highest priority first, under the given `Priority`.
+    Priority(Priority),
+    /// Uniformly at random among the packets left in the buffer, from the given seed.
+    Random { seed: u64 },
+}
+
+const FIFO_NAME: &str = "fifo";
+const RANDOM_NAME: &str = "random";
+
+impl Configurable for GreedyOrder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(FIFO_NAME) => Ok(Self::FIFO),
+            Some(RANDOM_NAME) => Ok(Self::Random {
+                seed: SimRng::new().seed(),
+            }),
+            _ => Ok(Self::Priority(Priority::from_config(config)?)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::FIFO => Value::String(FIFO_NAME.to_string()),
+            Self::Priority(priority) => priority.to_config(),
+            Self::Random { .. } => Value::String(RANDOM_NAME.to_string()),
         }
-        absorbed
     }
 }
 
-impl GreedyLIS {
-    fn get_buffer_packets_to_fwd(
+// The names under which a `Greedy` protocol with a fixed order can also be configured. The seed
+// of the random order is set from the config.
+const GREEDY_ALIASES: [(&str, GreedyOrder); 4] = [
+    (GREEDY_FIFO_NAME, GreedyOrder::FIFO),
+    (GREEDY_LIS_NAME, GreedyOrder::Priority(Priority::LIS)),
+    (
+        GREEDY_BUFFER_FIFO_NAME,
+        GreedyOrder::Priority(Priority::BufferFIFO),
+    ),
+    (GREEDY_RANDOM_NAME, GreedyOrder::Random { seed: 0 }),
+];
+
+/// The greedy protocol always forwards as many packets from a buffer as allowed by the buffer's
+/// capacity, in the given `GreedyOrder`: FIFO, by any `Priority`, so that new priority policies
+/// only need a comparator in `protocol::priority`, or at random, as a baseline against priority
+/// policies. Priorities take one scan of the buffer per packet forwarded. It may also be configured
+/// by the name of a fixed order, e.g. `greedy_fifo`, `greedy_lis`, or `greedy_random`.
+///
+/// In FIFO order, packets held back by node capacities are handled as set by its `HeadOfLine`
+/// mode.
+///
+/// With `heap` set, each buffer is kept as a binary heap by priority, so selecting the highest
+/// priority packet takes logarithmic rather than linear time. In this mode, packets must be added
+/// to the network through the protocol's `add_packet`.
+///
+/// In LIS order with random tie-breaking, a packet is chosen uniformly at random (from a seeded
+/// generator) among the oldest packets in a buffer, rather than taking the one with the smallest
+/// id.
+#[derive(Clone)]
+pub struct Greedy {
+    // The name this protocol was configured by, either `greedy` or the alias of its order.
+    name: &'static str,
+    capacities: EdgeCapacities,
+    order: GreedyOrder,
+    head_of_line: HeadOfLine,
+    heap: bool,
+    // The generator for random order or random tie-breaking, if either is used.
+    rng: Option<SimRng>,
+}
+
+impl Greedy {
+    /// Get a new `Greedy` struct forwarding in the given order.
+    pub fn new(capacity: usize, order: GreedyOrder) -> Self {
+        let rng = match order {
+            GreedyOrder::Random { seed } => Some(SimRng::from_seed(seed)),
+            _ => None,
+        };
+        Greedy {
+            name: GREEDY_NAME,
+            capacities: EdgeCapacities::uniform(capacity),
+            order,
+            head_of_line: HeadOfLine::Block,
+            heap: false,
+            rng,
+        }
+    }
+
+    /// Get a new greedy FIFO protocol, configured as `greedy_fifo`.
+    pub fn fifo(capacity: usize) -> Self {
+        Self::aliased(GREEDY_FIFO_NAME, capacity)
+    }
+
+    /// Get a new greedy LIS (longest-in-system) protocol, configured as `greedy_lis`.
+    pub fn lis(capacity: usize) -> Self {
+        Self::aliased(GREEDY_LIS_NAME, capacity)
+    }
+
+    /// Get a new greedy buffer-arrival FIFO protocol, configured as `greedy_buffer_fifo`, which
+    /// forwards the packets which entered a buffer in the earliest round first (see
+    /// `Packet::last_moved_rd`), ties broken by LIS. Unlike FIFO order, this doesn't depend on the
+    /// order in which packets were added to a buffer within a round.
+    pub fn buffer_fifo(capacity: usize) -> Self {
+        Self::aliased(GREEDY_BUFFER_FIFO_NAME, capacity)
+    }
+
+    /// Get a new greedy random-order protocol, configured as `greedy_random`, choosing packets
+    /// from the given seed if any.
+    pub fn random(capacity: usize, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| SimRng::new().seed());
+        Greedy {
+            name: GREEDY_RANDOM_NAME,
+            ..Self::new(capacity, GreedyOrder::Random { seed })
+        }
+    }
+
+    fn aliased(name: &'static str, capacity: usize) -> Self {
+        let (name, order) = GREEDY_ALIASES
+            .into_iter()
+            .find(|(alias, _)| *alias == name)
+            .unwrap();
+        Greedy {
+            name,
+            ..Self::new(capacity, order)
+        }
+    }
+
+    /// Use the given capacity on each buffer.
+    pub fn with_edge_capacities(mut self, capacities: EdgeCapacities) -> Self {
+        self.capacities = capacities;
+        self
+    }
+
+    /// Handle packets held back by node capacities as given. Only FIFO order has a head of line.
+    pub fn with_head_of_line(mut self, head_of_line: HeadOfLine) -> Self {
+        assert!(
+            head_of_line == HeadOfLine::Block || self.order == GreedyOrder::FIFO,
+            "Only FIFO order can requeue held-back packets."
+        );
+        self.head_of_line = head_of_line;
+        self
+    }
+
+    /// Keep each buffer as a binary heap by priority. Only priority orders can use heaps.
+    pub fn with_heap(mut self) -> Self {
+        assert!(
+            matches!(self.order, GreedyOrder::Priority(_)) && self.rng.is_none(),
+            "Heap buffers need a priority order without random tie-breaking."
+        );
+        self.heap = true;
+        self
+    }
+
+    /// Break ties between equally old packets randomly, from the given seed if any. Only LIS
+    /// order without heap buffers can break ties randomly.
+    pub fn with_random_ties(mut self, seed: Option<u64>) -> Self {
+        assert!(
+            self.order == GreedyOrder::Priority(Priority::LIS) && !self.heap,
+            "Random tie-breaking needs LIS order without heap buffers."
+        );
+        self.rng = Some(seed.map_or_else(SimRng::new, SimRng::from_seed));
+        self
+    }
+
+    /// Get the order in which this protocol forwards the packets in a buffer.
+    pub fn order(&self) -> GreedyOrder {
+        self.order
+    }
+
+    /// Get what this protocol does with packets held back by node capacities.
+    pub fn head_of_line(&self) -> HeadOfLine {
+        self.head_of_line
+    }
+
+    pub(super) fn get_buffer_packets_to_fwd(
         &mut self,
         from_id: NodeID,
         to_id: NodeID,
         network: &mut Network,
     ) -> Vec<Packet> {
-        let eb = network.get_edgebuffer_mut(from_id, to_id).unwrap();
-        let num_to_fwd = min(self.capacity, eb.buffer.len());
-        let mut packets_to_fwd = Vec::new();
-        if eb.buffer.len() < num_to_fwd { return packets_to_fwd };
-
-        for _ in 0..num_to_fwd {
-            let mut hipri_packet = &eb.buffer[0];
-            let mut hipri_idx = 0;
-            for i in 1..eb.buffer.len() {
-                if priority::lis_higher_priority(&eb.buffer[i], hipri_packet) {
-                    hipri_packet = &eb.buffer[i];
-                    hipri_idx = i;
+        let capacity = self.capacities.get(from_id, to_id);
+        match self.order {
+            GreedyOrder::FIFO => {
+                let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, capacity);
+                let num_held_back = capacity.min(eb.buffer.len()) - num_to_fwd;
+                let mut packets_to_fwd = Vec::new();
+                for _ in 0..num_to_fwd {
+                    // NOTE: We need to remove from the front to enforce FIFO.
+                    let mut packet_to_fwd = eb.buffer.remove(0);
+                    packet_to_fwd.increment_path_idx();
+                    packets_to_fwd.push(packet_to_fwd);
+                }
+                if self.head_of_line == HeadOfLine::Requeue {
+                    eb.buffer[..].rotate_left(num_held_back);
                 }
+                packets_to_fwd
+            }
+            GreedyOrder::Priority(priority) if self.heap => {
+                let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, capacity);
+                (0..num_to_fwd)
+                    .map(|_| {
+                        let mut packet_to_fwd = eb.heap_pop(priority.comparator()).unwrap();
+                        packet_to_fwd.increment_path_idx();
+                        packet_to_fwd
+                    })
+                    .collect()
+            }
+            GreedyOrder::Priority(priority) => match &mut self.rng {
+                Some(rng) => take_oldest_with_random_ties(from_id, to_id, capacity, rng, network),
+                None => take_by_priority(from_id, to_id, capacity, priority.comparator(), network),
+            },
+            GreedyOrder::Random { .. } => {
+                let rng = self.rng.as_mut().unwrap();
+                let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, capacity);
+                let mut packets_to_fwd = Vec::new();
+                for _ in 0..num_to_fwd {
+                    let idx = rng.rand_int(eb.buffer.len());
+                    let mut packet_to_fwd = eb.buffer.remove(idx);
+                    packet_to_fwd.increment_path_idx();
+                    packets_to_fwd.push(packet_to_fwd);
+                }
+                packets_to_fwd
             }
-            let mut packet_to_fwd = eb.buffer.remove(hipri_idx);
-            packet_to_fwd.increment_path_idx();
-            packets_to_fwd.push(packet_to_fwd);
         }
+    }
+}
 
-        packets_to_fwd
+impl ProtocolTrait for Greedy {
+    fn add_packet(&mut self, p: Packet, network: &mut Network) {
+        let cur = p.cur_node().unwrap();
+        let next = p.next_node().unwrap();
+        let priority = match self.order {
+            GreedyOrder::Priority(priority) if self.heap => priority,
+            _ => return network.add_packet(p, cur, next),
+        };
+        let mut p = p;
+        network.stamp_arrival(&mut p);
+        network.count_packet_move();
+        let eb = network.get_edgebuffer_mut(cur, next).unwrap();
+        eb.heap_push(p, priority.comparator());
+    }
+
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        forward_greedy(self, network, Self::get_buffer_packets_to_fwd)
+    }
+
+    /// Heap buffers take restored packets back by priority rather than at the front.
+    fn restore_packets(&mut self, packets: Vec<Packet>, network: &mut Network) {
+        let priority = match self.order {
+            GreedyOrder::Priority(priority) if self.heap => priority,
+            _ => return network.restore_packets(packets),
+        };
+        for p in packets {
+            let (cur, next) = (p.cur_node().unwrap(), p.next_node().unwrap());
+            let eb = network.get_edgebuffer_mut(cur, next).unwrap();
+            eb.heap_push(p, priority.comparator());
+        }
+    }
+
+    fn forwards_by_buffer(&self) -> bool {
+        true
+    }
+
+    fn buffer_packets_to_fwd(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        network: &mut Network,
+    ) -> Option<Vec<Packet>> {
+        Some(self.get_buffer_packets_to_fwd(from_id, to_id, network))
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacities.max()
+    }
+
+    fn check_graph_structure(&self, network: &Network) -> Result<(), CfgErrorMsg> {
+        self.capacities.check_edges(network)
+    }
+
+    /// The random number generator's state, if packets are ordered or ties broken randomly.
+    fn save_state(&self) -> Option<Value> {
+        self.rng.as_ref().map(SimRng::save_state)
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), CfgErrorMsg> {
+        match self.rng {
+            Some(_) => {
+                self.rng = Some(SimRng::load_state(state)?);
+                Ok(())
+            }
+            None => Err(String::from(
+                "This greedy protocol makes no random choices.",
+            )),
+        }
+    }
+}
+
+const PRIORITY_KEY: &str = "priority";
+const HEAD_OF_LINE_DOC: &str =
+    "What happens to packets held back by node capacities: \"block\" or \"requeue\" (FIFO only).";
+const HEAP_DOC: &str = "Keep each buffer as a binary heap, for logarithmic selection.";
+const TIE_BREAK_KEY: &str = "tie_break";
+const TIE_BREAK_ID_NAME: &str = "id";
+const TIE_BREAK_RANDOM_NAME: &str = "random";
+const TIE_BREAK_DOC: &str =
+    "How ties between equally old packets are broken: \"id\" or \"random\" (LIS only).";
+const SEED_KEY: &str = "seed";
+
+impl Greedy {
+    // The description shared by every name a `Greedy` protocol is configured by.
+    fn describe_as(name: &'static str, doc: &'static str) -> Description {
+        Description::new(PROTOCOL_NAME_KEY, name, doc)
+            .required(
+                CAPACITY_KEY,
+                "The most packets forwarded from each buffer per round.",
+                json!(1),
+            )
+            .optional(
+                EDGE_CAPACITIES_KEY,
+                EDGE_CAPACITIES_DOC,
+                json!([{ EDGE_KEY: [0, 1], CAPACITY_KEY: 2 }]),
+            )
+    }
+
+    /// Describe the `greedy_fifo` alias.
+    pub fn describe_fifo() -> Description {
+        Self::describe_as(
+            GREEDY_FIFO_NAME,
+            "Forwards packets from each buffer in the order they were added.",
+        )
+        .optional(
+            HEAD_OF_LINE_KEY,
+            HEAD_OF_LINE_DOC,
+            json!(HEAD_OF_LINE_REQUEUE_NAME),
+        )
+    }
+
+    /// Describe the `greedy_lis` alias.
+    pub fn describe_lis() -> Description {
+        Self::describe_as(
+            GREEDY_LIS_NAME,
+            "Forwards the oldest packets from each buffer (longest-in-system).",
+        )
+        .optional(HEAP_KEY, HEAP_DOC, json!(true))
+        .optional(TIE_BREAK_KEY, TIE_BREAK_DOC, json!(TIE_BREAK_RANDOM_NAME))
+        .optional(SEED_KEY, "The seed for random tie-breaking.", json!(0))
+    }
+
+    /// Describe the `greedy_random` alias.
+    pub fn describe_random() -> Description {
+        Self::describe_as(
+            GREEDY_RANDOM_NAME,
+            "Forwards packets chosen uniformly at random from each buffer.",
+        )
+        .optional(SEED_KEY, "The seed for choosing packets.", json!(0))
+    }
+
+    /// Describe the `greedy_buffer_fifo` alias.
+    pub fn describe_buffer_fifo() -> Description {
+        Self::describe_as(
+            GREEDY_BUFFER_FIFO_NAME,
+            "Forwards the packets which entered each buffer earliest, ties broken by LIS.",
+        )
+        .optional(HEAP_KEY, HEAP_DOC, json!(true))
+    }
+}
+
+impl Describable for Greedy {
+    fn describe() -> Description {
+        Self::describe_as(
+            GREEDY_NAME,
+            "Forwards packets from each buffer in FIFO order, by a priority policy, or at random.",
+        )
+        .required(
+            PRIORITY_KEY,
+            "\"fifo\", \"random\", or a priority: \"lis\", \"sis\", \"ntg\", \"ftg\", \"nfs\", \
+             \"ffs\", \"slack\", \"class_lis\", or \"buffer_fifo\".",
+            json!("lis"),
+        )
+        .optional(
+            HEAD_OF_LINE_KEY,
+            HEAD_OF_LINE_DOC,
+            json!(HEAD_OF_LINE_BLOCK_NAME),
+        )
+        .optional(HEAP_KEY, HEAP_DOC, json!(true))
+        .optional(TIE_BREAK_KEY, TIE_BREAK_DOC, json!(TIE_BREAK_RANDOM_NAME))
+        .optional(
+            SEED_KEY,
+            "The seed for random order or random tie-breaking.",
+            json!(0),
+        )
     }
 }
 
-impl Configurable for GreedyLIS {
+impl Configurable for Greedy {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
         let map = config.as_object().unwrap();
-        let capacity = match map.get(CAPACITY_KEY) {
-            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
-            _ => Err(String::from("No capacity provided.")),
+        let alias = GREEDY_ALIASES
+            .into_iter()
+            .find(|(alias, _)| map.get(PROTOCOL_NAME_KEY) == Some(&json!(alias)));
+        let (name, order) = match (alias, map.get(PRIORITY_KEY)) {
+            (Some((alias, _)), Some(_)) => Err(format!("The {} protocol has no priority.", alias)),
+            (Some(alias), None) => Ok(alias),
+            (None, Some(order)) => Ok((GREEDY_NAME, GreedyOrder::from_config(order.clone())?)),
+            (None, None) => Err(String::from("No priority provided.")),
         }?;
-        Ok(Self { capacity })
+        let order = match order {
+            GreedyOrder::Random { seed } => match map.get(SEED_KEY) {
+                Some(seed) => match seed.as_u64() {
+                    Some(seed) => GreedyOrder::Random { seed },
+                    None => return Err(String::from("Seed must be a nonnegative integer.")),
+                },
+                None if name == GREEDY_RANDOM_NAME => GreedyOrder::Random {
+                    seed: SimRng::new().seed(),
+                },
+                None => GreedyOrder::Random { seed },
+            },
+            order => order,
+        };
+        let mut protocol = Greedy {
+            name,
+            ..Self::new(0, order)
+        }
+        .with_edge_capacities(EdgeCapacities::from_protocol_config(map)?);
+
+        if let Some(head_of_line) = map.get(HEAD_OF_LINE_KEY) {
+            protocol.head_of_line = HeadOfLine::from_config(head_of_line.clone())?;
+            if protocol.head_of_line == HeadOfLine::Requeue && order != GreedyOrder::FIFO {
+                return Err(String::from(
+                    "Only FIFO order can requeue held-back packets.",
+                ));
+            }
+        }
+        let heap = matches!(map.get(HEAP_KEY), Some(Value::Bool(true)));
+        if heap {
+            if !matches!(order, GreedyOrder::Priority(_)) {
+                return Err(String::from("Heap buffers need a priority order."));
+            }
+            protocol.heap = true;
+        }
+        match map.get(TIE_BREAK_KEY).and_then(Value::as_str) {
+            None | Some(TIE_BREAK_ID_NAME) => (),
+            Some(TIE_BREAK_RANDOM_NAME) if heap => {
+                return Err(String::from(
+                    "Random tie-breaking is not supported with heap buffers.",
+                ))
+            }
+            Some(TIE_BREAK_RANDOM_NAME) if order != GreedyOrder::Priority(Priority::LIS) => {
+                return Err(String::from("Random tie-breaking needs LIS order."))
+            }
+            Some(TIE_BREAK_RANDOM_NAME) => {
+                let seed = map.get(SEED_KEY).and_then(Value::as_u64);
+                protocol = protocol.with_random_ties(seed);
+            }
+            Some(other) => return Err(format!("No tie-break mode {}.", other)),
+        }
+        Ok(protocol)
     }
 
     fn to_config(&self) -> Value {
         let mut map: Map<String, Value> = Map::new();
         map.insert(
             PROTOCOL_NAME_KEY.to_string(),
-            Value::String(GREEDY_LIS_NAME.to_string()),
-        );
-        map.insert(
-            CAPACITY_KEY.to_string(),
-            Value::Number(Number::from(self.capacity)),
+            Value::String(self.name.to_string()),
         );
+        self.capacities.insert_into_config(&mut map);
+        if self.name == GREEDY_NAME {
+            map.insert(PRIORITY_KEY.to_string(), self.order.to_config());
+        }
+        if self.head_of_line != HeadOfLine::Block {
+            map.insert(HEAD_OF_LINE_KEY.to_string(), self.head_of_line.to_config());
+        }
+        if self.heap {
+            map.insert(HEAP_KEY.to_string(), Value::Bool(true));
+        }
+        if let GreedyOrder::Random { seed } = self.order {
+            map.insert(SEED_KEY.to_string(), Value::Number(Number::from(seed)));
+        } else if let Some(rng) = &self.rng {
+            map.insert(
+                TIE_BREAK_KEY.to_string(),
+                Value::String(TIE_BREAK_RANDOM_NAME.to_string()),
+            );
+            map.insert(
+                SEED_KEY.to_string(),
+                Value::Number(Number::from(rng.seed())),
+            );
+        }
         Value::Object(map)
     }
 }
+
+/// Forward the packets each buffer's `get_buffer_packets_to_fwd` chooses, buffer by buffer in
+/// forwarding order, then add them to their next buffers through the protocol, or absorb them.
+/// Returns the absorbed packets.
+fn forward_greedy<P: ProtocolTrait>(
+    protocol: &mut P,
+    network: &mut Network,
+    get_buffer_packets_to_fwd: fn(&mut P, NodeID, NodeID, &mut Network) -> Vec<Packet>,
+) -> Vec<Packet> {
+    let mut absorbed = Vec::new();
+    let mut packets_to_fwd = Vec::new();
+    for (from_id, to_id) in network.forwarding_order() {
+        packets_to_fwd.append(&mut get_buffer_packets_to_fwd(
+            protocol, from_id, to_id, network,
+        ));
+    }
+    for p in packets_to_fwd {
+        if !p.should_be_absorbed() {
+            protocol.add_packet(p, network)
+        } else {
+            absorbed.push(p);
+        }
+    }
+    absorbed
+}
+
+/// Get the index of the highest-priority packet in a nonempty buffer.
+fn highest_priority_idx(buffer: &Buffer, cmp: PacketComparator) -> usize {
+    (0..buffer.len())
+        .min_by(|i, j| cmp(&buffer[*i], &buffer[*j]))
+        .unwrap()
+}
+
+/// Remove the packets to forward from the given buffer, highest priority first, with their path
+/// indices advanced. Each packet forwarded takes one scan of the buffer.
+fn take_by_priority(
+    from_id: NodeID,
+    to_id: NodeID,
+    capacity: usize,
+    cmp: PacketComparator,
+    network: &mut Network,
+) -> Vec<Packet> {
+    let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, capacity);
+    let mut packets_to_fwd = Vec::new();
+    for _ in 0..num_to_fwd {
+        let idx = highest_priority_idx(&eb.buffer, cmp);
+        let mut packet_to_fwd = eb.buffer.remove(idx);
+        packet_to_fwd.increment_path_idx();
+        packets_to_fwd.push(packet_to_fwd);
+    }
+    for _ in 0..num_to_fwd {
+        network.count_buffer_scan();
+    }
+    packets_to_fwd
+}
+
+/// Remove the packets to forward from the given buffer, oldest first, each chosen uniformly at
+/// random among the oldest packets left, with their path indices advanced. Each packet forwarded
+/// takes one scan of the buffer.
+fn take_oldest_with_random_ties(
+    from_id: NodeID,
+    to_id: NodeID,
+    capacity: usize,
+    rng: &mut SimRng,
+    network: &mut Network,
+) -> Vec<Packet> {
+    let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, to_id, capacity);
+    let mut packets_to_fwd = Vec::new();
+    for _ in 0..num_to_fwd {
+        let oldest_rd = eb.buffer.iter().map(|p| p.injection_rd()).min().unwrap();
+        let tied: Vec<usize> = (0..eb.buffer.len())
+            .filter(|i| eb.buffer[*i].injection_rd() == oldest_rd)
+            .collect();
+        let mut packet_to_fwd = eb.buffer.remove(tied[rng.rand_int(tied.len())]);
+        packet_to_fwd.increment_path_idx();
+        packets_to_fwd.push(packet_to_fwd);
+    }
+    for _ in 0..num_to_fwd {
+        network.count_buffer_scan();
+    }
+    packets_to_fwd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::SDPathRandomBurstyAdversary;
+    use crate::adversary::AdversaryTrait;
+    use crate::network::presets::construct_path;
+    use crate::network::NodeCapacities;
+    use crate::packet::PacketFactory;
+    use crate::protocol::Protocol;
+
+    /// Run a seeded bursty adversary against the given protocol and return the absorbed ids.
+    fn absorbed_ids<P: ProtocolTrait>(protocol: &mut P) -> Vec<usize> {
+        let mut network = construct_path(6);
+        let mut adversary = SDPathRandomBurstyAdversary::from_seed(7, 4);
+        let mut factory = PacketFactory::new();
+        let mut ids = Vec::new();
+        for rd in 1..200 {
+            for p in adversary.get_next_packets(&network, rd, &mut factory) {
+                protocol.add_packet(p, &mut network);
+            }
+            ids.extend(
+                protocol
+                    .forward_packets(&mut network)
+                    .iter()
+                    .map(|p| p.id().0),
+            );
+        }
+        ids
+    }
+
+    #[test]
+    fn test_heap_matches_scan() {
+        for priority in [Priority::LIS, Priority::FTG, Priority::BufferFIFO] {
+            let order = GreedyOrder::Priority(priority);
+            let scan_ids = absorbed_ids(&mut Greedy::new(1, order));
+            let heap_ids = absorbed_ids(&mut Greedy::new(1, order).with_heap());
+            assert!(!scan_ids.is_empty());
+            assert_eq!(scan_ids, heap_ids, "{:?}", priority);
+        }
+    }
+
+    #[test]
+    fn test_random_ties_are_seeded_and_keep_lis_order() {
+        let seeded = |seed| {
+            let cfg = serde_json::json!({
+                "protocol_name": "greedy_lis", "capacity": 1, "tie_break": "random", "seed": seed
+            });
+            Greedy::from_config(cfg).unwrap()
+        };
+        let ids = absorbed_ids(&mut seeded(3));
+        assert_eq!(ids, absorbed_ids(&mut seeded(3)));
+        // Only the order among packets injected in the same round may differ from LIS by id.
+        let mut by_id = ids.clone();
+        by_id.sort();
+        let mut scan_ids = absorbed_ids(&mut Greedy::lis(1));
+        scan_ids.sort();
+        assert_eq!(by_id, scan_ids);
+        assert_ne!(ids, absorbed_ids(&mut Greedy::lis(1)));
+        assert_eq!(seeded(3).to_config()["seed"], 3);
+    }
+
+    #[test]
+    fn test_node_capacities_limit_forwarding() {
+        // Nodes 0 and 1 both send to node 2, which can receive one packet per round.
+        let mut network = Network::from_adj_lists(vec![vec![2], vec![2], vec![3], vec![]]);
+        let node_capacities = serde_json::json!({ "receive": [2, 2, 1] });
+        network.set_node_capacities(NodeCapacities::from_config(node_capacities).unwrap());
+        let mut factory = PacketFactory::new();
+        for src_id in [0, 1] {
+            network.add_packet(factory.create_packet(vec![src_id, 2, 3], 1, 0), src_id, 2);
+        }
+
+        let mut protocol = Protocol::new_greedy_fifo(2);
+        protocol.forward_packets(&mut network);
+        let loads: Vec<usize> = network.edges().iter().map(|edge| edge.load()).collect();
+        assert_eq!(loads, [0, 1, 1]);
+        protocol.forward_packets(&mut network);
+        let loads: Vec<usize> = network.edges().iter().map(|edge| edge.load()).collect();
+        assert_eq!(loads, [0, 0, 1]);
+    }
+
+    #[test]
+    fn test_fifo_head_of_line_modes() {
+        // Node 0 sends one packet per round, so the second of three packets is held back.
+        let forwarded_ids = |head_of_line| {
+            let mut network = construct_path(2);
+            let send_capacities = serde_json::json!({ "send": 1 });
+            network.set_node_capacities(NodeCapacities::from_config(send_capacities).unwrap());
+            let mut factory = PacketFactory::new();
+            for _ in 0..3 {
+                network.add_packet(factory.create_packet(vec![0, 1], 1, 0), 0, 1);
+            }
+            let mut protocol = Protocol::new(Greedy::fifo(2).with_head_of_line(head_of_line));
+            (0..3)
+                .map(|_| protocol.forward_packets(&mut network)[0].id().0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(forwarded_ids(HeadOfLine::Block), [0, 1, 2]);
+        assert_eq!(forwarded_ids(HeadOfLine::Requeue), [0, 2, 1]);
+
+        let cfg = serde_json::json!({
+            "protocol_name": "greedy_fifo", "capacity": 2, "head_of_line": "requeue"
+        });
+        assert_eq!(Greedy::from_config(cfg.clone()).unwrap().to_config(), cfg);
+    }
+
+    #[test]
+    fn test_edge_capacities() {
+        // Edge (0, 1) forwards two packets per round, and edge (1, 2) only one.
+        let cfg = serde_json::json!({
+            "protocol_name": "greedy_lis",
+            "capacity": 1,
+            "edge_capacities": [{ "edge": [0, 1], "capacity": 2 }],
+        });
+        let mut greedy_cfg = cfg.clone();
+        greedy_cfg["protocol_name"] = json!("greedy");
+        greedy_cfg["priority"] = json!("fifo");
+        for cfg in [cfg, greedy_cfg] {
+            let mut protocol = Protocol::from_config(cfg.clone()).unwrap();
+            assert_eq!(protocol.to_config(), cfg);
+            assert_eq!(protocol.capacity(), 2);
+            let mut network = construct_path(3);
+            assert!(protocol.check_graph_structure(&network).is_ok());
+            let reversed = Network::from_adj_lists(vec![vec![], vec![0]]);
+            assert!(protocol.check_graph_structure(&reversed).is_err());
+            let mut factory = PacketFactory::new();
+            for _ in 0..4 {
+                network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+            }
+            protocol.forward_packets(&mut network);
+            let loads: Vec<usize> = network.edges().iter().map(|edge| edge.load()).collect();
+            assert_eq!(loads, [2, 2]);
+            protocol.forward_packets(&mut network);
+            let loads: Vec<usize> = network.edges().iter().map(|edge| edge.load()).collect();
+            assert_eq!(loads, [0, 3]);
+        }
+    }
+
+    #[test]
+    fn test_buffer_fifo_forwards_earliest_arrival() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut protocol = Greedy::buffer_fifo(1);
+        // The older packet is first in the buffer, but entered it after the younger one.
+        network.set_cur_rd(3);
+        let old = factory.create_packet(vec![0, 1, 2], 1, 1);
+        protocol.add_packet(old, &mut network);
+        network.set_cur_rd(2);
+        let young = factory.create_packet(vec![1, 2], 2, 0);
+        protocol.add_packet(young, &mut network);
+
+        let absorbed = protocol.forward_packets(&mut network);
+        assert_eq!(absorbed.len(), 1);
+        assert_eq!(absorbed[0].injection_rd(), 2);
+        assert_eq!(absorbed[0].last_moved_rd(), 2);
+    }
+
+    #[test]
+    fn test_aliases_configure_greedy() {
+        for (alias, priority) in [("greedy_fifo", "fifo"), ("greedy_lis", "lis")] {
+            let cfg = json!({ "protocol_name": alias, "capacity": 2, "heap": false });
+            let mut greedy = Greedy::from_config(cfg).unwrap();
+            let cfg = json!({ "protocol_name": "greedy", "capacity": 2, "priority": priority });
+            assert_eq!(
+                absorbed_ids(&mut greedy),
+                absorbed_ids(&mut Greedy::from_config(cfg).unwrap())
+            );
+            let cfg = json!({ "protocol_name": alias, "capacity": 2 });
+            assert_eq!(greedy.to_config(), cfg);
+            assert!(Protocol::from_config(cfg)
+                .unwrap()
+                .downcast_ref::<Greedy>()
+                .is_some());
+        }
+
+        let cfg = json!({ "protocol_name": "greedy", "capacity": 1, "priority": "ftg" });
+        let protocol = Protocol::from_config(cfg.clone()).unwrap();
+        assert_eq!(protocol.to_config(), cfg);
+        for cfg in [
+            json!({ "protocol_name": "greedy", "capacity": 1, "priority": "fastest" }),
+            json!({ "protocol_name": "greedy_lis", "capacity": 1, "priority": "lis" }),
+            json!({ "protocol_name": "greedy_lis", "capacity": 1, "head_of_line": "requeue" }),
+            json!({ "protocol_name": "greedy_fifo", "capacity": 1, "heap": true }),
+            json!({ "protocol_name": "greedy_fifo", "capacity": 1, "tie_break": "random" }),
+        ] {
+            assert!(Protocol::from_config(cfg.clone()).is_err(), "{}", cfg);
+        }
+    }
+
+    #[test]
+    fn test_greedy_ffs_forwards_furthest_travelled() {
+        let mut network = construct_path(4);
+        let mut factory = PacketFactory::new();
+        let cfg = json!({ "protocol_name": "greedy", "capacity": 1, "priority": "ffs" });
+        let mut protocol = Protocol::from_config(cfg).unwrap();
+        // The older packet starts at node 2; the younger one has come from node 0.
+        let near = factory.create_packet(vec![2, 3], 1, 0);
+        let far = factory.create_packet(vec![0, 1, 2, 3], 2, 2);
+        protocol.add_packet(near, &mut network);
+        protocol.add_packet(far, &mut network);
+
+        let absorbed = protocol.forward_packets(&mut network);
+        assert_eq!(absorbed.len(), 1);
+        assert_eq!(absorbed[0].injection_rd(), 2);
+    }
+
+    #[test]
+    fn test_random_order_is_seeded() {
+        let seeded = |seed| {
+            let cfg = json!({ "protocol_name": "greedy_random", "capacity": 1, "seed": seed });
+            Greedy::from_config(cfg).unwrap()
+        };
+        let ids = absorbed_ids(&mut seeded(3));
+        assert!(!ids.is_empty());
+        assert_eq!(ids, absorbed_ids(&mut seeded(3)));
+        assert_ne!(ids, absorbed_ids(&mut seeded(4)));
+        assert_ne!(ids, absorbed_ids(&mut Greedy::lis(1)));
+        assert_eq!(seeded(3).to_config()["seed"], 3);
+        let unseeded = Greedy::random(1, None);
+        let GreedyOrder::Random { seed } = unseeded.order() else {
+            panic!("greedy_random must order packets randomly");
+        };
+        assert_eq!(unseeded.to_config()["seed"], seed);
+
+        let cfg =
+            json!({ "protocol_name": "greedy", "capacity": 1, "priority": "random", "seed": 3 });
+        let mut greedy = Greedy::from_config(cfg.clone()).unwrap();
+        assert_eq!(ids, absorbed_ids(&mut greedy));
+        assert_eq!(greedy.to_config(), cfg);
+        for cfg in [
+            json!({ "protocol_name": "greedy_random", "capacity": 1, "seed": -1 }),
+            json!({ "protocol_name": "greedy_random", "capacity": 1, "heap": true }),
+            json!({ "protocol_name": "greedy_random", "capacity": 1, "tie_break": "random" }),
+        ] {
+            assert!(Greedy::from_config(cfg.clone()).is_err(), "{}", cfg);
+        }
+    }
+}
@@ -1,86 +1,211 @@
 //! This module contains implementations of protocols, which handle how packets are forwarded and
 //! how packets are added to the network.
 
-use self::greedy::{GreedyFIFO, GreedyLIS};
-use self::oed::OEDWithSwap;
-use crate::config::{CfgErrorMsg, Configurable};
-use crate::network::Network;
+use self::backpressure::Backpressure;
+use self::fair::{DeficitRoundRobin, RoundRobin};
+use self::greedy::Greedy;
+use self::node_scheduling::LongestQueueFirst;
+use self::oed::{OEDOnDAG, OEDWithSwap};
+use self::per_buffer::PerBufferProtocol;
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
 use crate::packet::Packet;
-use serde_json::{Map, Value};
+use serde_json::Value;
+use std::any::Any;
 
+pub mod admission;
+pub mod backpressure;
+pub mod conformance;
+pub mod fair;
 pub mod greedy;
+pub mod node_scheduling;
 pub mod oed;
+pub mod per_buffer;
 pub mod priority;
 
-/// Interface for forwarding protocol behaviors.
-// TODO: add check_graph_structure() to ensure that the graph we are using works with the given
-// protocol.
-#[derive(Clone)]
-pub enum Protocol {
-    OEDWithSwap(OEDWithSwap),
-    GreedyFIFO(GreedyFIFO),
-    GreedyLIS(GreedyLIS),
-}
+/// A forwarding protocol, behind which any `ProtocolTrait` implementation can be used.
+pub struct Protocol(Box<dyn BoxedProtocol>);
 
 impl Protocol {
+    /// Get a `Protocol` forwarding with the given implementation, which may be defined outside
+    /// this crate.
+    pub fn new<P>(protocol: P) -> Self
+    where
+        P: ProtocolTrait + Configurable + Clone + Send + 'static,
+    {
+        Protocol(Box::new(protocol))
+    }
+
     /// Get a new `OEDWithSwap` protocol.
     pub fn new_oed_with_swap() -> Self {
-        Self::OEDWithSwap(OEDWithSwap::new())
+        Self::new(OEDWithSwap::new())
     }
 
-    /// Get a new `GreedyFIFO` protocol.
+    /// Get a new greedy FIFO protocol.
     pub fn new_greedy_fifo(capacity: usize) -> Self {
-        Self::GreedyFIFO(GreedyFIFO::new(capacity))
+        Self::new(Greedy::fifo(capacity))
+    }
+
+    /// Get the implementation behind this protocol, if it is a `P`.
+    pub fn downcast_ref<P: 'static>(&self) -> Option<&P> {
+        self.0.as_any().downcast_ref()
+    }
+
+    /// Get the `Description` of every protocol.
+    pub fn descriptions() -> Vec<Description> {
+        PROTOCOLS.iter().map(|(describe, _)| describe()).collect()
     }
 
     /// Add a packet to the given `Network` via `ProtocolTrait`.
     pub fn add_packet(&mut self, p: Packet, network: &mut Network) {
-        match self {
-            Self::GreedyFIFO(protocol) => protocol.add_packet(p, network),
-            Self::OEDWithSwap(protocol) => protocol.add_packet(p, network),
-            Self::GreedyLIS(protocol) => protocol.add_packet(p, network),
-        }
+        self.0.add_packet(p, network)
+    }
+
+    /// Put back packets which could not be absorbed via `ProtocolTrait`.
+    pub fn restore_packets(&mut self, packets: Vec<Packet>, network: &mut Network) {
+        self.0.restore_packets(packets, network)
+    }
+
+    /// Returns whether this protocol chooses the packets to forward buffer by buffer, so that it
+    /// can be used on some buffers of a `PerBufferProtocol` (see `ProtocolTrait`).
+    pub fn forwards_by_buffer(&self) -> bool {
+        self.0.forwards_by_buffer()
+    }
+
+    /// Remove the packets this protocol forwards from the given buffer this round, with their path
+    /// indices advanced, if it chooses them buffer by buffer (see `forwards_by_buffer`).
+    fn buffer_packets_to_fwd(
+        &mut self,
+        from_id: NodeID,
+        to_id: NodeID,
+        network: &mut Network,
+    ) -> Option<Vec<Packet>> {
+        self.0.buffer_packets_to_fwd(from_id, to_id, network)
+    }
+
+    /// Returns whether this protocol enforces the network's node capacities (see
+    /// `Network::set_node_capacities`).
+    pub fn supports_node_capacities(&self) -> bool {
+        self.0.supports_node_capacities()
+    }
+
+    /// Get the most packets this protocol forwards from a buffer per round (see `ProtocolTrait`).
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    /// Check that the given `Network` has the shape this protocol requires via `ProtocolTrait`.
+    /// Returns an error describing the mismatch if not.
+    pub fn check_graph_structure(&self, network: &Network) -> Result<(), CfgErrorMsg> {
+        self.0.check_graph_structure(network)
+    }
+
+    /// Get the state this protocol keeps outside the network, if any, via `ProtocolTrait`.
+    pub fn save_state(&self) -> Option<Value> {
+        self.0.save_state()
+    }
+
+    /// Restore the state saved by `save_state` via `ProtocolTrait`.
+    pub fn load_state(&mut self, state: Value) -> Result<(), CfgErrorMsg> {
+        self.0.load_state(state)
     }
 
     /// Forward packets on the given `Network` via `ProtocolTrait`.
     pub fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
-        match self {
-            Self::OEDWithSwap(protocol) => protocol.forward_packets(network),
-            Self::GreedyFIFO(protocol) => protocol.forward_packets(network),
-            Self::GreedyLIS(protocol) => protocol.forward_packets(network),
-        }
+        network.reset_ports();
+        self.0.forward_packets(network)
     }
 }
 
-const PROTOCOL_NAME_KEY: &str = "protocol_name";
+impl Clone for Protocol {
+    fn clone(&self) -> Self {
+        Protocol(self.0.clone_box())
+    }
+}
+
+pub const PROTOCOL_NAME_KEY: &str = "protocol_name";
 const OED_WITH_SWAP_NAME: &str = "oed_swap";
+const OED_ON_DAG_NAME: &str = "oed_swap_dag";
 const GREEDY_FIFO_NAME: &str = "greedy_fifo";
 const GREEDY_LIS_NAME: &str = "greedy_lis";
+const GREEDY_BUFFER_FIFO_NAME: &str = "greedy_buffer_fifo";
+const GREEDY_RANDOM_NAME: &str = "greedy_random";
+const GREEDY_NAME: &str = "greedy";
+const ROUND_ROBIN_NAME: &str = "round_robin";
+const DEFICIT_ROUND_ROBIN_NAME: &str = "deficit_round_robin";
+const PER_BUFFER_NAME: &str = "per_buffer";
+const LONGEST_QUEUE_FIRST_NAME: &str = "lqf";
+const BACKPRESSURE_NAME: &str = "backpressure";
 const CAPACITY_KEY: &str = "capacity";
+const HEAP_KEY: &str = "heap";
+
+type Builder = fn(Value) -> Result<Protocol, CfgErrorMsg>;
+
+// Every built-in protocol, by its description and how to build it from its config.
+const PROTOCOLS: [(fn() -> Description, Builder); 12] = [
+    (OEDWithSwap::describe, build::<OEDWithSwap>),
+    (OEDOnDAG::describe, build::<OEDOnDAG>),
+    (Greedy::describe_fifo, build::<Greedy>),
+    (Greedy::describe_lis, build::<Greedy>),
+    (Greedy::describe_buffer_fifo, build::<Greedy>),
+    (Greedy::describe_random, build::<Greedy>),
+    (Greedy::describe, build::<Greedy>),
+    (RoundRobin::describe, build::<RoundRobin>),
+    (DeficitRoundRobin::describe, build::<DeficitRoundRobin>),
+    (PerBufferProtocol::describe, build::<PerBufferProtocol>),
+    (LongestQueueFirst::describe, build::<LongestQueueFirst>),
+    (Backpressure::describe, build::<Backpressure>),
+];
+
+fn build<P>(config: Value) -> Result<Protocol, CfgErrorMsg>
+where
+    P: ProtocolTrait + Configurable + Clone + Send + 'static,
+{
+    Ok(Protocol::new(P::from_config(config)?))
+}
 
 impl Configurable for Protocol {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
-        // TODO: correct error msg
-        let map: Map<String, Value> = config.as_object().unwrap().clone();
-        let protocol_name = match map.get(PROTOCOL_NAME_KEY) {
+        let protocol_name = match config.get(PROTOCOL_NAME_KEY) {
             Some(Value::String(name)) => Ok(name),
             _ => Err(String::from("No protocol name found.")),
         }?;
-
-        match &protocol_name[..] {
-            OED_WITH_SWAP_NAME => Ok(Self::OEDWithSwap(OEDWithSwap::from_config(config).unwrap())),
-            GREEDY_FIFO_NAME => Ok(Self::GreedyFIFO(GreedyFIFO::from_config(config).unwrap())),
-            GREEDY_LIS_NAME => Ok(Self::GreedyLIS(GreedyLIS::from_config(config).unwrap())),
-            _ => Err(format!("No protocol with name {}.", protocol_name)),
+        match PROTOCOLS
+            .iter()
+            .find(|(describe, _)| describe().name == protocol_name)
+        {
+            Some((_, build)) => build(config),
+            None => Err(format!("No protocol with name {}.", protocol_name)),
         }
     }
 
     fn to_config(&self) -> Value {
-        match self {
-            Self::OEDWithSwap(p) => p.to_config(),
-            Self::GreedyLIS(p) => p.to_config(),
-            Self::GreedyFIFO(p) => p.to_config(),
-        }
+        self.0.to_config()
+    }
+}
+
+// The object-safe view of a protocol which `Protocol` holds, implemented for every configurable
+// `ProtocolTrait` implementation.
+trait BoxedProtocol: ProtocolTrait + Send {
+    fn clone_box(&self) -> Box<dyn BoxedProtocol>;
+    fn to_config(&self) -> Value;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<P> BoxedProtocol for P
+where
+    P: ProtocolTrait + Configurable + Clone + Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn BoxedProtocol> {
+        Box::new(self.clone())
+    }
+
+    fn to_config(&self) -> Value {
+        Configurable::to_config(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
@@ -90,10 +215,185 @@ pub trait ProtocolTrait {
     fn add_packet(&mut self, p: Packet, network: &mut Network) {
         let cur = p.cur_node().unwrap();
         let next = p.next_node().unwrap();
-        let eb = network.get_edgebuffer_mut(cur, next).unwrap();
-        eb.buffer.push(p);
+        network.add_packet(p, cur, next);
     }
 
     /// Forward all `Packet`s on the network. Returns absorbed `Packet`s.
     fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet>;
+
+    /// Put back packets which `forward_packets` absorbed but which could not leave the network
+    /// this round, with their path indices restored, so that they are forwarded before the packets
+    /// which were behind them. By default, they go back to the front of their buffers in the given
+    /// order (see `Network::restore_packets`).
+    fn restore_packets(&mut self, packets: Vec<Packet>, network: &mut Network) {
+        network.restore_packets(packets);
+    }
+
+    /// Returns whether this protocol chooses the packets to forward buffer by buffer, via
+    /// `buffer_packets_to_fwd`. Protocols which move packets by swapping them between buffers, or
+    /// which choose between a node's buffers, don't.
+    fn forwards_by_buffer(&self) -> bool {
+        false
+    }
+
+    /// Remove the packets this protocol forwards from the given buffer this round, with their path
+    /// indices advanced, if it chooses them buffer by buffer (see `forwards_by_buffer`).
+    fn buffer_packets_to_fwd(
+        &mut self,
+        _from_id: NodeID,
+        _to_id: NodeID,
+        _network: &mut Network,
+    ) -> Option<Vec<Packet>> {
+        None
+    }
+
+    /// Returns whether this protocol enforces the network's node capacities (see
+    /// `Network::set_node_capacities`), which protocols forwarding through
+    /// `Network::edgebuffer_to_forward` do.
+    fn supports_node_capacities(&self) -> bool {
+        true
+    }
+
+    /// Get the most packets this protocol forwards from a buffer per round: 1 by default.
+    fn capacity(&self) -> usize {
+        1
+    }
+
+    /// Check that the given `Network` has the shape this protocol requires, such as a path,
+    /// before it is run on it. Most protocols run on any network, as by default.
+    fn check_graph_structure(&self, _network: &Network) -> Result<(), CfgErrorMsg> {
+        Ok(())
+    }
+
+    /// Get the state this protocol keeps between rounds outside the network, such as round-robin
+    /// pointers, deficit counters, or random number generators, so that a checkpoint can restore
+    /// it with `load_state`. Returns `None` if it keeps none, as by default.
+    fn save_state(&self) -> Option<Value> {
+        None
+    }
+
+    /// Restore the state saved by `save_state` into this protocol, which should have been built
+    /// from the same config. Returns an error if the state is invalid, or if the protocol keeps no
+    /// state, as by default.
+    fn load_state(&mut self, _state: Value) -> Result<(), CfgErrorMsg> {
+        Err(String::from("This protocol keeps no state to load."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+    use serde_json::json;
+
+    // A protocol defined outside the built-ins, which absorbs every packet at once.
+    #[derive(Clone)]
+    struct Teleport;
+
+    impl ProtocolTrait for Teleport {
+        fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+            let edges = network.forwarding_order();
+            edges
+                .into_iter()
+                .filter_map(|(from_id, to_id)| network.take_buffer(from_id, to_id))
+                .flatten()
+                .collect()
+        }
+    }
+
+    impl Configurable for Teleport {
+        fn from_config(_config: Value) -> Result<Self, CfgErrorMsg> {
+            Ok(Teleport)
+        }
+
+        fn to_config(&self) -> Value {
+            json!({ PROTOCOL_NAME_KEY: "teleport" })
+        }
+    }
+
+    #[test]
+    fn test_protocol_wraps_any_implementation() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let protocol = Protocol::new(Teleport);
+        let mut clone = protocol.clone();
+        clone.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), &mut network);
+        assert_eq!(clone.forward_packets(&mut network).len(), 1);
+        assert_eq!(clone.to_config(), json!({ "protocol_name": "teleport" }));
+        assert_eq!(clone.capacity(), 1);
+        assert!(!clone.forwards_by_buffer());
+        assert!(protocol.downcast_ref::<Teleport>().is_some());
+        assert!(protocol.downcast_ref::<Greedy>().is_none());
+        assert!(Protocol::from_config(clone.to_config()).is_err());
+    }
+
+    #[test]
+    fn test_described_example_configs_are_valid() {
+        for description in Protocol::descriptions() {
+            let config = description.example_config();
+            let protocol = Protocol::from_config(config.clone()).unwrap();
+            assert_eq!(protocol.to_config()[PROTOCOL_NAME_KEY], description.name);
+            for key in description.keys {
+                let mut config = config.clone();
+                config[key.key] = key.example;
+                assert!(Protocol::from_config(config).is_ok(), "{}", key.key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_loaded_state_resumes_protocol() {
+        let configs = [
+            json!({ "protocol_name": "round_robin", "capacity": 1 }),
+            json!({ "protocol_name": "deficit_round_robin", "capacity": 1, "weights": [0.5, 2.0] }),
+            json!({ "protocol_name": "greedy_random", "capacity": 1, "seed": 2 }),
+            json!({
+                "protocol_name": "greedy_lis", "capacity": 1, "tie_break": "random", "seed": 2
+            }),
+            json!({
+                "protocol_name": "per_buffer",
+                "default": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "overrides": [{
+                    "edge": [2, 3],
+                    "protocol": { "protocol_name": "greedy_random", "capacity": 1, "seed": 2 },
+                }],
+            }),
+        ];
+        // Inject packets from every node to the end of the path, so buffers hold several flows.
+        let run = |protocol: &mut Protocol,
+                   network: &mut Network,
+                   factory: &mut PacketFactory,
+                   rds: std::ops::Range<usize>| {
+            let mut absorbed = Vec::new();
+            for rd in rds {
+                for src_id in 0..3 {
+                    let p = factory.create_packet((src_id..4).collect(), rd, 0);
+                    protocol.add_packet(p, network);
+                }
+                absorbed.extend(protocol.forward_packets(network).iter().map(Packet::id));
+            }
+            absorbed
+        };
+        for config in configs {
+            let mut network = construct_path(4);
+            let mut factory = PacketFactory::new();
+            let mut protocol = Protocol::from_config(config.clone()).unwrap();
+            run(&mut protocol, &mut network, &mut factory, 1..3);
+            let state = protocol.save_state().unwrap();
+            let mut loaded = Protocol::from_config(config.clone()).unwrap();
+            loaded.load_state(state.clone()).unwrap();
+            assert_eq!(loaded.save_state(), Some(state), "{}", config);
+
+            let (mut loaded_network, mut loaded_factory) = (network.clone(), factory.clone());
+            let expected = run(&mut protocol, &mut network, &mut factory, 3..10);
+            let resumed = run(&mut loaded, &mut loaded_network, &mut loaded_factory, 3..10);
+            assert_eq!(resumed, expected, "{}", config);
+        }
+        let mut stateless =
+            Protocol::from_config(json!({ "protocol_name": "greedy_fifo", "capacity": 1 }))
+                .unwrap();
+        assert!(stateless.save_state().is_none());
+        assert!(stateless.load_state(json!(null)).is_err());
+    }
 }
@@ -0,0 +1,201 @@
+//! This module contains node-level schedulers: protocols in which each node shares a per-round
+//! bandwidth between its outgoing buffers, rather than each buffer forwarding up to its own
+//! capacity. A scheduler chooses, one packet at a time, which of a node's buffers to serve next.
+
+use super::{LONGEST_QUEUE_FIRST_NAME, PROTOCOL_NAME_KEY};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::Packet;
+use crate::protocol::ProtocolTrait;
+use serde_json::{json, Map, Number, Value};
+
+const BANDWIDTH_KEY: &str = "bandwidth";
+
+/// Forward up to `bandwidth` packets from each node per round, each from the head of the outgoing
+/// buffer chosen by `next_buffer`, which is given the node's outgoing buffers (by to-node id, in
+/// the network's forwarding order) with their current loads, and returns the index of a nonempty
+/// one to serve. Buffers held back by the network's node capacities are treated as empty. Returns
+/// the absorbed packets.
+pub fn forward_by_node<F>(
+    network: &mut Network,
+    bandwidth: usize,
+    mut next_buffer: F,
+) -> Vec<Packet>
+where
+    F: FnMut(&[(NodeID, usize)]) -> usize,
+{
+    // Each node's outgoing buffers with their loads, with nodes in order of their first buffer.
+    let mut nodes: Vec<(NodeID, Vec<(NodeID, usize)>)> = Vec::new();
+    let mut node_idxs = vec![None; network.get_num_nodes()];
+    for (from_id, to_id) in network.forwarding_order() {
+        let load = network.get_edgebuffer(from_id, to_id).unwrap().buffer.len();
+        let node_idx = *node_idxs[from_id].get_or_insert_with(|| {
+            nodes.push((from_id, Vec::new()));
+            nodes.len() - 1
+        });
+        nodes[node_idx].1.push((to_id, load));
+    }
+
+    let mut packets_to_fwd = Vec::new();
+    for (from_id, mut buffers) in nodes {
+        // Buffers held back don't use up the node's bandwidth.
+        let mut num_forwarded = 0;
+        while num_forwarded < bandwidth && buffers.iter().any(|(_, load)| *load > 0) {
+            let buffer_idx = next_buffer(&buffers);
+            let (to_id, load) = &mut buffers[buffer_idx];
+            let (eb, num_to_fwd) = network.edgebuffer_to_forward(from_id, *to_id, 1);
+            if num_to_fwd == 0 {
+                *load = 0;
+                continue;
+            }
+            let mut p = eb.buffer.remove(0);
+            p.increment_path_idx();
+            packets_to_fwd.push(p);
+            *load -= 1;
+            num_forwarded += 1;
+        }
+    }
+
+    let mut absorbed = Vec::new();
+    for p in packets_to_fwd {
+        if !p.should_be_absorbed() {
+            let cur = p.cur_node().unwrap();
+            let next = p.next_node().unwrap();
+            network.add_packet(p, cur, next);
+        } else {
+            absorbed.push(p);
+        }
+    }
+    absorbed
+}
+
+/// The longest-queue-first scheduler: each node forwards up to `bandwidth` packets per round,
+/// each from whichever of its outgoing buffers is currently the most loaded (ties going to the
+/// buffer first in the forwarding order), in FIFO order within a buffer.
+#[derive(Clone)]
+pub struct LongestQueueFirst {
+    bandwidth: usize,
+}
+
+impl LongestQueueFirst {
+    /// Get a new `LongestQueueFirst` protocol with the given per-node bandwidth.
+    pub fn new(bandwidth: usize) -> Self {
+        LongestQueueFirst { bandwidth }
+    }
+
+    /// Get the most packets each node forwards per round.
+    pub fn bandwidth(&self) -> usize {
+        self.bandwidth
+    }
+}
+
+impl ProtocolTrait for LongestQueueFirst {
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        forward_by_node(network, self.bandwidth, |buffers| {
+            // The first of the most loaded buffers.
+            let max_load = buffers.iter().map(|(_, load)| *load).max().unwrap();
+            buffers
+                .iter()
+                .position(|(_, load)| *load == max_load)
+                .unwrap()
+        })
+    }
+
+    fn capacity(&self) -> usize {
+        self.bandwidth
+    }
+}
+
+impl Describable for LongestQueueFirst {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            LONGEST_QUEUE_FIRST_NAME,
+            "Forwards packets from each node's most loaded buffers, within a per-node bandwidth.",
+        )
+        .required(
+            BANDWIDTH_KEY,
+            "The most packets forwarded from each node per round.",
+            json!(2),
+        )
+    }
+}
+
+impl Configurable for LongestQueueFirst {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.get(BANDWIDTH_KEY).and_then(Value::as_u64) {
+            Some(bandwidth) => Ok(Self::new(bandwidth as usize)),
+            None => Err(String::from("No bandwidth provided.")),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            PROTOCOL_NAME_KEY.to_string(),
+            Value::String(LONGEST_QUEUE_FIRST_NAME.to_string()),
+        );
+        map.insert(
+            BANDWIDTH_KEY.to_string(),
+            Value::Number(Number::from(self.bandwidth)),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_layered;
+    use crate::network::NodeCapacities;
+    use crate::packet::PacketFactory;
+    use crate::protocol::conformance;
+    use crate::protocol::Protocol;
+
+    #[test]
+    fn test_lqf_serves_longest_queues_within_bandwidth() {
+        // Node 0 has buffers to nodes 2 and 3, with 3 and 1 packets.
+        let mut network = construct_layered(2, 2);
+        let mut factory = PacketFactory::new();
+        let mut protocol = Protocol::from_config(json!({
+            "protocol_name": "lqf",
+            "bandwidth": 2,
+        }))
+        .unwrap();
+        for to_id in [2, 2, 2, 3] {
+            let p = factory.create_packet(vec![0, to_id], 1, 0);
+            protocol.add_packet(p, &mut network);
+        }
+        let load =
+            |network: &Network, to_id| network.get_edgebuffer(0, to_id).unwrap().buffer.len();
+
+        // The longer queue is served until it's no longer than the other, then both are.
+        let absorbed = protocol.forward_packets(&mut network);
+        assert_eq!(absorbed.len(), 2);
+        assert_eq!((load(&network, 2), load(&network, 3)), (1, 1));
+        protocol.forward_packets(&mut network);
+        assert_eq!(network.total_load(), 0);
+
+        conformance::check_protocol(LongestQueueFirst::new).unwrap();
+    }
+
+    #[test]
+    fn test_held_back_buffers_leave_bandwidth_to_others() {
+        // Node 0 has 3 packets for node 2, which receives none, and 2 for node 3.
+        let mut network = construct_layered(2, 2);
+        let receive = json!({ "receive": [5, 5, 0, 5] });
+        network.set_node_capacities(NodeCapacities::from_config(receive).unwrap());
+        let mut factory = PacketFactory::new();
+        let mut protocol = Protocol::new(LongestQueueFirst::new(2));
+        for to_id in [2, 2, 2, 3, 3] {
+            let p = factory.create_packet(vec![0, to_id], 1, 0);
+            protocol.add_packet(p, &mut network);
+        }
+
+        // The longest queue is held back, so both packets for node 3 go.
+        let absorbed = protocol.forward_packets(&mut network);
+        assert_eq!(absorbed.len(), 2);
+        assert!(absorbed.iter().all(|p| p.dest_node() == 3));
+        assert_eq!(network.get_edgebuffer(0, 2).unwrap().buffer.len(), 3);
+    }
+}
@@ -1,32 +1,80 @@
 //! This module contains implementations of OED protocols.
 
-use super::{OED_WITH_SWAP_NAME, PROTOCOL_NAME_KEY};
-use crate::config::{CfgErrorMsg, Configurable};
+use super::{OED_ON_DAG_NAME, OED_WITH_SWAP_NAME, PROTOCOL_NAME_KEY};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
 use crate::network::{Network, NodeID};
-use crate::packet::Packet;
+use crate::packet::{Packet, PacketId};
 use crate::protocol::{priority, ProtocolTrait};
+use hashbrown::HashMap;
 use serde_json::{Map, Value};
 
 /// In the OED With Swap protocol, we forward the oldest packet from buffer x if x and x+1 fulfill
 /// the OED criterion or the oldest packet in x is older than the youngest in x+1, and send the
 /// youngest packet in x backward if L(x-1) > 0, x-1 and x fail the OED criterion, and the youngest
 /// packet in x is younger than the oldest in x-1.
+///
+/// The positions of the oldest and youngest packet in each buffer are cached and updated as
+/// packets are added, so a buffer is only rescanned after one of its extreme packets leaves it or
+/// the buffer is modified outside of the protocol.
 #[derive(Clone)]
-pub struct OEDWithSwap;
+pub struct OEDWithSwap {
+    // Cached extrema of the buffer outgoing from each node, indexed by from-ID.
+    extrema: Vec<Option<BufferExtrema>>,
+}
+
+impl Default for OEDWithSwap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl OEDWithSwap {
     pub fn new() -> Self {
-        OEDWithSwap
+        OEDWithSwap {
+            extrema: Vec::new(),
+        }
     }
 }
 
 impl ProtocolTrait for OEDWithSwap {
+    fn add_packet(&mut self, p: Packet, network: &mut Network) {
+        let from_id = p.cur_node().unwrap();
+        let to_id = p.next_node().unwrap();
+        let mut p = p;
+        network.stamp_arrival(&mut p);
+        network.count_packet_move();
+        let buffer = &mut network.get_edgebuffer_mut(from_id, to_id).unwrap().buffer;
+
+        // Keep the cache up to date if it was valid before adding the packet.
+        let cached = self.cached_extrema(from_id, buffer);
+        buffer.push(p);
+        let idx = buffer.len() - 1;
+        let id = buffer[idx].id();
+        let updated = match cached {
+            Some(mut extrema) => {
+                if priority::lis_higher_priority(&buffer[idx], &buffer[extrema.oldest.0]) {
+                    extrema.oldest = (idx, id);
+                }
+                if priority::lis_higher_priority(&buffer[extrema.youngest.0], &buffer[idx]) {
+                    extrema.youngest = (idx, id);
+                }
+                extrema.load += 1;
+                Some(extrema)
+            }
+            None if idx == 0 => Some(BufferExtrema {
+                load: 1,
+                oldest: (0, id),
+                youngest: (0, id),
+            }),
+            None => None,
+        };
+        self.set_cached_extrema(from_id, updated);
+    }
+
     fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
         let mut absorbed = Vec::new();
-        let mut to_fwd_and_bwd = self.get_packets_to_fwd_and_bwd(network);
-        let num_to_fwd_and_bwd = to_fwd_and_bwd.len();
-        for _ in 0..num_to_fwd_and_bwd {
-            let p = to_fwd_and_bwd.remove(0);
+        let to_fwd_and_bwd = self.get_packets_to_fwd_and_bwd(network);
+        for p in to_fwd_and_bwd {
             if !p.should_be_absorbed() {
                 self.add_packet(p, network)
             } else {
@@ -35,33 +83,148 @@ impl ProtocolTrait for OEDWithSwap {
         }
         absorbed
     }
+
+    fn supports_node_capacities(&self) -> bool {
+        false
+    }
+
+    /// The network must be a path of at least one edge, from node 0 up.
+    fn check_graph_structure(&self, network: &Network) -> Result<(), CfgErrorMsg> {
+        let num_nodes = network.get_num_nodes();
+        if num_nodes < 2 {
+            return Err(String::from(
+                "OED with swap runs only on the path preset, but the network has no edges.",
+            ));
+        }
+        if let Some(from_id) =
+            (0..num_nodes - 1).find(|from_id| network.edge(*from_id, from_id + 1).is_none())
+        {
+            return Err(format!(
+                "OED with swap runs only on the path preset, but the network has no edge ({}, {}).",
+                from_id,
+                from_id + 1
+            ));
+        }
+        if let Some(edge) = network
+            .edges()
+            .iter()
+            .find(|edge| edge.to() != edge.from() + 1)
+        {
+            return Err(format!(
+                "OED with swap runs only on the path preset, but the network has edge ({}, {}).",
+                edge.from(),
+                edge.to()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The (index, id) pairs of the oldest and youngest packets in a buffer, along with the buffer's
+/// load when they were computed, which are used to check that the cache is still valid.
+#[derive(Clone, Copy)]
+struct BufferExtrema {
+    load: usize,
+    oldest: (usize, PacketId),
+    youngest: (usize, PacketId),
+}
+
+impl BufferExtrema {
+    /// Find the extrema of the given buffer by scanning it.
+    fn scan(buffer: &[Packet]) -> Option<Self> {
+        let first = buffer.first()?;
+        let mut extrema = BufferExtrema {
+            load: buffer.len(),
+            oldest: (0, first.id()),
+            youngest: (0, first.id()),
+        };
+        for (i, p) in buffer.iter().enumerate().skip(1) {
+            if priority::lis_higher_priority(p, &buffer[extrema.oldest.0]) {
+                extrema.oldest = (i, p.id());
+            }
+            if priority::lis_higher_priority(&buffer[extrema.youngest.0], p) {
+                extrema.youngest = (i, p.id());
+            }
+        }
+        Some(extrema)
+    }
+
+    /// Check whether these extrema still describe the given buffer.
+    fn is_valid(&self, buffer: &[Packet]) -> bool {
+        let matches = |(idx, id): (usize, PacketId)| buffer.get(idx).map(|p| p.id()) == Some(id);
+        buffer.len() == self.load && matches(self.oldest) && matches(self.youngest)
+    }
 }
 
 impl OEDWithSwap {
+    /// Get the cached extrema for the buffer outgoing from `from_id` if they are still valid.
+    fn cached_extrema(&self, from_id: NodeID, buffer: &[Packet]) -> Option<BufferExtrema> {
+        self.extrema
+            .get(from_id)
+            .copied()
+            .flatten()
+            .filter(|extrema| extrema.is_valid(buffer))
+    }
+
+    fn set_cached_extrema(&mut self, from_id: NodeID, extrema: Option<BufferExtrema>) {
+        if self.extrema.len() <= from_id {
+            self.extrema.resize(from_id + 1, None);
+        }
+        self.extrema[from_id] = extrema;
+    }
+
+    /// Get the extrema of the buffer outgoing from `from_id`, rescanning it only if the cache is
+    /// stale. Returns `None` if the buffer is empty.
+    fn buffer_extrema(&mut self, from_id: NodeID, network: &Network) -> Option<BufferExtrema> {
+        let buffer = &network.get_edgebuffer(from_id, from_id + 1).unwrap().buffer;
+        if let Some(extrema) = self.cached_extrema(from_id, buffer) {
+            return Some(extrema);
+        }
+        network.count_buffer_scan();
+        let extrema = BufferExtrema::scan(buffer);
+        self.set_cached_extrema(from_id, extrema);
+        extrema
+    }
+
     /// Get a vector of packets we need to move according to OED with swap.
     fn get_packets_to_fwd_and_bwd(&mut self, network: &mut Network) -> Vec<Packet> {
         let mut result = Vec::new();
         let forward_or_backward = self.get_should_forward_or_backward(network);
-        let num_nodes = network.get_num_nodes();
-        for from_id in 0..num_nodes - 1 {
-            let to_id = from_id + 1;
-            let eb = network.get_edgebuffer_mut(from_id, to_id).unwrap();
-            let load = eb.buffer.len();
-            if load == 0 {
+        for (from_id, (forward, backward)) in forward_or_backward.into_iter().enumerate() {
+            let extrema = match self.buffer_extrema(from_id, network) {
+                Some(extrema) => extrema,
+                None => continue,
+            };
+            if !forward && !backward {
                 continue;
             }
-            let (forward, backward) = forward_or_backward[from_id];
-            if forward {
-                let o_idx = self.highest_priority_idx(from_id, to_id, network).unwrap();
-                let buffer = &mut network.get_edgebuffer_mut(from_id, to_id).unwrap().buffer;
-                let mut p = buffer.remove(o_idx);
+
+            let buffer = &mut network
+                .get_edgebuffer_mut(from_id, from_id + 1)
+                .unwrap()
+                .buffer;
+            let (o_idx, y_idx) = (extrema.oldest.0, extrema.youngest.0);
+            // Remove the packet with the larger index first so the other index stays valid.
+            let (mut fwd_packet, mut bwd_packet) = match (forward, backward) {
+                (true, true) if o_idx > y_idx => {
+                    let o = buffer.remove(o_idx);
+                    (Some(o), Some(buffer.remove(y_idx)))
+                }
+                (true, true) => {
+                    let y = buffer.remove(y_idx);
+                    (Some(buffer.remove(o_idx)), Some(y))
+                }
+                (true, false) => (Some(buffer.remove(o_idx)), None),
+                (false, _) => (None, Some(buffer.remove(y_idx))),
+            };
+            // An extreme packet has left the buffer, so its extrema must be recomputed.
+            self.set_cached_extrema(from_id, None);
+
+            if let Some(mut p) = fwd_packet.take() {
                 p.increment_path_idx();
                 result.push(p);
             }
-            if backward {
-                let y_idx = self.lowest_priority_idx(from_id, to_id, network).unwrap();
-                let buffer = &mut network.get_edgebuffer_mut(from_id, to_id).unwrap().buffer;
-                let mut p = buffer.remove(y_idx);
+            if let Some(mut p) = bwd_packet.take() {
                 p.decrement_path_idx();
                 result.push(p);
             }
@@ -70,98 +233,28 @@ impl OEDWithSwap {
         result
     }
 
-    fn buffer_oldest_youngest_packets<'a>(
-        &self,
-        from_id: NodeID,
-        to_id: NodeID,
-        network: &'a Network,
-    ) -> Option<(&'a Packet, &'a Packet)> {
-        let eb = network.get_edgebuffer(from_id, to_id).unwrap();
-        let load = eb.buffer.len();
-        if load == 0 {
-            return None;
-        }
-
-        let o_idx = self.highest_priority_idx(from_id, to_id, network).unwrap();
-        let y_idx = self.lowest_priority_idx(from_id, to_id, network).unwrap();
-
-        Some((&eb.buffer[o_idx], &eb.buffer[y_idx]))
-    }
-
-    /// Get the index of the highest priority packet (lexicographically, smallest injection rd 
-    /// then smallest id) in the given buffer.
-    fn highest_priority_idx(
-        &self,
-        from_id: NodeID,
-        to_id: NodeID,
-        network: &Network,
-    ) -> Option<usize> {
-        let eb = network.get_edgebuffer(from_id, to_id).unwrap();
-        let load = eb.buffer.len();
-        if load == 0 {
-            return None;
-        }
-
-        let mut hipri_packet = &eb.buffer[0];
-        let mut hipri_idx = 0;
-        for i in 1..eb.buffer.len() {
-            if priority::lis_higher_priority(&eb.buffer[i], hipri_packet) {
-                hipri_packet = &eb.buffer[i];
-                hipri_idx = i;
-            }
-        }
-        Some(hipri_idx)
-    }
-
-    /// Get the index of the lowest priority packet (lexicographically, largest injection rd 
-    /// then largest id) in the given buffer.
-    fn lowest_priority_idx(
-        &self,
-        from_id: NodeID,
-        to_id: NodeID,
-        network: &Network,
-    ) -> Option<usize> {
-        let eb = network.get_edgebuffer(from_id, to_id).unwrap();
-        let load = eb.buffer.len();
-        if load == 0 {
-            return None;
-        }
-
-        let mut lopri_packet = &eb.buffer[0];
-        let mut lopri_idx = 0;
-        for i in 1..eb.buffer.len() {
-            if priority::lis_higher_priority(lopri_packet, &eb.buffer[i]) {
-                lopri_packet = &eb.buffer[i];
-                lopri_idx = i;
-            }
-        }
-        Some(lopri_idx)
-    }
-
     /// Get a vector of `elt = (bool, bool)` indexed by from-ID where `elt.0` is whether the buffer
     /// outgoing from the given from-ID should forward a packet, and `elt.1` is whether this
     /// buffer should send a packet backward.
-    fn get_should_forward_or_backward(&self, network: &mut Network) -> Vec<(bool, bool)> {
+    fn get_should_forward_or_backward(&mut self, network: &Network) -> Vec<(bool, bool)> {
         // Calculate OED criterion for each buffer.
         let mut oed_criterion = Vec::new();
         let num_nodes = network.get_num_nodes();
         for from_id in 0..num_nodes - 2 {
-            let this_eb = network.get_edgebuffer(from_id, from_id + 1).unwrap();
-            let this_load = this_eb.buffer.len();
-            let next_eb = network.get_edgebuffer(from_id + 1, from_id + 2).unwrap();
-            let next_load = next_eb.buffer.len();
+            let this_load = network.edge(from_id, from_id + 1).unwrap().load();
+            let next_load = network.edge(from_id + 1, from_id + 2).unwrap().load();
             let oed = this_load > next_load || (this_load == next_load && this_load % 2 == 1);
             oed_criterion.push(oed);
         }
-        let maybe_last_eb = network.get_edgebuffer(num_nodes - 2, num_nodes - 1);
-        let last_nonempty = maybe_last_eb.unwrap().buffer.len() > 0;
+        let last_nonempty = network.edge(num_nodes - 2, num_nodes - 1).unwrap().load() > 0;
         oed_criterion.push(last_nonempty);
 
         // Get max/min packet refs for each buffer.
         let mut oldest_youngest = Vec::new();
         for from_id in 0..num_nodes - 1 {
-            let to_id = from_id + 1;
-            oldest_youngest.push(self.buffer_oldest_youngest_packets(from_id, to_id, network));
+            let extrema = self.buffer_extrema(from_id, network);
+            let buffer = network.edge(from_id, from_id + 1).unwrap().buffer();
+            oldest_youngest.push(extrema.map(|e| (&buffer[e.oldest.0], &buffer[e.youngest.0])));
         }
 
         // Use OED with Swapping protocol to determine whether each buffer should send a packet
@@ -170,27 +263,26 @@ impl OEDWithSwap {
         let mut result = Vec::new();
         for from_id in 0..num_nodes - 1 {
             let this_oldest_youngest = oldest_youngest[from_id];
-            if this_oldest_youngest == None {
+            if this_oldest_youngest.is_none() {
                 result.push((false, false));
                 continue;
             }
             let (this_oldest, this_youngest) = this_oldest_youngest.unwrap();
 
-            let should_fwd;
-            if from_id != num_nodes - 2 {
+            let should_fwd = if from_id != num_nodes - 2 {
                 let next_oldest_youngest = oldest_youngest[from_id + 1];
-                should_fwd = oed_criterion[from_id]
+                oed_criterion[from_id]
                     || priority::lis_higher_priority(this_oldest, next_oldest_youngest.unwrap().1)
             } else {
                 // Always forward for the last buffer since at this point we know the last buffer
                 // is nonempty.
-                should_fwd = true;
-            }
+                true
+            };
 
             let mut should_bwd = false;
             if from_id != 0 {
                 let prev_oldest_youngest = oldest_youngest[from_id - 1];
-                should_bwd = prev_oldest_youngest != None
+                should_bwd = prev_oldest_youngest.is_some()
                     && (!oed_criterion[from_id - 1]
                         && priority::lis_higher_priority(
                             prev_oldest_youngest.unwrap().0,
@@ -204,9 +296,19 @@ impl OEDWithSwap {
     }
 }
 
+impl Describable for OEDWithSwap {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            OED_WITH_SWAP_NAME,
+            "Odd-even-downhill with swap. Runs only on the path preset.",
+        )
+    }
+}
+
 impl Configurable for OEDWithSwap {
     fn from_config(_config: Value) -> Result<Self, CfgErrorMsg> {
-        Ok(Self)
+        Ok(Self::new())
     }
 
     fn to_config(&self) -> Value {
@@ -219,10 +321,179 @@ impl Configurable for OEDWithSwap {
     }
 }
 
+/// OED with swap generalized to directed acyclic graphs. Rather than comparing buffer x with
+/// buffer x+1, each buffer compares itself with the buffer its oldest packet moves to next (its
+/// "downstream" buffer), and with the buffer its youngest packet came from (its "upstream"
+/// buffer). A buffer forwards its oldest packet if it and its downstream buffer fulfill the OED
+/// criterion, the downstream buffer's youngest packet is younger than it, or it is about to be
+/// absorbed. A buffer sends its youngest packet backward if the upstream buffer is forwarding its
+/// oldest packet into this buffer, the two fail the OED criterion, and the youngest packet is
+/// younger than the upstream buffer's oldest, so that the two packets swap. On the path preset,
+/// this behaves exactly like `OEDWithSwap`.
+#[derive(Clone, Default)]
+pub struct OEDOnDAG;
+
+// The load of a buffer and its oldest and youngest packets.
+type DAGBufferState<'a> = (usize, &'a Packet, &'a Packet);
+// A buffer, and the indices of its packets to send forward and backward, if any.
+type DAGMoves = ((NodeID, NodeID), Option<usize>, Option<usize>);
+
+impl OEDOnDAG {
+    pub fn new() -> Self {
+        OEDOnDAG
+    }
+
+    /// The edge the given packet will be in after it is forwarded, or `None` if it will be
+    /// absorbed.
+    fn next_edge(p: &Packet) -> Option<(NodeID, NodeID)> {
+        let path = p.get_path();
+        let idx = p.get_path_idx();
+        (idx + 2 < path.len()).then(|| (path[idx + 1], path[idx + 2]))
+    }
+
+    /// The edge the given packet was in before its last move, or `None` if it is at its source.
+    fn prev_edge(p: &Packet) -> Option<(NodeID, NodeID)> {
+        let path = p.get_path();
+        let idx = p.get_path_idx();
+        (idx > 0).then(|| (path[idx - 1], path[idx]))
+    }
+
+    fn oed_criterion(this_load: usize, next_load: usize) -> bool {
+        this_load > next_load || (this_load == next_load && this_load % 2 == 1)
+    }
+
+    /// Get the (oldest, youngest) indices of the packets to move from each buffer, if any.
+    fn get_packets_to_fwd_and_bwd(network: &Network) -> Vec<DAGMoves> {
+        let mut states: HashMap<(NodeID, NodeID), DAGBufferState> = HashMap::new();
+        let mut indices = Vec::new();
+        for edge in network.edges() {
+            network.count_buffer_scan();
+            if let Some(extrema) = BufferExtrema::scan(edge.buffer()) {
+                let buffer = edge.buffer();
+                let (oldest, youngest) = (&buffer[extrema.oldest.0], &buffer[extrema.youngest.0]);
+                states.insert(edge.ids(), (extrema.load, oldest, youngest));
+                indices.push((edge.ids(), extrema.oldest.0, extrema.youngest.0));
+            }
+        }
+        let load = |edge: (NodeID, NodeID)| states.get(&edge).map_or(0, |state| state.0);
+
+        let mut result = Vec::new();
+        for (edge, o_idx, y_idx) in indices {
+            let (this_load, oldest, youngest) = states[&edge];
+            let should_fwd = match Self::next_edge(oldest) {
+                None => true,
+                Some(next) => {
+                    Self::oed_criterion(this_load, load(next))
+                        || states.get(&next).is_some_and(|(_, _, next_youngest)| {
+                            priority::lis_higher_priority(oldest, next_youngest)
+                        })
+                }
+            };
+            let should_bwd = o_idx != y_idx
+                && Self::prev_edge(youngest).is_some_and(|prev| {
+                    states
+                        .get(&prev)
+                        .is_some_and(|(prev_load, prev_oldest, _)| {
+                            Self::next_edge(prev_oldest) == Some(edge)
+                                && !Self::oed_criterion(*prev_load, this_load)
+                                && priority::lis_higher_priority(prev_oldest, youngest)
+                        })
+                });
+            if should_fwd || should_bwd {
+                result.push((
+                    edge,
+                    should_fwd.then_some(o_idx),
+                    should_bwd.then_some(y_idx),
+                ));
+            }
+        }
+        // Move buffers in a deterministic order.
+        result.sort_unstable_by_key(|(edge, _, _)| *edge);
+        result
+    }
+}
+
+impl ProtocolTrait for OEDOnDAG {
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        let to_fwd_and_bwd = Self::get_packets_to_fwd_and_bwd(network);
+        let mut moved = Vec::new();
+        for ((from_id, to_id), o_idx, y_idx) in to_fwd_and_bwd {
+            let buffer = &mut network.get_edgebuffer_mut(from_id, to_id).unwrap().buffer;
+            // Remove the packet with the larger index first so the other index stays valid.
+            let mut indices: Vec<(usize, bool)> = o_idx
+                .map(|idx| (idx, true))
+                .into_iter()
+                .chain(y_idx.map(|idx| (idx, false)))
+                .collect();
+            indices.sort_unstable_by_key(|(idx, _)| std::cmp::Reverse(*idx));
+            let mut removed: Vec<(Packet, bool)> = indices
+                .into_iter()
+                .map(|(idx, forward)| (buffer.remove(idx), forward))
+                .collect();
+            removed.sort_by_key(|(_, forward)| !*forward);
+            for (mut p, forward) in removed {
+                match forward {
+                    true => p.increment_path_idx(),
+                    false => p.decrement_path_idx(),
+                }
+                moved.push(p);
+            }
+        }
+
+        let mut absorbed = Vec::new();
+        for p in moved {
+            if !p.should_be_absorbed() {
+                self.add_packet(p, network)
+            } else {
+                absorbed.push(p)
+            };
+        }
+        absorbed
+    }
+
+    fn supports_node_capacities(&self) -> bool {
+        false
+    }
+
+    fn check_graph_structure(&self, network: &Network) -> Result<(), CfgErrorMsg> {
+        match network.topological_levels() {
+            Some(_) => Ok(()),
+            None => Err(String::from(
+                "OED on DAG runs only on acyclic networks, but the network has a cycle.",
+            )),
+        }
+    }
+}
+
+impl Describable for OEDOnDAG {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            OED_ON_DAG_NAME,
+            "Odd-even-downhill with swap on directed acyclic graphs.",
+        )
+    }
+}
+
+impl Configurable for OEDOnDAG {
+    fn from_config(_config: Value) -> Result<Self, CfgErrorMsg> {
+        Ok(Self::new())
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map: Map<String, Value> = Map::new();
+        map.insert(
+            PROTOCOL_NAME_KEY.to_string(),
+            Value::String(OED_ON_DAG_NAME.to_string()),
+        );
+        Value::Object(map)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::OEDWithSwap;
     use super::ProtocolTrait;
+    use super::{OEDOnDAG, OEDWithSwap};
     use crate::network::presets::construct_path;
     use crate::network::Network;
     use crate::packet::{PacketFactory, PacketPath};
@@ -411,4 +682,169 @@ mod tests {
         assert!(b2.contains(&p2_c));
         assert!(b3.contains(&p1_c));
     }
+
+    #[test]
+    fn test_cached_extrema_match_fresh_scan() {
+        // A persistent protocol (with a warm cache) must behave exactly like a fresh one each
+        // round, including when packets are added to the network outside of the protocol.
+        let (mut cached_network, packet_path) = setup_network_and_packet_path();
+        let (mut fresh_network, _) = setup_network_and_packet_path();
+        let mut factory = PacketFactory::new();
+        let mut oed = OEDWithSwap::new();
+
+        for rd in 1..=40 {
+            let src = (rd * 7) % (PATH_LEN - 1);
+            let p = factory.create_packet(packet_path.clone(), rd, src);
+            if rd % 3 == 0 {
+                cached_network.add_packet(p.clone(), src, src + 1);
+            } else {
+                oed.add_packet(p.clone(), &mut cached_network);
+            }
+            fresh_network.add_packet(p, src, src + 1);
+
+            let cached_absorbed: Vec<usize> = oed
+                .forward_packets(&mut cached_network)
+                .iter()
+                .map(|p| p.id().0)
+                .collect();
+            let fresh_absorbed: Vec<usize> = OEDWithSwap::new()
+                .forward_packets(&mut fresh_network)
+                .iter()
+                .map(|p| p.id().0)
+                .collect();
+            assert_eq!(cached_absorbed, fresh_absorbed);
+            for from_id in 0..PATH_LEN - 1 {
+                let ids = |network: &Network| -> Vec<usize> {
+                    let mut ids: Vec<usize> = network
+                        .get_edgebuffer(from_id, from_id + 1)
+                        .unwrap()
+                        .buffer
+                        .iter()
+                        .map(|p| p.id().0)
+                        .collect();
+                    ids.sort();
+                    ids
+                };
+                assert_eq!(ids(&cached_network), ids(&fresh_network));
+            }
+        }
+    }
+
+    fn sorted_ids(network: &Network) -> Vec<((usize, usize), Vec<usize>)> {
+        let mut buffers: Vec<((usize, usize), Vec<usize>)> = network
+            .edges()
+            .into_iter()
+            .map(|edge| {
+                let mut ids: Vec<usize> = edge.iter_packets().map(|p| p.id().0).collect();
+                ids.sort();
+                (edge.ids(), ids)
+            })
+            .collect();
+        buffers.sort();
+        buffers
+    }
+
+    #[test]
+    fn test_dag_matches_path_oed() {
+        let (mut path_network, packet_path) = setup_network_and_packet_path();
+        let (mut dag_network, _) = setup_network_and_packet_path();
+        let mut factory = PacketFactory::new();
+        let mut oed = OEDWithSwap::new();
+        let mut dag_oed = OEDOnDAG::new();
+
+        for rd in 1..=40 {
+            for src in [(rd * 7) % (PATH_LEN - 1), (rd * 3) % (PATH_LEN - 1)] {
+                let p = factory.create_packet(packet_path.clone(), rd, src);
+                oed.add_packet(p.clone(), &mut path_network);
+                dag_oed.add_packet(p, &mut dag_network);
+            }
+            let mut absorbed: Vec<usize> = oed
+                .forward_packets(&mut path_network)
+                .iter()
+                .map(|p| p.id().0)
+                .collect();
+            let mut dag_absorbed: Vec<usize> = dag_oed
+                .forward_packets(&mut dag_network)
+                .iter()
+                .map(|p| p.id().0)
+                .collect();
+            absorbed.sort();
+            dag_absorbed.sort();
+            assert_eq!(absorbed, dag_absorbed);
+            assert_eq!(sorted_ids(&path_network), sorted_ids(&dag_network));
+        }
+    }
+
+    #[test]
+    fn test_dag_forwards_along_packet_paths() {
+        // A diamond 0 -> {1, 2} -> 3, where packets on (0, 1) are compared with (1, 3) and packets
+        // on (0, 2) with (2, 3).
+        let mut network = Network::from_adj_lists(vec![vec![1, 2], vec![3], vec![3], vec![]]);
+        let mut factory = PacketFactory::new();
+        let mut oed = OEDOnDAG::new();
+        //    (0, 1): 0 1   (1, 3): 2 3 4   =>   (0, 1): 1     (1, 3): 3 4 0
+        //    (0, 2): 5     (2, 3): -       =>   (0, 2): -     (2, 3): 5
+        for (path, injection_rd) in [
+            (vec![0, 1, 3], 0),
+            (vec![0, 1, 3], 1),
+            (vec![1, 3], 2),
+            (vec![1, 3], 3),
+            (vec![1, 3], 4),
+            (vec![0, 2, 3], 5),
+        ] {
+            oed.add_packet(factory.create_packet(path, injection_rd, 0), &mut network);
+        }
+        let absorbed = oed.forward_packets(&mut network);
+
+        assert_eq!(absorbed.iter().map(|p| p.id().0).collect::<Vec<_>>(), [2]);
+        assert_eq!(
+            sorted_ids(&network),
+            [
+                ((0, 1), vec![1]),
+                ((0, 2), vec![]),
+                ((1, 3), vec![0, 3, 4]),
+                ((2, 3), vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_graph_structure_checks() {
+        use crate::config::Configurable;
+        use crate::network::presets::construct_layered;
+        use crate::protocol::Protocol;
+        use crate::simulation::recorder::Recorder;
+        use crate::simulation::threshold::Threshold;
+        use crate::simulation::Simulation;
+
+        let oed = Protocol::new_oed_with_swap();
+        assert!(oed.check_graph_structure(&construct_path(PATH_LEN)).is_ok());
+        let mut branched = construct_path(3);
+        branched.add_edgebuffer(0, 2);
+        let err = oed.check_graph_structure(&branched).unwrap_err();
+        assert!(err.contains("edge (0, 2)"));
+        assert!(oed.check_graph_structure(&construct_layered(2, 2)).is_err());
+
+        let dag = Protocol::new(OEDOnDAG::new());
+        assert!(dag.check_graph_structure(&construct_layered(2, 3)).is_ok());
+        let mut cyclic = construct_path(3);
+        cyclic.add_edgebuffer(2, 0);
+        assert!(dag.check_graph_structure(&cyclic).is_err());
+
+        // A simulation fails at construction rather than mid-run.
+        let simulation = |network| {
+            let config = serde_json::json!({ "threshold_name": "timed", "max_rds": 1 });
+            Simulation::try_new(
+                network,
+                Protocol::new_oed_with_swap(),
+                Vec::new(),
+                Threshold::from_config(config).unwrap(),
+                Vec::<Recorder>::new(),
+                String::from("aqt_sim_test_graph_structure"),
+            )
+        };
+        assert!(simulation(construct_path(PATH_LEN)).is_ok());
+        let err = simulation(construct_layered(2, 2)).err().unwrap();
+        assert!(err.starts_with("The protocol doesn't support the network"));
+    }
 }
@@ -0,0 +1,270 @@
+//! This module contains a protocol which dispatches to different protocols buffer by buffer, so
+//! that networks can mix scheduling disciplines.
+
+use super::{Protocol, ProtocolTrait, PER_BUFFER_NAME, PROTOCOL_NAME_KEY};
+use crate::config::{CfgErrorMsg, Configurable, Describable, Description};
+use crate::network::{Network, NodeID};
+use crate::packet::Packet;
+use hashbrown::HashMap;
+use serde_json::{json, Value};
+
+/// Forwards each buffer's packets with the protocol overriding it, if any, and otherwise with the
+/// default protocol, e.g. FIFO on edge (3, 4) and LIS everywhere else. The protocols only choose
+/// which packets leave their own buffers, and every forwarded packet still moves at the same time,
+/// so each protocol behaves on its buffers as it would on a network of its own. Only protocols
+/// which forward buffer by buffer may be used, i.e. not the OED protocols.
+#[derive(Clone)]
+pub struct PerBufferProtocol {
+    default: Box<Protocol>,
+    overrides: HashMap<(NodeID, NodeID), Protocol>,
+}
+
+impl PerBufferProtocol {
+    /// Get a new `PerBufferProtocol` using the given protocols on their buffers, and the default
+    /// protocol on every other buffer.
+    pub fn new(default: Protocol, overrides: Vec<((NodeID, NodeID), Protocol)>) -> Self {
+        for protocol in overrides.iter().map(|(_, p)| p).chain([&default]) {
+            assert!(
+                protocol.forwards_by_buffer(),
+                "Per-buffer protocols must forward buffer by buffer."
+            );
+        }
+        PerBufferProtocol {
+            default: Box::new(default),
+            overrides: overrides.into_iter().collect(),
+        }
+    }
+
+    fn protocol_mut(&mut self, from_id: NodeID, to_id: NodeID) -> &mut Protocol {
+        self.overrides
+            .get_mut(&(from_id, to_id))
+            .unwrap_or(&mut self.default)
+    }
+}
+
+impl ProtocolTrait for PerBufferProtocol {
+    fn add_packet(&mut self, p: Packet, network: &mut Network) {
+        let cur = p.cur_node().unwrap();
+        let next = p.next_node().unwrap();
+        self.protocol_mut(cur, next).add_packet(p, network);
+    }
+
+    fn forward_packets(&mut self, network: &mut Network) -> Vec<Packet> {
+        let mut absorbed = Vec::new();
+        let mut packets_to_fwd = Vec::new();
+        for (from_id, to_id) in network.forwarding_order() {
+            let protocol = self.protocol_mut(from_id, to_id);
+            packets_to_fwd.append(
+                &mut protocol
+                    .buffer_packets_to_fwd(from_id, to_id, network)
+                    .unwrap(),
+            );
+        }
+        for p in packets_to_fwd {
+            if !p.should_be_absorbed() {
+                self.add_packet(p, network)
+            } else {
+                absorbed.push(p);
+            }
+        }
+        absorbed
+    }
+
+    fn restore_packets(&mut self, packets: Vec<Packet>, network: &mut Network) {
+        let mut by_edge: HashMap<(NodeID, NodeID), Vec<Packet>> = HashMap::new();
+        for p in packets {
+            let edge = (p.cur_node().unwrap(), p.next_node().unwrap());
+            by_edge.entry(edge).or_default().push(p);
+        }
+        for ((from_id, to_id), packets) in by_edge {
+            self.protocol_mut(from_id, to_id)
+                .restore_packets(packets, network);
+        }
+    }
+
+    /// Get the most packets any of the protocols forwards from a buffer per round.
+    fn capacity(&self) -> usize {
+        self.overrides
+            .values()
+            .map(Protocol::capacity)
+            .fold(self.default.capacity(), usize::max)
+    }
+
+    fn check_graph_structure(&self, network: &Network) -> Result<(), CfgErrorMsg> {
+        self.overrides
+            .values()
+            .chain([&*self.default])
+            .try_for_each(|protocol| protocol.check_graph_structure(network))
+    }
+
+    /// The default protocol's state, and each overriding protocol's state by edge, leaving out
+    /// protocols which keep none.
+    fn save_state(&self) -> Option<Value> {
+        let mut overrides: Vec<(&(NodeID, NodeID), Value)> = self
+            .overrides
+            .iter()
+            .filter_map(|(edge, protocol)| protocol.save_state().map(|state| (edge, state)))
+            .collect();
+        let default = self.default.save_state();
+        if default.is_none() && overrides.is_empty() {
+            return None;
+        }
+        overrides.sort_unstable_by_key(|(edge, _)| **edge);
+        let overrides: Vec<Value> = overrides
+            .into_iter()
+            .map(|(edge, state)| json!({ EDGE_KEY: [edge.0, edge.1], STATE_KEY: state }))
+            .collect();
+        Some(json!({ DEFAULT_KEY: default, OVERRIDES_KEY: overrides }))
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<(), CfgErrorMsg> {
+        match state.get(DEFAULT_KEY) {
+            Some(Value::Null) | None => (),
+            Some(default) => self.default.load_state(default.clone())?,
+        }
+        let overrides = match state.get(OVERRIDES_KEY) {
+            Some(Value::Array(overrides)) => overrides.clone(),
+            Some(_) => return Err(String::from("Invalid per-buffer protocol state.")),
+            None => Vec::new(),
+        };
+        for saved in overrides {
+            let edge = parse_edge(&saved[EDGE_KEY])?;
+            match self.overrides.get_mut(&edge) {
+                Some(protocol) => protocol.load_state(saved[STATE_KEY].clone())?,
+                None => return Err(format!("No protocol overrides edge {:?}.", edge)),
+            }
+        }
+        Ok(())
+    }
+}
+
+const DEFAULT_KEY: &str = "default";
+const OVERRIDES_KEY: &str = "overrides";
+const EDGE_KEY: &str = "edge";
+const PROTOCOL_KEY: &str = "protocol";
+const STATE_KEY: &str = "state";
+
+impl Describable for PerBufferProtocol {
+    fn describe() -> Description {
+        Description::new(
+            PROTOCOL_NAME_KEY,
+            PER_BUFFER_NAME,
+            "Forwards each buffer with its overriding protocol, or else the default protocol.",
+        )
+        .required(
+            DEFAULT_KEY,
+            "The protocol used on buffers without an override.",
+            json!({ PROTOCOL_NAME_KEY: "greedy_lis", "capacity": 1 }),
+        )
+        .optional(
+            OVERRIDES_KEY,
+            "The buffers to override, each an edge with the protocol used on it.",
+            json!([{
+                EDGE_KEY: [0, 1],
+                PROTOCOL_KEY: { PROTOCOL_NAME_KEY: "greedy_fifo", "capacity": 1 },
+            }]),
+        )
+    }
+}
+
+impl Configurable for PerBufferProtocol {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let check = |protocol: Protocol| match protocol.forwards_by_buffer() {
+            true => Ok(protocol),
+            false => Err(String::from(
+                "Per-buffer protocols must forward buffer by buffer.",
+            )),
+        };
+        let default = match config.get(DEFAULT_KEY) {
+            Some(default_cfg) => check(Protocol::from_config(default_cfg.clone())?),
+            None => Err(String::from("No default protocol provided.")),
+        }?;
+        let override_cfgs = match config.get(OVERRIDES_KEY) {
+            Some(Value::Array(override_cfgs)) => Ok(override_cfgs.clone()),
+            Some(_) => Err(String::from("Overrides must be an array.")),
+            None => Ok(Vec::new()),
+        }?;
+        let mut overrides = Vec::new();
+        for override_cfg in override_cfgs {
+            let edge = parse_edge(&override_cfg[EDGE_KEY])?;
+            let protocol = match override_cfg.get(PROTOCOL_KEY) {
+                Some(protocol_cfg) => check(Protocol::from_config(protocol_cfg.clone())?),
+                None => Err(String::from("Each override needs a protocol.")),
+            }?;
+            overrides.push((edge, protocol));
+        }
+        Ok(Self::new(default, overrides))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut overrides: Vec<_> = self.overrides.iter().collect();
+        overrides.sort_unstable_by_key(|(edge, _)| **edge);
+        let overrides: Vec<Value> = overrides
+            .into_iter()
+            .map(|((from_id, to_id), protocol)| {
+                json!({ EDGE_KEY: [from_id, to_id], PROTOCOL_KEY: protocol.to_config() })
+            })
+            .collect();
+        json!({
+            PROTOCOL_NAME_KEY: PER_BUFFER_NAME,
+            DEFAULT_KEY: self.default.to_config(),
+            OVERRIDES_KEY: overrides,
+        })
+    }
+}
+
+// Parse an override's edge, an array of two node ids.
+fn parse_edge(edge: &Value) -> Result<(NodeID, NodeID), CfgErrorMsg> {
+    match edge.as_array() {
+        Some(ids) if ids.len() == 2 => match (ids[0].as_u64(), ids[1].as_u64()) {
+            (Some(from_id), Some(to_id)) => Ok((from_id as usize, to_id as usize)),
+            _ => Err(String::from("Edge node ids must be integers.")),
+        },
+        _ => Err(String::from(
+            "Each override's edge must be an array of two node ids.",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+    use crate::protocol::greedy::Greedy;
+
+    #[test]
+    fn test_overridden_buffer_uses_its_own_discipline() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        // Each buffer holds a younger packet added before an older one.
+        for src_id in [0, 1] {
+            for injection_rd in [2, 1] {
+                let p = factory.create_packet(vec![src_id, src_id + 1], injection_rd, 0);
+                network.add_packet(p, src_id, src_id + 1);
+            }
+        }
+        let mut protocol = Protocol::new(PerBufferProtocol::new(
+            Protocol::new(Greedy::lis(1)),
+            vec![((0, 1), Protocol::new_greedy_fifo(1))],
+        ));
+        let absorbed = protocol.forward_packets(&mut network);
+        let mut absorbed: Vec<(NodeID, usize)> = absorbed
+            .iter()
+            .map(|p| (p.get_path()[0], p.injection_rd()))
+            .collect();
+        absorbed.sort_unstable();
+        // FIFO on (0, 1) takes the first-added packet, and LIS on (1, 2) the oldest.
+        assert_eq!(absorbed, [(0, 2), (1, 1)]);
+
+        let config = protocol.to_config();
+        assert_eq!(config["overrides"][0]["edge"], json!([0, 1]));
+        assert_eq!(Protocol::from_config(config).unwrap().capacity(), 1);
+        let oed_override = json!({
+            "protocol_name": "per_buffer",
+            "default": { "protocol_name": "greedy_lis", "capacity": 1 },
+            "overrides": [{ "edge": [0, 1], "protocol": { "protocol_name": "oed_swap" } }],
+        });
+        assert!(Protocol::from_config(oed_override).is_err());
+    }
+}
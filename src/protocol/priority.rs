@@ -1,12 +1,238 @@
 //! This module contains functions for comparing packet priorities according to different criteria.
+//!
+//! Each comparator returns how `p` should be ordered relative to `q` when sorting by priority, so
+//! `Ordering::Less` means that `p` has higher priority than `q`. All comparators are total orders:
+//! ties in the primary criterion are broken by LIS, and LIS ties are broken by packet id.
+use crate::config::{CfgErrorMsg, Configurable};
 use crate::protocol::Packet;
+use serde_json::Value;
 use std::cmp::Ordering;
 
 /// Returns whether `p` has higher priority than `q` under LIS.
 pub fn lis_higher_priority(p: &Packet, q: &Packet) -> bool {
-    match p.injection_rd().cmp(&q.injection_rd()) {
-        Ordering::Less => true,
-        Ordering::Equal => p.id() < q.id(),
-        Ordering::Greater => false,
+    lis_cmp(p, q) == Ordering::Less
+}
+
+/// Longest-in-system: earliest injection round first.
+pub fn lis_cmp(p: &Packet, q: &Packet) -> Ordering {
+    p.injection_rd()
+        .cmp(&q.injection_rd())
+        .then_with(|| p.id().cmp(&q.id()))
+}
+
+/// Shortest-in-system: latest injection round first.
+pub fn sis_cmp(p: &Packet, q: &Packet) -> Ordering {
+    q.injection_rd()
+        .cmp(&p.injection_rd())
+        .then_with(|| p.id().cmp(&q.id()))
+}
+
+/// Nearest-to-go: fewest remaining steps to absorption first.
+pub fn ntg_cmp(p: &Packet, q: &Packet) -> Ordering {
+    p.dist_to_go()
+        .cmp(&q.dist_to_go())
+        .then_with(|| lis_cmp(p, q))
+}
+
+/// Furthest-to-go: most remaining steps to absorption first.
+pub fn ftg_cmp(p: &Packet, q: &Packet) -> Ordering {
+    q.dist_to_go()
+        .cmp(&p.dist_to_go())
+        .then_with(|| lis_cmp(p, q))
+}
+
+/// Nearest-from-source: fewest steps travelled from the packet's source first.
+pub fn nfs_cmp(p: &Packet, q: &Packet) -> Ordering {
+    p.get_path_idx()
+        .cmp(&q.get_path_idx())
+        .then_with(|| lis_cmp(p, q))
+}
+
+/// Furthest-from-source: most steps travelled from the packet's source first.
+pub fn ffs_cmp(p: &Packet, q: &Packet) -> Ordering {
+    q.get_path_idx()
+        .cmp(&p.get_path_idx())
+        .then_with(|| lis_cmp(p, q))
+}
+
+/// Slack-to-deadline: smallest slack (deadline minus remaining steps) first. Packets without a
+/// deadline have lower priority than any packet with one.
+pub fn slack_cmp(p: &Packet, q: &Packet) -> Ordering {
+    let slack = |packet: &Packet| {
+        packet
+            .deadline()
+            .map(|deadline| deadline as i64 - packet.dist_to_go() as i64)
+    };
+    match (slack(p), slack(q)) {
+        (Some(p_slack), Some(q_slack)) => p_slack.cmp(&q_slack),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+    .then_with(|| lis_cmp(p, q))
+}
+
+/// Buffer-arrival FIFO: earliest round of entering the current buffer first.
+pub fn buffer_fifo_cmp(p: &Packet, q: &Packet) -> Ordering {
+    p.last_moved_rd()
+        .cmp(&q.last_moved_rd())
+        .then_with(|| lis_cmp(p, q))
+}
+
+/// Class-then-LIS: lowest class first, then LIS within a class.
+pub fn class_lis_cmp(p: &Packet, q: &Packet) -> Ordering {
+    p.class().cmp(&q.class()).then_with(|| lis_cmp(p, q))
+}
+
+/// A priority policy, which can be constructed from its name in a config.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Priority {
+    LIS,
+    SIS,
+    NTG,
+    FTG,
+    NFS,
+    FFS,
+    Slack,
+    ClassLIS,
+    BufferFIFO,
+}
+
+const LIS_NAME: &str = "lis";
+const SIS_NAME: &str = "sis";
+const NTG_NAME: &str = "ntg";
+const FTG_NAME: &str = "ftg";
+const NFS_NAME: &str = "nfs";
+const FFS_NAME: &str = "ffs";
+const SLACK_NAME: &str = "slack";
+const CLASS_LIS_NAME: &str = "class_lis";
+const BUFFER_FIFO_NAME: &str = "buffer_fifo";
+
+impl Priority {
+    /// Get the `Priority` with the given config name.
+    pub fn from_name(name: &str) -> Result<Self, CfgErrorMsg> {
+        match name {
+            LIS_NAME => Ok(Self::LIS),
+            SIS_NAME => Ok(Self::SIS),
+            NTG_NAME => Ok(Self::NTG),
+            FTG_NAME => Ok(Self::FTG),
+            NFS_NAME => Ok(Self::NFS),
+            FFS_NAME => Ok(Self::FFS),
+            SLACK_NAME => Ok(Self::Slack),
+            CLASS_LIS_NAME => Ok(Self::ClassLIS),
+            BUFFER_FIFO_NAME => Ok(Self::BufferFIFO),
+            _ => Err(format!("No priority with name {}.", name)),
+        }
+    }
+
+    /// Get the config name of this `Priority`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LIS => LIS_NAME,
+            Self::SIS => SIS_NAME,
+            Self::NTG => NTG_NAME,
+            Self::FTG => FTG_NAME,
+            Self::NFS => NFS_NAME,
+            Self::FFS => FFS_NAME,
+            Self::Slack => SLACK_NAME,
+            Self::ClassLIS => CLASS_LIS_NAME,
+            Self::BufferFIFO => BUFFER_FIFO_NAME,
+        }
+    }
+
+    /// Get the comparator for this `Priority`.
+    pub fn comparator(&self) -> fn(&Packet, &Packet) -> Ordering {
+        match self {
+            Self::LIS => lis_cmp,
+            Self::SIS => sis_cmp,
+            Self::NTG => ntg_cmp,
+            Self::FTG => ftg_cmp,
+            Self::NFS => nfs_cmp,
+            Self::FFS => ffs_cmp,
+            Self::Slack => slack_cmp,
+            Self::ClassLIS => class_lis_cmp,
+            Self::BufferFIFO => buffer_fifo_cmp,
+        }
+    }
+
+    /// Compare two packets under this `Priority`; `Ordering::Less` means `p` has higher priority.
+    pub fn compare(&self, p: &Packet, q: &Packet) -> Ordering {
+        (self.comparator())(p, q)
+    }
+
+    /// Returns whether `p` has higher priority than `q` under this `Priority`.
+    pub fn higher_priority(&self, p: &Packet, q: &Packet) -> bool {
+        self.compare(p, q) == Ordering::Less
+    }
+}
+
+impl Configurable for Priority {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(name) => Self::from_name(name),
+            None => Err(String::from("Priority must be a string.")),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        Value::String(self.name().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_orderings() {
+        let mut factory = PacketFactory::new();
+        // Injected earlier, one step travelled, two to go.
+        let mut old = factory.create_packet(vec![0, 1, 2, 3], 1, 1);
+        // Injected later, at its source, one step to go.
+        let mut young = factory.create_packet(vec![2, 3], 5, 0);
+
+        assert_eq!(lis_cmp(&old, &young), Ordering::Less);
+        assert_eq!(sis_cmp(&old, &young), Ordering::Greater);
+        assert_eq!(ntg_cmp(&old, &young), Ordering::Greater);
+        assert_eq!(ftg_cmp(&old, &young), Ordering::Less);
+        assert_eq!(nfs_cmp(&old, &young), Ordering::Greater);
+        assert_eq!(ffs_cmp(&old, &young), Ordering::Less);
+
+        // Deadlines: packets with a deadline beat packets without one, then smaller slack wins.
+        young.set_deadline(Some(10));
+        assert_eq!(slack_cmp(&old, &young), Ordering::Greater);
+        old.set_deadline(Some(6));
+        assert_eq!(slack_cmp(&old, &young), Ordering::Less);
+
+        // Classes: lower class first, LIS within a class.
+        assert_eq!(class_lis_cmp(&old, &young), Ordering::Less);
+        old.set_class(1);
+        assert_eq!(class_lis_cmp(&old, &young), Ordering::Greater);
+
+        // Buffer arrival: earliest arrival at the current buffer first, then LIS.
+        assert_eq!(buffer_fifo_cmp(&old, &young), Ordering::Less);
+        old.set_last_moved_rd(6);
+        assert_eq!(buffer_fifo_cmp(&old, &young), Ordering::Greater);
+
+        assert_eq!(lis_cmp(&old, &old), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_priority_names() {
+        for priority in [
+            Priority::LIS,
+            Priority::SIS,
+            Priority::NTG,
+            Priority::FTG,
+            Priority::NFS,
+            Priority::FFS,
+            Priority::Slack,
+            Priority::ClassLIS,
+            Priority::BufferFIFO,
+        ] {
+            assert_eq!(Priority::from_config(priority.to_config()), Ok(priority));
+        }
+        assert!(Priority::from_name("nope").is_err());
     }
 }
@@ -0,0 +1,135 @@
+//! This module contains `Aggregate`, which merges the metrics of the replications of a simulation
+//! config into per-metric means, standard deviations, and confidence intervals.
+
+use crate::simulation::metrics::SimMetrics;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The name of the file to which a replicated config's aggregate is written, in its output path.
+pub const AGGREGATE_FILENAME: &str = "aggregate.json";
+
+// Two-sided 95% critical values of Student's t distribution for 1 to 30 degrees of freedom.
+const T_CRITICAL_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+// The normal critical value, used past 30 degrees of freedom.
+const Z_CRITICAL_95: f64 = 1.960;
+
+/// Statistics of one metric over a set of replications.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+pub struct MetricStats {
+    pub mean: f64,
+    /// The sample standard deviation.
+    pub std_dev: f64,
+    /// The 95% confidence interval for the mean, by Student's t. Unset for a single replication.
+    pub ci95: Option<(f64, f64)>,
+}
+
+impl MetricStats {
+    /// Get the statistics of the given nonempty values.
+    pub fn new(values: &[f64]) -> Self {
+        let n = values.len();
+        let mean = values.iter().sum::<f64>() / n as f64;
+        if n < 2 {
+            return MetricStats {
+                mean,
+                std_dev: 0.0,
+                ci95: None,
+            };
+        }
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        let std_dev = variance.sqrt();
+        let t = T_CRITICAL_95.get(n - 2).copied().unwrap_or(Z_CRITICAL_95);
+        let half_width = t * std_dev / (n as f64).sqrt();
+        MetricStats {
+            mean,
+            std_dev,
+            ci95: Some((mean - half_width, mean + half_width)),
+        }
+    }
+}
+
+/// The aggregate of the metrics of the replications of a simulation config which succeeded.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Aggregate {
+    pub num_replications: usize,
+    pub metrics: BTreeMap<String, MetricStats>,
+}
+
+impl Aggregate {
+    /// Aggregate the given nonempty metrics, one per replication.
+    pub fn new(replications: &[SimMetrics]) -> Self {
+        let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for metrics in replications {
+            if let Value::Object(map) = serde_json::to_value(metrics).unwrap() {
+                for (name, value) in map {
+                    values
+                        .entry(name)
+                        .or_default()
+                        .push(value.as_f64().unwrap());
+                }
+            }
+        }
+        let mean_latencies: Vec<f64> = replications
+            .iter()
+            .filter_map(SimMetrics::mean_latency)
+            .collect();
+        if !mean_latencies.is_empty() {
+            values.insert(String::from("mean_latency"), mean_latencies);
+        }
+        Aggregate {
+            num_replications: replications.len(),
+            metrics: values
+                .into_iter()
+                .map(|(name, values)| (name, MetricStats::new(&values)))
+                .collect(),
+        }
+    }
+
+    /// Write this aggregate to `aggregate.json` in the given directory.
+    pub fn save(&self, output_path: &Path) -> io::Result<()> {
+        fs::create_dir_all(output_path)?;
+        let data = serde_json::to_string_pretty(self).unwrap();
+        fs::write(output_path.join(AGGREGATE_FILENAME), format!("{}\n", data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_of_replications() {
+        let replications: Vec<SimMetrics> = [2, 4, 6]
+            .into_iter()
+            .map(|num_absorbed| SimMetrics {
+                num_rds: 10,
+                num_absorbed,
+                total_latency: 2 * num_absorbed,
+                ..Default::default()
+            })
+            .collect();
+        let aggregate = Aggregate::new(&replications);
+        assert_eq!(aggregate.num_replications, 3);
+
+        let absorbed = aggregate.metrics["num_absorbed"];
+        assert_eq!((absorbed.mean, absorbed.std_dev), (4.0, 2.0));
+        let (low, high) = absorbed.ci95.unwrap();
+        assert!((high - 4.0 - 4.303 * 2.0 / 3f64.sqrt()).abs() < 1e-9);
+        assert!((4.0 - low - (high - 4.0)).abs() < 1e-9);
+
+        let rds = aggregate.metrics["num_rds"];
+        assert_eq!(rds.ci95, Some((10.0, 10.0)));
+        assert_eq!(aggregate.metrics["mean_latency"].mean, 2.0);
+        assert_eq!(
+            Aggregate::new(&replications[..1]).metrics["num_rds"].ci95,
+            None
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! This module contains the `ConservationAuditRecorder`, which checks at every record that no
+//! packet has been duplicated or lost, and writes any discrepancy it finds, with context, to
+//! `output_path/conservation_audit.jsonl`.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::Network;
+use crate::packet::{Packet, PacketId};
+use crate::simulation::metadata::Metadata;
+use crate::simulation::recorder::{
+    RecordedStates, RecorderTrait, WriteErrorMsg, CONSERVATION_AUDIT_NAME, RECORDER_NAME_KEY,
+};
+use crate::simulation::time::SimTime;
+use hashbrown::{HashMap, HashSet};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const AUDIT_FILENAME: &str = "conservation_audit.jsonl";
+
+/// Checks that between consecutive records, `injected - absorbed - dropped` equals the change in
+/// the number of resident packets (those in buffers and injection queues), and that no packet id
+/// appears in two places. Injected packets are those with ids not seen before, absorbed packets
+/// are those reported by the protocol, and dropped packets are counted by
+/// `Network::drop_packet`. Each discrepancy is written as a JSON line with the round, the counts,
+/// and the ids and locations of the packets involved.
+#[derive(Clone)]
+pub struct ConservationAuditRecorder {
+    states: RecordedStates,
+    file_path: Option<PathBuf>,
+    // The ids of the resident packets, the number of resident packets (counting duplicates),
+    // and the number of dropped packets as of the last record.
+    resident_ids: HashSet<PacketId>,
+    num_resident: usize,
+    num_dropped: u64,
+    // The largest packet id seen so far; packet ids are handed out in increasing order.
+    max_id: Option<PacketId>,
+    discrepancies: Vec<Value>,
+    // The metadata added to every discrepancy, if any.
+    metadata: Option<Value>,
+    // The first error hit saving the discrepancies, if any.
+    write_error: Option<WriteErrorMsg>,
+}
+
+impl ConservationAuditRecorder {
+    /// Get a new `ConservationAuditRecorder`.
+    pub fn new() -> Self {
+        ConservationAuditRecorder {
+            states: RecordedStates::Both,
+            file_path: None,
+            resident_ids: HashSet::new(),
+            num_resident: 0,
+            num_dropped: 0,
+            max_id: None,
+            discrepancies: Vec::new(),
+            metadata: None,
+            write_error: None,
+        }
+    }
+
+    fn is_new(&self, id: PacketId) -> bool {
+        self.max_id.is_none_or(|max_id| id > max_id)
+    }
+
+    fn report(&mut self, time: SimTime, kind: &str, details: Value) {
+        let mut discrepancy = json!({
+            "rd": time.round,
+            "prime": time.is_prime(),
+            "kind": kind,
+            "details": details,
+        });
+        if let Some(metadata) = &self.metadata {
+            discrepancy["metadata"] = metadata.clone();
+        }
+        self.discrepancies.push(discrepancy);
+    }
+}
+
+impl Default for ConservationAuditRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Configurable for ConservationAuditRecorder {
+    fn from_config(_config: Value) -> Result<Self, CfgErrorMsg> {
+        Ok(Self::new())
+    }
+
+    fn to_config(&self) -> Value {
+        json!({ RECORDER_NAME_KEY: CONSERVATION_AUDIT_NAME })
+    }
+}
+
+/// The locations of every resident packet in the network, by id.
+fn packet_locations(network: &Network) -> HashMap<PacketId, Vec<String>> {
+    let mut locations: HashMap<PacketId, Vec<String>> = HashMap::new();
+    for edge in network.edges() {
+        for p in edge.iter_packets() {
+            let location = format!("buffer ({}, {})", edge.from(), edge.to());
+            locations.entry(p.id()).or_default().push(location);
+        }
+    }
+    for node_id in network.get_nodes() {
+        for p in network.get_injection_queue(node_id) {
+            let location = format!("injection queue {}", node_id);
+            locations.entry(p.id()).or_default().push(location);
+        }
+    }
+    locations
+}
+
+impl RecorderTrait for ConservationAuditRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        let locations = packet_locations(network);
+        let num_resident: usize = locations.values().map(Vec::len).sum();
+        // At most the number of packets in the network since the last record, so it fits a usize.
+        let num_dropped = (network.num_dropped() - self.num_dropped) as usize;
+        let absorbed_ids: Vec<PacketId> = absorbed
+            .map(|packets| packets.iter().map(|p| p.id()).collect())
+            .unwrap_or_default();
+
+        let mut duplicated: Vec<(&PacketId, &Vec<String>)> = locations
+            .iter()
+            .filter(|(_, id_locations)| id_locations.len() > 1)
+            .collect();
+        duplicated.sort_unstable();
+        for (id, id_locations) in duplicated {
+            let details = json!({ "packet_id": id, "locations": id_locations });
+            self.report(time, "duplicate", details);
+        }
+
+        // Packets which are resident now but weren't at the last record must be new, and packets
+        // absorbed since the last record must have been resident or new.
+        let mut injected: HashSet<PacketId> = locations
+            .keys()
+            .filter(|id| !self.resident_ids.contains(*id))
+            .copied()
+            .collect();
+        injected.extend(absorbed_ids.iter().filter(|id| self.is_new(**id)));
+        let mut reappeared: Vec<PacketId> = injected
+            .iter()
+            .filter(|id| !self.is_new(**id))
+            .copied()
+            .collect();
+        reappeared.sort_unstable();
+        if !reappeared.is_empty() {
+            let reappeared_locations: Vec<Value> = reappeared
+                .iter()
+                .map(|id| json!({ "packet_id": id, "locations": locations.get(id) }))
+                .collect();
+            self.report(time, "reappeared", Value::Array(reappeared_locations));
+        }
+        let mut misabsorbed: Vec<Value> = absorbed_ids
+            .iter()
+            .filter(|id| locations.contains_key(*id))
+            .map(|id| json!({ "packet_id": id, "locations": locations[id] }))
+            .collect();
+        misabsorbed.sort_by_key(|details| details["packet_id"].as_u64());
+        if !misabsorbed.is_empty() {
+            self.report(time, "absorbed_but_resident", Value::Array(misabsorbed));
+        }
+
+        // Packets which left without being absorbed must have been dropped.
+        let absorbed_set: HashSet<PacketId> = absorbed_ids.iter().copied().collect();
+        let mut vanished: Vec<PacketId> = self
+            .resident_ids
+            .iter()
+            .filter(|id| !locations.contains_key(*id) && !absorbed_set.contains(*id))
+            .copied()
+            .collect();
+        vanished.sort_unstable();
+        if vanished.len() != num_dropped {
+            let details = json!({ "vanished_ids": vanished, "dropped": num_dropped });
+            self.report(time, "lost", details);
+        }
+
+        let expected = self.num_resident as i64 + injected.len() as i64
+            - absorbed_ids.len() as i64
+            - num_dropped as i64;
+        if expected != num_resident as i64 {
+            let details = json!({
+                "resident_before": self.num_resident,
+                "injected": injected.len(),
+                "absorbed": absorbed_ids.len(),
+                "dropped": num_dropped,
+                "resident_after": num_resident,
+            });
+            self.report(time, "balance", details);
+        }
+
+        self.max_id = locations
+            .keys()
+            .chain(absorbed_ids.iter())
+            .copied()
+            .chain(self.max_id)
+            .max();
+        self.resident_ids = locations.into_keys().collect();
+        self.num_resident = num_resident;
+        self.num_dropped = network.num_dropped();
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.file_path = Some(dir_path.join(AUDIT_FILENAME));
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.metadata = Some(metadata.to_json());
+    }
+
+    fn close(&mut self) {
+        let file_path = self
+            .file_path
+            .as_ref()
+            .expect("You must set an output path for each recorder.");
+        let data: String = self
+            .discrepancies
+            .iter()
+            .map(|discrepancy| format!("{}\n", discrepancy))
+            .collect();
+        if let Err(err) = fs::write(file_path, data) {
+            let msg = format!(
+                "Failed to save simulation results to {}: {}",
+                file_path.display(),
+                err
+            );
+            self.write_error.get_or_insert(msg);
+        }
+        if !self.discrepancies.is_empty() {
+            eprintln!(
+                "Conservation audit found {} discrepancies; see {}",
+                self.discrepancies.len(),
+                file_path.display()
+            );
+        }
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_audit_flags_duplicates_and_losses() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut recorder = ConservationAuditRecorder::new();
+
+        let p = factory.create_packet(vec![0, 1, 2, 3], 1, 0);
+        network.add_packet(p.clone(), 0, 1);
+        network.add_packet(factory.create_packet(vec![0, 1, 2, 3], 1, 0), 0, 1);
+        recorder.record(SimTime::injection(1), &network, None);
+        assert!(recorder.discrepancies.is_empty());
+
+        // Dropping a packet is accounted for.
+        network.drop_packet((0, 1), |p| p.id().0 == 1);
+        recorder.record(SimTime::forwarding(1), &network, Some(&Vec::new()));
+        assert!(recorder.discrepancies.is_empty());
+
+        // A duplicated packet is flagged, and so is the imbalance it causes.
+        network.add_packet(p, 0, 1);
+        recorder.record(SimTime::injection(2), &network, None);
+        let kinds: Vec<&str> = recorder
+            .discrepancies
+            .iter()
+            .map(|d| d["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["duplicate", "balance"]);
+
+        // A packet which disappears without being absorbed or dropped is lost.
+        recorder.discrepancies.clear();
+        network.take_buffer(0, 1);
+        recorder.record(SimTime::forwarding(2), &network, Some(&Vec::new()));
+        let kinds: Vec<&str> = recorder
+            .discrepancies
+            .iter()
+            .map(|d| d["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["lost", "balance"]);
+    }
+}
@@ -0,0 +1,165 @@
+//! This module contains the `InitialBacklog`, which pre-fills a network's buffers with a synthetic
+//! backlog of packets before the first round, for warm-start experiments.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketFactory, PacketPath};
+use crate::protocol::Protocol;
+use crate::simulation::random::SimRng;
+use serde_json::{Map, Number, Value};
+
+const TRIALS_KEY: &str = "trials";
+const P_KEY: &str = "p";
+const MAX_AGE_KEY: &str = "max_age";
+const SEED_KEY: &str = "seed";
+
+/// Describes a random initial backlog: each buffer gets Binomial(`trials`, `p`) packets, each with
+/// an age drawn uniformly from 1 to `max_age`. Since a packet's age is the number of rounds it has
+/// spent in the system, the simulation then starts in round `max_age + 1`.
+///
+/// A packet in buffer (u, v) follows a path starting with u and v, which is extended by random
+/// out-neighbors (without revisiting nodes) until it reaches a node with no unvisited
+/// out-neighbors. Packets are added through the simulation's protocol, oldest first, so that the
+/// buffers are consistent with the protocol's priority scheme.
+pub struct InitialBacklog {
+    trials: usize,
+    p: f64,
+    max_age: usize,
+    rng: SimRng,
+}
+
+impl InitialBacklog {
+    /// Get a new `InitialBacklog` with the given parameters and seed, or a random seed if none is
+    /// given.
+    pub fn new(trials: usize, p: f64, max_age: usize, seed: Option<u64>) -> Self {
+        InitialBacklog {
+            trials,
+            p,
+            max_age: max_age.max(1),
+            rng: match seed {
+                Some(seed) => SimRng::from_seed(seed),
+                None => SimRng::new(),
+            },
+        }
+    }
+
+    /// Get the round in which a simulation starting with this backlog begins.
+    pub fn first_rd(&self) -> usize {
+        self.max_age + 1
+    }
+
+    /// Generate the backlog and add it to the network via the given protocol. Packets are created
+    /// with the factory's current origin.
+    pub fn fill(
+        &mut self,
+        network: &mut Network,
+        protocol: &mut Protocol,
+        factory: &mut PacketFactory,
+    ) {
+        let mut eb_ids = network.get_edgebuffers();
+        eb_ids.sort_unstable();
+
+        let mut packets: Vec<Packet> = Vec::new();
+        for (from_id, to_id) in eb_ids {
+            let num_packets = (0..self.trials)
+                .filter(|_| self.rng.rand_bool(self.p))
+                .count();
+            for _ in 0..num_packets {
+                let path = self.random_path(network, from_id, to_id);
+                let age = 1 + self.rng.rand_int(self.max_age);
+                packets.push(factory.create_packet(path, self.first_rd() - age, 0));
+            }
+        }
+
+        packets.sort_by_key(|p| p.injection_rd());
+        for p in packets {
+            protocol.add_packet(p, network);
+        }
+    }
+
+    fn random_path(&mut self, network: &Network, from_id: NodeID, to_id: NodeID) -> PacketPath {
+        let mut path = vec![from_id, to_id];
+        loop {
+            let cur_id = *path.last().unwrap();
+            let mut next_ids: Vec<NodeID> = network
+                .get_neighbors(cur_id)
+                .into_iter()
+                .filter(|id| !path.contains(id))
+                .collect();
+            if next_ids.is_empty() {
+                return path;
+            }
+            // Sort since neighbor order isn't deterministic.
+            next_ids.sort_unstable();
+            path.push(next_ids[self.rng.rand_int(next_ids.len())]);
+        }
+    }
+}
+
+impl Configurable for InitialBacklog {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let trials = match config.get(TRIALS_KEY).and_then(Value::as_u64) {
+            Some(trials) => Ok(trials as usize),
+            None => Err(String::from("No backlog trials provided.")),
+        }?;
+        let p = match config.get(P_KEY).and_then(Value::as_f64) {
+            Some(p) if (0.0..=1.0).contains(&p) => Ok(p),
+            _ => Err(String::from("Backlog p must be a probability.")),
+        }?;
+        let max_age = match config.get(MAX_AGE_KEY).and_then(Value::as_u64) {
+            Some(max_age) if max_age > 0 => Ok(max_age as usize),
+            _ => Err(String::from("Backlog max_age must be a positive integer.")),
+        }?;
+        let seed = config.get(SEED_KEY).and_then(Value::as_u64);
+        Ok(Self::new(trials, p, max_age, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            TRIALS_KEY.to_string(),
+            Value::Number(Number::from(self.trials)),
+        );
+        map.insert(
+            P_KEY.to_string(),
+            Value::Number(Number::from_f64(self.p).unwrap()),
+        );
+        map.insert(
+            MAX_AGE_KEY.to_string(),
+            Value::Number(Number::from(self.max_age)),
+        );
+        map.insert(
+            SEED_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+
+    #[test]
+    fn test_backlog_fill() {
+        let mut network = construct_path(5);
+        let mut protocol = Protocol::new_greedy_fifo(1);
+        let mut factory = PacketFactory::new();
+        let mut backlog = InitialBacklog::new(4, 1.0, 3, Some(0));
+        backlog.fill(&mut network, &mut protocol, &mut factory);
+
+        assert_eq!(backlog.first_rd(), 4);
+        for edge in network.edges() {
+            assert_eq!(edge.load(), 4);
+            let injection_rds: Vec<usize> = edge.iter_packets().map(|p| p.injection_rd()).collect();
+            // FIFO buffers hold the oldest packets first.
+            assert!(injection_rds.windows(2).all(|w| w[0] <= w[1]));
+            assert!(injection_rds.iter().all(|rd| (1..=3).contains(rd)));
+            for p in edge.iter_packets() {
+                assert_eq!(p.get_path()[..2], [edge.from(), edge.to()]);
+                assert_eq!(*p.get_path().last().unwrap(), 4);
+            }
+        }
+    }
+}
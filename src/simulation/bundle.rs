@@ -0,0 +1,319 @@
+//! This module contains the `Bundle`, which packs a run's output directory (its saved config,
+//! summary, and recorder outputs) into a single gzipped tar archive with a stable layout, so that
+//! results can be shared as one file and read back on any platform:
+//!
+//! - `manifest.json` holds the bundle format's name and version, and lists every other file in the
+//!   bundle with its size in bytes,
+//! - `config.json` is the run's saved `sim_config.json`,
+//! - `summary.json` is the run's summary, if it has one, and
+//! - `outputs/` holds every other file in the run's directory, at the same relative path.
+//!
+//! Paths in the archive always use `/` as their separator, and entries carry no timestamps or
+//! owners, so bundling the same output twice gives the same archive.
+
+use super::{SIM_CONFIG_FILENAME, SUMMARY_FILENAME};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+/// The name of the bundle's manifest.
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+/// The name of the run's saved config in the bundle.
+pub const CONFIG_FILENAME: &str = "config.json";
+/// The directory of the bundle holding the run's other output files.
+pub const OUTPUTS_DIR: &str = "outputs";
+
+const FORMAT: &str = "aqt_sim_bundle";
+const FORMAT_VERSION: u64 = 1;
+const FORMAT_KEY: &str = "format";
+const VERSION_KEY: &str = "version";
+const FILES_KEY: &str = "files";
+const PATH_KEY: &str = "path";
+const BYTES_KEY: &str = "bytes";
+
+/// A run's output files, by their path in the bundle, along with the bundle's manifest.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bundle {
+    manifest: Value,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Bundle {
+    /// Read the output directory of the run at the given path into a bundle. Returns an error if
+    /// the directory has no saved config.
+    pub fn from_run(run_path: &Path) -> io::Result<Self> {
+        let mut files = BTreeMap::new();
+        for rel_path in list_files(run_path)? {
+            let data = fs::read(run_path.join(&rel_path))?;
+            files.insert(to_bundle_path(&rel_path), data);
+        }
+        if !files.contains_key(CONFIG_FILENAME) {
+            return Err(invalid_data(format!(
+                "No {} in {}, so it isn't a run's output.",
+                SIM_CONFIG_FILENAME,
+                run_path.display()
+            )));
+        }
+        let listing: Vec<Value> = files
+            .iter()
+            .map(|(path, data)| json!({ PATH_KEY: path, BYTES_KEY: data.len() }))
+            .collect();
+        let manifest = json!({
+            FORMAT_KEY: FORMAT,
+            VERSION_KEY: FORMAT_VERSION,
+            FILES_KEY: listing,
+        });
+        Ok(Bundle { manifest, files })
+    }
+
+    /// Read the bundle archive at the given path. Returns an error if it isn't a bundle of a
+    /// supported version, or if its files don't match its manifest.
+    pub fn read(bundle_path: &Path) -> io::Result<Self> {
+        let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(bundle_path)?));
+        let mut files = BTreeMap::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?;
+            let parts: Option<Vec<&str>> = path
+                .components()
+                .map(|component| match component {
+                    Component::Normal(part) => part.to_str(),
+                    _ => None,
+                })
+                .collect();
+            let path = match parts {
+                Some(parts) if !parts.is_empty() => parts.join("/"),
+                _ => {
+                    return Err(invalid_data(format!(
+                        "Bundle entry {} isn't a relative path.",
+                        path.display()
+                    )))
+                }
+            };
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            files.insert(path, data);
+        }
+
+        let manifest: Value = match files.remove(MANIFEST_FILENAME) {
+            Some(data) => serde_json::from_slice(&data)
+                .map_err(|err| invalid_data(format!("Invalid bundle manifest: {}", err)))?,
+            None => return Err(invalid_data(String::from("The bundle has no manifest."))),
+        };
+        if manifest[FORMAT_KEY] != FORMAT {
+            return Err(invalid_data(String::from("The archive isn't a bundle.")));
+        }
+        match manifest[VERSION_KEY].as_u64() {
+            Some(FORMAT_VERSION) => (),
+            version => {
+                return Err(invalid_data(format!(
+                    "Unsupported bundle version {}.",
+                    version.map_or(String::from("(none)"), |v| v.to_string())
+                )))
+            }
+        }
+        let listing = manifest[FILES_KEY].as_array().cloned().unwrap_or_default();
+        if listing.len() != files.len() {
+            return Err(invalid_data(String::from(
+                "The bundle's files don't match its manifest.",
+            )));
+        }
+        for listed in listing {
+            let size = listed[PATH_KEY]
+                .as_str()
+                .and_then(|path| files.get(path))
+                .map(|data| data.len() as u64);
+            if size.is_none() || size != listed[BYTES_KEY].as_u64() {
+                return Err(invalid_data(format!(
+                    "Bundle file {} doesn't match its manifest.",
+                    listed[PATH_KEY]
+                )));
+            }
+        }
+        Ok(Bundle { manifest, files })
+    }
+
+    /// Write this bundle to an archive at the given path, manifest first.
+    pub fn write(&self, bundle_path: &Path) -> io::Result<()> {
+        if let Some(dir_path) = bundle_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(dir_path)?;
+        }
+        let encoder = GzEncoder::new(fs::File::create(bundle_path)?, Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let manifest = format!(
+            "{}\n",
+            serde_json::to_string_pretty(&self.manifest).unwrap()
+        );
+        let entries = std::iter::once((MANIFEST_FILENAME, manifest.as_bytes())).chain(
+            self.files
+                .iter()
+                .map(|(path, data)| (path.as_str(), &data[..])),
+        );
+        for (path, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(0);
+            builder.append_data(&mut header, path, data)?;
+        }
+        builder.into_inner()?.finish()?;
+        Ok(())
+    }
+
+    /// Write the bundled files to a new run output directory at the given path, as they were in
+    /// the bundled run's directory. Returns an error if the directory exists and isn't empty.
+    pub fn extract(&self, run_path: &Path) -> io::Result<()> {
+        if fs::read_dir(run_path).is_ok_and(|mut entries| entries.next().is_some()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists and isn't empty.", run_path.display()),
+            ));
+        }
+        for (path, data) in &self.files {
+            let file_path = run_path.join(from_bundle_path(path));
+            fs::create_dir_all(file_path.parent().unwrap())?;
+            fs::write(file_path, data)?;
+        }
+        Ok(())
+    }
+
+    /// Get the bundle's manifest.
+    pub fn manifest(&self) -> &Value {
+        &self.manifest
+    }
+
+    /// Get the bundled file at the given path in the bundle, if there is one.
+    pub fn file(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(Vec::as_slice)
+    }
+
+    /// Get the paths of the bundled files, with their sizes in bytes, in order.
+    pub fn files(&self) -> Vec<(&str, usize)> {
+        self.files
+            .iter()
+            .map(|(path, data)| (path.as_str(), data.len()))
+            .collect()
+    }
+
+    /// Get the bundled run's saved config.
+    pub fn config(&self) -> Option<Value> {
+        self.json_file(CONFIG_FILENAME)
+    }
+
+    /// Get the bundled run's summary, if it finished.
+    pub fn summary(&self) -> Option<Value> {
+        self.json_file(SUMMARY_FILENAME)
+    }
+
+    fn json_file(&self, path: &str) -> Option<Value> {
+        serde_json::from_slice(self.file(path)?).ok()
+    }
+}
+
+// Get the paths of every file under the given directory, relative to it and joined with `/`,
+// in sorted order.
+fn list_files(dir_path: &Path) -> io::Result<Vec<String>> {
+    let mut rel_paths = Vec::new();
+    let mut dirs = vec![(dir_path.to_path_buf(), String::new())];
+    while let Some((dir_path, prefix)) = dirs.pop() {
+        for entry in fs::read_dir(&dir_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push((entry.path(), format!("{}{}/", prefix, name)));
+            } else if file_type.is_file() {
+                rel_paths.push(format!("{}{}", prefix, name));
+            }
+        }
+    }
+    rel_paths.sort_unstable();
+    Ok(rel_paths)
+}
+
+// Map a path relative to the run's directory to its path in the bundle.
+fn to_bundle_path(rel_path: &str) -> String {
+    match rel_path {
+        SIM_CONFIG_FILENAME => CONFIG_FILENAME.to_string(),
+        SUMMARY_FILENAME => SUMMARY_FILENAME.to_string(),
+        rel_path => format!("{}/{}", OUTPUTS_DIR, rel_path),
+    }
+}
+
+// Map a path in the bundle back to its path relative to the run's directory.
+fn from_bundle_path(path: &str) -> std::path::PathBuf {
+    let rel_path = match path {
+        CONFIG_FILENAME => SIM_CONFIG_FILENAME,
+        path => path
+            .strip_prefix(OUTPUTS_DIR)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .unwrap_or(path),
+    };
+    rel_path.split('/').collect()
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let root = std::env::temp_dir().join("aqt_sim_test_bundle");
+        let _ = fs::remove_dir_all(&root);
+        let run_path = root.join("run");
+        let config = json!({
+            "parallel": false,
+            "simulations": [{
+                "graph_adjacency": { "preset": "path", "num_nodes": 3 },
+                "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "adversary": { "adversary_name": "sd_path_random", "seed": 1 },
+                "threshold": { "threshold_name": "timed", "max_rds": 5 },
+                "recorders": [{ "recorder_name": "buffer_load" }],
+                "output_path": run_path.to_string_lossy(),
+            }],
+        });
+        let sim_config = Config::from_string(config.to_string()).unwrap().sim_configs[0].clone();
+        Simulation::from_config(sim_config).run();
+
+        let bundle = Bundle::from_run(&run_path).unwrap();
+        let paths: Vec<&str> = bundle.files().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(
+            paths,
+            ["config.json", "outputs/buffer_load.csv", "summary.json"]
+        );
+        assert_eq!(bundle.summary().unwrap()["status"], "finished");
+        let bundle_path = root.join("run.tar.gz");
+        bundle.write(&bundle_path).unwrap();
+        let first_archive = fs::read(&bundle_path).unwrap();
+        bundle.write(&bundle_path).unwrap();
+        assert_eq!(fs::read(&bundle_path).unwrap(), first_archive);
+
+        let read = Bundle::read(&bundle_path).unwrap();
+        assert_eq!(read, bundle);
+        let imported_path = root.join("imported");
+        read.extract(&imported_path).unwrap();
+        for filename in [SIM_CONFIG_FILENAME, SUMMARY_FILENAME, "buffer_load.csv"] {
+            assert_eq!(
+                fs::read(imported_path.join(filename)).unwrap(),
+                fs::read(run_path.join(filename)).unwrap()
+            );
+        }
+        assert!(read.extract(&imported_path).is_err());
+        assert!(Bundle::from_run(&root.join("imported").join("missing")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+//! This module contains `CoSimulation`, which runs two `Simulation`s in lockstep, so that their
+//! per-round loads and latencies can be compared round by round.
+
+use super::Simulation;
+use crate::config::Configurable;
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufWriter};
+use std::path::Path;
+
+/// The name of the file to which a `CoSimulation` writes its per-round comparison.
+pub const COSIM_FILENAME: &str = "cosim.csv";
+
+const COSIM_HEADER: &str = "rd,load_a,load_b,load_diff,absorbed_a,absorbed_b,\
+                            mean_latency_a,mean_latency_b,mean_latency_diff";
+
+/// Two simulations with the same adversaries (e.g. the same adversary configs and seeds, with
+/// different protocols or topologies), advanced round by round together. Each round, the total
+/// load of each network, the number of packets absorbed in the round, and their mean latency are
+/// written side by side to `cosim.csv`, with the differences (`b - a`). Once one simulation has
+/// finished, the other keeps running and the finished one's columns are left empty, so rounds stay
+/// aligned even when their thresholds fire at different times. Each simulation still writes its
+/// own output as usual.
+pub struct CoSimulation {
+    a: Simulation,
+    b: Simulation,
+}
+
+impl CoSimulation {
+    /// Get a new `CoSimulation` of the given simulations, which must have the same adversary
+    /// configs, seeds included, so that they're given the same injections.
+    pub fn new(a: Simulation, b: Simulation) -> Result<Self, String> {
+        let adversary_cfgs = |sim: &Simulation| -> Vec<_> {
+            sim.adversaries.iter().map(|a| a.to_config()).collect()
+        };
+        if adversary_cfgs(&a) != adversary_cfgs(&b) {
+            return Err(String::from(
+                "Co-simulated simulations must have the same adversary configs and seeds.",
+            ));
+        }
+        Ok(CoSimulation { a, b })
+    }
+
+    /// Get the two simulations.
+    pub fn simulations(&self) -> (&Simulation, &Simulation) {
+        (&self.a, &self.b)
+    }
+
+    /// Run both simulations until they finish, writing the per-round comparison to `cosim.csv` in
+    /// the given directory, then close them.
+    pub fn run(&mut self, output_path: &Path) -> io::Result<()> {
+        fs::create_dir_all(output_path)?;
+        let mut file = BufWriter::new(File::create(output_path.join(COSIM_FILENAME))?);
+        writeln!(file, "{}", COSIM_HEADER)?;
+        while !(self.a.is_finished() && self.b.is_finished()) {
+            // A simulation with an initial backlog may start past round 1, so step only those
+            // whose next round is the earliest.
+            let rd = [&self.a, &self.b]
+                .iter()
+                .filter(|sim| !sim.is_finished())
+                .map(|sim| sim.rd())
+                .min()
+                .unwrap();
+            let row_a = Self::step_if_at(&mut self.a, rd);
+            let row_b = Self::step_if_at(&mut self.b, rd);
+            let diff = |f: fn(&RoundRow) -> Option<f64>| match (&row_a, &row_b) {
+                (Some(a), Some(b)) => f(b).zip(f(a)).map(|(b, a)| (b - a).to_string()),
+                _ => None,
+            };
+            let column = |row: &Option<RoundRow>, f: fn(&RoundRow) -> Option<String>| {
+                row.as_ref().and_then(f).unwrap_or_default()
+            };
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{},{}",
+                rd,
+                column(&row_a, |r| Some(r.load.to_string())),
+                column(&row_b, |r| Some(r.load.to_string())),
+                diff(|r| Some(r.load as f64)).unwrap_or_default(),
+                column(&row_a, |r| Some(r.num_absorbed.to_string())),
+                column(&row_b, |r| Some(r.num_absorbed.to_string())),
+                column(&row_a, |r| r.mean_latency.map(|l| l.to_string())),
+                column(&row_b, |r| r.mean_latency.map(|l| l.to_string())),
+                diff(|r| r.mean_latency).unwrap_or_default(),
+            )?;
+        }
+        file.flush()?;
+        self.a.close();
+        self.b.close();
+        Ok(())
+    }
+
+    /// Run the given simulation's next round if it's the given round, and get its state after it.
+    fn step_if_at(sim: &mut Simulation, rd: usize) -> Option<RoundRow> {
+        if sim.is_finished() || sim.rd() != rd {
+            return None;
+        }
+        let before = *sim.metrics();
+        sim.step();
+        let after = sim.metrics();
+        let num_absorbed = after.num_absorbed - before.num_absorbed;
+        let total_latency = after.total_latency - before.total_latency;
+        Some(RoundRow {
+            load: sim.network().total_load(),
+            num_absorbed,
+            mean_latency: (num_absorbed > 0).then(|| total_latency as f64 / num_absorbed as f64),
+        })
+    }
+}
+
+// The state of one simulation after a round: its total load, and the number of packets absorbed
+// in the round with their mean latency.
+struct RoundRow {
+    load: usize,
+    num_absorbed: u64,
+    mean_latency: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::SDPathRandomAdversary;
+    use crate::adversary::Adversary;
+    use crate::network::presets::construct_path;
+    use crate::protocol::Protocol;
+    use crate::simulation::threshold::Threshold;
+
+    #[test]
+    fn test_cosimulation_aligns_rounds() {
+        let sim = |protocol: Protocol, max_rds: usize| {
+            let mut sim = Simulation::new(
+                construct_path(4),
+                protocol,
+                vec![Adversary::new(SDPathRandomAdversary::from_seed(3))],
+                Threshold::timed_from_rds(max_rds),
+                Vec::new(),
+                String::new(),
+            );
+            sim.set_metrics_only(true);
+            sim
+        };
+        let output_path = std::env::temp_dir().join("aqt_sim_test_cosimulation");
+        let mut cosim = CoSimulation::new(
+            sim(Protocol::new_greedy_fifo(1), 5),
+            sim(Protocol::new_greedy_fifo(0), 8),
+        )
+        .unwrap();
+        cosim.run(&output_path).unwrap();
+        let (a, b) = cosim.simulations();
+        assert_eq!((a.metrics().num_rds, b.metrics().num_rds), (5, 8));
+
+        let data = fs::read_to_string(output_path.join(COSIM_FILENAME)).unwrap();
+        let rows: Vec<Vec<&str>> = data
+            .lines()
+            .skip(1)
+            .map(|l| l.split(',').collect())
+            .collect();
+        assert_eq!(rows.len(), 8);
+        // Nothing is forwarded in b, so its load is the number of rounds so far.
+        assert_eq!(rows[4][2], "5");
+        assert_eq!(
+            rows[4][3],
+            (5 - a.network().total_load() as i64).to_string()
+        );
+        assert_eq!(rows[7][..4], ["8", "", "8", ""]);
+        fs::remove_dir_all(output_path).unwrap();
+
+        let other_seed = Simulation::new(
+            construct_path(4),
+            Protocol::new_greedy_fifo(1),
+            vec![Adversary::new(SDPathRandomAdversary::from_seed(4))],
+            Threshold::timed_from_rds(5),
+            Vec::new(),
+            String::new(),
+        );
+        assert!(CoSimulation::new(sim(Protocol::new_greedy_fifo(1), 5), other_seed).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! This module contains the `DeadlineViolationRecorder`, which counts packets which missed their
+//! deadlines, per round and per flow, for deadline scheduling experiments.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::Network;
+use crate::packet::{Packet, PacketId};
+use crate::protocol::fair::FlowKey;
+use crate::simulation::metadata::Metadata;
+use crate::simulation::recorder::{
+    RecordedStates, RecorderTrait, WriteErrorMsg, DEADLINE_VIOLATIONS_NAME, RECORDER_NAME_KEY,
+};
+use crate::simulation::time::SimTime;
+use hashbrown::HashMap;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const VIOLATIONS_FILENAME: &str = "deadline_violations.csv";
+const VIOLATIONS_HEADER: &str = "rd,prime,expired,dropped,late,on_time\n";
+const FLOWS_FILENAME: &str = "deadline_flows.csv";
+const FLOWS_HEADER: &str = "flow,num_packets,expired,dropped,late,on_time,violation_rate\n";
+const FLOW_KEY_KEY: &str = "flow_key";
+
+// The fates of packets with deadlines, since the last record or over a flow's whole run.
+#[derive(Clone, Copy, Default)]
+struct Outcomes {
+    expired: usize,
+    dropped: usize,
+    late: usize,
+    on_time: usize,
+}
+
+impl Outcomes {
+    fn num_packets(&self) -> usize {
+        self.expired + self.dropped + self.late + self.on_time
+    }
+}
+
+/// Records what became of the packets with deadlines since each record: those dropped after their
+/// deadline passed (expired, e.g. by the `drop_expired` round step), those dropped before it, and
+/// those absorbed after (late) or by (on time) their deadline. These are written per record to
+/// `deadline_violations.csv`, and once the simulation is closed, per flow (by `FlowKey`) with each
+/// flow's violation rate, the fraction of its finished packets which weren't absorbed on time, to
+/// `deadline_flows.csv`. Packets without deadlines are ignored.
+#[derive(Clone)]
+pub struct DeadlineViolationRecorder {
+    states: RecordedStates,
+    flow_key: FlowKey,
+    dir_path: Option<PathBuf>,
+    rows: String,
+    // The flow and deadline of every resident packet with a deadline as of the last record.
+    last_resident: HashMap<PacketId, (usize, usize)>,
+    flows: BTreeMap<usize, Outcomes>,
+    // The metadata columns prepended to the headers and to every row, if any.
+    header_prefix: String,
+    row_prefix: String,
+    // The first error hit saving the output, if any.
+    write_error: Option<WriteErrorMsg>,
+}
+
+impl DeadlineViolationRecorder {
+    /// Get a new `DeadlineViolationRecorder` grouping packets into flows by the given key.
+    pub fn new(flow_key: FlowKey) -> Self {
+        DeadlineViolationRecorder {
+            states: RecordedStates::Both,
+            flow_key,
+            dir_path: None,
+            rows: VIOLATIONS_HEADER.to_string(),
+            last_resident: HashMap::new(),
+            flows: BTreeMap::new(),
+            header_prefix: String::new(),
+            row_prefix: String::new(),
+            write_error: None,
+        }
+    }
+
+    /// Get the key by which packets are grouped into flows.
+    pub fn flow_key(&self) -> FlowKey {
+        self.flow_key
+    }
+
+    fn save(&mut self, filename: &str, data: &str) {
+        let dir_path = self
+            .dir_path
+            .as_ref()
+            .expect("You must set an output path for each recorder.");
+        let file_path = dir_path.join(filename);
+        if let Err(err) = fs::write(&file_path, data) {
+            let msg = format!(
+                "Failed to save simulation results to {}: {}",
+                file_path.display(),
+                err
+            );
+            self.write_error.get_or_insert(msg);
+        }
+    }
+}
+
+impl RecorderTrait for DeadlineViolationRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        let mut resident = HashMap::new();
+        let queued = network
+            .get_nodes()
+            .into_iter()
+            .flat_map(|node_id| network.get_injection_queue(node_id).iter());
+        let buffered = network
+            .edges()
+            .into_iter()
+            .flat_map(|edge| edge.iter_packets());
+        for p in buffered.chain(queued) {
+            if let Some(deadline) = p.deadline() {
+                resident.insert(p.id(), (self.flow_key.flow_id(p), deadline));
+            }
+        }
+
+        let mut outcomes = Outcomes::default();
+        let mut add = |flows: &mut BTreeMap<usize, Outcomes>,
+                       flow,
+                       field: fn(&mut Outcomes) -> &mut usize| {
+            *field(&mut outcomes) += 1;
+            *field(flows.entry(flow).or_default()) += 1;
+        };
+        for p in absorbed.into_iter().flatten() {
+            self.last_resident.remove(&p.id());
+            if let Some(deadline) = p.deadline() {
+                let flow = self.flow_key.flow_id(p);
+                match time.round > deadline {
+                    true => add(&mut self.flows, flow, |o| &mut o.late),
+                    false => add(&mut self.flows, flow, |o| &mut o.on_time),
+                }
+            }
+        }
+        // Packets which left without being absorbed were dropped.
+        for (id, (flow, deadline)) in &self.last_resident {
+            if resident.contains_key(id) {
+                continue;
+            }
+            match time.round > *deadline {
+                true => add(&mut self.flows, *flow, |o| &mut o.expired),
+                false => add(&mut self.flows, *flow, |o| &mut o.dropped),
+            }
+        }
+        self.last_resident = resident;
+
+        writeln!(
+            self.rows,
+            "{}{},{},{},{},{},{}",
+            self.row_prefix,
+            time.round,
+            if time.is_prime() { 1 } else { 0 },
+            outcomes.expired,
+            outcomes.dropped,
+            outcomes.late,
+            outcomes.on_time
+        )
+        .unwrap();
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.dir_path = Some(dir_path.to_path_buf());
+    }
+
+    fn close(&mut self) {
+        let rows = std::mem::take(&mut self.rows);
+        self.save(VIOLATIONS_FILENAME, &rows);
+        let mut flows = self.header_prefix.clone() + FLOWS_HEADER;
+        for (flow, outcomes) in &self.flows {
+            let violations = outcomes.expired + outcomes.dropped + outcomes.late;
+            writeln!(
+                flows,
+                "{}{},{},{},{},{},{},{}",
+                self.row_prefix,
+                flow,
+                outcomes.num_packets(),
+                outcomes.expired,
+                outcomes.dropped,
+                outcomes.late,
+                outcomes.on_time,
+                violations as f64 / outcomes.num_packets() as f64
+            )
+            .unwrap();
+        }
+        self.save(FLOWS_FILENAME, &flows);
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.header_prefix = metadata.header_prefix();
+        self.row_prefix = metadata.row_prefix();
+        self.rows = self.header_prefix.clone() + VIOLATIONS_HEADER;
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+}
+
+impl Configurable for DeadlineViolationRecorder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.get(FLOW_KEY_KEY) {
+            Some(flow_key) => Ok(Self::new(FlowKey::from_config(flow_key.clone())?)),
+            None => Ok(Self::new(FlowKey::Source)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            RECORDER_NAME_KEY.to_string(),
+            Value::String(DEADLINE_VIOLATIONS_NAME.to_string()),
+        );
+        map.insert(FLOW_KEY_KEY.to_string(), self.flow_key.to_config());
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+    use crate::simulation::recorder::Recorder;
+    use serde_json::json;
+
+    #[test]
+    fn test_deadline_violations_per_round_and_flow() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut recorder = DeadlineViolationRecorder::new(FlowKey::Source);
+        let output_path = std::env::temp_dir().join("aqt_sim_test_deadline_violations");
+        recorder.set_output_path(&output_path);
+
+        // Flow 0 has packets with deadlines 1, 2, and 5 and flow 1 one with deadline 3, plus one
+        // without a deadline, which is ignored.
+        for (src_id, deadline) in [
+            (0, Some(1)),
+            (0, Some(2)),
+            (0, Some(5)),
+            (1, Some(3)),
+            (1, None),
+        ] {
+            let mut p = factory.create_packet(vec![src_id, src_id + 1], 1, 0);
+            p.set_deadline(deadline);
+            network.add_packet(p, src_id, src_id + 1);
+        }
+        recorder.record(SimTime::injection(1), &network, None);
+
+        // In round 2, the first packet has expired and is dropped, the second is dropped before
+        // its deadline, and flow 1's packets are absorbed, one on time.
+        network.set_cur_rd(2);
+        network.drop_packet((0, 1), |p| p.deadline() == Some(1));
+        network.drop_packet((0, 1), |p| p.deadline() == Some(2));
+        let absorbed = vec![
+            network.remove_packet((1, 2), |_| true).unwrap(),
+            network.remove_packet((1, 2), |_| true).unwrap(),
+        ];
+        recorder.record(SimTime::forwarding(2), &network, Some(&absorbed));
+        // In round 6, the last packet is absorbed late.
+        let absorbed = vec![network.remove_packet((0, 1), |_| true).unwrap()];
+        recorder.record(SimTime::forwarding(6), &network, Some(&absorbed));
+        recorder.close();
+
+        let rows = fs::read_to_string(output_path.join(VIOLATIONS_FILENAME)).unwrap();
+        assert_eq!(
+            rows.lines().skip(1).collect::<Vec<_>>(),
+            ["1,0,0,0,0,0", "2,1,1,1,0,1", "6,1,0,0,1,0"]
+        );
+        let flows = fs::read_to_string(output_path.join(FLOWS_FILENAME)).unwrap();
+        assert_eq!(
+            flows.lines().skip(1).collect::<Vec<_>>(),
+            ["0,3,1,1,1,0,1", "1,1,0,0,0,1,0"]
+        );
+        fs::remove_dir_all(output_path).unwrap();
+
+        let config = json!({ "recorder_name": "deadline_violations", "flow_key": "class" });
+        let recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+    }
+}
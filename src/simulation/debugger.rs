@@ -0,0 +1,508 @@
+//! This module contains the `Debugger`, an interactive command loop around a `Simulation` which
+//! can step through rounds, inspect buffers, watch packets, and stop at breakpoints such as
+//! `load(3,4) > 10`. It is run with `aqt_sim debug <config_filepath>`.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::NodeID;
+use crate::packet::{Packet, PacketId};
+use crate::simulation::recorder::Recorder;
+use crate::simulation::time::SimTime;
+use crate::simulation::{SimStatus, Simulation};
+use std::fs;
+use std::io::prelude::*;
+
+/// The number of rounds which can be undone with `back` by default.
+pub const DEFAULT_MAX_SNAPSHOTS: usize = 100;
+
+const PROMPT: &str = "(aqt) ";
+const HELP_MSG: &str = "Commands:
+  step [n]          run n rounds (default 1), stopping early at a breakpoint
+  continue          run until a breakpoint is hit or the simulation finishes
+  back [n]          undo the last n rounds (default 1)
+  buffer <u> <v>    print the packets in buffer (u, v)
+  network           print every buffer
+  dump <file>       save a full snapshot of the network as JSON
+  load <file>       resume from a snapshot saved after forwarding
+  watch <id>        print packet <id>'s position after every step
+  unwatch <id>      stop watching packet <id>
+  break <cond>      stop when <cond> holds after a round, e.g. load(3,4) > 10,
+                    total_load >= 100, or rd == 50
+  delete <i>        delete breakpoint i
+  attach <json>     attach a recorder from its config, e.g.
+                    attach {\"recorder_name\": \"event_trace\"}
+  detach <i>        close and detach recorder i
+  info              list breakpoints, watched packets, and recorders
+  help              print this message
+  quit              close the recorders and exit";
+
+/// A quantity of the simulation which a `Breakpoint` compares against a value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Metric {
+    Load(NodeID, NodeID),
+    TotalLoad,
+    Rd,
+}
+
+/// The comparison a `Breakpoint` makes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+// Two-character operators come first so that e.g. ">=" isn't parsed as ">".
+const COMPARISONS: [(&str, Comparison); 6] = [
+    (">=", Comparison::Ge),
+    ("<=", Comparison::Le),
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    (">", Comparison::Gt),
+    ("<", Comparison::Lt),
+];
+
+/// A condition on the state of the simulation after a round, of the form `<metric> <op> <value>`,
+/// where the metric is `load(u,v)`, `total_load`, or `rd` (the last round run).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Breakpoint {
+    metric: Metric,
+    comparison: Comparison,
+    value: usize,
+    text: String,
+}
+
+impl Breakpoint {
+    /// Parse a `Breakpoint` from a condition such as `load(3,4) > 10`.
+    pub fn parse(text: &str) -> Result<Self, CfgErrorMsg> {
+        let condition: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let (op_idx, op, comparison) = COMPARISONS
+            .iter()
+            .find_map(|(op, comparison)| condition.find(op).map(|idx| (idx, *op, *comparison)))
+            .ok_or(format!("No comparison in breakpoint {}.", text))?;
+        let (lhs, rhs) = (&condition[..op_idx], &condition[op_idx + op.len()..]);
+
+        let metric = match lhs {
+            "total_load" => Metric::TotalLoad,
+            "rd" => Metric::Rd,
+            _ => {
+                let ids: Option<Vec<NodeID>> = lhs
+                    .strip_prefix("load(")
+                    .and_then(|args| args.strip_suffix(')'))
+                    .and_then(|args| args.split(',').map(|id| id.parse().ok()).collect());
+                match ids.as_deref() {
+                    Some([from_id, to_id]) => Metric::Load(*from_id, *to_id),
+                    _ => return Err(format!("Unknown breakpoint metric {}.", lhs)),
+                }
+            }
+        };
+        let value = rhs
+            .parse()
+            .map_err(|_| format!("Breakpoint value {} must be a nonnegative integer.", rhs))?;
+        Ok(Breakpoint {
+            metric,
+            comparison,
+            value,
+            text: text.trim().to_string(),
+        })
+    }
+
+    /// Returns whether the condition holds for the given simulation.
+    pub fn is_hit(&self, simulation: &Simulation) -> bool {
+        let actual = match self.metric {
+            Metric::Load(from_id, to_id) => simulation
+                .network()
+                .edge(from_id, to_id)
+                .map(|edge| edge.load())
+                .unwrap_or(0),
+            Metric::TotalLoad => simulation.network().total_load(),
+            Metric::Rd => simulation.rd() - 1,
+        };
+        match self.comparison {
+            Comparison::Lt => actual < self.value,
+            Comparison::Le => actual <= self.value,
+            Comparison::Gt => actual > self.value,
+            Comparison::Ge => actual >= self.value,
+            Comparison::Eq => actual == self.value,
+            Comparison::Ne => actual != self.value,
+        }
+    }
+}
+
+/// Runs debugger commands against a `Simulation`. Snapshots are enabled so that rounds can be
+/// undone, and a pause-mode threshold stops stepping like a breakpoint; recorders keep recording
+/// as usual, and are not rolled back.
+pub struct Debugger {
+    simulation: Simulation,
+    breakpoints: Vec<Breakpoint>,
+    watched: Vec<PacketId>,
+}
+
+impl Debugger {
+    /// Get a new `Debugger` for the given simulation, which keeps `DEFAULT_MAX_SNAPSHOTS`
+    /// snapshots.
+    pub fn new(simulation: Simulation) -> Self {
+        let mut simulation = simulation;
+        simulation.set_max_snapshots(DEFAULT_MAX_SNAPSHOTS);
+        simulation.set_pausable(true);
+        Debugger {
+            simulation,
+            breakpoints: Vec::new(),
+            watched: Vec::new(),
+        }
+    }
+
+    /// Read commands from `input` and write their results to `output` until `quit` or the end of
+    /// the input, then close the simulation's recorders.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) {
+        let mut lines = input.lines();
+        loop {
+            let _ = write!(output, "{}", PROMPT);
+            let _ = output.flush();
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => break,
+            };
+            if matches!(line.trim(), "quit" | "q") {
+                break;
+            }
+            let result = match self.execute(&line) {
+                Ok(result) => result,
+                Err(msg) => msg,
+            };
+            if !result.is_empty() {
+                let _ = writeln!(output, "{}", result);
+            }
+        }
+        self.simulation.close();
+    }
+
+    /// Execute a single command, returning the text to print.
+    pub fn execute(&mut self, line: &str) -> Result<String, CfgErrorMsg> {
+        let mut words = line.split_whitespace();
+        let cmd = match words.next() {
+            Some(cmd) => cmd,
+            None => return Ok(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+        match cmd {
+            "step" | "s" => {
+                let n = parse_count(&args)?;
+                Ok(self.step(Some(n)))
+            }
+            "continue" | "c" => Ok(self.step(None)),
+            "back" => {
+                let n = parse_count(&args)?;
+                let undone = self.simulation.rollback(n);
+                Ok(format!(
+                    "Undid {} round(s); next round is {}.",
+                    undone,
+                    self.simulation.rd()
+                ))
+            }
+            "buffer" | "b" => match parse_ids(&args)?[..] {
+                [from_id, to_id] => self.buffer(from_id, to_id),
+                _ => Err(String::from("Usage: buffer <u> <v>")),
+            },
+            "network" | "n" => Ok(self.simulation.network().to_string()),
+            "dump" => match args[..] {
+                [file_path] => {
+                    let time = SimTime::forwarding(self.simulation.rd() - 1);
+                    let snapshot = self.simulation.snapshot_to_config(time);
+                    fs::write(file_path, snapshot.to_string())
+                        .map(|_| format!("Saved snapshot to {}.", file_path))
+                        .map_err(|err| format!("Failed to save snapshot to {}: {}", file_path, err))
+                }
+                _ => Err(String::from("Usage: dump <file>")),
+            },
+            "load" => match args[..] {
+                [file_path] => {
+                    let snapshot = fs::read_to_string(file_path)
+                        .map_err(|err| format!("Failed to read {}: {}", file_path, err))?;
+                    let snapshot = serde_json::from_str(&snapshot)
+                        .map_err(|err| format!("Failed to parse {}: {}", file_path, err))?;
+                    self.simulation.load_snapshot(&snapshot)?;
+                    Ok(format!(
+                        "Loaded snapshot from {}; the next round is {}.",
+                        file_path,
+                        self.simulation.rd()
+                    ))
+                }
+                _ => Err(String::from("Usage: load <file>")),
+            },
+            "watch" | "w" => match parse_ids(&args)?[..] {
+                [id] => {
+                    let id = PacketId(id);
+                    if !self.watched.contains(&id) {
+                        self.watched.push(id);
+                    }
+                    Ok(self.describe_packet(id))
+                }
+                _ => Err(String::from("Usage: watch <id>")),
+            },
+            "unwatch" => match parse_ids(&args)?[..] {
+                [id] => {
+                    self.watched
+                        .retain(|watched_id| *watched_id != PacketId(id));
+                    Ok(String::new())
+                }
+                _ => Err(String::from("Usage: unwatch <id>")),
+            },
+            "break" => {
+                let breakpoint = Breakpoint::parse(&args.join(" "))?;
+                if let Metric::Load(from_id, to_id) = breakpoint.metric {
+                    if self.simulation.network().edge(from_id, to_id).is_none() {
+                        return Err(format!("No buffer ({}, {}).", from_id, to_id));
+                    }
+                }
+                self.breakpoints.push(breakpoint);
+                Ok(format!("Breakpoint {} set.", self.breakpoints.len() - 1))
+            }
+            "delete" => match parse_ids(&args)?[..] {
+                [idx] if idx < self.breakpoints.len() => {
+                    self.breakpoints.remove(idx);
+                    Ok(String::new())
+                }
+                _ => Err(String::from("Usage: delete <breakpoint index>")),
+            },
+            "attach" => {
+                let recorder_cfg = serde_json::from_str(&args.join(" "))
+                    .map_err(|_| String::from("Usage: attach <recorder config json>"))?;
+                self.simulation
+                    .attach_recorder(Recorder::from_config(recorder_cfg)?)?;
+                Ok(format!(
+                    "Recorder {} attached.",
+                    self.simulation.recorders().len() - 1
+                ))
+            }
+            "detach" => match parse_ids(&args)?[..] {
+                [idx] => match self.simulation.detach_recorder(idx) {
+                    Some(recorder) => match recorder.write_error() {
+                        Some(msg) => Err(format!("Recorder {} detached, but failed: {}", idx, msg)),
+                        None => Ok(format!("Recorder {} detached.", idx)),
+                    },
+                    None => Err(format!("No recorder {}.", idx)),
+                },
+                _ => Err(String::from("Usage: detach <recorder index>")),
+            },
+            "info" => Ok(self.info()),
+            "help" | "h" => Ok(HELP_MSG.to_string()),
+            _ => Err(format!("Unknown command {}. Type help for a list.", cmd)),
+        }
+    }
+
+    /// Step up to `max_rds` rounds (or until the simulation finishes if `None`), stopping early
+    /// if a breakpoint is hit.
+    fn step(&mut self, max_rds: Option<usize>) -> String {
+        let mut lines = Vec::new();
+        let mut num_rds = 0;
+        while max_rds.is_none_or(|max_rds| num_rds < max_rds) {
+            if !self.simulation.step() {
+                match self.simulation.status() {
+                    SimStatus::Stalled => lines.push(String::from("Simulation stalled.")),
+                    SimStatus::Failed => lines.push(format!(
+                        "Simulation failed: {}",
+                        self.simulation.write_error().unwrap()
+                    )),
+                    _ => lines.push(String::from("Simulation finished.")),
+                }
+                break;
+            }
+            num_rds += 1;
+            if let Some(time) = self.simulation.take_pause() {
+                let state = if time.is_prime() {
+                    "after forwarding"
+                } else {
+                    "after injection"
+                };
+                lines.push(format!(
+                    "Paused by threshold in round {} {}.",
+                    time.round, state
+                ));
+                break;
+            }
+            let hit: Vec<String> = self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .filter(|(_, breakpoint)| breakpoint.is_hit(&self.simulation))
+                .map(|(idx, breakpoint)| format!("Breakpoint {}: {}", idx, breakpoint.text))
+                .collect();
+            if !hit.is_empty() {
+                lines.extend(hit);
+                break;
+            }
+        }
+        lines.push(format!(
+            "Ran to round {} (total load {}).",
+            self.simulation.rd() - 1,
+            self.simulation.network().total_load()
+        ));
+        lines.extend(self.watched.iter().map(|id| self.describe_packet(*id)));
+        lines.join("\n")
+    }
+
+    fn buffer(&self, from_id: NodeID, to_id: NodeID) -> Result<String, CfgErrorMsg> {
+        let edge = self
+            .simulation
+            .network()
+            .edge(from_id, to_id)
+            .ok_or(format!("No buffer ({}, {}).", from_id, to_id))?;
+        let mut lines = vec![format!("({}, {}): load {}", from_id, to_id, edge.load())];
+        lines.extend(edge.buffer().iter().map(describe));
+        Ok(lines.join("\n"))
+    }
+
+    fn describe_packet(&self, id: PacketId) -> String {
+        let network = self.simulation.network();
+        for edge in network.edges() {
+            if let Some(p) = edge.buffer().iter().find(|p| p.id() == id) {
+                return format!(
+                    "In buffer ({}, {}): {}",
+                    edge.from(),
+                    edge.to(),
+                    describe(p)
+                );
+            }
+        }
+        for node_id in network.get_nodes() {
+            let queue = network.get_injection_queue(node_id);
+            if let Some(p) = queue.iter().find(|p| p.id() == id) {
+                return format!("In injection queue at {}: {}", node_id, describe(p));
+            }
+        }
+        format!("Packet {} is not in the network.", id)
+    }
+
+    fn info(&self) -> String {
+        let mut lines = vec![format!("Next round: {}", self.simulation.rd())];
+        lines.extend(
+            self.breakpoints
+                .iter()
+                .enumerate()
+                .map(|(idx, breakpoint)| format!("Breakpoint {}: {}", idx, breakpoint.text)),
+        );
+        if !self.watched.is_empty() {
+            let watched: Vec<String> = self.watched.iter().map(PacketId::to_string).collect();
+            lines.push(format!("Watching: [{}]", watched.join(", ")));
+        }
+        lines.extend(
+            self.simulation
+                .recorders()
+                .iter()
+                .enumerate()
+                .map(|(idx, recorder)| format!("Recorder {}: {}", idx, recorder.to_config())),
+        );
+        lines.join("\n")
+    }
+}
+
+fn describe(p: &Packet) -> String {
+    format!(
+        "packet {} path {:?} at index {}, injected in round {}",
+        p.id(),
+        p.get_path(),
+        p.get_path_idx(),
+        p.injection_rd()
+    )
+}
+
+fn parse_ids(args: &[&str]) -> Result<Vec<usize>, CfgErrorMsg> {
+    args.iter()
+        .map(|arg| {
+            arg.parse()
+                .map_err(|_| format!("{} must be a nonnegative integer.", arg))
+        })
+        .collect()
+}
+
+fn parse_count(args: &[&str]) -> Result<usize, CfgErrorMsg> {
+    match parse_ids(args)?[..] {
+        [] => Ok(1),
+        [n] => Ok(n),
+        _ => Err(String::from("Expected at most one count.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::SDPathRandomBurstyAdversary;
+    use crate::adversary::Adversary;
+    use crate::network::presets::construct_path;
+    use crate::protocol::Protocol;
+    use crate::simulation::threshold::Threshold;
+
+    #[test]
+    fn test_breakpoint_parse() {
+        let breakpoint = Breakpoint::parse("load(3, 4) >= 10").unwrap();
+        assert_eq!(breakpoint.metric, Metric::Load(3, 4));
+        assert_eq!(breakpoint.comparison, Comparison::Ge);
+        assert_eq!(breakpoint.value, 10);
+        assert_eq!(
+            Breakpoint::parse("total_load<5").unwrap().metric,
+            Metric::TotalLoad
+        );
+        assert!(Breakpoint::parse("load(3) > 1").is_err());
+        assert!(Breakpoint::parse("rd 5").is_err());
+    }
+
+    #[test]
+    fn test_continue_stops_at_breakpoint() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_debugger");
+        let simulation = Simulation::new(
+            construct_path(5),
+            Protocol::new_greedy_fifo(1),
+            vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(1, 2))],
+            Threshold::timed_from_rds(100),
+            Vec::new(),
+            output_path.to_string_lossy().to_string(),
+        );
+        let mut debugger = Debugger::new(simulation);
+        debugger.execute("break rd == 20").unwrap();
+        let result = debugger.execute("continue").unwrap();
+        assert!(result.starts_with("Breakpoint 0: rd == 20"));
+        assert_eq!(debugger.simulation.rd(), 21);
+
+        debugger.execute("back 5").unwrap();
+        assert_eq!(debugger.simulation.rd(), 16);
+        assert!(debugger.execute("buffer 0 2").is_err());
+    }
+
+    #[test]
+    fn test_attach_trace_mid_run() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_debugger_attach");
+        let simulation = Simulation::new(
+            construct_path(5),
+            Protocol::new_greedy_fifo(1),
+            vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(1, 2))],
+            Threshold::timed_from_rds(100),
+            Vec::new(),
+            output_path.to_string_lossy().to_string(),
+        );
+        let mut debugger = Debugger::new(simulation);
+        debugger.execute("step 20").unwrap();
+        let expected = debugger.simulation.network().clone();
+        debugger
+            .execute(r#"attach {"recorder_name": "event_trace"}"#)
+            .unwrap();
+        assert!(debugger.info().contains("Recorder 0: "));
+        debugger.execute("step 10").unwrap();
+        let expected_later = debugger.simulation.network().clone();
+        assert_eq!(
+            debugger.execute("detach 0").unwrap(),
+            "Recorder 0 detached."
+        );
+        assert!(debugger.execute("detach 0").is_err());
+
+        // The trace starts from the network as it was when the recorder was attached.
+        let trace = fs::read_to_string(output_path.join("event_trace.jsonl")).unwrap();
+        let replayed = crate::simulation::trace::replay(&trace, 20, true).unwrap();
+        assert_eq!(replayed.to_string(), expected.to_string());
+        let replayed = crate::simulation::trace::replay(&trace, 30, true).unwrap();
+        assert_eq!(replayed.to_string(), expected_later.to_string());
+        fs::remove_dir_all(output_path).unwrap();
+    }
+}
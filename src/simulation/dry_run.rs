@@ -0,0 +1,190 @@
+//! This module contains the dry run, which estimates how much output a simulation will write
+//! before it's launched, by running short probes of it into a scratch directory.
+
+use super::Simulation;
+use crate::config::{Configurable, SimConfig};
+use crate::simulation::threshold::Threshold;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The number of rounds of the longer of a dry run's two probes by default.
+pub const DEFAULT_PROBE_RDS: usize = 100;
+
+/// The estimated size of one output file: a fixed part (e.g. the saved config) and a part which
+/// grows with each round.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FileEstimate {
+    pub filename: String,
+    pub fixed_bytes: f64,
+    pub bytes_per_rd: f64,
+}
+
+/// The estimated output of a simulation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OutputEstimate {
+    /// The round by which the simulation is sure to finish, if its threshold fixes one.
+    pub max_rds: Option<usize>,
+    pub files: Vec<FileEstimate>,
+}
+
+impl OutputEstimate {
+    /// Estimate the output of the simulation with the given config by running it for
+    /// `probe_rds / 2` and then `probe_rds` rounds, writing to the scratch directory
+    /// `scratch_path`, which is removed afterwards, and fitting each output file's size as a linear
+    /// function of the round. A simulation which finishes within the shorter probe is measured
+    /// exactly.
+    pub fn from_probe(
+        sim_config: &SimConfig,
+        probe_rds: usize,
+        scratch_path: &Path,
+    ) -> io::Result<Self> {
+        let max_rds = Threshold::from_config(sim_config.threshold_cfg.clone())
+            .ok()
+            .and_then(|threshold| threshold.max_rds());
+        let short = probe(sim_config, (probe_rds / 2).max(1), scratch_path)?;
+        let long = match short.finished {
+            true => None,
+            false => Some(probe(sim_config, probe_rds.max(2), scratch_path)?),
+        };
+        let files = match long {
+            Some(long) if long.num_rds > short.num_rds => long
+                .sizes
+                .iter()
+                .map(|(filename, long_bytes)| {
+                    let short_bytes = short.sizes.get(filename).copied().unwrap_or(0);
+                    let bytes_per_rd = long_bytes.saturating_sub(short_bytes) as f64
+                        / (long.num_rds - short.num_rds) as f64;
+                    FileEstimate {
+                        filename: filename.clone(),
+                        fixed_bytes: (*long_bytes as f64 - bytes_per_rd * long.num_rds as f64)
+                            .max(0.0),
+                        bytes_per_rd,
+                    }
+                })
+                .collect(),
+            _ => short
+                .sizes
+                .iter()
+                .map(|(filename, bytes)| FileEstimate {
+                    filename: filename.clone(),
+                    fixed_bytes: *bytes as f64,
+                    bytes_per_rd: 0.0,
+                })
+                .collect(),
+        };
+        let max_rds = match short.finished {
+            true => Some(short.num_rds),
+            false => max_rds,
+        };
+        Ok(OutputEstimate { max_rds, files })
+    }
+
+    /// Get the estimated number of bytes written per round, over every file.
+    pub fn bytes_per_rd(&self) -> f64 {
+        self.files.iter().map(|file| file.bytes_per_rd).sum()
+    }
+
+    /// Get the estimated total number of bytes written by the end of the simulation, if its
+    /// threshold fixes how long it runs.
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.max_rds.map(|max_rds| {
+            self.files
+                .iter()
+                .map(|file| file.fixed_bytes + file.bytes_per_rd * max_rds as f64)
+                .sum::<f64>()
+                .round() as u64
+        })
+    }
+}
+
+/// Format a number of bytes with a binary unit, e.g. `512B`, `1.5KiB`, or `2.0GiB`.
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024.0 {
+        return format!("{:.0}B", bytes);
+    }
+    let mut value = bytes / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+// The output of one probe: the size of each file written, the number of rounds run, and whether
+// the simulation finished.
+struct Probe {
+    sizes: BTreeMap<String, u64>,
+    num_rds: usize,
+    finished: bool,
+}
+
+fn probe(sim_config: &SimConfig, rds: usize, scratch_path: &Path) -> io::Result<Probe> {
+    let mut sim_config = sim_config.clone();
+    sim_config.output_path = scratch_path.to_string_lossy().to_string();
+    let mut simulation = Simulation::from_config(sim_config);
+    while (simulation.metrics().num_rds as usize) < rds && simulation.step() {}
+    let num_rds = simulation.metrics().num_rds as usize;
+    let finished = simulation.is_finished();
+    simulation.close();
+    let mut sizes = BTreeMap::new();
+    if scratch_path.exists() {
+        for entry in fs::read_dir(scratch_path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let filename = entry.file_name().to_string_lossy().to_string();
+                sizes.insert(filename, entry.metadata()?.len());
+            }
+        }
+        fs::remove_dir_all(scratch_path)?;
+    }
+    Ok(Probe {
+        sizes,
+        num_rds,
+        finished,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use serde_json::json;
+
+    #[test]
+    fn test_dry_run_projects_output() {
+        let config = json!({
+            "parallel": false,
+            "simulations": [{
+                "graph_adjacency": { "preset": "path", "num_nodes": 5 },
+                "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "adversary": { "adversary_name": "sd_path_random", "seed": 1 },
+                "threshold": { "threshold_name": "timed", "max_rds": 10000 },
+                "recorders": [{ "recorder_name": "buffer_load" }],
+                "output_path": "unused",
+            }],
+        });
+        let config = Config::from_string(config.to_string()).unwrap();
+        let scratch_path = std::env::temp_dir().join("aqt_sim_test_dry_run");
+        let estimate =
+            OutputEstimate::from_probe(&config.sim_configs[0], 40, &scratch_path).unwrap();
+        assert!(!scratch_path.exists());
+        assert_eq!(estimate.max_rds, Some(10000));
+        let buffer_load = estimate
+            .files
+            .iter()
+            .find(|file| file.filename == "buffer_load.csv")
+            .unwrap();
+        // Each state writes a row per edge.
+        assert!(buffer_load.bytes_per_rd > 8.0 * 4.0);
+        let total = estimate.total_bytes().unwrap() as f64;
+        assert!(total > 10000.0 * estimate.bytes_per_rd());
+
+        assert_eq!(format_bytes(512.0), "512B");
+        assert_eq!(format_bytes(1536.0), "1.5KiB");
+        assert_eq!(format_bytes(3.0 * 1024.0 * 1024.0 * 1024.0), "3.0GiB");
+    }
+}
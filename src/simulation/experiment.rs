@@ -0,0 +1,466 @@
+//! This module contains `Experiment`, which bundles a sweep over a base simulation config with a
+//! replication policy, an output layout, and analysis steps, so that a single config produces the
+//! experiment's final aggregated results.
+
+use crate::config::{self, CfgErrorMsg, Configurable};
+use crate::simulation::aggregate::Aggregate;
+use crate::simulation::metrics::SimMetrics;
+use serde_json::{json, Map, Value};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+/// The name of the file to which an experiment's points and analysis are written, in its root.
+pub const EXPERIMENT_FILENAME: &str = "experiment.json";
+/// The name of the file to which an experiment's `table` analysis step writes, in its root.
+pub const RESULTS_FILENAME: &str = "results.csv";
+
+/// A parameter swept by an experiment: a dotted path into the base simulation config (e.g.
+/// `adversary.rate`, or `adversary.0.rate` into an array), and the values it takes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SweepParam {
+    pub param: String,
+    pub values: Vec<Value>,
+}
+
+/// A step of an experiment's analysis, run on the aggregate of each point's runs once they finish.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AnalysisStep {
+    /// Write `results.csv`, with a row per point of its parameters and each metric's mean and 95%
+    /// confidence interval. Every metric is included if none are given.
+    Table { metrics: Option<Vec<String>> },
+    /// Find the point with the smallest (or largest) mean of a metric.
+    Best { metric: String, minimize: bool },
+}
+
+/// A sweep over a base simulation config: every combination of its parameters' values (the last
+/// parameter varying fastest) is a point, run as one simulation or a group of replications under
+/// the experiment's output root. Once they finish, the experiment's analysis steps are run on
+/// each point's aggregate, and written with it to `experiment.json` in the root.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Experiment {
+    name: String,
+    base: Value,
+    sweep: Vec<SweepParam>,
+    replications: Option<usize>,
+    resample_topology: bool,
+    analysis: Vec<AnalysisStep>,
+    output_root: String,
+}
+
+impl Experiment {
+    /// Get the experiment's output root.
+    pub fn output_root(&self) -> &str {
+        &self.output_root
+    }
+
+    /// Get the parameter values of each point, in order.
+    pub fn points(&self) -> Vec<Vec<Value>> {
+        let mut points = vec![Vec::new()];
+        for param in &self.sweep {
+            points = points
+                .into_iter()
+                .flat_map(|point| {
+                    param.values.iter().map(move |value| {
+                        let mut point = point.clone();
+                        point.push(value.clone());
+                        point
+                    })
+                })
+                .collect();
+        }
+        points
+    }
+
+    /// Get the simulation config of each point: the base config with the point's parameter
+    /// values set, and the experiment's replication policy.
+    pub fn sim_configs(&self) -> Result<Vec<Value>, CfgErrorMsg> {
+        self.points()
+            .into_iter()
+            .map(|point| {
+                let mut sim_cfg = self.base.clone();
+                for (param, value) in self.sweep.iter().zip(point) {
+                    set_param(&mut sim_cfg, &param.param, value)?;
+                }
+                if let Some(replications) = self.replications {
+                    sim_cfg[REPLICATIONS_KEY] = Value::from(replications);
+                    sim_cfg[RESAMPLE_TOPOLOGY_KEY] = Value::Bool(self.resample_topology);
+                }
+                Ok(sim_cfg)
+            })
+            .collect()
+    }
+
+    /// Run the analysis on the metrics of the batch's runs, given the run ids of each point, and
+    /// write it to the output root. Runs which failed (with no metrics) are left out.
+    pub fn analyze(
+        &self,
+        point_run_ids: &[Range<usize>],
+        metrics: &[Option<SimMetrics>],
+    ) -> io::Result<()> {
+        let aggregates: Vec<Option<Aggregate>> = point_run_ids
+            .iter()
+            .map(|run_ids| {
+                let runs: Vec<SimMetrics> =
+                    metrics[run_ids.clone()].iter().flatten().copied().collect();
+                (!runs.is_empty()).then(|| Aggregate::new(&runs))
+            })
+            .collect();
+        let points = self.points();
+        let root = config::path_from_config(&self.output_root);
+        fs::create_dir_all(&root)?;
+
+        let mut best = Vec::new();
+        for step in &self.analysis {
+            match step {
+                AnalysisStep::Table { metrics } => {
+                    let table = self.table(&points, &aggregates, metrics.as_deref());
+                    fs::write(root.join(RESULTS_FILENAME), table)?;
+                }
+                AnalysisStep::Best { metric, minimize } => {
+                    let means = aggregates.iter().enumerate().filter_map(|(i, aggregate)| {
+                        Some((i, aggregate.as_ref()?.metrics.get(metric)?.mean))
+                    });
+                    let found = match minimize {
+                        true => means.min_by(|(_, a), (_, b)| a.total_cmp(b)),
+                        false => means.max_by(|(_, a), (_, b)| a.total_cmp(b)),
+                    };
+                    best.push(json!({
+                        METRIC_KEY: metric,
+                        MINIMIZE_KEY: minimize,
+                        "point": found.map(|(i, _)| i),
+                        "params": found.map(|(i, _)| self.params(&points[i])),
+                        "mean": found.map(|(_, mean)| mean),
+                    }));
+                }
+            }
+        }
+
+        let point_summaries: Vec<Value> = points
+            .iter()
+            .zip(&aggregates)
+            .enumerate()
+            .map(|(i, (point, aggregate))| {
+                json!({ "point": i, "params": self.params(point), "aggregate": aggregate })
+            })
+            .collect();
+        let summary = json!({ NAME_KEY: self.name, "points": point_summaries, "best": best });
+        let data = serde_json::to_string_pretty(&summary).unwrap();
+        fs::write(root.join(EXPERIMENT_FILENAME), format!("{}\n", data))
+    }
+
+    /// Get the given point's parameter values, by parameter.
+    fn params(&self, point: &[Value]) -> Value {
+        let params: Map<String, Value> = self
+            .sweep
+            .iter()
+            .zip(point)
+            .map(|(param, value)| (param.param.clone(), value.clone()))
+            .collect();
+        Value::Object(params)
+    }
+
+    /// Get the `table` analysis step's csv of the given points' aggregates.
+    fn table(
+        &self,
+        points: &[Vec<Value>],
+        aggregates: &[Option<Aggregate>],
+        metrics: Option<&[String]>,
+    ) -> String {
+        let metrics: Vec<String> = match metrics {
+            Some(metrics) => metrics.to_vec(),
+            None => {
+                let mut metrics: Vec<String> = aggregates
+                    .iter()
+                    .flatten()
+                    .flat_map(|aggregate| aggregate.metrics.keys().cloned())
+                    .collect();
+                metrics.sort();
+                metrics.dedup();
+                metrics
+            }
+        };
+        let mut header = vec![String::from("point")];
+        header.extend(self.sweep.iter().map(|param| param.param.clone()));
+        header.push(String::from("num_replications"));
+        for metric in &metrics {
+            for column in ["mean", "ci95_low", "ci95_high"] {
+                header.push(format!("{}_{}", metric, column));
+            }
+        }
+        let mut table = format!("{}\n", header.join(","));
+        for (i, (point, aggregate)) in points.iter().zip(aggregates).enumerate() {
+            let mut row = vec![i.to_string()];
+            row.extend(point.iter().map(|value| match value {
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            }));
+            row.push(
+                aggregate
+                    .as_ref()
+                    .map_or(0, |a| a.num_replications)
+                    .to_string(),
+            );
+            for metric in &metrics {
+                let stats = aggregate.as_ref().and_then(|a| a.metrics.get(metric));
+                let ci95 = stats.and_then(|s| s.ci95);
+                row.push(stats.map(|s| s.mean.to_string()).unwrap_or_default());
+                row.push(ci95.map(|(low, _)| low.to_string()).unwrap_or_default());
+                row.push(ci95.map(|(_, high)| high.to_string()).unwrap_or_default());
+            }
+            writeln!(table, "{}", row.join(",")).unwrap();
+        }
+        table
+    }
+}
+
+/// Set the value at the given dotted path in a config, adding any missing object keys on the way.
+pub(crate) fn set_param(config: &mut Value, param: &str, value: Value) -> Result<(), CfgErrorMsg> {
+    let mut target = config;
+    for key in param.split('.') {
+        target = match target {
+            Value::Array(values) => key
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| values.get_mut(i))
+                .ok_or_else(|| format!("No index {} in sweep parameter {}.", key, param))?,
+            Value::Object(map) => map.entry(key).or_insert(Value::Null),
+            Value::Null => {
+                *target = Value::Object(Map::new());
+                target
+                    .as_object_mut()
+                    .unwrap()
+                    .entry(key)
+                    .or_insert(Value::Null)
+            }
+            _ => return Err(format!("Sweep parameter {} isn't in an object.", param)),
+        };
+    }
+    *target = value;
+    Ok(())
+}
+
+const NAME_KEY: &str = "name";
+const BASE_KEY: &str = "base";
+const SWEEP_KEY: &str = "sweep";
+const PARAM_KEY: &str = "param";
+const VALUES_KEY: &str = "values";
+const REPLICATIONS_KEY: &str = "replications";
+const RESAMPLE_TOPOLOGY_KEY: &str = "resample_topology";
+const ANALYSIS_KEY: &str = "analysis";
+const STEP_KEY: &str = "step";
+const METRICS_KEY: &str = "metrics";
+const METRIC_KEY: &str = "metric";
+const MINIMIZE_KEY: &str = "minimize";
+const OUTPUT_ROOT_KEY: &str = "output_root";
+const TABLE_STEP: &str = "table";
+const BEST_STEP: &str = "best";
+
+impl Configurable for AnalysisStep {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.get(STEP_KEY).and_then(Value::as_str) {
+            Some(TABLE_STEP) => {
+                let metrics = match config.get(METRICS_KEY) {
+                    Some(Value::Array(metrics)) => Some(
+                        metrics
+                            .iter()
+                            .map(|m| m.as_str().map(String::from))
+                            .collect::<Option<Vec<String>>>()
+                            .ok_or_else(|| String::from("Table metrics must be strings."))?,
+                    ),
+                    Some(_) => return Err(String::from("Table metrics must be an array.")),
+                    None => None,
+                };
+                Ok(AnalysisStep::Table { metrics })
+            }
+            Some(BEST_STEP) => {
+                let metric = match config.get(METRIC_KEY) {
+                    Some(Value::String(metric)) => Ok(metric.clone()),
+                    _ => Err(String::from("The best step needs a metric name.")),
+                }?;
+                let minimize = match config.get(MINIMIZE_KEY) {
+                    Some(Value::Bool(minimize)) => Ok(*minimize),
+                    Some(_) => Err(String::from("Minimize must be a boolean.")),
+                    None => Ok(true),
+                }?;
+                Ok(AnalysisStep::Best { metric, minimize })
+            }
+            _ => Err(String::from("Unknown analysis step.")),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            AnalysisStep::Table { metrics: None } => json!({ STEP_KEY: TABLE_STEP }),
+            AnalysisStep::Table {
+                metrics: Some(metrics),
+            } => json!({ STEP_KEY: TABLE_STEP, METRICS_KEY: metrics }),
+            AnalysisStep::Best { metric, minimize } => {
+                json!({ STEP_KEY: BEST_STEP, METRIC_KEY: metric, MINIMIZE_KEY: minimize })
+            }
+        }
+    }
+}
+
+impl Configurable for Experiment {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let name = match config.get(NAME_KEY) {
+            Some(Value::String(name)) => Ok(name.clone()),
+            Some(_) => Err(String::from("Experiment name must be a string.")),
+            None => Ok(String::new()),
+        }?;
+        let base = match config.get(BASE_KEY) {
+            Some(base @ Value::Object(_)) => Ok(base.clone()),
+            _ => Err(String::from(
+                "An experiment needs a base simulation config object.",
+            )),
+        }?;
+        let mut sweep = Vec::new();
+        match config.get(SWEEP_KEY) {
+            Some(Value::Array(param_cfgs)) => {
+                for param_cfg in param_cfgs {
+                    let param = match param_cfg.get(PARAM_KEY) {
+                        Some(Value::String(param)) => Ok(param.clone()),
+                        _ => Err(String::from("Each sweep parameter needs a param path.")),
+                    }?;
+                    let values = match param_cfg.get(VALUES_KEY) {
+                        Some(Value::Array(values)) if !values.is_empty() => Ok(values.clone()),
+                        _ => Err(String::from(
+                            "Each sweep parameter needs a nonempty array of values.",
+                        )),
+                    }?;
+                    sweep.push(SweepParam { param, values });
+                }
+            }
+            Some(_) => return Err(String::from("Sweep must be an array.")),
+            None => (),
+        }
+        let replications = match config.get(REPLICATIONS_KEY) {
+            Some(num) => match num.as_u64() {
+                Some(num) if num > 0 => Ok(Some(num as usize)),
+                _ => Err(String::from("Replications must be a positive integer.")),
+            },
+            None => Ok(None),
+        }?;
+        let resample_topology = match config.get(RESAMPLE_TOPOLOGY_KEY) {
+            Some(Value::Bool(_)) if replications.is_none() => Err(String::from(
+                "Resampling the topology requires replications.",
+            )),
+            Some(Value::Bool(resample)) => Ok(*resample),
+            Some(_) => Err(String::from("Resample topology must be a boolean.")),
+            None => Ok(false),
+        }?;
+        let analysis = match config.get(ANALYSIS_KEY) {
+            Some(Value::Array(step_cfgs)) => step_cfgs
+                .iter()
+                .map(|step_cfg| AnalysisStep::from_config(step_cfg.clone()))
+                .collect::<Result<Vec<_>, _>>(),
+            Some(_) => Err(String::from("Analysis must be an array of steps.")),
+            None => Ok(Vec::new()),
+        }?;
+        let output_root = match config.get(OUTPUT_ROOT_KEY) {
+            Some(Value::String(root)) => Ok(root.clone()),
+            _ => Err(String::from("An experiment needs an output root string.")),
+        }?;
+        Ok(Experiment {
+            name,
+            base,
+            sweep,
+            replications,
+            resample_topology,
+            analysis,
+            output_root,
+        })
+    }
+
+    fn to_config(&self) -> Value {
+        let sweep: Vec<Value> = self
+            .sweep
+            .iter()
+            .map(|param| json!({ PARAM_KEY: param.param, VALUES_KEY: param.values }))
+            .collect();
+        let analysis: Vec<Value> = self.analysis.iter().map(|s| s.to_config()).collect();
+        let mut config = json!({
+            NAME_KEY: self.name,
+            BASE_KEY: self.base,
+            SWEEP_KEY: sweep,
+            ANALYSIS_KEY: analysis,
+            OUTPUT_ROOT_KEY: self.output_root,
+        });
+        if let Some(replications) = self.replications {
+            config[REPLICATIONS_KEY] = Value::from(replications);
+            config[RESAMPLE_TOPOLOGY_KEY] = Value::Bool(self.resample_topology);
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_experiment_sweeps_and_analyzes() {
+        let output_root = std::env::temp_dir().join("aqt_sim_test_experiment");
+        let experiment = Experiment::from_config(json!({
+            "name": "rates",
+            "base": {
+                "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "adversary": [{ "adversary_name": "sd_path_random" }],
+            },
+            "sweep": [
+                { "param": "protocol.protocol_name", "values": ["greedy_fifo", "greedy_lis"] },
+                { "param": "adversary.0.seed", "values": [1, 2] },
+            ],
+            "replications": 2,
+            "analysis": [
+                { "step": "table", "metrics": ["num_absorbed"] },
+                { "step": "best", "metric": "num_absorbed", "minimize": false },
+            ],
+            "output_root": output_root.to_str().unwrap(),
+        }))
+        .unwrap();
+        assert_eq!(
+            Experiment::from_config(experiment.to_config()).unwrap(),
+            experiment
+        );
+
+        let sim_cfgs = experiment.sim_configs().unwrap();
+        assert_eq!(sim_cfgs.len(), 4);
+        assert_eq!(sim_cfgs[2]["protocol"]["protocol_name"], "greedy_lis");
+        assert_eq!(sim_cfgs[2]["adversary"][0]["seed"], 1);
+        assert_eq!(sim_cfgs[3]["replications"], 2);
+
+        // Each point gets two runs, except the last, whose runs both failed.
+        let run = |num_absorbed| {
+            Some(SimMetrics {
+                num_absorbed,
+                ..Default::default()
+            })
+        };
+        let metrics = [run(1), run(3), run(5), run(7), run(4), None, None, None];
+        let point_run_ids: Vec<Range<usize>> = (0..4).map(|i| 2 * i..2 * i + 2).collect();
+        experiment.analyze(&point_run_ids, &metrics).unwrap();
+
+        let table = fs::read_to_string(output_root.join(RESULTS_FILENAME)).unwrap();
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            rows[0],
+            "point,protocol.protocol_name,adversary.0.seed,num_replications,\
+             num_absorbed_mean,num_absorbed_ci95_low,num_absorbed_ci95_high"
+        );
+        assert!(rows[2].starts_with("1,greedy_fifo,2,2,6,"));
+        assert_eq!(rows[3], "2,greedy_lis,1,1,4,,");
+        assert_eq!(rows[4], "3,greedy_lis,2,0,,,");
+
+        let summary: Value = serde_json::from_str(
+            &fs::read_to_string(output_root.join(EXPERIMENT_FILENAME)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(summary["best"][0]["point"], 1);
+        assert_eq!(summary["best"][0]["params"]["adversary.0.seed"], 2);
+        assert_eq!(summary["points"][3]["aggregate"], Value::Null);
+        fs::remove_dir_all(output_root).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+//! This module contains the `FairnessRecorder`, which tracks each flow's throughput over a sliding
+//! window of rounds and records how evenly it is shared, by Jain's fairness index, for comparing
+//! fair queueing protocols.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::Network;
+use crate::packet::Packet;
+use crate::protocol::fair::FlowKey;
+use crate::simulation::metadata::Metadata;
+use crate::simulation::recorder::{
+    RecordedStates, RecorderTrait, WriteErrorMsg, FAIRNESS_NAME, RECORDER_NAME_KEY,
+};
+use crate::simulation::time::SimTime;
+use serde_json::{Map, Number, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FAIRNESS_FILENAME: &str = "fairness.csv";
+const FAIRNESS_HEADER: &str = "rd,num_flows,throughput,jain_index,min_share,max_share\n";
+const FLOW_KEY_KEY: &str = "flow_key";
+const WINDOW_KEY: &str = "window";
+const DEFAULT_WINDOW: usize = 100;
+
+// The flows active in a round, and how many of each flow's packets were absorbed in it.
+#[derive(Clone)]
+struct RoundFlows {
+    active: BTreeSet<usize>,
+    absorbed: BTreeMap<usize, usize>,
+}
+
+/// Get Jain's fairness index of the given throughputs, `(sum x)^2 / (n * sum x^2)`: 1 when every
+/// flow gets the same throughput, down to `1 / n` when a single flow gets all of it. Returns `None`
+/// if there are no flows or no throughput.
+pub fn jain_index(throughputs: &[usize]) -> Option<f64> {
+    let sum: f64 = throughputs.iter().map(|x| *x as f64).sum();
+    let sum_squares: f64 = throughputs.iter().map(|x| (*x as f64).powi(2)).sum();
+    match sum_squares > 0.0 {
+        true => Some(sum * sum / (throughputs.len() as f64 * sum_squares)),
+        false => None,
+    }
+}
+
+/// Records, after forwarding in every round, how the throughput (packets absorbed) over the last
+/// `window` rounds is shared among the flows (by `FlowKey`) active in them, i.e. those with packets
+/// in the network or absorbed during the window, so that starved flows count with no throughput.
+/// Each row of `fairness.csv` holds the number of active flows, their total throughput, Jain's
+/// fairness index of their throughputs, and the smallest and largest flow's share of the total.
+#[derive(Clone)]
+pub struct FairnessRecorder {
+    states: RecordedStates,
+    flow_key: FlowKey,
+    window: usize,
+    rds: VecDeque<RoundFlows>,
+    // Per flow, the packets absorbed and the number of rounds active over the window.
+    throughputs: BTreeMap<usize, usize>,
+    active_rds: BTreeMap<usize, usize>,
+    // Packets passed as absorbed with a non-prime state, which count toward the next prime state.
+    carried: Vec<Packet>,
+    dir_path: Option<PathBuf>,
+    rows: String,
+    // The metadata columns prepended to the header and to every row, if any.
+    row_prefix: String,
+    // The first error hit saving the output, if any.
+    write_error: Option<WriteErrorMsg>,
+}
+
+impl FairnessRecorder {
+    /// Get a new `FairnessRecorder` grouping packets into flows by the given key, over windows of
+    /// the given number of rounds.
+    pub fn new(flow_key: FlowKey, window: usize) -> Self {
+        FairnessRecorder {
+            states: RecordedStates::Both,
+            flow_key,
+            window,
+            rds: VecDeque::with_capacity(window + 1),
+            throughputs: BTreeMap::new(),
+            active_rds: BTreeMap::new(),
+            carried: Vec::new(),
+            dir_path: None,
+            rows: FAIRNESS_HEADER.to_string(),
+            row_prefix: String::new(),
+            write_error: None,
+        }
+    }
+
+    /// Get the key by which packets are grouped into flows.
+    pub fn flow_key(&self) -> FlowKey {
+        self.flow_key
+    }
+
+    /// Get the number of rounds over which throughput is measured.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Get the throughput of each flow active over the current window, by flow id.
+    pub fn throughputs(&self) -> Vec<(usize, usize)> {
+        self.active_rds
+            .keys()
+            .map(|flow| (*flow, self.throughputs.get(flow).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    fn push_rd(&mut self, rd_flows: RoundFlows) {
+        for flow in &rd_flows.active {
+            *self.active_rds.entry(*flow).or_default() += 1;
+        }
+        for (flow, num) in &rd_flows.absorbed {
+            *self.throughputs.entry(*flow).or_default() += num;
+        }
+        self.rds.push_back(rd_flows);
+        if self.rds.len() > self.window {
+            let expired = self.rds.pop_front().unwrap();
+            for flow in expired.active {
+                let num_rds = self.active_rds.get_mut(&flow).unwrap();
+                *num_rds -= 1;
+                if *num_rds == 0 {
+                    self.active_rds.remove(&flow);
+                }
+            }
+            for (flow, num) in expired.absorbed {
+                let throughput = self.throughputs.get_mut(&flow).unwrap();
+                *throughput -= num;
+                if *throughput == 0 {
+                    self.throughputs.remove(&flow);
+                }
+            }
+        }
+    }
+}
+
+impl RecorderTrait for FairnessRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        // Throughput only changes when packets are forwarded, but packets absorbed in rounds which
+        // weren't recorded may come with the next non-prime state (see `Recorder::record`).
+        let absorbed = match absorbed {
+            Some(absorbed) if time.is_prime() => absorbed,
+            Some(absorbed) => {
+                self.carried.extend(absorbed.iter().cloned());
+                return;
+            }
+            None => return,
+        };
+        let carried = std::mem::take(&mut self.carried);
+        let mut rd_flows = RoundFlows {
+            active: BTreeSet::new(),
+            absorbed: BTreeMap::new(),
+        };
+        let queued = network
+            .get_nodes()
+            .into_iter()
+            .flat_map(|node_id| network.get_injection_queue(node_id).iter());
+        let buffered = network
+            .edges()
+            .into_iter()
+            .flat_map(|edge| edge.iter_packets());
+        for p in buffered.chain(queued) {
+            rd_flows.active.insert(self.flow_key.flow_id(p));
+        }
+        for p in carried.iter().chain(absorbed) {
+            let flow = self.flow_key.flow_id(p);
+            rd_flows.active.insert(flow);
+            *rd_flows.absorbed.entry(flow).or_default() += 1;
+        }
+        self.push_rd(rd_flows);
+
+        let throughputs: Vec<usize> = self.throughputs().into_iter().map(|(_, x)| x).collect();
+        let total: usize = throughputs.iter().sum();
+        let format_opt = |value: Option<f64>| value.map_or(String::new(), |v| v.to_string());
+        let share = |x: Option<&usize>| x.filter(|_| total > 0).map(|x| *x as f64 / total as f64);
+        writeln!(
+            self.rows,
+            "{}{},{},{},{},{},{}",
+            self.row_prefix,
+            time.round,
+            throughputs.len(),
+            total,
+            format_opt(jain_index(&throughputs)),
+            format_opt(share(throughputs.iter().min())),
+            format_opt(share(throughputs.iter().max()))
+        )
+        .unwrap();
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.dir_path = Some(dir_path.to_path_buf());
+    }
+
+    fn close(&mut self) {
+        let dir_path = self
+            .dir_path
+            .as_ref()
+            .expect("You must set an output path for each recorder.");
+        let file_path = dir_path.join(FAIRNESS_FILENAME);
+        if let Err(err) = fs::write(&file_path, std::mem::take(&mut self.rows)) {
+            let msg = format!(
+                "Failed to save simulation results to {}: {}",
+                file_path.display(),
+                err
+            );
+            self.write_error.get_or_insert(msg);
+        }
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.row_prefix = metadata.row_prefix();
+        self.rows = metadata.header_prefix() + FAIRNESS_HEADER;
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+}
+
+impl Configurable for FairnessRecorder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let flow_key = match config.get(FLOW_KEY_KEY) {
+            Some(flow_key) => FlowKey::from_config(flow_key.clone())?,
+            None => FlowKey::Source,
+        };
+        let window = match config.get(WINDOW_KEY) {
+            Some(window) => match window.as_u64() {
+                Some(window) if window > 0 => window as usize,
+                _ => return Err(String::from("Fairness window must be a positive integer.")),
+            },
+            None => DEFAULT_WINDOW,
+        };
+        Ok(Self::new(flow_key, window))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            RECORDER_NAME_KEY.to_string(),
+            Value::String(FAIRNESS_NAME.to_string()),
+        );
+        map.insert(FLOW_KEY_KEY.to_string(), self.flow_key.to_config());
+        map.insert(
+            WINDOW_KEY.to_string(),
+            Value::Number(Number::from(self.window)),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+    use crate::simulation::recorder::Recorder;
+    use serde_json::json;
+
+    #[test]
+    fn test_fairness_over_sliding_window() {
+        assert_eq!(jain_index(&[3, 3, 3]), Some(1.0));
+        assert_eq!(jain_index(&[4, 0]), Some(0.5));
+        assert_eq!(jain_index(&[0, 0]), None);
+
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut recorder = FairnessRecorder::new(FlowKey::Source, 2);
+        let output_path = std::env::temp_dir().join("aqt_sim_test_fairness");
+        recorder.set_output_path(&output_path);
+
+        // Flow 1 waits in the network while flow 0 gets two packets through in round 1 and one in
+        // round 2; in round 3, flow 1 gets one through, and flow 0's first round leaves the window.
+        let waiting = factory.create_packet(vec![1, 2], 1, 0);
+        network.add_packet(waiting, 1, 2);
+        let mut absorb = |src_id: usize| vec![factory.create_packet(vec![src_id], 1, 0)];
+        let (first, second) = (absorb(0), absorb(0));
+        let rd_1 = [first, second].concat();
+        recorder.record(SimTime::injection(1), &network, None);
+        recorder.record(SimTime::forwarding(1), &network, Some(&rd_1));
+        recorder.record(SimTime::forwarding(2), &network, Some(&absorb(0)));
+        assert_eq!(recorder.throughputs(), [(0, 3), (1, 0)]);
+        network.remove_packet((1, 2), |_| true);
+        recorder.record(SimTime::forwarding(3), &network, Some(&absorb(1)));
+        assert_eq!(recorder.throughputs(), [(0, 1), (1, 1)]);
+        recorder.close();
+
+        let rows = fs::read_to_string(output_path.join(FAIRNESS_FILENAME)).unwrap();
+        assert_eq!(
+            rows.lines().collect::<Vec<_>>(),
+            [
+                FAIRNESS_HEADER.trim_end(),
+                "1,2,2,0.5,0,1",
+                "2,2,3,0.5,0,1",
+                "3,2,2,1,0.5,0.5"
+            ]
+        );
+        fs::remove_dir_all(output_path).unwrap();
+
+        let config = json!({ "recorder_name": "fairness", "flow_key": "class", "window": 50 });
+        let recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        let config = json!({ "recorder_name": "fairness", "window": 0 });
+        assert!(Recorder::from_config(config).is_err());
+    }
+}
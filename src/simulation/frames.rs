@@ -0,0 +1,258 @@
+//! This module contains the `FramesRecorder`, which records per-round node and edge loads as
+//! "frames" which can be animated after the simulation, optionally with a bundled HTML viewer.
+//!
+//! The frames are written to `output_path/frames.json` as a single object:
+//! `{"nodes": n, "edges": [[from, to], ...], "frames": [{"rd": 1, "prime": false,
+//! "node_loads": [...], "edge_loads": [...]}, ...]}`, where `edge_loads[i]` is the load of the
+//! buffer `edges[i]` and `node_loads[v]` is the number of packets at node `v` (in its outgoing
+//! buffers and its injection queue).
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::{Network, NodeID};
+use crate::packet::Packet;
+use crate::simulation::metadata::Metadata;
+use crate::simulation::recorder::{
+    RecordedStates, RecorderTrait, WriteErrorMsg, FRAMES_NAME, RECORDER_NAME_KEY,
+};
+use crate::simulation::time::SimTime;
+use serde_json::{json, Map, Number, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FRAMES_FILENAME: &str = "frames.json";
+const HTML_FILENAME: &str = "frames.html";
+const HTML_TEMPLATE: &str = include_str!("frames.html");
+// Replaced with the frames data in the HTML template.
+const HTML_DATA_PLACEHOLDER: &str = "__FRAMES__";
+
+const EVERY_KEY: &str = "every";
+const HTML_KEY: &str = "html";
+
+/// Records a frame of node and edge loads every `every` rounds, and writes them all when the
+/// simulation finishes. If `html` is set, also writes a standalone `frames.html` which animates
+/// the frames.
+#[derive(Clone)]
+pub struct FramesRecorder {
+    states: RecordedStates,
+    every: usize,
+    html: bool,
+    edges: Vec<(NodeID, NodeID)>,
+    num_nodes: usize,
+    frames: Vec<Value>,
+    // The metadata written with the frames, if any.
+    metadata: Option<Value>,
+    dir_path: Option<PathBuf>,
+    // The first error hit saving the frames, if any.
+    write_error: Option<WriteErrorMsg>,
+}
+
+impl FramesRecorder {
+    /// Get a new `FramesRecorder` which records a frame every `every` rounds.
+    pub fn new(every: usize, html: bool) -> Self {
+        FramesRecorder {
+            states: RecordedStates::Both,
+            every: every.max(1),
+            html,
+            edges: Vec::new(),
+            num_nodes: 0,
+            frames: Vec::new(),
+            metadata: None,
+            dir_path: None,
+            write_error: None,
+        }
+    }
+
+    fn frames_data(&self) -> Value {
+        let edges: Vec<Value> = self
+            .edges
+            .iter()
+            .map(|(from_id, to_id)| json!([from_id, to_id]))
+            .collect();
+        let mut data = json!({
+            "nodes": self.num_nodes,
+            "edges": edges,
+            "frames": self.frames,
+        });
+        if let Some(metadata) = &self.metadata {
+            data["metadata"] = metadata.clone();
+        }
+        data
+    }
+
+    fn write_file(dir_path: &Path, filename: &str, data: &str) -> Result<(), WriteErrorMsg> {
+        let file_path = dir_path.join(filename);
+        fs::write(&file_path, data).map_err(|err| {
+            format!(
+                "Failed to save simulation results to {}: {}",
+                file_path.display(),
+                err
+            )
+        })
+    }
+}
+
+impl RecorderTrait for FramesRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, _absorbed: Option<&Vec<Packet>>) {
+        if !(time.round - 1).is_multiple_of(self.every) {
+            return;
+        }
+        if self.frames.is_empty() {
+            self.edges = network.get_edgebuffers();
+            self.num_nodes = network.get_num_nodes();
+        }
+
+        let mut node_loads: Vec<usize> = network
+            .get_nodes()
+            .into_iter()
+            .map(|node_id| network.get_injection_queue(node_id).len())
+            .collect();
+        let mut edge_loads = Vec::new();
+        for (from_id, to_id) in &self.edges {
+            let load = network.edge(*from_id, *to_id).unwrap().load();
+            node_loads[*from_id] += load;
+            edge_loads.push(load);
+        }
+        self.frames.push(json!({
+            "rd": time.round,
+            "prime": time.is_prime(),
+            "node_loads": node_loads,
+            "edge_loads": edge_loads,
+        }));
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.dir_path = Some(dir_path.to_path_buf());
+    }
+
+    fn close(&mut self) {
+        let dir_path = self
+            .dir_path
+            .as_ref()
+            .expect("You must set an output path for each recorder.");
+        let data = self.frames_data().to_string();
+        let mut result = Self::write_file(dir_path, FRAMES_FILENAME, &data);
+        if self.html {
+            let html = HTML_TEMPLATE.replace(HTML_DATA_PLACEHOLDER, &data);
+            result = result.and(Self::write_file(dir_path, HTML_FILENAME, &html));
+        }
+        if let Err(msg) = result {
+            self.write_error.get_or_insert(msg);
+        }
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.metadata = Some(metadata.to_json());
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+}
+
+impl Configurable for FramesRecorder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let every = match config.get(EVERY_KEY) {
+            Some(Value::Number(num)) => match num.as_u64() {
+                Some(every) if every > 0 => Ok(every as usize),
+                _ => Err(String::from("Frame period must be a positive integer.")),
+            },
+            None => Ok(1),
+            _ => Err(String::from("Frame period must be a positive integer.")),
+        }?;
+        let html = match config.get(HTML_KEY) {
+            Some(Value::Bool(html)) => Ok(*html),
+            None => Ok(false),
+            _ => Err(String::from("Frames html flag must be a bool.")),
+        }?;
+        Ok(Self::new(every, html))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            RECORDER_NAME_KEY.to_string(),
+            Value::String(FRAMES_NAME.to_string()),
+        );
+        map.insert(
+            EVERY_KEY.to_string(),
+            Value::Number(Number::from(self.every)),
+        );
+        if self.html {
+            map.insert(HTML_KEY.to_string(), Value::Bool(true));
+        }
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+    use crate::simulation::recorder::Recorder;
+
+    #[test]
+    fn test_frames() {
+        let config = json!({ "recorder_name": "frames", "every": 2, "html": true });
+        let mut recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        let dir_path = std::env::temp_dir().join("aqt_sim_test_frames");
+        let _ = fs::remove_dir_all(&dir_path);
+        recorder.set_output_path(&dir_path);
+
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        for _ in 0..2 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        }
+        network.add_to_injection_queue(factory.create_packet(vec![1, 2], 1, 0));
+        for rd in 1..=3 {
+            recorder.record(SimTime::injection(rd), &network, None);
+            network.move_packet((0, 1), (1, 2), 0);
+            recorder.record(SimTime::forwarding(rd), &network, Some(&Vec::new()));
+            if rd == 1 {
+                network.add_packet(factory.create_packet(vec![0, 1, 2], 2, 0), 0, 1);
+            }
+        }
+        recorder.close();
+
+        // Frames are only recorded in rounds 1 and 3, and node 1's load counts its queue.
+        let data = fs::read_to_string(dir_path.join(FRAMES_FILENAME)).unwrap();
+        let html = fs::read_to_string(dir_path.join(HTML_FILENAME)).unwrap();
+        fs::remove_dir_all(&dir_path).unwrap();
+        let frame = |rd: usize, prime: bool, edge_loads: [usize; 2]| {
+            json!({
+                "rd": rd,
+                "prime": prime,
+                "node_loads": [edge_loads[0], edge_loads[1] + 1, 0],
+                "edge_loads": edge_loads,
+            })
+        };
+        let expected = json!({
+            "nodes": 3,
+            "edges": [[0, 1], [1, 2]],
+            "frames": [
+                frame(1, false, [2, 0]),
+                frame(1, true, [1, 1]),
+                frame(3, false, [1, 2]),
+                frame(3, true, [0, 3]),
+            ],
+        });
+        assert_eq!(serde_json::from_str::<Value>(&data).unwrap(), expected);
+        assert!(html.contains(&data) && !html.contains(HTML_DATA_PLACEHOLDER));
+    }
+}
@@ -0,0 +1,203 @@
+//! This module contains `OutputLayout`, which places every simulation of a batch under one root
+//! directory in a standard layout, and writes an `index.json` describing each level of it:
+//!
+//! - `root/index.json` lists the batch's simulations,
+//! - `root/<sim>/index.json` lists a replicated simulation's replications, and
+//! - `root/<sim>/index.json` (or `root/<sim>/rep_<i>/index.json`) lists a run's status and files.
+//!
+//! It also expands the placeholders in simulations' output paths (see `expand_output_path`).
+
+use super::SUMMARY_FILENAME;
+use crate::adversary::ADVERSARY_NAME_KEY;
+use crate::config::{self, CfgErrorMsg, ADVERSARY_KEY, OUTPUT_PATH_KEY, PROTOCOL_KEY};
+use crate::protocol::PROTOCOL_NAME_KEY;
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+/// The name of the file written at each level of an `OutputLayout`.
+pub const INDEX_FILENAME: &str = "index.json";
+/// The output path of a simulation in an `OutputLayout` which doesn't give one.
+pub const DEFAULT_PATH_TEMPLATE: &str = "{index}_{protocol}_{adversary}";
+
+const INDEX_PLACEHOLDER: &str = "{index}";
+const PROTOCOL_PLACEHOLDER: &str = "{protocol}";
+const ADVERSARY_PLACEHOLDER: &str = "{adversary}";
+
+// A simulation in the layout: its directory relative to the root, the ids of its runs, and
+// whether they're replications (in subdirectories) rather than a single run.
+#[derive(Clone)]
+struct LayoutEntry {
+    dir: String,
+    run_ids: Range<usize>,
+    replicated: bool,
+}
+
+/// A standard directory layout for a batch's output under a root directory.
+#[derive(Clone)]
+pub struct OutputLayout {
+    root: String,
+    entries: Vec<LayoutEntry>,
+}
+
+impl OutputLayout {
+    /// Get a new `OutputLayout` under the given root directory.
+    pub fn new(root: String) -> Self {
+        OutputLayout {
+            root,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a simulation whose output path relative to the root is `dir`, run as the given runs.
+    pub fn add_entry(&mut self, dir: String, run_ids: Range<usize>, replicated: bool) {
+        self.entries.push(LayoutEntry {
+            dir,
+            run_ids,
+            replicated,
+        });
+    }
+
+    /// Write an `index.json` at every level of the layout which has output.
+    pub fn write_indexes(&self) -> io::Result<()> {
+        let root = config::path_from_config(&self.root);
+        let simulations: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "path": entry.dir,
+                    "run_ids": [entry.run_ids.start, entry.run_ids.end],
+                    "replicated": entry.replicated,
+                })
+            })
+            .collect();
+        fs::create_dir_all(&root)?;
+        write_index(&root, json!({ "simulations": simulations }))?;
+
+        for entry in &self.entries {
+            let sim_path = root.join(config::path_from_config(&entry.dir));
+            if !entry.replicated {
+                write_run_index(&sim_path)?;
+                continue;
+            }
+            let replications: Vec<Value> = (0..entry.run_ids.len())
+                .map(|i| {
+                    let rep_dir = format!("{}{}", config::REPLICATION_DIR_PREFIX, i);
+                    let status = run_status(&sim_path.join(&rep_dir));
+                    json!({ "path": rep_dir, "status": status })
+                })
+                .collect();
+            if sim_path.is_dir() {
+                write_index(&sim_path, json!({ "replications": replications }))?;
+            }
+            for i in 0..entry.run_ids.len() {
+                let rep_dir = format!("{}{}", config::REPLICATION_DIR_PREFIX, i);
+                write_run_index(&sim_path.join(rep_dir))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Expand the placeholders `{index}` (the simulation's index in the config), `{protocol}`, and
+/// `{adversary}` (the component names, joined by `+` for several adversaries) in the given
+/// simulation config's output path. With a layout, the output path is relative to the layout's
+/// root and defaults to `DEFAULT_PATH_TEMPLATE`. Returns the expanded path, before any root.
+pub fn expand_output_path(
+    sim_cfg: &mut Value,
+    index: usize,
+    layout: Option<&OutputLayout>,
+) -> Result<String, CfgErrorMsg> {
+    let template = match (sim_cfg.get(OUTPUT_PATH_KEY), layout) {
+        (Some(Value::String(path)), _) => path.clone(),
+        (None, Some(_)) => DEFAULT_PATH_TEMPLATE.to_string(),
+        _ => return Err(String::from("No output path string found.")),
+    };
+    let protocol_name = sim_cfg[PROTOCOL_KEY][PROTOCOL_NAME_KEY]
+        .as_str()
+        .unwrap_or_default();
+    let adversary_names: Vec<&str> = match &sim_cfg[ADVERSARY_KEY] {
+        Value::Array(adversary_cfgs) => adversary_cfgs
+            .iter()
+            .filter_map(|c| c[ADVERSARY_NAME_KEY].as_str())
+            .collect(),
+        adversary_cfg => adversary_cfg[ADVERSARY_NAME_KEY]
+            .as_str()
+            .into_iter()
+            .collect(),
+    };
+    let path = template
+        .replace(INDEX_PLACEHOLDER, &index.to_string())
+        .replace(PROTOCOL_PLACEHOLDER, protocol_name)
+        .replace(ADVERSARY_PLACEHOLDER, &adversary_names.join("+"));
+    let output_path = match layout {
+        Some(layout) => format!("{}/{}", layout.root, path),
+        None => path.clone(),
+    };
+    sim_cfg[OUTPUT_PATH_KEY] = Value::String(output_path);
+    Ok(path)
+}
+
+fn write_index(dir: &Path, index: Value) -> io::Result<()> {
+    let data = serde_json::to_string_pretty(&index).unwrap();
+    fs::write(dir.join(INDEX_FILENAME), format!("{}\n", data))
+}
+
+/// Get a run's status from its summary, if it has one.
+fn run_status(run_path: &Path) -> Value {
+    fs::read_to_string(run_path.join(SUMMARY_FILENAME))
+        .ok()
+        .and_then(|summary| serde_json::from_str::<Value>(&summary).ok())
+        .map(|summary| summary["status"].clone())
+        .unwrap_or(Value::Null)
+}
+
+/// Write the index of a run's directory, listing its status and output files, if it has output.
+fn write_run_index(run_path: &Path) -> io::Result<()> {
+    if !run_path.is_dir() {
+        return Ok(());
+    }
+    let mut files: Vec<String> = fs::read_dir(run_path)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name != INDEX_FILENAME)
+        .collect();
+    files.sort();
+    write_index(
+        run_path,
+        json!({ "status": run_status(run_path), "files": files }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_path_templates() {
+        let mut sim_cfg = json!({
+            "protocol": { "protocol_name": "greedy_lis", "capacity": 1 },
+            "adversary": [
+                { "adversary_name": "sd_path_random" },
+                { "adversary_name": "preset", "schedule": [] },
+            ],
+        });
+        let layout = OutputLayout::new(String::from("results"));
+        let dir = expand_output_path(&mut sim_cfg, 3, Some(&layout)).unwrap();
+        assert_eq!(dir, "3_greedy_lis_sd_path_random+preset");
+        assert_eq!(
+            sim_cfg[OUTPUT_PATH_KEY],
+            "results/3_greedy_lis_sd_path_random+preset"
+        );
+
+        sim_cfg[OUTPUT_PATH_KEY] = json!("out/{protocol}/run_{index}");
+        expand_output_path(&mut sim_cfg, 0, None).unwrap();
+        assert_eq!(sim_cfg[OUTPUT_PATH_KEY], "out/greedy_lis/run_0");
+        sim_cfg.as_object_mut().unwrap().remove(OUTPUT_PATH_KEY);
+        assert!(expand_output_path(&mut sim_cfg, 0, None).is_err());
+    }
+}
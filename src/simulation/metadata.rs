@@ -0,0 +1,127 @@
+//! This module contains the `Metadata` a simulation can stamp on its recorders' output: constant
+//! columns, such as the run id, protocol name, and seed, prepended to every CSV row (and added to
+//! the header of JSON outputs), so that each output file can be analyzed on its own.
+
+use crate::config::CfgErrorMsg;
+use serde_json::{Map, Value};
+
+/// The metadata column holding the run's id in its batch.
+pub const RUN_ID_COLUMN: &str = "run_id";
+
+/// Constant columns describing a run, by name, in order.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Metadata {
+    columns: Vec<(String, Value)>,
+}
+
+impl Metadata {
+    /// Get the metadata with the given columns, each either `"run_id"` or a dotted path into the
+    /// simulation's saved config, such as `"protocol.protocol_name"`, `"adversary.rho"`, or
+    /// `"adversary.0.rho"` for the first of several adversaries. The run id is null if the run
+    /// has none. Returns an error if a path isn't in the config.
+    pub fn resolve(
+        columns: &[String],
+        sim_config: &Value,
+        run_id: Option<usize>,
+    ) -> Result<Self, CfgErrorMsg> {
+        let columns = columns
+            .iter()
+            .map(|column| {
+                let value = match column.as_str() {
+                    RUN_ID_COLUMN => Ok(run_id.map_or(Value::Null, Value::from)),
+                    path => sim_config
+                        .pointer(&format!("/{}", path.replace('.', "/")))
+                        .cloned()
+                        .ok_or(format!("No {} in the simulation config.", path)),
+                }?;
+                Ok((column.clone(), value))
+            })
+            .collect::<Result<_, CfgErrorMsg>>()?;
+        Ok(Metadata { columns })
+    }
+
+    /// Get the column names followed by a comma each, to prepend to a CSV header.
+    pub fn header_prefix(&self) -> String {
+        self.columns
+            .iter()
+            .map(|(name, _)| format!("{},", csv_field(name)))
+            .collect()
+    }
+
+    /// Get the column values followed by a comma each, to prepend to a CSV row. Null values are
+    /// left empty.
+    pub fn row_prefix(&self) -> String {
+        self.columns
+            .iter()
+            .map(|(_, value)| match value {
+                Value::Null => String::from(","),
+                Value::String(value) => format!("{},", csv_field(value)),
+                value => format!("{},", csv_field(&value.to_string())),
+            })
+            .collect()
+    }
+
+    /// Get the columns as a JSON object, to add to JSON outputs.
+    pub fn to_json(&self) -> Value {
+        let map: Map<String, Value> = self.columns.iter().cloned().collect();
+        Value::Object(map)
+    }
+}
+
+// Quote a CSV field if it contains a separator, quote, or newline.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::simulation::Simulation;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_metadata_columns_prefix_rows() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_metadata");
+        let sim_config = |columns: Value| {
+            let config = json!({
+                "parallel": false,
+                "simulations": [{
+                    "graph_adjacency": { "preset": "path", "num_nodes": 3 },
+                    "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                    "adversary": { "adversary_name": "sd_path_random" },
+                    "threshold": { "threshold_name": "timed", "max_rds": 5 },
+                    "recorders": [{ "recorder_name": "buffer_load" }],
+                    "output_path": output_path.to_string_lossy(),
+                    "seed": 7,
+                    "metadata_columns": columns,
+                }],
+            });
+            Config::from_string(config.to_string()).unwrap().sim_configs[0].clone()
+        };
+        let columns = json!(["run_id", "protocol.protocol_name", "seed"]);
+        Simulation::from_config(sim_config(columns)).run();
+        let data = fs::read_to_string(output_path.join("buffer_load.csv")).unwrap();
+        let mut lines = data.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "run_id,protocol.protocol_name,seed,rd,prime,buffer_from,buffer_to,load"
+        );
+        assert!(lines.all(|line| line.starts_with(",greedy_fifo,7,")));
+        fs::remove_dir_all(&output_path).unwrap();
+
+        let columns = ["protocol.rho".to_string()];
+        let sim_config = json!({ "protocol": { "protocol_name": "greedy_fifo" } });
+        assert!(Metadata::resolve(&columns, &sim_config, None).is_err());
+        let columns = ["protocol".to_string()];
+        let metadata = Metadata::resolve(&columns, &sim_config, None).unwrap();
+        assert_eq!(
+            metadata.row_prefix(),
+            "\"{\"\"protocol_name\"\":\"\"greedy_fifo\"\"}\","
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! This module contains `SimMetrics`, the summary statistics which every `Simulation` accumulates
+//! in memory and returns from `run`, so that sweeps which only need them can skip file output.
+
+use crate::network::Network;
+use crate::packet::Packet;
+use serde::{Deserialize, Serialize};
+
+/// Summary statistics of a simulation run. The counters are `u64` so that they can't overflow on
+/// long runs, whatever the platform's `usize`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct SimMetrics {
+    /// The number of rounds run.
+    pub num_rds: u64,
+    /// The number of packets injected by the adversaries.
+    pub num_injected: u64,
+    /// The number of packets discarded by the per-round injection cap (see
+    /// `Simulation::set_max_injections_per_rd`), which aren't counted in `num_injected`.
+    pub num_excess_injections: u64,
+    /// The number of packets absorbed at their destinations.
+    pub num_absorbed: u64,
+    /// The number of packets dropped (see `Network::num_dropped`).
+    pub num_dropped: u64,
+    /// The largest total load of the network's buffers, after injection or forwarding.
+    pub max_total_load: u64,
+    /// The largest load of any single buffer, after injection or forwarding.
+    pub max_buffer_load: u64,
+    /// The largest latency of any absorbed packet.
+    pub max_latency: u64,
+    /// The sum of the latencies of all absorbed packets.
+    pub total_latency: u64,
+}
+
+impl SimMetrics {
+    /// Get the mean latency of the absorbed packets, if any were absorbed.
+    pub fn mean_latency(&self) -> Option<f64> {
+        (self.num_absorbed > 0).then(|| self.total_latency as f64 / self.num_absorbed as f64)
+    }
+
+    /// Update the load maxima with the current state of the network.
+    pub(super) fn add_loads(&mut self, network: &Network) {
+        let mut total_load = 0;
+        for edge in network.edges() {
+            total_load += edge.load() as u64;
+            self.max_buffer_load = self.max_buffer_load.max(edge.load() as u64);
+        }
+        self.max_total_load = self.max_total_load.max(total_load);
+    }
+
+    /// Add the packets absorbed in the given round.
+    pub(super) fn add_absorbed(&mut self, rd: usize, absorbed: &[Packet]) {
+        for packet in absorbed {
+            let latency = (rd - packet.injection_rd()) as u64;
+            self.max_latency = self.max_latency.max(latency);
+            self.total_latency += latency;
+        }
+        self.num_absorbed += absorbed.len() as u64;
+    }
+}
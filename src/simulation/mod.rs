@@ -4,59 +4,379 @@
 
 use crate::adversary::Adversary;
 use crate::config;
-use crate::config::{Configurable, SimConfig};
-use crate::network::Network;
+use crate::config::{CfgErrorMsg, Configurable, SimConfig};
+use crate::network::{Network, NodeCapacities, NodeID, NodeLimit};
+use crate::packet::{Packet, PacketFactory, PacketId, PacketPath};
+use crate::protocol::admission::Admission;
 use crate::protocol::Protocol;
-use crate::simulation::recorder::Recorder;
-use crate::simulation::threshold::Threshold;
-use serde_json::{Map, Value};
+use crate::simulation::backlog::InitialBacklog;
+use crate::simulation::metadata::Metadata;
+use crate::simulation::metrics::SimMetrics;
+use crate::simulation::pipeline::{RoundPipeline, RoundStep};
+use crate::simulation::random::SimRng;
+use crate::simulation::recorder::{MergedOutput, Recorder, WriteErrorMsg};
+use crate::simulation::status::{Progress, StatusBoard};
+use crate::simulation::threshold::{Threshold, ThresholdMode};
+use crate::simulation::time::SimTime;
+use hashbrown::{HashMap, HashSet};
+use serde_json::{json, Map, Value};
+use std::collections::VecDeque;
 use std::fs;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
+pub mod aggregate;
+pub mod audit;
+pub mod backlog;
+#[cfg(feature = "bundle")]
+pub mod bundle;
+pub mod cosim;
+pub mod deadline;
+pub mod debugger;
+pub mod dry_run;
+pub mod experiment;
+pub mod fairness;
+pub mod frames;
+pub mod layout;
+pub mod metadata;
+pub mod metrics;
+pub mod pipeline;
 pub mod random;
 pub mod recorder;
+pub mod reservoir;
+pub mod search;
+pub mod status;
 pub mod threshold;
+pub mod time;
+pub mod trace;
+pub mod warm_up;
 
 /// Stores all data related to a run of a simulation, including the `Network`, `Protocol`, and
-/// `Adversary`.
+/// `Adversary`s. Every adversary injects each round, and all of them draw packet ids from the
+/// simulation's shared `PacketFactory`. If an `Admission` protocol is set, injected packets first
+/// wait in per-node injection queues and are admitted into the network by it each round.
 pub struct Simulation {
     network: Network,
     protocol: Protocol,
-    adversary: Adversary,
+    admission: Option<Admission>,
+    adversaries: Vec<Adversary>,
+    end_of_injection: EndOfInjection,
+    absorption: Absorption,
+    // If set, the most packets each adversary may inject per round, and what to do with more.
+    max_injections_per_rd: Option<(usize, ExcessInjections)>,
+    // If set, the base seed from which unseeded components were seeded (see `from_config`).
+    seed: Option<u64>,
+    initial_backlog: Option<InitialBacklog>,
+    pipeline: RoundPipeline,
+    factory: PacketFactory,
     threshold: Threshold,
     recorders: Vec<Recorder>,
-    output_path: String,
+    output_path: PathBuf,
+    // The summary statistics so far, and whether they're the only output (see
+    // `set_metrics_only`).
+    metrics: SimMetrics,
+    metrics_only: bool,
+    // The round to run next, and whether the simulation has been set up and has finished.
+    rd: usize,
+    started: bool,
+    finished: bool,
+    // When the simulation started running, and the last round run before then, to estimate its
+    // progress.
+    started_at: Option<(Instant, usize)>,
+    // The state before each of the last `max_snapshots` rounds, most recent last.
+    snapshots: VecDeque<RoundSnapshot>,
+    max_snapshots: usize,
+    // Whether a pause-mode threshold pauses the simulation, and the round and state in which it
+    // last did so.
+    pausable: bool,
+    paused: Option<SimTime>,
+    // If set, the simulation stalls once forwarding has changed nothing for this many
+    // consecutive rounds while the network is nonempty.
+    stall_rds: Option<usize>,
+    num_stalled_rds: usize,
+    stalled: bool,
+    // If set, the number of rounds of the protocol warm-up check run at setup (see
+    // `check_warm_up`).
+    warm_up_rds: Option<usize>,
+    // The first error hit writing the simulation's output, and whether to stop once one is hit.
+    write_error: Option<WriteErrorMsg>,
+    stop_on_write_error: bool,
+    // The batch-level status board to report progress to, with this simulation's run id.
+    status_board: Option<(usize, Arc<StatusBoard>)>,
+    // The batch-level merged output recorders write to, with this simulation's run id.
+    merged_output: Option<(usize, Arc<MergedOutput>)>,
+    // The metadata columns stamped on the recorders' output (see `set_metadata_columns`).
+    metadata_columns: Vec<String>,
+}
+
+/// The state of a `Simulation` before a round, which it can be rolled back to. Recorders are not
+/// part of the snapshot, since their output has already been written.
+#[derive(Clone)]
+struct RoundSnapshot {
+    network: Network,
+    protocol: Protocol,
+    admission: Option<Admission>,
+    adversaries: Vec<Adversary>,
+    factory: PacketFactory,
+    threshold: Threshold,
+    metrics: SimMetrics,
+    rd: usize,
+    finished: bool,
+    num_stalled_rds: usize,
+    stalled: bool,
 }
 
 const SIM_CONFIG_FILENAME: &str = "sim_config.json";
+const SUMMARY_FILENAME: &str = "summary.json";
+const SNAPSHOT_FILENAME_PREFIX: &str = "snapshot_rd_";
+
+/// The status of a `Simulation`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SimStatus {
+    /// The simulation has rounds left to run.
+    Running,
+    /// The simulation finished normally: its threshold fired or the network drained.
+    Finished,
+    /// The simulation was stopped by the stall watchdog (see `Simulation::set_stall_rds`).
+    Stalled,
+    /// The simulation ended, but some of its output couldn't be written (see
+    /// `Simulation::write_error`).
+    Failed,
+}
+
+impl SimStatus {
+    /// Get the name of this status, as written to a simulation's summary.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Stalled => "stalled",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// What a `Simulation` does once every adversary reports that it is done injecting.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EndOfInjection {
+    /// Stop injecting and keep forwarding until the network is empty (or the threshold fires).
+    Drain,
+    /// Stop the simulation immediately.
+    Terminate,
+}
+
+/// When a `Simulation` absorbs packets, since models differ on whether the last hop of a path takes
+/// a round.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Absorption {
+    /// Packets are absorbed when the protocol forwards them across the last edge of their path.
+    #[default]
+    Forward,
+    /// Packets are absorbed as soon as they're in the buffer of the last edge of their path, at the
+    /// end of each forwarding step, whichever protocol is run. The last hop takes no round, and a
+    /// packet injected onto a single edge is absorbed in the round it's injected.
+    OnArrival,
+}
+
+const FORWARD_NAME: &str = "forward";
+const ON_ARRIVAL_NAME: &str = "on_arrival";
+
+impl Configurable for Absorption {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(FORWARD_NAME) => Ok(Self::Forward),
+            Some(ON_ARRIVAL_NAME) => Ok(Self::OnArrival),
+            _ => Err(format!("No absorption behavior {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Forward => Value::String(FORWARD_NAME.to_string()),
+            Self::OnArrival => Value::String(ON_ARRIVAL_NAME.to_string()),
+        }
+    }
+}
+
+/// What a `Simulation` does with an adversary's packets in a round where it injects more than
+/// `max_injections_per_rd` of them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExcessInjections {
+    /// Inject the first `max_injections_per_rd` packets and discard the rest.
+    Truncate,
+    /// Discard all of the adversary's packets for the round.
+    Reject,
+}
+
+const TRUNCATE_NAME: &str = "truncate";
+const REJECT_NAME: &str = "reject";
+
+impl Configurable for ExcessInjections {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(TRUNCATE_NAME) => Ok(Self::Truncate),
+            Some(REJECT_NAME) => Ok(Self::Reject),
+            _ => Err(format!("No excess injections behavior {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Truncate => Value::String(TRUNCATE_NAME.to_string()),
+            Self::Reject => Value::String(REJECT_NAME.to_string()),
+        }
+    }
+}
+
+const DRAIN_NAME: &str = "drain";
+const TERMINATE_NAME: &str = "terminate";
+
+impl Configurable for EndOfInjection {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(DRAIN_NAME) => Ok(Self::Drain),
+            Some(TERMINATE_NAME) => Ok(Self::Terminate),
+            _ => Err(format!("No end of injection behavior {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Drain => Value::String(DRAIN_NAME.to_string()),
+            Self::Terminate => Value::String(TERMINATE_NAME.to_string()),
+        }
+    }
+}
 
 impl Simulation {
-    /// Create a new `Simulation`. Use this to run non-debug sims.
+    /// Create a new `Simulation`. Use this to run non-debug sims. The output path may use either
+    /// `/` or `\` as separators (see `config::path_from_config`). Panics if the protocol can't run
+    /// on the network (see `try_new`).
     pub fn new(
         network: Network,
         protocol: Protocol,
-        adversary: Adversary,
+        adversaries: Vec<Adversary>,
         threshold: Threshold,
         recorders: Vec<Recorder>,
         output_path: String,
     ) -> Self {
+        match Self::try_new(
+            network,
+            protocol,
+            adversaries,
+            threshold,
+            recorders,
+            output_path,
+        ) {
+            Ok(simulation) => simulation,
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+    /// Like `new`, but returns an error if the protocol can't run on the network (see
+    /// `Protocol::check_graph_structure`).
+    pub fn try_new(
+        network: Network,
+        protocol: Protocol,
+        adversaries: Vec<Adversary>,
+        threshold: Threshold,
+        recorders: Vec<Recorder>,
+        output_path: String,
+    ) -> Result<Self, CfgErrorMsg> {
+        if let Err(msg) = protocol.check_graph_structure(&network) {
+            return Err(format!("The protocol doesn't support the network: {}", msg));
+        }
+        let mut network = network;
+        if recorders.iter().any(|r| r.instruments_network()) {
+            network.set_instrumented(true);
+        }
+        if threshold.needs_service_tracking() {
+            network.set_service_tracked(true);
+        }
         let mut new_sim = Simulation {
             network,
             protocol,
-            adversary,
+            admission: None,
+            adversaries,
+            end_of_injection: EndOfInjection::Drain,
+            absorption: Absorption::default(),
+            max_injections_per_rd: None,
+            seed: None,
+            initial_backlog: None,
+            pipeline: RoundPipeline::default(),
+            factory: PacketFactory::new(),
             threshold,
             recorders,
-            output_path: output_path.clone(),
+            output_path: config::path_from_config(&output_path),
+            metrics: SimMetrics::default(),
+            metrics_only: false,
+            rd: 1,
+            started: false,
+            finished: false,
+            started_at: None,
+            snapshots: VecDeque::new(),
+            max_snapshots: 0,
+            pausable: false,
+            paused: None,
+            stall_rds: None,
+            num_stalled_rds: 0,
+            stalled: false,
+            warm_up_rds: None,
+            write_error: None,
+            stop_on_write_error: false,
+            status_board: None,
+            merged_output: None,
+            metadata_columns: Vec::new(),
         };
-        new_sim.save_config(&output_path);
         for recorder in &mut new_sim.recorders {
-            recorder.set_output_path(output_path.clone())
+            recorder.set_output_path(&new_sim.output_path)
         }
-        new_sim
+        Ok(new_sim)
     }
 
     /// Create a new `Simulation` from the provided `SimConfig`.
+    ///
+    /// If the config sets a base `"seed"`, each unseeded random component is seeded from its own
+    /// stream of it: `"adversary_i"` for the `i`th adversary, `"protocol"`, `"initial_backlog"`,
+    /// and `"topology"` for a random network preset.
+    /// Simulations which differ only in their protocol then use common random numbers for their
+    /// adversaries, however much randomness the protocols consume.
     pub fn from_config(cfg: SimConfig) -> Self {
+        let mut cfg = cfg;
+        let seed = cfg
+            .seed_cfg
+            .as_ref()
+            .map(|seed_cfg| match seed_cfg.as_u64() {
+                Some(seed) => seed,
+                None => panic!("Seed must be a nonnegative integer."),
+            });
+        if let Some(seed) = seed {
+            let fill = |cfg: &mut Value, stream: &str| {
+                SimRng::fill_seed(cfg, SimRng::stream_seed(seed, stream))
+            };
+            match &mut cfg.adversary_cfg {
+                Value::Array(adversary_cfgs) => {
+                    for (i, adversary_cfg) in adversary_cfgs.iter_mut().enumerate() {
+                        fill(adversary_cfg, &format!("adversary_{}", i));
+                    }
+                }
+                adversary_cfg => fill(adversary_cfg, "adversary_0"),
+            }
+            fill(&mut cfg.protocol_cfg, "protocol");
+            // Only a preset network is an object; adjacency lists hold no seeds.
+            if cfg.graph_adjacency.is_object() {
+                fill(&mut cfg.graph_adjacency, "topology");
+            }
+            if let Some(initial_backlog_cfg) = &mut cfg.initial_backlog_cfg {
+                fill(initial_backlog_cfg, "initial_backlog");
+            }
+            if let Some(random_edge_order_cfg) = &mut cfg.random_edge_order_cfg {
+                fill(random_edge_order_cfg, "edge_order");
+            }
+        }
+
         let recorders = cfg
             .recorder_cfgs
             .as_array()
@@ -65,61 +385,1016 @@ impl Simulation {
             .map(|c| Recorder::from_config(c.clone()).unwrap())
             .collect();
 
-        Simulation::new(
+        // The adversary config is either a single adversary or a list of adversaries.
+        let adversaries = match cfg.adversary_cfg {
+            Value::Array(adversary_cfgs) => adversary_cfgs
+                .into_iter()
+                .map(|c| Adversary::from_config(c).unwrap())
+                .collect(),
+            adversary_cfg => vec![Adversary::from_config(adversary_cfg).unwrap()],
+        };
+
+        let mut simulation = Simulation::try_new(
             Network::from_config(cfg.graph_adjacency).unwrap(),
             Protocol::from_config(cfg.protocol_cfg).unwrap(),
-            Adversary::from_config(cfg.adversary_cfg).unwrap(),
+            adversaries,
             Threshold::from_config(cfg.threshold_cfg).unwrap(),
             recorders,
             cfg.output_path,
         )
+        .unwrap();
+        simulation.seed = seed;
+        if let Some(admission_cfg) = cfg.admission_cfg {
+            simulation.set_admission(Admission::from_config(admission_cfg).unwrap());
+        }
+        if let Some(end_of_injection_cfg) = cfg.end_of_injection_cfg {
+            simulation
+                .set_end_of_injection(EndOfInjection::from_config(end_of_injection_cfg).unwrap());
+        }
+        if let Some(absorption_cfg) = cfg.absorption_cfg {
+            simulation.set_absorption(Absorption::from_config(absorption_cfg).unwrap());
+        }
+        if let Some(initial_backlog_cfg) = cfg.initial_backlog_cfg {
+            simulation
+                .set_initial_backlog(InitialBacklog::from_config(initial_backlog_cfg).unwrap());
+        }
+        if let Some(node_capacities_cfg) = cfg.node_capacities_cfg {
+            simulation
+                .set_node_capacities(NodeCapacities::from_config(node_capacities_cfg).unwrap());
+        }
+        if let Some(random_edge_order_cfg) = cfg.random_edge_order_cfg {
+            match random_edge_order_cfg {
+                Value::Object(map) => simulation
+                    .network
+                    .set_random_edge_order(map.get(config::SEED_KEY).and_then(Value::as_u64)),
+                _ => panic!("Random edge order must be an object, with an optional seed."),
+            }
+        }
+        if let Some(round_steps_cfg) = cfg.round_steps_cfg {
+            simulation.set_pipeline(RoundPipeline::from_config(round_steps_cfg).unwrap());
+        }
+        if let Some(max_injections_per_rd_cfg) = cfg.max_injections_per_rd_cfg {
+            let excess_injections = match cfg.excess_injections_cfg {
+                Some(excess_injections_cfg) => {
+                    ExcessInjections::from_config(excess_injections_cfg).unwrap()
+                }
+                None => ExcessInjections::Truncate,
+            };
+            match max_injections_per_rd_cfg.as_u64() {
+                Some(max) => simulation.set_max_injections_per_rd(max as usize, excess_injections),
+                None => panic!("Max injections per round must be a nonnegative integer."),
+            }
+        }
+        if let Some(stall_rds_cfg) = cfg.stall_rds_cfg {
+            match stall_rds_cfg.as_u64() {
+                Some(stall_rds) if stall_rds > 0 => simulation.set_stall_rds(stall_rds as usize),
+                _ => panic!("Stall rounds must be a positive integer."),
+            }
+        }
+        if let Some(warm_up_rds_cfg) = cfg.warm_up_rds_cfg {
+            match warm_up_rds_cfg.as_u64() {
+                Some(warm_up_rds) if warm_up_rds > 0 => {
+                    if let Err(msg) = simulation.check_warm_up(warm_up_rds as usize) {
+                        panic!("Protocol warm-up check failed: {}", msg);
+                    }
+                }
+                _ => panic!("Warm-up rounds must be a positive integer."),
+            }
+        }
+        if let Some(metrics_only_cfg) = cfg.metrics_only_cfg {
+            match metrics_only_cfg {
+                Value::Bool(metrics_only) => simulation.set_metrics_only(metrics_only),
+                _ => panic!("Metrics only must be a bool."),
+            }
+        }
+        if let Some(metadata_columns_cfg) = cfg.metadata_columns_cfg {
+            let columns: Option<Vec<String>> =
+                metadata_columns_cfg.as_array().and_then(|columns| {
+                    columns
+                        .iter()
+                        .map(|column| column.as_str().map(String::from))
+                        .collect()
+                });
+            match columns {
+                Some(columns) => simulation.set_metadata_columns(columns).unwrap(),
+                None => panic!("Metadata columns must be an array of strings."),
+            }
+        }
+        if let Some(stop_on_write_error_cfg) = cfg.stop_on_write_error_cfg {
+            match stop_on_write_error_cfg {
+                Value::Bool(stop) => simulation.set_stop_on_write_error(stop),
+                _ => panic!("Stop on write error must be a bool."),
+            }
+        }
+        simulation
+    }
+
+    /// Set the `Admission` protocol, so that injected packets are queued at their source nodes
+    /// and admitted into the network by it.
+    pub fn set_admission(&mut self, admission: Admission) {
+        self.admission = Some(admission);
+    }
+
+    /// Set what the simulation does once all adversaries are done injecting. Defaults to
+    /// `EndOfInjection::Drain`.
+    pub fn set_end_of_injection(&mut self, end_of_injection: EndOfInjection) {
+        self.end_of_injection = end_of_injection;
+    }
+
+    /// Set when the simulation absorbs packets. Defaults to `Absorption::Forward`.
+    pub fn set_absorption(&mut self, absorption: Absorption) {
+        self.absorption = absorption;
+    }
+
+    /// Limit how many packets each adversary may inject per round, as a safety net against buggy
+    /// adversaries, independently of any auditing. Excess packets are handled by
+    /// `excess_injections`, counted in `SimMetrics::num_excess_injections`, and logged the first
+    /// time they occur.
+    pub fn set_max_injections_per_rd(&mut self, max: usize, excess_injections: ExcessInjections) {
+        self.max_injections_per_rd = Some((max, excess_injections));
+    }
+
+    /// Limit how many packets each node may send, receive, and absorb per round (see
+    /// `Network::set_node_capacities`). Panics if the protocol doesn't enforce node capacities.
+    pub fn set_node_capacities(&mut self, node_capacities: NodeCapacities) {
+        assert!(
+            self.protocol.supports_node_capacities(),
+            "The protocol doesn't support node capacities."
+        );
+        self.network.set_node_capacities(node_capacities);
     }
 
-    /// Run the simulation for the given number of rounds.
-    pub fn run(&mut self) {
-        let mut rd = 1;
-        loop {
-            // Inject.
-            let mut packets_to_inject = self.adversary.get_next_packets(&self.network, rd);
-            let num_to_inject = packets_to_inject.len();
-            for _ in 0..num_to_inject {
-                let p = packets_to_inject.remove(0);
-                self.protocol.add_packet(p, &mut self.network);
+    /// Set the steps run each round. Defaults to `RoundPipeline::default()`.
+    pub fn set_pipeline(&mut self, pipeline: RoundPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    /// Write this simulation's recorder rows to the given batch-level `MergedOutput` under the
+    /// given run id, rather than to files in its own output path.
+    pub fn set_merged_output(&mut self, run_id: usize, merged_output: Arc<MergedOutput>) {
+        if let Err(msg) = merged_output.register_run(run_id, &self.output_path) {
+            self.write_error.get_or_insert(msg);
+        }
+        for recorder in &mut self.recorders {
+            recorder.set_merged_output(run_id, merged_output.clone());
+        }
+        self.merged_output = Some((run_id, merged_output));
+    }
+
+    /// Stamp the given metadata columns on every row of the recorders' output (see
+    /// `Metadata::resolve`), so that each output file can be analyzed on its own. The values are
+    /// taken from the simulation's config when it starts. Returns an error if a column isn't in
+    /// the config.
+    pub fn set_metadata_columns(&mut self, columns: Vec<String>) -> Result<(), CfgErrorMsg> {
+        self.metadata_columns = columns;
+        self.metadata().map(|_| ())
+    }
+
+    /// The metadata stamped on the recorders' output, with the run id under which the simulation
+    /// reports to its batch, if any.
+    fn metadata(&self) -> Result<Metadata, CfgErrorMsg> {
+        let run_id = self
+            .merged_output
+            .as_ref()
+            .map(|(run_id, _)| *run_id)
+            .or(self.status_board.as_ref().map(|(run_id, _)| *run_id));
+        Metadata::resolve(&self.metadata_columns, &self.to_config_val(), run_id)
+    }
+
+    /// Attach a recorder to the simulation at the current round boundary, e.g. to start tracing
+    /// packets once a breakpoint is hit. If the simulation has started, the recorder is first told
+    /// the last state run (see `RecorderTrait::attach`), and records every state after it. Its
+    /// output goes to the simulation's output path, but it isn't saved in `sim_config.json`, which
+    /// was saved when the simulation started.
+    pub fn attach_recorder(&mut self, mut recorder: Recorder) -> Result<(), String> {
+        if self.metrics_only {
+            return Err(String::from(
+                "A metrics-only simulation can't have recorders.",
+            ));
+        }
+        recorder.set_output_path(&self.output_path);
+        if let Some((run_id, merged_output)) = &self.merged_output {
+            recorder.set_merged_output(*run_id, merged_output.clone());
+        }
+        if recorder.instruments_network() && self.network.access_counts().is_none() {
+            self.network.set_instrumented(true);
+        }
+        if self.started {
+            if !self.metadata_columns.is_empty() {
+                recorder.set_metadata(&self.metadata()?);
+            }
+            recorder.attach(SimTime::forwarding(self.rd - 1), &self.network);
+        }
+        self.recorders.push(recorder);
+        Ok(())
+    }
+
+    /// Detach the recorder with the given index (in the order recorders were configured, then
+    /// attached) at the current round boundary, closing it. Returns the recorder, or `None` if
+    /// there is no recorder with that index.
+    pub fn detach_recorder(&mut self, idx: usize) -> Option<Recorder> {
+        if idx >= self.recorders.len() {
+            return None;
+        }
+        let mut recorder = self.recorders.remove(idx);
+        recorder.close();
+        if let Some(msg) = recorder.write_error() {
+            self.write_error.get_or_insert(msg.clone());
+        }
+        Some(recorder)
+    }
+
+    /// Get the simulation's recorders.
+    pub fn recorders(&self) -> &[Recorder] {
+        &self.recorders
+    }
+
+    /// Report this simulation's progress to the given batch-level `StatusBoard` under the given run
+    /// id after every round.
+    pub fn set_status_board(&mut self, run_id: usize, status_board: Arc<StatusBoard>) {
+        self.status_board = Some((run_id, status_board));
+    }
+
+    /// Set an `InitialBacklog` with which to fill the network before the first round. The
+    /// simulation then starts in round `initial_backlog.first_rd()`.
+    pub fn set_initial_backlog(&mut self, initial_backlog: InitialBacklog) {
+        self.initial_backlog = Some(initial_backlog);
+    }
+
+    /// Keep snapshots of the state before each of the last `max_snapshots` rounds, so that the
+    /// simulation can be rolled back with `rollback`. This is meant for debugging small
+    /// simulations, since each snapshot is a full copy of the network. Defaults to 0.
+    pub fn set_max_snapshots(&mut self, max_snapshots: usize) {
+        self.max_snapshots = max_snapshots;
+        while self.snapshots.len() > max_snapshots {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Set whether a threshold in `ThresholdMode::Pause` pauses the simulation (see `take_pause`).
+    /// Otherwise, it saves a snapshot like `ThresholdMode::Snapshot`. Defaults to false.
+    pub fn set_pausable(&mut self, pausable: bool) {
+        self.pausable = pausable;
+    }
+
+    /// Stop the simulation with `SimStatus::Stalled` once forwarding has moved, absorbed, and
+    /// dropped no packets for `stall_rds` consecutive rounds while the network is nonempty, e.g.
+    /// because a buggy protocol never forwards. Disabled by default.
+    pub fn set_stall_rds(&mut self, stall_rds: usize) {
+        self.stall_rds = Some(stall_rds);
+    }
+
+    /// Run the protocol warm-up check (see `warm_up::check_protocol`) for the given number of
+    /// rounds on a miniature copy of this simulation's network, and record it to be saved with
+    /// the config.
+    pub fn check_warm_up(&mut self, num_rds: usize) -> Result<(), String> {
+        self.warm_up_rds = Some(num_rds);
+        warm_up::check_protocol(&self.protocol, &self.network, num_rds)
+    }
+
+    /// Set whether the simulation stops as soon as some of its output can't be written, rather
+    /// than running to the end and failing then. Defaults to false.
+    pub fn set_stop_on_write_error(&mut self, stop_on_write_error: bool) {
+        self.stop_on_write_error = stop_on_write_error;
+    }
+
+    /// Set whether the simulation's in-memory `SimMetrics` are its only output, in which case it
+    /// writes no files at all, not even its config and summary. Such a simulation must have no
+    /// recorders. Defaults to false.
+    pub fn set_metrics_only(&mut self, metrics_only: bool) {
+        assert!(
+            !metrics_only || self.recorders.is_empty(),
+            "A metrics-only simulation can't have recorders."
+        );
+        self.metrics_only = metrics_only;
+    }
+
+    /// Returns whether the simulation's `SimMetrics` are its only output.
+    pub fn metrics_only(&self) -> bool {
+        self.metrics_only
+    }
+
+    /// Get the summary statistics of the rounds run so far.
+    pub fn metrics(&self) -> &SimMetrics {
+        &self.metrics
+    }
+
+    /// Get the first error hit writing the simulation's output, if any. A simulation which hits
+    /// one ends with `SimStatus::Failed`, since its output is incomplete.
+    pub fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+
+    /// If a pause-mode threshold fired during the last call to `step`, get the time at which it
+    /// fired, and clear the pause.
+    pub fn take_pause(&mut self) -> Option<SimTime> {
+        self.paused.take()
+    }
+
+    /// Get the round which will be run by the next call to `step`.
+    pub fn rd(&self) -> usize {
+        self.rd
+    }
+
+    /// Get the simulation's progress: its rate of rounds so far and, if its threshold fixes its
+    /// number of rounds (e.g. a timed threshold), its estimated time remaining. `None` before it
+    /// has started.
+    pub fn progress(&self) -> Option<Progress> {
+        let (started_at, first_rd) = self.started_at?;
+        Some(Progress::estimate(
+            started_at,
+            first_rd,
+            self.rd - 1,
+            self.threshold.max_rds(),
+            Instant::now(),
+        ))
+    }
+
+    /// Get a reference to the simulation's `Network`.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Returns whether the simulation has finished, normally or by stalling.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Get the status of the simulation.
+    pub fn status(&self) -> SimStatus {
+        match (self.finished, self.stalled) {
+            (true, _) if self.write_error.is_some() => SimStatus::Failed,
+            (_, true) => SimStatus::Stalled,
+            (true, false) => SimStatus::Finished,
+            (false, false) => SimStatus::Running,
+        }
+    }
+
+    fn adversaries_done(&self) -> bool {
+        self.adversaries.iter().all(|a| a.is_done())
+    }
+
+    /// Run the simulation until it finishes, then close its recorders. Returns the summary
+    /// statistics of the run.
+    pub fn run(&mut self) -> SimMetrics {
+        while self.step() {}
+        self.close();
+        self.metrics
+    }
+
+    /// Close the simulation's recorders. Call this after stepping a simulation manually. If the
+    /// simulation has finished, also write `output_path/summary.json`, which marks its output as
+    /// complete (see `is_complete`).
+    pub fn close(&mut self) {
+        for recorder in &mut self.recorders {
+            recorder.record_skipped(&self.network);
+            recorder.close()
+        }
+        self.check_write_errors();
+        if self.finished && !self.metrics_only {
+            self.save_summary();
+        }
+    }
+
+    /// Returns whether this simulation's output path already holds the complete output of a run
+    /// of the same config: its summary exists and says the run finished or stalled, and its saved
+    /// config matches this simulation's. A summary which can't be parsed, e.g. because writing it
+    /// was interrupted, doesn't count.
+    pub fn is_complete(&self) -> bool {
+        if self.metrics_only {
+            return false;
+        }
+        let read = |filename: &str| fs::read_to_string(self.output_path.join(filename));
+        match (read(SUMMARY_FILENAME), read(SIM_CONFIG_FILENAME)) {
+            (Ok(summary), Ok(saved_config)) => {
+                let complete = [SimStatus::Finished, SimStatus::Stalled];
+                match serde_json::from_str::<Value>(&summary) {
+                    Ok(summary) => {
+                        complete
+                            .iter()
+                            .any(|status| summary["status"] == status.name())
+                            && saved_config.trim_end() == self.to_config_str()
+                    }
+                    Err(_) => false,
+                }
             }
+            _ => false,
+        }
+    }
+
+    /// Note the first write error hit by any recorder, if there is a new one. If the simulation
+    /// stops on write errors, it finishes.
+    fn check_write_errors(&mut self) {
+        if self.write_error.is_some() {
+            return;
+        }
+        let write_error = self.recorders.iter().find_map(|r| r.write_error()).cloned();
+        if let Some(msg) = write_error {
+            eprintln!(
+                "Simulation at {} failed: {}",
+                self.output_path.display(),
+                msg
+            );
+            self.write_error = Some(msg);
+            self.finished |= self.stop_on_write_error;
+        }
+    }
+
+    /// Get the metrics saved in this simulation's summary by an earlier run, if there is one.
+    pub fn saved_metrics(&self) -> Option<SimMetrics> {
+        let summary = fs::read_to_string(self.output_path.join(SUMMARY_FILENAME)).ok()?;
+        let summary: Value = serde_json::from_str(&summary).ok()?;
+        serde_json::from_value(summary.get("metrics")?.clone()).ok()
+    }
 
+    fn save_summary(&self) {
+        let mut summary = json!({
+            "status": self.status().name(),
+            "last_rd": self.rd - 1,
+            "metrics": self.metrics,
+        });
+        if let Some(msg) = &self.write_error {
+            summary["error"] = Value::String(msg.clone());
+        }
+        let reports: Vec<Option<Value>> = self.adversaries.iter().map(Adversary::report).collect();
+        if reports.iter().any(Option::is_some) {
+            summary["adversary_reports"] = json!(reports);
+        }
+        let file_path = self.output_path.join(SUMMARY_FILENAME);
+        if fs::write(&file_path, format!("{}\n", summary)).is_err() {
+            eprintln!(
+                "Failed to save simulation summary to {}",
+                file_path.display()
+            );
+        }
+    }
+
+    /// Save the config and fill the initial backlog, if any. Any summary of an earlier run is
+    /// removed, so that the output isn't complete until this run finishes.
+    fn start(&mut self) {
+        self.started = true;
+        if !self.metadata_columns.is_empty() {
+            let metadata = self.metadata().unwrap();
             for recorder in &mut self.recorders {
-                recorder.record(rd, false, &self.network, None);
+                recorder.set_metadata(&metadata);
+            }
+        }
+        if !self.metrics_only {
+            let _ = fs::remove_file(self.output_path.join(SUMMARY_FILENAME));
+            self.save_config(&self.output_path);
+        }
+        if let Some(initial_backlog) = &mut self.initial_backlog {
+            // Backlog packets are stamped with an origin past the last adversary's index.
+            self.factory.set_origin(self.adversaries.len());
+            initial_backlog.fill(&mut self.network, &mut self.protocol, &mut self.factory);
+            self.rd = initial_backlog.first_rd();
+        }
+        self.started_at = Some((Instant::now(), self.rd - 1));
+    }
+
+    /// Run a single round of the simulation. Returns whether the simulation should continue, i.e.
+    /// `false` once it has finished.
+    pub fn step(&mut self) -> bool {
+        if !self.started {
+            self.start();
+        }
+        if self.finished {
+            return false;
+        }
+        self.paused = None;
+        if self.max_snapshots > 0 {
+            if self.snapshots.len() == self.max_snapshots {
+                self.snapshots.pop_front();
             }
+            self.snapshots.push_back(self.snapshot());
+        }
+        self.finished = self.run_round(self.rd);
+        self.rd += 1;
+        self.metrics.num_rds += 1;
+        self.metrics.num_dropped = self.network.num_dropped();
+        self.check_write_errors();
+        if let Some((run_id, status_board)) = &self.status_board {
+            status_board.report(
+                *run_id,
+                self.status().into(),
+                self.rd - 1,
+                self.network.total_load(),
+                self.threshold.max_rds(),
+            );
+        }
+        !self.finished
+    }
+
+    /// Roll the simulation back by (up to) `n` rounds, to the state before the `n`th most recent
+    /// round, using the snapshots kept according to `set_max_snapshots`. Recorder output is not
+    /// rolled back. Returns the number of rounds actually rolled back.
+    pub fn rollback(&mut self, n: usize) -> usize {
+        let n = n.min(self.snapshots.len());
+        if n == 0 {
+            return 0;
+        }
+        let keep = self.snapshots.len() - n;
+        let snapshot = self.snapshots.drain(keep..).next().unwrap();
+        self.network = snapshot.network;
+        self.protocol = snapshot.protocol;
+        self.admission = snapshot.admission;
+        self.adversaries = snapshot.adversaries;
+        self.factory = snapshot.factory;
+        self.threshold = snapshot.threshold;
+        self.metrics = snapshot.metrics;
+        self.rd = snapshot.rd;
+        self.finished = snapshot.finished;
+        self.num_stalled_rds = snapshot.num_stalled_rds;
+        self.stalled = snapshot.stalled;
+        self.paused = None;
+        n
+    }
 
-            if self.threshold.check_termination(rd, &self.network) {
-                break;
+    fn snapshot(&self) -> RoundSnapshot {
+        RoundSnapshot {
+            network: self.network.clone(),
+            protocol: self.protocol.clone(),
+            admission: self.admission.clone(),
+            adversaries: self.adversaries.clone(),
+            factory: self.factory.clone(),
+            threshold: self.threshold.clone(),
+            metrics: self.metrics,
+            rd: self.rd,
+            finished: self.finished,
+            num_stalled_rds: self.num_stalled_rds,
+            stalled: self.stalled,
+        }
+    }
+
+    /// Run the given round by running each step of the round pipeline in order. Returns whether
+    /// the simulation is finished.
+    fn run_round(&mut self, rd: usize) -> bool {
+        self.network.set_cur_rd(rd);
+        let adversaries_done = self.adversaries_done();
+        if adversaries_done && self.end_of_injection == EndOfInjection::Terminate {
+            return true;
+        }
+
+        // The packets absorbed by this round's forward step, once it has run.
+        let mut absorbed: Option<Vec<Packet>> = None;
+        for step_idx in 0..self.pipeline.steps().len() {
+            let done = match self.pipeline.steps()[step_idx] {
+                RoundStep::Inject => {
+                    if !adversaries_done {
+                        self.inject(rd);
+                    }
+                    self.metrics.add_loads(&self.network);
+                    false
+                }
+                RoundStep::Admit => {
+                    if let Some(admission) = &mut self.admission {
+                        admission.admit_packets(&mut self.network, &mut self.protocol);
+                    }
+                    false
+                }
+                RoundStep::Forward => {
+                    let positions = self
+                        .stall_rds
+                        .map(|_| Self::packet_positions(&self.network));
+                    let buffers = self
+                        .network
+                        .tracks_service()
+                        .then(|| Self::packet_buffers(&self.network));
+                    let mut forwarded = self.protocol.forward_packets(&mut self.network);
+                    let mut num_absorbed = HashMap::new();
+                    self.hold_back_absorptions(&mut forwarded, &mut num_absorbed);
+                    if self.absorption == Absorption::OnArrival {
+                        forwarded.append(&mut self.absorb_arrived(&mut num_absorbed));
+                    }
+                    if let Some(buffers) = buffers {
+                        self.record_service(rd, buffers);
+                    }
+                    let stalled = match positions {
+                        Some(positions) => self.check_stall(rd, positions, &forwarded),
+                        None => false,
+                    };
+                    self.metrics.add_loads(&self.network);
+                    self.metrics.add_absorbed(rd, &forwarded);
+                    absorbed = Some(forwarded);
+                    stalled
+                }
+                RoundStep::DropExpired => {
+                    self.drop_expired(rd);
+                    false
+                }
+                RoundStep::Record => {
+                    let time = match absorbed {
+                        Some(_) => SimTime::forwarding(rd),
+                        None => SimTime::injection(rd),
+                    };
+                    for recorder in &mut self.recorders {
+                        recorder.record(time, &self.network, absorbed.as_ref());
+                    }
+                    self.check_threshold(time, absorbed.as_ref())
+                }
+            };
+            if done {
+                return true;
             }
+        }
 
-            // Forward.
-            let absorbed = self.protocol.forward_packets(&mut self.network);
+        if let Some(absorbed) = absorbed {
+            self.factory.recycle(absorbed);
+        }
 
-            for recorder in &mut self.recorders {
-                recorder.record(rd, true, &self.network, Some(&absorbed));
+        // Drain: once no more packets will be injected, stop when the network is empty.
+        self.adversaries_done()
+            && self.network.total_load() == 0
+            && self.network.injection_queue_load() == 0
+    }
+
+    /// Have every adversary inject its packets for the given round, marking any change in an
+    /// adversary's phase on the recorders.
+    fn inject(&mut self, rd: usize) {
+        for (adversary_idx, adversary) in self.adversaries.iter_mut().enumerate() {
+            self.factory.set_origin(adversary_idx);
+            let last_phase = adversary.phase().map(str::to_string);
+            let mut packets_to_inject =
+                adversary.get_next_packets(&self.network, rd, &mut self.factory);
+            if let Some(phase) = adversary
+                .phase()
+                .filter(|p| Some(*p) != last_phase.as_deref())
+            {
+                let time = SimTime::injection(rd);
+                for recorder in &mut self.recorders {
+                    recorder.record_phase(time, &self.network, adversary_idx, phase);
+                }
+            }
+            if let Some((max, excess_injections)) = self.max_injections_per_rd {
+                if packets_to_inject.len() > max {
+                    if self.metrics.num_excess_injections == 0 {
+                        eprintln!(
+                            "Adversary {} injected {} packets in round {}, over the cap of {}; \
+                             excess packets are discarded ({}).",
+                            adversary_idx,
+                            packets_to_inject.len(),
+                            rd,
+                            max,
+                            self.output_path.display()
+                        );
+                    }
+                    let keep = match excess_injections {
+                        ExcessInjections::Truncate => max,
+                        ExcessInjections::Reject => 0,
+                    };
+                    self.metrics.num_excess_injections += (packets_to_inject.len() - keep) as u64;
+                    packets_to_inject.truncate(keep);
+                }
+            }
+            self.metrics.num_injected += packets_to_inject.len() as u64;
+            if !packets_to_inject.is_empty() {
+                let time = SimTime::injection(rd);
+                for recorder in &mut self.recorders {
+                    recorder.record_injection(time, &self.network, &packets_to_inject);
+                }
             }
+            for p in packets_to_inject {
+                match self.admission {
+                    Some(_) => self.network.add_to_injection_queue(p),
+                    None => self.protocol.add_packet(p, &mut self.network),
+                }
+            }
+        }
+    }
+
+    /// Drop every buffered packet whose deadline is before the given round. Packets still in
+    /// injection queues are kept.
+    fn drop_expired(&mut self, rd: usize) {
+        let expired = |p: &Packet| p.deadline().is_some_and(|deadline| deadline < rd);
+        for edge in self.network.get_edgebuffers() {
+            while self.network.drop_packet(edge, expired).is_some() {}
+        }
+    }
 
-            if self.threshold.check_termination(rd, &self.network) {
-                break;
+    /// The most packets each node may absorb per round, if limited (see `NodeCapacities`).
+    fn absorb_limit(&self) -> Option<NodeLimit> {
+        self.network
+            .node_capacities()
+            .and_then(|node_capacities| node_capacities.absorb.clone())
+    }
+
+    /// Hand each packet the protocol absorbed past its destination's absorb limit back to the
+    /// protocol, to wait at the front of the last buffer of its path, and count the packets each
+    /// node absorbs.
+    fn hold_back_absorptions(
+        &mut self,
+        absorbed: &mut Vec<Packet>,
+        num_absorbed: &mut HashMap<NodeID, usize>,
+    ) {
+        let Some(limit) = self.absorb_limit() else {
+            return;
+        };
+        let (kept, held_back): (Vec<Packet>, Vec<Packet>) = absorbed.drain(..).partition(|p| {
+            let count = num_absorbed.entry(p.dest_node()).or_insert(0);
+            *count += 1;
+            *count <= limit.get(p.dest_node())
+        });
+        *absorbed = kept;
+        let held_back = held_back
+            .into_iter()
+            .map(|mut p| {
+                p.decrement_path_idx();
+                p
+            })
+            .collect();
+        self.protocol.restore_packets(held_back, &mut self.network);
+    }
+
+    /// Remove and return every packet in the buffer of the last edge of its path, as absorbed, up
+    /// to each node's absorb limit given the packets it has already absorbed this round. Packets
+    /// past the limit stay where they are.
+    fn absorb_arrived(&mut self, num_absorbed: &mut HashMap<NodeID, usize>) -> Vec<Packet> {
+        let limit = self.absorb_limit();
+        let mut absorbed = Vec::new();
+        let mut eb_ids = self.network.get_edgebuffers();
+        eb_ids.sort_unstable();
+        for edge in eb_ids {
+            let on_last_edge = |p: &Packet| p.get_path_idx() + 2 == p.get_path().len();
+            let left = limit.as_ref().map_or(usize::MAX, |limit| {
+                let count = num_absorbed.get(&edge.1).copied().unwrap_or(0);
+                limit.get(edge.1).saturating_sub(count)
+            });
+            let mut num_taken = 0;
+            while num_taken < left {
+                let Some(mut p) = self.network.remove_packet(edge, on_last_edge) else {
+                    break;
+                };
+                p.increment_path_idx();
+                absorbed.push(p);
+                num_taken += 1;
+            }
+            if num_taken > 0 {
+                *num_absorbed.entry(edge.1).or_insert(0) += num_taken;
             }
-            rd += 1;
         }
-        for recorder in &mut self.recorders {
-            recorder.close()
+        absorbed
+    }
+
+    /// The id and path index of every packet in the network, sorted.
+    fn packet_positions(network: &Network) -> Vec<(PacketId, usize)> {
+        let mut positions: Vec<(PacketId, usize)> = network
+            .edges()
+            .into_iter()
+            .flat_map(|edge| edge.iter_packets().map(|p| (p.id(), p.get_path_idx())))
+            .collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    /// The buffer and path index of every packet in the network, by id.
+    fn packet_buffers(network: &Network) -> HashMap<PacketId, ((NodeID, NodeID), usize)> {
+        network
+            .edges()
+            .into_iter()
+            .flat_map(|edge| {
+                edge.iter_packets()
+                    .map(move |p| (p.id(), (edge.ids(), p.get_path_idx())))
+            })
+            .collect()
+    }
+
+    /// Record in the network which buffers forwarded a packet in this round's forwarding step,
+    /// given every packet's buffer and path index before it: those holding a packet which has
+    /// since moved further along its path or left the network.
+    fn record_service(&mut self, rd: usize, buffers: HashMap<PacketId, ((NodeID, NodeID), usize)>) {
+        let after = Self::packet_buffers(&self.network);
+        let forwarded: HashSet<(NodeID, NodeID)> = buffers
+            .into_iter()
+            .filter(|(id, (_, path_idx))| {
+                after
+                    .get(id)
+                    .is_none_or(|(_, new_path_idx)| new_path_idx > path_idx)
+            })
+            .map(|(_, (edge, _))| edge)
+            .collect();
+        self.network.record_service(rd, &forwarded);
+    }
+
+    /// Update the stall watchdog given the packet positions before forwarding and the packets
+    /// absorbed by it. Returns whether the simulation has stalled.
+    fn check_stall(
+        &mut self,
+        rd: usize,
+        positions: Vec<(PacketId, usize)>,
+        absorbed: &[Packet],
+    ) -> bool {
+        let unchanged = !positions.is_empty()
+            && absorbed.is_empty()
+            && positions == Self::packet_positions(&self.network);
+        self.num_stalled_rds = if unchanged {
+            self.num_stalled_rds + 1
+        } else {
+            0
+        };
+        self.stalled = self.stall_rds == Some(self.num_stalled_rds);
+        if self.stalled {
+            eprintln!(
+                "Simulation stalled in round {}: forwarding changed nothing for {} rounds ({}).",
+                rd,
+                self.num_stalled_rds,
+                self.output_path.display()
+            );
+        }
+        self.stalled
+    }
+
+    /// Check the threshold and pause or save a snapshot if it fires in a non-terminating mode.
+    /// Returns whether the simulation should terminate.
+    fn check_threshold(&mut self, time: SimTime, absorbed: Option<&Vec<Packet>>) -> bool {
+        if !self
+            .threshold
+            .check_termination(time, &self.network, absorbed)
+        {
+            return false;
+        }
+        match self.threshold.mode() {
+            ThresholdMode::Terminate => return true,
+            ThresholdMode::Pause if self.pausable => self.paused = Some(time),
+            ThresholdMode::Pause | ThresholdMode::Snapshot => self.save_snapshot(time),
+        }
+        false
+    }
+
+    /// Get a full snapshot of the network as JSON: its adjacency lists, and every packet in each
+    /// buffer and injection queue, as of the given time, along with the protocol's state if it
+    /// keeps any.
+    pub fn snapshot_to_config(&self, time: SimTime) -> Value {
+        let packet_cfg = |p: &Packet| {
+            json!({
+                "id": p.id(),
+                "path": p.get_path(),
+                "path_idx": p.get_path_idx(),
+                "injection_rd": p.injection_rd(),
+                "last_moved_rd": p.last_moved_rd(),
+                "origin": p.origin(),
+            })
+        };
+        let buffers: Vec<Value> = self
+            .network
+            .edges()
+            .into_iter()
+            .map(|edge| {
+                let packets: Vec<Value> = edge.iter_packets().map(packet_cfg).collect();
+                json!({ "from": edge.from(), "to": edge.to(), "packets": packets })
+            })
+            .collect();
+        let injection_queues: Vec<Value> = self
+            .network
+            .get_nodes()
+            .into_iter()
+            .map(|node_id| {
+                let queue = self.network.get_injection_queue(node_id);
+                Value::Array(queue.iter().map(packet_cfg).collect())
+            })
+            .collect();
+        let mut snapshot = json!({
+            "rd": time.round,
+            "prime": time.is_prime(),
+            config::ADJACENCY_KEY: self.network.to_config(),
+            "buffers": buffers,
+            "injection_queues": injection_queues,
+        });
+        if let Some(state) = self.protocol.save_state() {
+            snapshot["protocol_state"] = state;
+        }
+        snapshot
+    }
+
+    /// Resume from a snapshot of this simulation's network taken by `snapshot_to_config` after
+    /// forwarding in some round: every buffer and injection queue is replaced by the snapshot's
+    /// packets, the protocol's state is restored with `Protocol::load_state`, and the next round
+    /// is the one after the snapshot's. The adversaries, threshold, and metrics aren't part of a
+    /// snapshot and carry on as they were, and any rounds kept for `rollback` are dropped.
+    pub fn load_snapshot(&mut self, snapshot: &Value) -> Result<(), CfgErrorMsg> {
+        if snapshot.get(config::ADJACENCY_KEY) != Some(&self.network.to_config()) {
+            return Err(String::from("The snapshot is of a different network."));
+        }
+        if snapshot.get("prime").and_then(Value::as_bool) != Some(true) {
+            return Err(String::from(
+                "Only snapshots taken after forwarding can be loaded.",
+            ));
+        }
+        let rd = get_snapshot_usize(snapshot, "rd")?;
+        if !self.started {
+            self.start();
+        }
+
+        let mut buffered = Vec::new();
+        for buffer in snapshot
+            .get("buffers")
+            .and_then(Value::as_array)
+            .ok_or("No buffers in the snapshot.")?
+        {
+            let packets = buffer.get("packets").and_then(Value::as_array);
+            for cfg in packets.ok_or("No packets in a snapshot buffer.")? {
+                let p = self.packet_from_snapshot(cfg)?;
+                let edge = (
+                    get_snapshot_usize(buffer, "from")?,
+                    get_snapshot_usize(buffer, "to")?,
+                );
+                if (p.cur_node(), p.next_node()) != (Some(edge.0), Some(edge.1)) {
+                    return Err(format!(
+                        "Snapshot packet {} isn't on its buffer's edge.",
+                        p.id()
+                    ));
+                }
+                buffered.push(p);
+            }
+        }
+        let mut queued = Vec::new();
+        for queue in snapshot
+            .get("injection_queues")
+            .and_then(Value::as_array)
+            .ok_or("No injection queues in the snapshot.")?
+        {
+            for cfg in queue
+                .as_array()
+                .ok_or("A snapshot injection queue isn't a list.")?
+            {
+                queued.push(self.packet_from_snapshot(cfg)?);
+            }
+        }
+        if let Some(state) = snapshot.get("protocol_state") {
+            self.protocol.load_state(state.clone())?;
+        }
+
+        let edges: Vec<(NodeID, NodeID)> = self
+            .network
+            .edges()
+            .iter()
+            .map(|edge| (edge.from(), edge.to()))
+            .collect();
+        for (from_id, to_id) in edges {
+            self.network.take_buffer(from_id, to_id);
+        }
+        for node_id in self.network.get_nodes() {
+            self.network.take_injection_queue(node_id);
+        }
+        self.network.restore_packets(buffered);
+        for p in queued {
+            self.network.add_to_injection_queue(p);
+        }
+        self.rd = rd + 1;
+        self.finished = false;
+        self.snapshots.clear();
+        self.started_at = Some((Instant::now(), rd));
+        Ok(())
+    }
+
+    /// Recreate a packet saved in a snapshot, with its original id, origin, and rounds.
+    fn packet_from_snapshot(&mut self, cfg: &Value) -> Result<Packet, CfgErrorMsg> {
+        let path: Option<PacketPath> = cfg.get("path").and_then(Value::as_array).and_then(|ids| {
+            ids.iter()
+                .map(|id| id.as_u64().map(|id| id as usize))
+                .collect()
+        });
+        let path = path.ok_or(format!("No path in snapshot packet {}.", cfg))?;
+        self.factory.set_origin(get_snapshot_usize(cfg, "origin")?);
+        let mut p = self.factory.restore_packet(
+            PacketId(get_snapshot_usize(cfg, "id")?),
+            path,
+            get_snapshot_usize(cfg, "injection_rd")?,
+            get_snapshot_usize(cfg, "path_idx")?,
+        );
+        p.set_last_moved_rd(get_snapshot_usize(cfg, "last_moved_rd")?);
+        Ok(p)
+    }
+
+    /// Save a full snapshot of the network to `output_path/snapshot_rd_<rd>[_prime].json`.
+    fn save_snapshot(&self, time: SimTime) {
+        let suffix = if time.is_prime() { "_prime" } else { "" };
+        let file_path = self.output_path.join(format!(
+            "{}{}{}.json",
+            SNAPSHOT_FILENAME_PREFIX, time.round, suffix
+        ));
+        let data = serde_json::to_string_pretty(&self.snapshot_to_config(time)).unwrap();
+        fs::create_dir_all(&self.output_path).unwrap();
+        if fs::write(&file_path, data).is_err() {
+            eprintln!(
+                "Failed to save simulation snapshot to {}",
+                file_path.display()
+            );
         }
     }
 
     fn to_config_str(&self) -> String {
+        serde_json::to_string_pretty(&self.to_config_val()).unwrap()
+    }
+
+    fn to_config_val(&self) -> Value {
         let mut map = Map::new();
         map.insert(config::ADJACENCY_KEY.to_string(), self.network.to_config());
         map.insert(config::PROTOCOL_KEY.to_string(), self.protocol.to_config());
-        map.insert(
-            config::ADVERSARY_KEY.to_string(),
-            self.adversary.to_config(),
-        );
+        let adversary_cfg = match &self.adversaries[..] {
+            [adversary] => adversary.to_config(),
+            adversaries => Value::Array(adversaries.iter().map(|a| a.to_config()).collect()),
+        };
+        map.insert(config::ADVERSARY_KEY.to_string(), adversary_cfg);
         map.insert(
             config::THRESHOLD_KEY.to_string(),
             self.threshold.to_config(),
@@ -131,28 +1406,644 @@ impl Simulation {
         );
         map.insert(
             config::OUTPUT_PATH_KEY.to_string(),
-            Value::String(self.output_path.clone()),
+            Value::String(self.output_path.to_string_lossy().to_string()),
         );
-        serde_json::to_string_pretty(&Value::Object(map)).unwrap()
+        if let Some(admission) = &self.admission {
+            map.insert(config::ADMISSION_KEY.to_string(), admission.to_config());
+        }
+        map.insert(
+            config::END_OF_INJECTION_KEY.to_string(),
+            self.end_of_injection.to_config(),
+        );
+        if self.absorption != Absorption::default() {
+            map.insert(
+                config::ABSORPTION_KEY.to_string(),
+                self.absorption.to_config(),
+            );
+        }
+        if let Some(initial_backlog) = &self.initial_backlog {
+            map.insert(
+                config::INITIAL_BACKLOG_KEY.to_string(),
+                initial_backlog.to_config(),
+            );
+        }
+        if let Some(node_capacities) = self.network.node_capacities() {
+            map.insert(
+                config::NODE_CAPACITIES_KEY.to_string(),
+                node_capacities.to_config(),
+            );
+        }
+        if let Some(seed) = self.network.random_edge_order_seed() {
+            map.insert(
+                config::RANDOM_EDGE_ORDER_KEY.to_string(),
+                json!({ config::SEED_KEY: seed }),
+            );
+        }
+        if self.pipeline != RoundPipeline::default() {
+            map.insert(
+                config::ROUND_STEPS_KEY.to_string(),
+                self.pipeline.to_config(),
+            );
+        }
+        if let Some(seed) = self.seed {
+            map.insert(config::SEED_KEY.to_string(), json!(seed));
+        }
+        if let Some((max, excess_injections)) = self.max_injections_per_rd {
+            map.insert(config::MAX_INJECTIONS_PER_RD_KEY.to_string(), json!(max));
+            map.insert(
+                config::EXCESS_INJECTIONS_KEY.to_string(),
+                excess_injections.to_config(),
+            );
+        }
+        if let Some(stall_rds) = self.stall_rds {
+            map.insert(config::STALL_RDS_KEY.to_string(), json!(stall_rds));
+        }
+        if let Some(warm_up_rds) = self.warm_up_rds {
+            map.insert(config::WARM_UP_RDS_KEY.to_string(), json!(warm_up_rds));
+        }
+        if self.stop_on_write_error {
+            map.insert(config::STOP_ON_WRITE_ERROR_KEY.to_string(), json!(true));
+        }
+        if self.metrics_only {
+            map.insert(config::METRICS_ONLY_KEY.to_string(), json!(true));
+        }
+        if !self.metadata_columns.is_empty() {
+            map.insert(
+                config::METADATA_COLUMNS_KEY.to_string(),
+                json!(self.metadata_columns),
+            );
+        }
+        Value::Object(map)
     }
 
-    fn save_config(&self, output_path: &str) {
+    fn save_config(&self, output_path: &Path) {
         let data = self.to_config_str();
         fs::create_dir_all(output_path).unwrap();
-        let file_path = format!("{}/{}", output_path, SIM_CONFIG_FILENAME);
+        let file_path = output_path.join(SIM_CONFIG_FILENAME);
 
         let mut file = fs::OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .open(&file_path)
-            .expect(&format!(
+            .unwrap_or_else(|_| {
+                panic!(
+                    "Failed to save simulation config to {}",
+                    file_path.display()
+                )
+            });
+
+        if writeln!(file, "{}", data).is_err() {
+            eprintln!(
                 "Failed to save simulation config to {}",
-                file_path
-            ));
+                file_path.display()
+            );
+        }
+    }
+}
+
+fn get_snapshot_usize(cfg: &Value, key: &str) -> Result<usize, CfgErrorMsg> {
+    match cfg.get(key).and_then(Value::as_u64) {
+        Some(val) => Ok(val as usize),
+        None => Err(format!("No {} in snapshot entry {}.", key, cfg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::SDPathRandomBurstyAdversary;
+    use crate::network::presets::{construct_layered, construct_path};
+
+    fn buffer_ids(network: &Network) -> Vec<Vec<usize>> {
+        network
+            .edges()
+            .into_iter()
+            .map(|edge| edge.iter_packets().map(|p| p.id().0).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_rollback_replays_same_rounds() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_rollback");
+        let mut simulation = Simulation::new(
+            construct_path(5),
+            Protocol::new_greedy_fifo(1),
+            vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(7, 2))],
+            Threshold::timed_from_rds(100),
+            Vec::new(),
+            output_path.to_string_lossy().to_string(),
+        );
+        simulation.set_max_snapshots(5);
+
+        for _ in 0..10 {
+            simulation.step();
+        }
+        let states: Vec<Vec<Vec<usize>>> = (0..3)
+            .map(|_| {
+                simulation.step();
+                buffer_ids(simulation.network())
+            })
+            .collect();
+
+        assert_eq!(simulation.rollback(3), 3);
+        assert_eq!(simulation.rd(), 11);
+        for state in states {
+            simulation.step();
+            assert_eq!(buffer_ids(simulation.network()), state);
+        }
+        // Only the last 5 rounds can be rolled back.
+        assert_eq!(simulation.rollback(10), 5);
+        assert_eq!(simulation.rd(), 9);
+    }
+
+    #[test]
+    fn test_loaded_snapshot_resumes_simulation() {
+        let new_simulation = |network| {
+            let protocol = json!({ "protocol_name": "greedy_random", "capacity": 1, "seed": 2 });
+            let mut simulation = Simulation::new(
+                network,
+                Protocol::from_config(protocol).unwrap(),
+                Vec::new(),
+                Threshold::timed_from_rds(20),
+                Vec::new(),
+                std::env::temp_dir()
+                    .join("aqt_sim_test_load_snapshot")
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            simulation.set_metrics_only(true);
+            simulation
+        };
+        let mut network = construct_path(5);
+        let mut factory = PacketFactory::new();
+        for src_id in [0, 0, 0, 1, 1, 2, 2, 0] {
+            let p = factory.create_packet((src_id..5).collect(), 1, 0);
+            network.add_packet(p, src_id, src_id + 1);
+        }
+        let mut simulation = new_simulation(network);
+        simulation.step();
+        simulation.step();
+        let snapshot = simulation.snapshot_to_config(SimTime::forwarding(simulation.rd() - 1));
+        let snapshot: Value = serde_json::from_str(&snapshot.to_string()).unwrap();
+        let states: Vec<Vec<Vec<usize>>> = (0..6)
+            .map(|_| {
+                simulation.step();
+                buffer_ids(simulation.network())
+            })
+            .collect();
+
+        let resume = |snapshot: &Value| {
+            let mut loaded = new_simulation(construct_path(5));
+            loaded.load_snapshot(snapshot).unwrap();
+            assert_eq!(loaded.rd(), 3);
+            (0..6)
+                .map(|_| {
+                    loaded.step();
+                    buffer_ids(loaded.network())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(resume(&snapshot), states);
+        // Without the protocol's state, its random choices start over and the run differs.
+        let mut stateless = snapshot.clone();
+        stateless.as_object_mut().unwrap().remove("protocol_state");
+        assert_ne!(resume(&stateless), states);
+
+        let mut loaded = new_simulation(construct_path(4));
+        assert!(loaded.load_snapshot(&snapshot).is_err());
+        let mut injection = snapshot.clone();
+        injection["prime"] = json!(false);
+        assert!(new_simulation(construct_path(5))
+            .load_snapshot(&injection)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pipeline_drops_expired_packets() {
+        let mut network = construct_path(5);
+        let mut factory = PacketFactory::new();
+        for deadline in [1, 10] {
+            let mut p = factory.create_packet(vec![0, 1, 2, 3, 4], 1, 0);
+            p.set_deadline(Some(deadline));
+            network.add_packet(p, 0, 1);
+        }
+        let mut simulation = Simulation::new(
+            network,
+            Protocol::new_greedy_fifo(1),
+            Vec::new(),
+            Threshold::timed_from_rds(2),
+            Vec::new(),
+            std::env::temp_dir()
+                .join("aqt_sim_test_pipeline")
+                .to_string_lossy()
+                .to_string(),
+        );
+        let steps = json!(["forward", "drop_expired", "record"]);
+        simulation.set_pipeline(RoundPipeline::from_config(steps).unwrap());
+
+        simulation.step();
+        assert_eq!(simulation.network().num_dropped(), 0);
+        simulation.step();
+        assert_eq!(simulation.network().num_dropped(), 1);
+        assert_eq!(
+            buffer_ids(simulation.network()),
+            [vec![], vec![1], vec![], vec![]]
+        );
+        assert!(RoundPipeline::from_config(json!(["record", "inject", "record"])).is_err());
+    }
+
+    #[test]
+    fn test_absorb_on_arrival_skips_last_hop() {
+        let run = |absorption| {
+            let mut network = construct_path(4);
+            let mut factory = PacketFactory::new();
+            network.add_packet(factory.create_packet(vec![0, 1, 2, 3], 1, 0), 0, 1);
+            let mut simulation = Simulation::new(
+                network,
+                Protocol::new_greedy_fifo(1),
+                Vec::new(),
+                Threshold::timed_from_rds(10),
+                Vec::new(),
+                std::env::temp_dir()
+                    .join("aqt_sim_test_absorption")
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            simulation.set_metrics_only(true);
+            simulation.set_absorption(absorption);
+            simulation.run()
+        };
+        let forward = run(Absorption::Forward);
+        assert_eq!((forward.num_absorbed, forward.max_latency), (1, 2));
+        let on_arrival = run(Absorption::OnArrival);
+        assert_eq!((on_arrival.num_absorbed, on_arrival.max_latency), (1, 1));
+        assert_eq!(
+            Absorption::from_config(json!("on_arrival")).unwrap(),
+            Absorption::OnArrival
+        );
+    }
+
+    #[test]
+    fn test_absorb_limits_make_packets_wait() {
+        // Node 3 absorbs one packet per round, so of the three packets arriving together, the
+        // last waits two rounds in the buffer of its last edge.
+        let run = |absorption| {
+            let mut network = construct_path(4);
+            let mut factory = PacketFactory::new();
+            for _ in 0..3 {
+                network.add_packet(factory.create_packet(vec![0, 1, 2, 3], 1, 0), 0, 1);
+            }
+            let mut simulation = Simulation::new(
+                network,
+                Protocol::new_greedy_fifo(3),
+                Vec::new(),
+                Threshold::timed_from_rds(10),
+                Vec::new(),
+                std::env::temp_dir()
+                    .join("aqt_sim_test_absorb_limits")
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            let absorb = json!({ "absorb": [5, 5, 5, 1] });
+            simulation.set_node_capacities(NodeCapacities::from_config(absorb).unwrap());
+            simulation.set_metrics_only(true);
+            simulation.set_absorption(absorption);
+            simulation.run()
+        };
+        let forward = run(Absorption::Forward);
+        assert_eq!((forward.num_absorbed, forward.max_latency), (3, 4));
+        let on_arrival = run(Absorption::OnArrival);
+        assert_eq!((on_arrival.num_absorbed, on_arrival.max_latency), (3, 3));
+    }
+
+    #[test]
+    fn test_absorb_limits_keep_absorption_order() {
+        // Node 3 absorbs one packet per round. Packets 0, 1, and 2 wait in the last buffer while
+        // packet 3 is forwarded into it, and must still be absorbed first.
+        let mut network = construct_path(4);
+        let mut factory = PacketFactory::new();
+        for _ in 0..3 {
+            network.add_packet(factory.create_packet(vec![2, 3], 1, 0), 2, 3);
+        }
+        network.add_packet(factory.create_packet(vec![1, 2, 3], 1, 0), 1, 2);
+        let mut simulation = Simulation::new(
+            network,
+            Protocol::new_greedy_fifo(3),
+            Vec::new(),
+            Threshold::timed_from_rds(10),
+            Vec::new(),
+            std::env::temp_dir()
+                .join("aqt_sim_test_absorption_order")
+                .to_string_lossy()
+                .to_string(),
+        );
+        let absorb = json!({ "absorb": [5, 5, 5, 1] });
+        simulation.set_node_capacities(NodeCapacities::from_config(absorb).unwrap());
+        simulation.set_metrics_only(true);
+        simulation.set_absorption(Absorption::Forward);
+
+        let mut absorbed_order: Vec<usize> = Vec::new();
+        let mut left = buffer_ids(simulation.network()).concat();
+        while !left.is_empty() {
+            simulation.step();
+            let now = buffer_ids(simulation.network()).concat();
+            absorbed_order.extend(left.iter().filter(|id| !now.contains(id)));
+            left = now;
+        }
+        assert_eq!(absorbed_order, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_unseeded_run_is_reproducible() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_unseeded");
+        let mut simulation = Simulation::new(
+            construct_path(5),
+            Protocol::new_greedy_fifo(1),
+            vec![Adversary::new(SDPathRandomBurstyAdversary::new(2))],
+            Threshold::timed_from_rds(20),
+            Vec::new(),
+            output_path.to_string_lossy().to_string(),
+        );
+        simulation.run();
+
+        let saved_config = fs::read_to_string(output_path.join(SIM_CONFIG_FILENAME)).unwrap();
+        let saved_config = SimConfig::from_val(serde_json::from_str(&saved_config).unwrap());
+        let mut rerun = Simulation::from_config(saved_config.unwrap());
+        rerun.run();
+        assert_eq!(
+            buffer_ids(rerun.network()),
+            buffer_ids(simulation.network())
+        );
+    }
+
+    #[test]
+    fn test_base_seed_gives_common_adversary_randomness() {
+        let run = |protocol_cfg: Value| {
+            let cfg = json!({
+                "graph_adjacency": construct_path(6).to_config(),
+                "protocol": protocol_cfg,
+                "adversary": { "adversary_name": "sd_path_random_bursty", "sigma": 4 },
+                "threshold": { "threshold_name": "timed", "max_rds": 50 },
+                "recorders": [],
+                "output_path": "aqt_sim_test_base_seed",
+                "metrics_only": true,
+                "seed": 11,
+            });
+            let mut simulation = Simulation::from_config(SimConfig::from_val(cfg).unwrap());
+            simulation.run();
+            simulation.adversaries[0].to_config()["seed"].clone()
+        };
+        let fifo_seed = run(json!({ "protocol_name": "greedy_fifo", "capacity": 1 }));
+        let lis_seed = run(json!({
+            "protocol_name": "greedy_lis", "capacity": 1, "tie_break": "random"
+        }));
+        assert_eq!(fifo_seed, lis_seed);
+        assert_eq!(fifo_seed, SimRng::stream_seed(11, "adversary_0"));
+        assert_ne!(
+            SimRng::stream_seed(11, "adversary_0"),
+            SimRng::stream_seed(11, "protocol")
+        );
+    }
+
+    #[test]
+    fn test_random_edge_order_is_seeded() {
+        let cfg = json!({
+            "graph_adjacency": construct_layered(3, 3).to_config(),
+            "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+            "adversary": { "adversary_name": "sd_path_random" },
+            "threshold": { "threshold_name": "timed", "max_rds": 5 },
+            "recorders": [],
+            "output_path": "aqt_sim_test_random_edge_order",
+            "random_edge_order": {},
+            "seed": 2,
+        });
+        let orders = || {
+            let mut simulation = Simulation::from_config(SimConfig::from_val(cfg.clone()).unwrap());
+            let orders: Vec<_> = (0..3)
+                .map(|_| simulation.network.forwarding_order())
+                .collect();
+            (orders, simulation.to_config_str())
+        };
+        let (first, saved_config) = orders();
+        assert_eq!(first, orders().0);
+        // Each round's order is a new permutation of every buffer.
+        assert_ne!(first[0], first[1]);
+        let mut sorted = first[0].clone();
+        sorted.sort_unstable();
+        let mut eb_ids = construct_layered(3, 3).get_edgebuffers();
+        eb_ids.sort_unstable();
+        assert_eq!(sorted, eb_ids);
+        let saved_config: Value = serde_json::from_str(&saved_config).unwrap();
+        assert_eq!(
+            saved_config["random_edge_order"]["seed"],
+            SimRng::stream_seed(2, "edge_order")
+        );
+    }
 
-        if let Err(_) = writeln!(file, "{}", data) {
-            eprintln!("Failed to save simulation config to {}", file_path);
+    #[test]
+    fn test_metrics_only_run_writes_nothing() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_metrics_only");
+        let _ = fs::remove_dir_all(&output_path);
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        for _ in 0..2 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
         }
+        let mut simulation = Simulation::new(
+            network,
+            Protocol::new_greedy_fifo(1),
+            Vec::new(),
+            Threshold::timed_from_rds(100),
+            Vec::new(),
+            output_path.to_string_lossy().to_string(),
+        );
+        simulation.set_metrics_only(true);
+
+        let metrics = simulation.run();
+        assert!(!output_path.exists());
+        assert_eq!(
+            metrics,
+            SimMetrics {
+                num_rds: 3,
+                num_injected: 0,
+                num_excess_injections: 0,
+                num_absorbed: 2,
+                num_dropped: 0,
+                max_total_load: 2,
+                max_buffer_load: 2,
+                max_latency: 2,
+                total_latency: 3,
+            }
+        );
+        assert_eq!(metrics.mean_latency(), Some(1.5));
+    }
+
+    #[test]
+    fn test_completed_output_is_detected() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_complete");
+        let _ = fs::remove_dir_all(&output_path);
+        let new_simulation = |max_rds: usize| {
+            Simulation::new(
+                construct_path(3),
+                Protocol::new_greedy_fifo(1),
+                vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(7, 2))],
+                Threshold::timed_from_rds(max_rds),
+                Vec::new(),
+                output_path.to_string_lossy().to_string(),
+            )
+        };
+
+        let mut simulation = new_simulation(10);
+        assert!(!simulation.is_complete());
+        simulation.step();
+        simulation.close();
+        assert!(!simulation.is_complete());
+        simulation.run();
+        assert!(simulation.is_complete());
+        assert!(new_simulation(10).is_complete());
+        // A different config doesn't match the saved one.
+        assert!(!new_simulation(20).is_complete());
+        // Nor does a truncated summary.
+        let summary_path = output_path.join(SUMMARY_FILENAME);
+        let summary = fs::read_to_string(&summary_path).unwrap();
+        fs::write(&summary_path, &summary[..summary.len() / 2]).unwrap();
+        assert!(!new_simulation(10).is_complete());
+    }
+
+    #[test]
+    fn test_windows_separators_in_output_path() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_paths");
+        let _ = fs::remove_dir_all(&output_path);
+        let mut simulation = Simulation::new(
+            construct_path(3),
+            Protocol::new_greedy_fifo(1),
+            Vec::new(),
+            Threshold::timed_from_rds(2),
+            Vec::new(),
+            format!("{}\\batch\\run_1", output_path.to_string_lossy()),
+        );
+        simulation.run();
+        let run_path = output_path.join("batch").join("run_1");
+        assert!(run_path.join(SIM_CONFIG_FILENAME).exists());
+        assert!(run_path.join(SUMMARY_FILENAME).exists());
+    }
+
+    #[test]
+    fn test_injection_cap_discards_excess_packets() {
+        let run = |excess_injections| {
+            let mut simulation = Simulation::new(
+                construct_path(5),
+                Protocol::new_greedy_fifo(1),
+                vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(
+                    7, 20,
+                ))],
+                Threshold::timed_from_rds(50),
+                Vec::new(),
+                std::env::temp_dir()
+                    .join("aqt_sim_test_injection_cap")
+                    .to_string_lossy()
+                    .to_string(),
+            );
+            simulation.set_metrics_only(true);
+            simulation.set_max_injections_per_rd(3, excess_injections);
+            let mut injected = Vec::new();
+            while simulation.step() {
+                injected.push(simulation.metrics().num_injected);
+            }
+            (injected, *simulation.metrics())
+        };
+        let (injected, metrics) = run(ExcessInjections::Truncate);
+        assert!(injected.windows(2).all(|w| w[1] - w[0] <= 3));
+        assert!(metrics.num_excess_injections > 0);
+        let (_, rejected) = run(ExcessInjections::Reject);
+        assert!(rejected.num_excess_injections > metrics.num_excess_injections);
+        assert_eq!(
+            rejected.num_injected + rejected.num_excess_injections,
+            metrics.num_injected + metrics.num_excess_injections
+        );
+    }
+
+    #[test]
+    fn test_stall_watchdog() {
+        // A protocol which never forwards stalls once the first packet has sat for 4 rounds.
+        let output_path = std::env::temp_dir().join("aqt_sim_test_stall");
+        let mut simulation = Simulation::new(
+            construct_path(5),
+            Protocol::new_greedy_fifo(0),
+            vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(7, 2))],
+            Threshold::timed_from_rds(100),
+            Vec::new(),
+            output_path.to_string_lossy().to_string(),
+        );
+        simulation.set_stall_rds(4);
+        let first_rd = (1..).find(|_| {
+            simulation.step();
+            simulation.network().total_load() > 0
+        });
+        while simulation.step() {}
+        assert_eq!(simulation.status(), SimStatus::Stalled);
+        assert_eq!(simulation.rd(), first_rd.unwrap() + 4);
+    }
+
+    #[test]
+    fn test_write_error_fails_run() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_write_error");
+        let _ = fs::remove_dir_all(&output_path);
+        // A directory where the recorder's file should be makes every write to it fail.
+        fs::create_dir_all(output_path.join("buffer_load.csv")).unwrap();
+        let recorder_cfg = json!({ "recorder_name": "buffer_load", "flush_rounds": 1 });
+        let mut simulation = Simulation::new(
+            construct_path(3),
+            Protocol::new_greedy_fifo(1),
+            vec![Adversary::new(SDPathRandomBurstyAdversary::from_seed(7, 2))],
+            Threshold::timed_from_rds(100),
+            vec![Recorder::from_config(recorder_cfg).unwrap()],
+            output_path.to_string_lossy().to_string(),
+        );
+        simulation.set_stop_on_write_error(true);
+        simulation.run();
+
+        // The first flush, at the start of round 2, fails and stops the run.
+        assert_eq!(simulation.status(), SimStatus::Failed);
+        assert_eq!(simulation.rd(), 3);
+        assert!(simulation
+            .write_error()
+            .unwrap()
+            .contains("buffer_load.csv"));
+        let summary = fs::read_to_string(output_path.join(SUMMARY_FILENAME)).unwrap();
+        assert!(summary.contains("\"status\":\"failed\""));
+        assert!(!simulation.is_complete());
+    }
+
+    #[test]
+    fn test_phase_markers_on_schedule_switches() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_phase_markers");
+        let _ = fs::remove_dir_all(&output_path);
+        let cfg = json!({
+            "graph_adjacency": construct_path(3).to_config(),
+            "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+            "adversary": {
+                "adversary_name": "scheduled",
+                "windows": [[1, 2]],
+                "period": 3,
+                "adversary": { "adversary_name": "sd_path_random", "seed": 1 },
+            },
+            "threshold": { "threshold_name": "timed", "max_rds": 7 },
+            "recorders": [{ "recorder_name": "phases" }, { "recorder_name": "event_trace" }],
+            "output_path": output_path.to_string_lossy(),
+        });
+        Simulation::from_config(SimConfig::from_val(cfg).unwrap()).run();
+
+        let phases = fs::read_to_string(output_path.join("phases.csv")).unwrap();
+        assert_eq!(
+            phases,
+            "rd,adversary,phase\n1,0,active\n3,0,paused\n4,0,active\n6,0,paused\n7,0,active\n"
+        );
+        // Replay skips the markers in the trace.
+        let trace = fs::read_to_string(output_path.join("event_trace.jsonl")).unwrap();
+        assert!(trace.contains(r#""event":"phase","phase":"paused","prime":false,"rd":3"#));
+        assert!(trace::replay(&trace, 5, true).is_ok());
+        fs::remove_dir_all(&output_path).unwrap();
     }
 }
@@ -0,0 +1,126 @@
+//! This module contains the `RoundPipeline`, the configurable sequence of steps which a
+//! `Simulation` runs each round, so that model variants can add or reorder steps without changing
+//! the simulation loop.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use serde_json::Value;
+
+/// A single step of a round.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RoundStep {
+    /// Every adversary injects its packets, into the network or, if an `Admission` protocol is
+    /// set, into the injection queues.
+    Inject,
+    /// The `Admission` protocol, if any, admits packets from the injection queues.
+    Admit,
+    /// The protocol forwards packets, and the stall watchdog is checked.
+    Forward,
+    /// Packets whose deadline round has passed are dropped from the buffers.
+    DropExpired,
+    /// Every recorder records the current state and the threshold is checked. The state is the
+    /// prime state if the round's `Forward` step has already run.
+    Record,
+}
+
+const INJECT_NAME: &str = "inject";
+const ADMIT_NAME: &str = "admit";
+const FORWARD_NAME: &str = "forward";
+const DROP_EXPIRED_NAME: &str = "drop_expired";
+const RECORD_NAME: &str = "record";
+
+impl Configurable for RoundStep {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(INJECT_NAME) => Ok(Self::Inject),
+            Some(ADMIT_NAME) => Ok(Self::Admit),
+            Some(FORWARD_NAME) => Ok(Self::Forward),
+            Some(DROP_EXPIRED_NAME) => Ok(Self::DropExpired),
+            Some(RECORD_NAME) => Ok(Self::Record),
+            _ => Err(format!("No round step {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        let name = match self {
+            Self::Inject => INJECT_NAME,
+            Self::Admit => ADMIT_NAME,
+            Self::Forward => FORWARD_NAME,
+            Self::DropExpired => DROP_EXPIRED_NAME,
+            Self::Record => RECORD_NAME,
+        };
+        Value::String(name.to_string())
+    }
+}
+
+/// The steps run each round, in order. Each round has exactly one `Forward` step, each other step
+/// except `Record` appears at most once, and there is at most one `Record` step on each side of
+/// `Forward`, so that each state is recorded at most once per round.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RoundPipeline {
+    steps: Vec<RoundStep>,
+}
+
+impl RoundPipeline {
+    /// Get a new `RoundPipeline` with the given steps, or an error if they don't form a valid
+    /// round.
+    pub fn new(steps: Vec<RoundStep>) -> Result<Self, CfgErrorMsg> {
+        let forward_idx = match steps.iter().filter(|s| **s == RoundStep::Forward).count() {
+            1 => steps.iter().position(|s| *s == RoundStep::Forward).unwrap(),
+            _ => return Err(String::from("A round must have exactly one forward step.")),
+        };
+        for step in [RoundStep::Inject, RoundStep::Admit, RoundStep::DropExpired] {
+            if steps.iter().filter(|s| **s == step).count() > 1 {
+                return Err(format!(
+                    "Round step {} appears more than once.",
+                    step.to_config()
+                ));
+            }
+        }
+        let (before, after) = steps.split_at(forward_idx);
+        for half in [before, after] {
+            if half.iter().filter(|s| **s == RoundStep::Record).count() > 1 {
+                return Err(String::from(
+                    "A round may record at most once before and once after forwarding.",
+                ));
+            }
+        }
+        Ok(RoundPipeline { steps })
+    }
+
+    /// Get the steps of this pipeline, in order.
+    pub fn steps(&self) -> &[RoundStep] {
+        &self.steps
+    }
+}
+
+impl Default for RoundPipeline {
+    /// The original round: inject, admit, record, forward, record.
+    fn default() -> Self {
+        RoundPipeline {
+            steps: vec![
+                RoundStep::Inject,
+                RoundStep::Admit,
+                RoundStep::Record,
+                RoundStep::Forward,
+                RoundStep::Record,
+            ],
+        }
+    }
+}
+
+impl Configurable for RoundPipeline {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let steps = match config.as_array() {
+            Some(step_cfgs) => step_cfgs
+                .iter()
+                .map(|c| RoundStep::from_config(c.clone()))
+                .collect::<Result<Vec<RoundStep>, CfgErrorMsg>>()?,
+            None => return Err(String::from("Round steps must be an array.")),
+        };
+        Self::new(steps)
+    }
+
+    fn to_config(&self) -> Value {
+        Value::Array(self.steps.iter().map(|s| s.to_config()).collect())
+    }
+}
@@ -1,41 +1,127 @@
-//! This module contains the `SimRng` struct, which wraps either a seeded or unseeded random number
-//! generator.
+//! This module contains the `SimRng` struct, a seeded random number generator which remembers its
+//! seed, so that every run can be reproduced.
 
-use rand::{rngs::ThreadRng, Rng, SeedableRng};
+use crate::config::CfgErrorMsg;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const SEED_KEY: &str = "seed";
+
+// A `SimRng`'s saved state: its seed, and how many 32-bit words it has generated since.
+#[derive(Serialize, Deserialize)]
+struct SavedRng {
+    seed: u64,
+    word_pos: u64,
+}
 
 // Wrapper for a random number generator.
+#[derive(Clone)]
 pub struct SimRng {
-    seeded_rng: Option<ChaCha8Rng>,
-    unseeded_rng: Option<ThreadRng>,
+    rng: ChaCha8Rng,
+    seed: u64,
 }
 
 impl SimRng {
-    /// Get a new `SimRng` with no seed.
+    /// Get a new `SimRng` with a randomly generated seed. Components which fall back to this when
+    /// no seed is configured should still write `seed()` to their config, so that the run can be
+    /// reproduced.
     pub fn new() -> Self {
-        SimRng {
-            seeded_rng: None,
-            unseeded_rng: Some(rand::thread_rng()),
-        }
+        Self::from_seed(rand::thread_rng().gen())
     }
 
     /// Get a new `SimRng` with the given seed.
     pub fn from_seed(seed: u64) -> Self {
         SimRng {
-            seeded_rng: Some(ChaCha8Rng::seed_from_u64(seed)),
-            unseeded_rng: None,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            seed,
+        }
+    }
+
+    /// Get the seed this `SimRng` was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Get this `SimRng`'s state, its seed and how far it has advanced since, so that a checkpoint
+    /// can restore it with `load_state`.
+    pub fn save_state(&self) -> Value {
+        let word_pos = u64::try_from(self.rng.get_word_pos()).expect("SimRng advanced too far.");
+        serde_json::to_value(SavedRng {
+            seed: self.seed,
+            word_pos,
+        })
+        .unwrap()
+    }
+
+    /// Get a `SimRng` in the state saved by `save_state`.
+    pub fn load_state(state: Value) -> Result<Self, CfgErrorMsg> {
+        let saved: SavedRng = serde_json::from_value(state)
+            .map_err(|err| format!("Invalid random number generator state: {}", err))?;
+        let mut rng = Self::from_seed(saved.seed);
+        rng.rng.set_word_pos(saved.word_pos as u128);
+        Ok(rng)
+    }
+
+    /// Get the seed of the named stream of the given base seed. Each stream is an independent
+    /// ChaCha stream, so components seeded from different streams of one base seed don't share
+    /// randomness, and a component's stream doesn't depend on which other components exist.
+    pub fn stream_seed(base_seed: u64, stream: &str) -> u64 {
+        // FNV-1a, so that stream ids are stable across platforms and Rust versions.
+        let stream_id = stream.bytes().fold(0xcbf29ce484222325, |hash: u64, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        let mut rng = ChaCha8Rng::seed_from_u64(base_seed);
+        rng.set_stream(stream_id);
+        rng.gen()
+    }
+
+    /// Set the `"seed"` of the given component config, and of any component configs nested in it
+    /// (e.g. a shaped adversary's inner adversary), where it isn't already set.
+    pub fn fill_seed(config: &mut Value, seed: u64) {
+        if let Value::Object(map) = config {
+            map.entry(SEED_KEY).or_insert(Value::from(seed));
+            for nested in map.values_mut() {
+                Self::fill_seed(nested, seed);
+            }
         }
     }
 
     /// Get a random `usize` between 0 (inclusive) and max (exclusive).
     pub fn rand_int(&mut self, max: usize) -> usize {
-        if let Some(rng) = &mut self.seeded_rng {
-            return rng.gen_range(0..max);
-        }
-        if let Some(rng) = &mut self.unseeded_rng {
-            return rng.gen_range(0..max);
+        self.rng.gen_range(0..max)
+    }
+
+    /// Get a random index into the given positive weights, each chosen with probability
+    /// proportional to its weight.
+    pub fn rand_weighted(&mut self, weights: &[f64]) -> usize {
+        let total: f64 = weights.iter().sum();
+        let mut x = self.rng.gen::<f64>() * total;
+        for (i, weight) in weights.iter().enumerate() {
+            if x < *weight {
+                return i;
+            }
+            x -= weight;
         }
-        panic!("No rng for this config");
+        // Only reachable through rounding error.
+        weights.len() - 1
+    }
+
+    /// Get a random `f64` between 0 (inclusive) and 1 (exclusive).
+    pub fn rand_f64(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    /// Get a random `bool` which is true with probability `p`.
+    pub fn rand_bool(&mut self, p: f64) -> bool {
+        self.rng.gen_bool(p)
+    }
+
+    /// Shuffle the given slice uniformly at random.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        items.shuffle(&mut self.rng);
     }
 }
 
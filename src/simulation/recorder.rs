@@ -1,64 +1,426 @@
 use crate::config::{CfgErrorMsg, Configurable};
-use crate::network::Network;
-use crate::packet::Packet;
-use serde_json::{Map, Value};
+use crate::network::{AccessCounts, Network, NodeID};
+use crate::packet::{Packet, PacketId};
+use crate::simulation::audit::ConservationAuditRecorder;
+use crate::simulation::deadline::DeadlineViolationRecorder;
+use crate::simulation::fairness::FairnessRecorder;
+use crate::simulation::frames::FramesRecorder;
+use crate::simulation::metadata::Metadata;
+use crate::simulation::reservoir::ReservoirRecorder;
+use crate::simulation::time::{Phase, SimTime};
+use crate::simulation::trace::EventTraceRecorder;
+use hashbrown::{HashMap, HashSet};
+use serde_json::{json, Map, Value};
+use std::any::Any;
+use std::fmt;
+use std::fmt::Write as _;
 use std::fs;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-// For CSV/file writing, how many lines to keep in memory before writing to disk.
-const LINE_LIMIT: usize = 5000;
+// For CSV/file writing, how many lines to keep in memory before writing to disk by default.
+pub(super) const LINE_LIMIT: usize = 5000;
 
-/// Enum for all `Recorder`s.
-#[derive(Clone)]
-pub enum Recorder {
-    DebugPrint(DebugPrintRecorder),
-    File(FileRecorder),
+/// A message describing a failure to write a recorder's output.
+pub type WriteErrorMsg = String;
+
+/// Append data to the file at the given path, creating it if needed.
+pub(super) fn append_to_file(file_path: &Path, data: &str) -> Result<(), WriteErrorMsg> {
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)
+        .and_then(|mut file| file.write_all(data.as_bytes()))
+        .map_err(|err| {
+            format!(
+                "Failed to save simulation results to {}: {}",
+                file_path.display(),
+                err
+            )
+        })
+}
+
+/// Records some aspect of a `Simulation`, behind which any `RecorderTrait` implementation can be
+/// used.
+pub struct Recorder {
+    recorder: Box<dyn BoxedRecorder>,
+    // Only rounds divisible by this are recorded.
+    every_rds: usize,
+    // The packets absorbed in rounds which weren't recorded, and the last such round, to pass on
+    // at the next record.
+    skipped_absorbed: Vec<Packet>,
+    last_skipped: Option<SimTime>,
 }
 
 impl Recorder {
+    /// Get a `Recorder` recording with the given implementation, which may be defined outside this
+    /// crate. Its config must include its `recorder_name`.
+    pub fn new<R>(recorder: R) -> Self
+    where
+        R: RecorderTrait + Configurable + Clone + Send + 'static,
+    {
+        Recorder {
+            recorder: Box::new(recorder),
+            every_rds: 1,
+            skipped_absorbed: Vec::new(),
+            last_skipped: None,
+        }
+    }
+
     /// Get a new `DebugPrintRecorder`.
     pub fn new_debug_print() -> Self {
-        Recorder::DebugPrint(DebugPrintRecorder::new())
+        Self::new(DebugPrintRecorder::new())
+    }
+
+    /// Get the implementation behind this recorder, if it is an `R`.
+    pub fn downcast_ref<R: 'static>(&self) -> Option<&R> {
+        self.recorder.as_any().downcast_ref()
     }
 
-    /// Record the state of the `Simulation` via the `RecorderTrait`.
-    pub fn record(
+    /// Record the state of the `Simulation` via the `RecorderTrait`. Packets absorbed in rounds
+    /// which aren't recorded (see `set_every_rds`) are passed on as absorbed at the next record,
+    /// whichever state it is, so that every absorption is recorded, as of that state.
+    pub fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        if !self.recorded_states().includes(time.phase) {
+            return;
+        }
+        if !time.round.is_multiple_of(self.every_rds) {
+            if let Some(absorbed) = absorbed {
+                self.skipped_absorbed.extend(absorbed.iter().cloned());
+                self.last_skipped = Some(time);
+            }
+            return;
+        }
+        if self.skipped_absorbed.is_empty() {
+            return self.recorder.record(time, network, absorbed);
+        }
+        let mut all_absorbed = std::mem::take(&mut self.skipped_absorbed);
+        all_absorbed.extend(absorbed.into_iter().flatten().cloned());
+        self.last_skipped = None;
+        self.recorder.record(time, network, Some(&all_absorbed))
+    }
+
+    /// Record the packets absorbed since the last recorded round, if any, as of the last round
+    /// in which packets were absorbed. The `Simulation` calls this when it closes, so that the
+    /// absorptions of a run which ends between recorded rounds are still recorded.
+    pub fn record_skipped(&mut self, network: &Network) {
+        if let Some(time) = self.last_skipped.take() {
+            let absorbed = std::mem::take(&mut self.skipped_absorbed);
+            self.recorder.record(time, network, Some(&absorbed))
+        }
+    }
+
+    /// Get how often this `Recorder` records: only in rounds divisible by this.
+    pub fn every_rds(&self) -> usize {
+        self.every_rds
+    }
+
+    /// Set this `Recorder` to only record in rounds divisible by `every_rds`, which must be
+    /// positive.
+    pub fn set_every_rds(&mut self, every_rds: usize) {
+        assert!(
+            every_rds > 0,
+            "A recorder must record every positive number of rounds."
+        );
+        self.every_rds = every_rds;
+    }
+
+    /// Mark that the given adversary entered the given phase in the given round, via the
+    /// `RecorderTrait`. Markers are passed on whichever states and rounds are recorded.
+    pub fn record_phase(
         &mut self,
-        rd: usize,
-        prime: bool,
+        time: SimTime,
         network: &Network,
-        absorbed: Option<&Vec<Packet>>,
+        adversary_idx: usize,
+        phase: &str,
     ) {
-        match self {
-            Self::DebugPrint(rec) => rec.record(rd, prime, network, absorbed),
-            Self::File(rec) => rec.record(rd, prime, network, absorbed),
-        }
+        self.recorder
+            .record_phase(time, network, adversary_idx, phase)
+    }
+
+    /// Pass on the packets injected in the given round, before they enter the network, via the
+    /// `RecorderTrait`. Injections are passed on whichever states and rounds are recorded.
+    pub fn record_injection(&mut self, time: SimTime, network: &Network, injected: &[Packet]) {
+        self.recorder.record_injection(time, network, injected)
+    }
+
+    /// Returns whether this `Recorder` needs the `Network` to count its structural accesses.
+    pub fn instruments_network(&self) -> bool {
+        self.recorder.instruments_network()
+    }
+
+    /// Get when this `Recorder` flushes its buffered lines to disk, if it buffers lines.
+    pub fn flush_policy(&self) -> Option<FlushPolicy> {
+        self.recorder.flush_policy()
+    }
+
+    /// Set when this `Recorder` flushes its buffered lines to disk. Returns an error if it doesn't
+    /// buffer lines.
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) -> Result<(), CfgErrorMsg> {
+        self.recorder.set_flush_policy(policy)
+    }
+
+    /// Get the first error this `Recorder` hit writing its output, if any, via the
+    /// `RecorderTrait`.
+    pub fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.recorder.write_error()
+    }
+
+    /// Get which states this `Recorder` records via the `RecorderTrait`.
+    pub fn recorded_states(&self) -> RecordedStates {
+        self.recorder.recorded_states()
+    }
+
+    /// Set which states this `Recorder` records via the `RecorderTrait`.
+    pub fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.recorder.set_recorded_states(states)
     }
 
     /// Set the output path for this `Recorder` via the `RecorderTrait`.
-    pub fn set_output_path(&mut self, output_path: String) {
-        match self {
-            Self::DebugPrint(rec) => rec.set_output_path(output_path),
-            Self::File(rec) => rec.set_output_path(output_path),
-        }
+    pub fn set_output_path(&mut self, output_path: &Path) {
+        self.recorder.set_output_path(output_path)
+    }
+
+    /// Prepare this `Recorder` to record from the middle of a run via the `RecorderTrait`.
+    pub fn attach(&mut self, time: SimTime, network: &Network) {
+        self.recorder.attach(time, network)
+    }
+
+    /// Stamp the given metadata on this `Recorder`'s output via the `RecorderTrait`.
+    pub fn set_metadata(&mut self, metadata: &Metadata) {
+        self.recorder.set_metadata(metadata)
     }
 
     /// Close this `Recorder` via the `RecorderTrait`.
     pub fn close(&mut self) {
-        match self {
-            Self::DebugPrint(rec) => rec.close(),
-            Self::File(rec) => rec.close(),
+        self.recorder.close()
+    }
+
+    /// Write this `Recorder`'s rows to the given batch-level `MergedOutput` via the
+    /// `RecorderTrait`.
+    pub fn set_merged_output(&mut self, run_id: usize, merged_output: Arc<MergedOutput>) {
+        self.recorder.set_merged_output(run_id, merged_output)
+    }
+}
+
+impl Clone for Recorder {
+    fn clone(&self) -> Self {
+        Recorder {
+            recorder: self.recorder.clone_box(),
+            every_rds: self.every_rds,
+            skipped_absorbed: self.skipped_absorbed.clone(),
+            last_skipped: self.last_skipped,
         }
     }
 }
 
-const RECORDER_NAME_KEY: &str = "recorder_name";
+pub(super) const RECORDER_NAME_KEY: &str = "recorder_name";
 const DEBUG_PRINT_NAME: &str = "debug_print";
 const BUFFER_LOAD_NAME: &str = "buffer_load";
 const ABSORPTION_NAME: &str = "absorption";
 const SMOOTHED_CONFIG_LIS_NAME: &str = "smoothed_config_lis";
 const NUM_RDS_NAME: &str = "num_rds";
 const MAX_LATENCY_NAME: &str = "max_latency";
+const INJECTION_QUEUE_LOAD_NAME: &str = "injection_queue_load";
+pub(super) const EVENT_TRACE_NAME: &str = "event_trace";
+const ACCESS_COUNTS_NAME: &str = "access_counts";
+pub(super) const FRAMES_NAME: &str = "frames";
+pub(super) const CONSERVATION_AUDIT_NAME: &str = "conservation_audit";
+pub(super) const DEADLINE_VIOLATIONS_NAME: &str = "deadline_violations";
+pub(super) const RESERVOIR_NAME: &str = "reservoir";
+pub(super) const FAIRNESS_NAME: &str = "fairness";
+const FLOW_COUNTS_NAME: &str = "flow_counts";
+const HOP_WAIT_NAME: &str = "hop_wait";
+const ABSORBED_PATH_NAME: &str = "absorbed_path";
+const PHASES_NAME: &str = "phases";
+const GROUP_COMPLETION_NAME: &str = "group_completion";
+const HASH_KEY: &str = "hash";
+const DELTA_KEY: &str = "delta";
+const STATES_KEY: &str = "states";
+const EVERY_RDS_KEY: &str = "every_rds";
+const FLUSH_LINES_KEY: &str = "flush_lines";
+const FLUSH_ROUNDS_KEY: &str = "flush_rounds";
+const FLUSH_BYTES_KEY: &str = "flush_bytes";
+
+/// When a recorder which buffers lines in memory writes them to disk: once it holds the given
+/// number of lines, lines from the given number of rounds, or the given number of bytes.
+/// `Rounds(1)` flushes every round, which is useful for debugging. Defaults to `LINE_LIMIT` lines.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlushPolicy {
+    Lines(usize),
+    Rounds(usize),
+    Bytes(usize),
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self::Lines(LINE_LIMIT)
+    }
+}
+
+impl FlushPolicy {
+    /// Parse the flush policy from a recorder's config, if it sets one.
+    fn from_recorder_config(map: &Map<String, Value>) -> Result<Option<Self>, CfgErrorMsg> {
+        let keys = [FLUSH_LINES_KEY, FLUSH_ROUNDS_KEY, FLUSH_BYTES_KEY];
+        let mut set = keys
+            .iter()
+            .filter_map(|key| map.get(*key).map(|val| (*key, val)));
+        let (key, val) = match (set.next(), set.next()) {
+            (None, _) => return Ok(None),
+            (Some(entry), None) => entry,
+            (Some(_), Some(_)) => {
+                return Err(String::from("A recorder may set only one flush option."))
+            }
+        };
+        let limit = match val.as_u64() {
+            Some(limit) if limit > 0 => limit as usize,
+            _ => return Err(format!("{} must be a positive integer.", key)),
+        };
+        Ok(Some(match key {
+            FLUSH_LINES_KEY => Self::Lines(limit),
+            FLUSH_ROUNDS_KEY => Self::Rounds(limit),
+            _ => Self::Bytes(limit),
+        }))
+    }
+
+    /// Add this flush policy to a recorder's config.
+    fn insert_into_config(&self, map: &mut Map<String, Value>) {
+        let (key, limit) = match *self {
+            Self::Lines(limit) => (FLUSH_LINES_KEY, limit),
+            Self::Rounds(limit) => (FLUSH_ROUNDS_KEY, limit),
+            Self::Bytes(limit) => (FLUSH_BYTES_KEY, limit),
+        };
+        map.insert(key.to_string(), Value::from(limit));
+    }
+}
+
+/// Tracks what a recorder has buffered since its last flush, to check against its `FlushPolicy`.
+#[derive(Clone, Default)]
+pub(super) struct FlushTracker {
+    policy: FlushPolicy,
+    num_bytes: usize,
+    // The round of the first line buffered since the last flush, and the round being recorded.
+    first_rd: Option<usize>,
+    cur_rd: usize,
+}
+
+impl FlushTracker {
+    /// Get the flush policy.
+    pub(super) fn policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    /// Set the flush policy.
+    pub(super) fn set_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+    }
+
+    /// Note that the recorder is recording the given round.
+    pub(super) fn start_rd(&mut self, rd: usize) {
+        self.cur_rd = rd;
+    }
+
+    /// Note that the given line was buffered.
+    pub(super) fn add_line(&mut self, line: &str) {
+        self.num_bytes += line.len();
+        self.first_rd.get_or_insert(self.cur_rd);
+    }
+
+    /// Returns whether a buffer of the given number of lines should be flushed.
+    pub(super) fn is_due(&self, num_lines: usize) -> bool {
+        match self.policy {
+            FlushPolicy::Lines(limit) => num_lines >= limit,
+            FlushPolicy::Rounds(limit) => self
+                .first_rd
+                .is_some_and(|first_rd| self.cur_rd - first_rd >= limit),
+            FlushPolicy::Bytes(limit) => self.num_bytes >= limit,
+        }
+    }
+
+    /// Note that the buffer was flushed.
+    pub(super) fn reset(&mut self) {
+        self.num_bytes = 0;
+        self.first_rd = None;
+    }
+}
+
+/// Which of the two states recorded each round a `Recorder` records: the state after injection
+/// (non-prime), the state after forwarding (prime), or both. Recorders which only record
+/// absorptions only ever record prime states.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordedStates {
+    Both,
+    Prime,
+    NonPrime,
+}
+
+const BOTH_STATES_NAME: &str = "both";
+const PRIME_STATES_NAME: &str = "prime";
+const NON_PRIME_STATES_NAME: &str = "non_prime";
+
+impl RecordedStates {
+    /// Returns whether the state after the given phase should be recorded.
+    pub fn includes(&self, phase: Phase) -> bool {
+        match self {
+            Self::Both => true,
+            Self::Prime => phase.is_prime(),
+            Self::NonPrime => !phase.is_prime(),
+        }
+    }
+}
+
+impl Configurable for RecordedStates {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(BOTH_STATES_NAME) => Ok(Self::Both),
+            Some(PRIME_STATES_NAME) => Ok(Self::Prime),
+            Some(NON_PRIME_STATES_NAME) => Ok(Self::NonPrime),
+            _ => Err(format!("No recorded states {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Both => Value::String(BOTH_STATES_NAME.to_string()),
+            Self::Prime => Value::String(PRIME_STATES_NAME.to_string()),
+            Self::NonPrime => Value::String(NON_PRIME_STATES_NAME.to_string()),
+        }
+    }
+}
+
+type Builder = fn(Value) -> Result<Recorder, CfgErrorMsg>;
+
+// Every built-in recorder, by its name and how to build it from its config. The file recorders
+// take their type from their name.
+const RECORDERS: [(&str, Builder); 19] = [
+    (DEBUG_PRINT_NAME, build::<DebugPrintRecorder>),
+    (BUFFER_LOAD_NAME, build::<FileRecorder>),
+    (ABSORPTION_NAME, build::<FileRecorder>),
+    (SMOOTHED_CONFIG_LIS_NAME, build::<FileRecorder>),
+    (NUM_RDS_NAME, build::<FileRecorder>),
+    (MAX_LATENCY_NAME, build::<FileRecorder>),
+    (INJECTION_QUEUE_LOAD_NAME, build::<FileRecorder>),
+    (EVENT_TRACE_NAME, build::<EventTraceRecorder>),
+    (ACCESS_COUNTS_NAME, build::<FileRecorder>),
+    (FLOW_COUNTS_NAME, build::<FileRecorder>),
+    (HOP_WAIT_NAME, build::<FileRecorder>),
+    (PHASES_NAME, build::<FileRecorder>),
+    (GROUP_COMPLETION_NAME, build::<FileRecorder>),
+    (ABSORBED_PATH_NAME, build::<FileRecorder>),
+    (FRAMES_NAME, build::<FramesRecorder>),
+    (CONSERVATION_AUDIT_NAME, build::<ConservationAuditRecorder>),
+    (DEADLINE_VIOLATIONS_NAME, build::<DeadlineViolationRecorder>),
+    (RESERVOIR_NAME, build::<ReservoirRecorder>),
+    (FAIRNESS_NAME, build::<FairnessRecorder>),
+];
+
+fn build<R>(config: Value) -> Result<Recorder, CfgErrorMsg>
+where
+    R: RecorderTrait + Configurable + Clone + Send + 'static,
+{
+    Ok(Recorder::new(R::from_config(config)?))
+}
 
 impl Configurable for Recorder {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
@@ -69,93 +431,294 @@ impl Configurable for Recorder {
             _ => Err(String::from("No protocol name found.")),
         }?;
 
-        match &recorder_name[..] {
-            DEBUG_PRINT_NAME => Ok(Self::DebugPrint(DebugPrintRecorder::new())),
-            BUFFER_LOAD_NAME => Ok(Self::File(FileRecorder::new(
-                FileRecorderType::BufferLoadCSV,
-            ))),
-            ABSORPTION_NAME => Ok(Self::File(FileRecorder::new(
-                FileRecorderType::AbsorptionCSV,
-            ))),
-            SMOOTHED_CONFIG_LIS_NAME => Ok(Self::File(FileRecorder::new(
-                FileRecorderType::SmoothedConfigLISCSV,
-            ))),
-            NUM_RDS_NAME => Ok(Self::File(FileRecorder::new(
-                FileRecorderType::NumRdsMetric(0),
-            ))),
-            MAX_LATENCY_NAME => Ok(Self::File(FileRecorder::new(
-                FileRecorderType::MaxLatencyMetric(0),
-            ))),
-            _ => Err(format!("No recorder with name {}.", recorder_name)),
+        let mut recorder = match RECORDERS.iter().find(|(name, _)| name == recorder_name) {
+            Some((_, build)) => build(config.clone()),
+            None => Err(format!("No recorder with name {}.", recorder_name)),
+        }?;
+        if let Some(states_cfg) = map.get(STATES_KEY) {
+            recorder.set_recorded_states(RecordedStates::from_config(states_cfg.clone())?);
         }
+        if let Some(policy) = FlushPolicy::from_recorder_config(&map)? {
+            recorder.set_flush_policy(policy)?;
+        }
+        if let Some(every_rds) = map.get(EVERY_RDS_KEY) {
+            match every_rds.as_u64() {
+                Some(every_rds) if every_rds > 0 => recorder.set_every_rds(every_rds as usize),
+                _ => {
+                    return Err(String::from(
+                        "Recorder every_rds must be a positive integer.",
+                    ))
+                }
+            }
+        }
+        Ok(recorder)
     }
 
     fn to_config(&self) -> Value {
-        let mut map: Map<String, Value> = Map::new();
-        let key = RECORDER_NAME_KEY.to_string();
-        let val = match self {
-            Self::DebugPrint(_) => DEBUG_PRINT_NAME.to_string(),
-            Self::File(r) => match r.recorder_type {
-                FileRecorderType::BufferLoadCSV => BUFFER_LOAD_NAME.to_string(),
-                FileRecorderType::AbsorptionCSV => ABSORPTION_NAME.to_string(),
-                FileRecorderType::SmoothedConfigLISCSV => SMOOTHED_CONFIG_LIS_NAME.to_string(),
-                FileRecorderType::NumRdsMetric(_) => NUM_RDS_NAME.to_string(),
-                FileRecorderType::MaxLatencyMetric(_) => MAX_LATENCY_NAME.to_string(),
-            },
+        let mut map = match self.recorder.to_config() {
+            Value::Object(map) => map,
+            _ => Map::new(),
         };
-        map.insert(key, Value::String(val));
+        if self.recorded_states() != RecordedStates::Both {
+            map.insert(STATES_KEY.to_string(), self.recorded_states().to_config());
+        }
+        if let Some(policy) = self.flush_policy().filter(|p| *p != FlushPolicy::default()) {
+            policy.insert_into_config(&mut map);
+        }
+        if self.every_rds != 1 {
+            map.insert(EVERY_RDS_KEY.to_string(), Value::from(self.every_rds));
+        }
         Value::Object(map)
     }
 }
 
 /// Trait implemented by all recorders.
 pub trait RecorderTrait {
-    fn record(&mut self, rd: usize, prime: bool, network: &Network, absorbed: Option<&Vec<Packet>>);
-    fn set_output_path(&mut self, output_path: String);
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>);
+    fn set_output_path(&mut self, output_path: &Path);
     fn close(&mut self);
+    fn recorded_states(&self) -> RecordedStates;
+    fn set_recorded_states(&mut self, states: RecordedStates);
+    fn set_merged_output(&mut self, _run_id: usize, _merged_output: Arc<MergedOutput>) {}
+    /// Prepare to record from the middle of a run, given the last state before it. Most recorders
+    /// need no preparation, so this does nothing by default.
+    fn attach(&mut self, _time: SimTime, _network: &Network) {}
+    /// Stamp the given metadata on every row (or the header) of the output, before anything has
+    /// been recorded. Recorders which don't write files ignore it.
+    fn set_metadata(&mut self, _metadata: &Metadata) {}
+    /// Mark that the adversary with the given index entered the given phase, so that the output
+    /// can be segmented by phase. Most recorders ignore phases.
+    fn record_phase(
+        &mut self,
+        _time: SimTime,
+        _network: &Network,
+        _adversary_idx: usize,
+        _phase: &str,
+    ) {
+    }
+    /// Note the packets an adversary injects in the given round, before they enter the network.
+    /// Most recorders only look at the network, so ignore them.
+    fn record_injection(&mut self, _time: SimTime, _network: &Network, _injected: &[Packet]) {}
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        None
+    }
+    /// Returns whether this recorder needs the `Network` to count its structural accesses. Most
+    /// don't.
+    fn instruments_network(&self) -> bool {
+        false
+    }
+    /// Get when this recorder flushes its buffered lines to disk, if it buffers lines.
+    fn flush_policy(&self) -> Option<FlushPolicy> {
+        None
+    }
+    /// Set when this recorder flushes its buffered lines to disk. Returns an error if it doesn't
+    /// buffer lines, as by default.
+    fn set_flush_policy(&mut self, _policy: FlushPolicy) -> Result<(), CfgErrorMsg> {
+        Err(String::from("This recorder doesn't buffer lines to flush."))
+    }
+}
+
+// The object-safe view of a recorder which `Recorder` holds, implemented for every configurable
+// `RecorderTrait` implementation.
+trait BoxedRecorder: RecorderTrait + Send {
+    fn clone_box(&self) -> Box<dyn BoxedRecorder>;
+    fn to_config(&self) -> Value;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<R> BoxedRecorder for R
+where
+    R: RecorderTrait + Configurable + Clone + Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn BoxedRecorder> {
+        Box::new(self.clone())
+    }
+
+    fn to_config(&self) -> Value {
+        Configurable::to_config(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-/// Prints the network and any to the console.
+const CHANGED_ONLY_KEY: &str = "changed_only";
+const COLOR_KEY: &str = "color";
+const RDS_KEY: &str = "rds";
+// ANSI escape codes for moved and absorbed packets.
+const MOVED_COLOR: &str = "\x1b[32m";
+const ABSORBED_COLOR: &str = "\x1b[31m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Prints the network and any to the console. Optionally, only prints the rounds in a given range,
+/// only prints the buffers whose contents changed since the previous print, and colors packets
+/// which moved to another buffer since the previous print (green) and absorbed packets (red).
 #[derive(Clone)]
-pub struct DebugPrintRecorder;
+pub struct DebugPrintRecorder {
+    states: RecordedStates,
+    changed_only: bool,
+    color: bool,
+    // The first and last rounds to print, if limited.
+    rds: Option<(usize, usize)>,
+    // The packets in each buffer, and the buffer of each packet, at the previous print.
+    last_buffers: HashMap<(NodeID, NodeID), Vec<PacketId>>,
+    last_positions: HashMap<PacketId, (NodeID, NodeID)>,
+}
 
 impl DebugPrintRecorder {
     fn new() -> Self {
-        DebugPrintRecorder
+        Self::with_options(false, false, None)
     }
-}
 
-impl RecorderTrait for DebugPrintRecorder {
-    fn record(
+    /// Get a new `DebugPrintRecorder` which prints only the buffers whose contents changed since
+    /// the previous print if `changed_only` is set, colors moved and absorbed packets if `color`
+    /// is set, and prints only the rounds in the given inclusive range, if any.
+    pub fn with_options(changed_only: bool, color: bool, rds: Option<(usize, usize)>) -> Self {
+        DebugPrintRecorder {
+            states: RecordedStates::Both,
+            changed_only,
+            color,
+            rds,
+            last_buffers: HashMap::new(),
+            last_positions: HashMap::new(),
+        }
+    }
+
+    /// Get what to print for the given state, or `None` if its round isn't printed.
+    fn render(
         &mut self,
-        rd: usize,
-        prime: bool,
+        time: SimTime,
         network: &Network,
         absorbed: Option<&Vec<Packet>>,
-    ) {
-        if prime {
-            println!("{}':", rd)
-        } else {
-            println!("{}:", rd)
+    ) -> Option<String> {
+        if let Some((first_rd, last_rd)) = self.rds {
+            if time.round < first_rd || time.round > last_rd {
+                return None;
+            }
         }
-        println!("{}", network);
-        if let Some(absorbed_packets) = absorbed {
-            if absorbed_packets.len() == 0 {
-                return;
+        let mut out = match time.is_prime() {
+            true => format!("{}':\n", time.round),
+            false => format!("{}:\n", time.round),
+        };
+        let mut buffers = HashMap::new();
+        let mut positions = HashMap::new();
+        for edge in network.edges() {
+            let buffer = (edge.from(), edge.to());
+            let ids: Vec<PacketId> = edge.buffer().iter().map(Packet::id).collect();
+            let unchanged = self
+                .last_buffers
+                .get(&buffer)
+                .map_or(ids.is_empty(), |last| *last == ids);
+            if !self.changed_only || !unchanged {
+                let packets: Vec<String> = edge
+                    .buffer()
+                    .iter()
+                    .map(|p| {
+                        let moved = self
+                            .last_positions
+                            .get(&p.id())
+                            .is_some_and(|last| *last != buffer);
+                        self.paint(format!("{:?}", p), moved, MOVED_COLOR)
+                    })
+                    .collect();
+                writeln!(out, "{}, {}: [{}]", buffer.0, buffer.1, packets.join(", ")).unwrap();
             }
-            println!("Absorbed Packets:");
+            positions.extend(ids.iter().map(|id| (*id, buffer)));
+            buffers.insert(buffer, ids);
+        }
+        self.last_buffers = buffers;
+        self.last_positions = positions;
+        out.push('\n');
+        if let Some(absorbed_packets) = absorbed.filter(|packets| !packets.is_empty()) {
+            out.push_str("Absorbed Packets:\n");
             for packet in absorbed_packets {
-                println!("{:?}", packet);
+                let packet = self.paint(format!("{:?}", packet), true, ABSORBED_COLOR);
+                writeln!(out, "{}", packet).unwrap();
             }
+            out.push('\n');
+        }
+        Some(out)
+    }
+
+    // Wrap the given text in the given color if coloring is on and `highlight` is set.
+    fn paint(&self, text: String, highlight: bool, color: &str) -> String {
+        match self.color && highlight {
+            true => format!("{}{}{}", color, text, RESET_COLOR),
+            false => text,
+        }
+    }
+}
+
+impl RecorderTrait for DebugPrintRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        if let Some(out) = self.render(time, network, absorbed) {
+            print!("{}", out);
         }
-        println!();
     }
 
     fn close(&mut self) {
         println!("Simulation finished.");
     }
 
-    fn set_output_path(&mut self, _output_path: String) {}
+    fn set_output_path(&mut self, _output_path: &Path) {}
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+}
+
+impl Configurable for DebugPrintRecorder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let flag = |key: &str| match config.get(key) {
+            Some(Value::Bool(flag)) => Ok(*flag),
+            None => Ok(false),
+            _ => Err(format!("Debug print {} must be a bool.", key)),
+        };
+        let rds = match config.get(RDS_KEY).map(|rds| {
+            let rds = rds.as_array().map(|rds| rds.iter().map(Value::as_u64));
+            rds.map_or_else(Vec::new, Iterator::collect::<Vec<_>>)
+        }) {
+            Some(rds) => match rds[..] {
+                [Some(first_rd), Some(last_rd)] if first_rd <= last_rd => {
+                    Some((first_rd as usize, last_rd as usize))
+                }
+                _ => {
+                    return Err(String::from(
+                        "Debug print rounds must be a first and last round, in order.",
+                    ))
+                }
+            },
+            None => None,
+        };
+        Ok(Self::with_options(
+            flag(CHANGED_ONLY_KEY)?,
+            flag(COLOR_KEY)?,
+            rds,
+        ))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            RECORDER_NAME_KEY.to_string(),
+            Value::String(DEBUG_PRINT_NAME.to_string()),
+        );
+        if self.changed_only {
+            map.insert(CHANGED_ONLY_KEY.to_string(), Value::Bool(true));
+        }
+        if self.color {
+            map.insert(COLOR_KEY.to_string(), Value::Bool(true));
+        }
+        if let Some((first_rd, last_rd)) = self.rds {
+            map.insert(RDS_KEY.to_string(), json!([first_rd, last_rd]));
+        }
+        Value::Object(map)
+    }
 }
 
 /// Types of file recorders.
@@ -163,19 +726,62 @@ impl RecorderTrait for DebugPrintRecorder {
 enum FileRecorderType {
     AbsorptionCSV,
     BufferLoadCSV,
+    // Like `BufferLoadCSV`, but only writes a row when a buffer's load has changed since the
+    // last record (taking every load to be 0 before the first record).
+    BufferLoadDeltaCSV,
     SmoothedConfigLISCSV,
     NumRdsMetric(usize),
     MaxLatencyMetric(usize),
+    InjectionQueueLoadCSV,
+    AccessCountsCSV,
+    // The number of packets which arrived at (were injected or forwarded into) and departed from
+    // (were forwarded out of, absorbed from, or dropped from) each buffer since the last record.
+    FlowCountsCSV,
+    // For each packet which left a buffer (by being forwarded or absorbed) since the last record,
+    // the round it entered the buffer and how many rounds it waited there.
+    HopWaitCSV,
+    // Each absorbed packet's route, as its nodes or (if set) a hash of them.
+    AbsorbedPathCSV { hash: bool },
+    // A marker row whenever an adversary changes phase.
+    PhasesCSV,
+    // The completion time of each group of packets, once its last packet is absorbed.
+    GroupCompletionCSV,
 }
 
 /// Write some aspect of the simulation state to a file.
 #[derive(Clone)]
 pub struct FileRecorder {
     recorder_type: FileRecorderType,
-    lines: Vec<String>,
+    states: RecordedStates,
+    // The buffered lines, and how many there are. Rows are formatted straight into the buffer,
+    // which keeps its capacity between flushes, so recording doesn't allocate per row.
+    buf: String,
+    num_lines: usize,
+    flush: FlushTracker,
     // We require the output dir path to be set; optional so that Simulation::new() caller doesn't
     // have to construct and provide every individual file's output path.
-    file_path: Option<String>,
+    file_path: Option<PathBuf>,
+    // The last recorded load of each buffer, for delta recording.
+    last_loads: HashMap<(NodeID, NodeID), usize>,
+    // The loads of every buffer at the current record, kept between records to reuse its capacity.
+    loads: Vec<(NodeID, NodeID, usize)>,
+    // The network's access counts at the last record.
+    last_access_counts: AccessCounts,
+    // The buffer of every packet in the network at the last record, by packet id, for flow counts.
+    last_buffers: HashMap<PacketId, (NodeID, NodeID)>,
+    // The buffer of every packet in the network at the last record and the round it entered it,
+    // by packet id, for hop waits.
+    last_arrivals: HashMap<PacketId, ((NodeID, NodeID), usize)>,
+    // The size of each injected group of packets which isn't complete yet, and how many of its
+    // packets are still to be absorbed, by group id.
+    open_groups: HashMap<usize, (usize, usize)>,
+    // If set, rows are prefixed with the run id and written to the batch's merged file instead.
+    merged_output: Option<(usize, Arc<MergedOutput>)>,
+    // The metadata columns prepended to the header and to every row, if any.
+    header_prefix: String,
+    row_prefix: String,
+    // The first error hit saving lines, if any.
+    write_error: Option<WriteErrorMsg>,
 }
 
 impl FileRecorder {
@@ -183,8 +789,21 @@ impl FileRecorder {
     fn new(recorder_type: FileRecorderType) -> Self {
         FileRecorder {
             recorder_type,
-            lines: vec![Self::type_to_header(recorder_type).to_string()],
+            states: RecordedStates::Both,
+            buf: Self::type_to_header(recorder_type).to_string(),
+            num_lines: 1,
+            flush: FlushTracker::default(),
             file_path: None,
+            last_loads: HashMap::new(),
+            loads: Vec::new(),
+            last_access_counts: AccessCounts::default(),
+            last_buffers: HashMap::new(),
+            last_arrivals: HashMap::new(),
+            open_groups: HashMap::new(),
+            merged_output: None,
+            header_prefix: String::new(),
+            row_prefix: String::new(),
+            write_error: None,
         }
     }
 
@@ -192,62 +811,97 @@ impl FileRecorder {
         match recorder_type {
             FileRecorderType::AbsorptionCSV => "absorption.csv",
             FileRecorderType::BufferLoadCSV => "buffer_load.csv",
+            FileRecorderType::BufferLoadDeltaCSV => "buffer_load.csv",
             FileRecorderType::SmoothedConfigLISCSV => "smoothed_config_lis.csv",
             FileRecorderType::NumRdsMetric(_) => "num_rds.csv",
             FileRecorderType::MaxLatencyMetric(_) => "max_latency.csv",
+            FileRecorderType::InjectionQueueLoadCSV => "injection_queue_load.csv",
+            FileRecorderType::AccessCountsCSV => "access_counts.csv",
+            FileRecorderType::FlowCountsCSV => "flow_counts.csv",
+            FileRecorderType::HopWaitCSV => "hop_wait.csv",
+            FileRecorderType::AbsorbedPathCSV { .. } => "absorbed_path.csv",
+            FileRecorderType::PhasesCSV => "phases.csv",
+            FileRecorderType::GroupCompletionCSV => "group_completion.csv",
         }
     }
 
     const fn type_to_header(recorder_type: FileRecorderType) -> &'static str {
         match recorder_type {
-            FileRecorderType::AbsorptionCSV => "rd,packet_id,packet_injection_rd\n",
+            FileRecorderType::AbsorptionCSV => {
+                "rd,packet_id,packet_injection_rd,adversary,src,dest,path_len,latency\n"
+            }
             FileRecorderType::BufferLoadCSV => "rd,prime,buffer_from,buffer_to,load\n",
+            FileRecorderType::BufferLoadDeltaCSV => "rd,prime,buffer_from,buffer_to,load\n",
             FileRecorderType::SmoothedConfigLISCSV => {
                 "rd,prime,buffer_from,buffer_to,packet_id,injection_rd\n"
             }
             FileRecorderType::NumRdsMetric(_) => "num_rds\n",
             FileRecorderType::MaxLatencyMetric(_) => "max_latency\n",
+            FileRecorderType::InjectionQueueLoadCSV => "rd,prime,node,load\n",
+            FileRecorderType::AccessCountsCSV => {
+                "rd,prime,edgebuffer_lookups,packet_moves,buffer_scans\n"
+            }
+            FileRecorderType::FlowCountsCSV => {
+                "rd,prime,buffer_from,buffer_to,arrivals,departures\n"
+            }
+            FileRecorderType::HopWaitCSV => "rd,packet_id,buffer_from,buffer_to,arrival_rd,wait\n",
+            FileRecorderType::AbsorbedPathCSV { hash: false } => "rd,packet_id,src,dest,path\n",
+            FileRecorderType::AbsorbedPathCSV { hash: true } => "rd,packet_id,src,dest,path_hash\n",
+            FileRecorderType::PhasesCSV => "rd,adversary,phase\n",
+            FileRecorderType::GroupCompletionCSV => {
+                "rd,group,adversary,size,injection_rd,completion_time\n"
+            }
         }
     }
 
     /// Write a line to the recorder.
-    fn write(&mut self, line: String) {
-        if self.lines.len() >= LINE_LIMIT {
+    fn write(&mut self, line: fmt::Arguments) {
+        self.flush_if_due();
+        let start = self.buf.len();
+        if let Some((run_id, _)) = &self.merged_output {
+            write!(self.buf, "{},", run_id).unwrap();
+        }
+        self.buf.push_str(&self.row_prefix);
+        self.buf.write_fmt(line).unwrap();
+        self.flush.add_line(&self.buf[start..]);
+        self.num_lines += 1;
+    }
+
+    /// Save and clear the buffered lines if the flush policy says so.
+    fn flush_if_due(&mut self) {
+        if self.flush.is_due(self.num_lines) {
             self.save();
-            self.lines = Vec::new();
+            self.buf.clear();
+            self.num_lines = 0;
+            self.flush.reset();
         }
-        self.lines.push(line);
     }
 
     /// Save the lines to a file.
     fn save(&mut self) {
         match self.recorder_type {
-            FileRecorderType::NumRdsMetric(metric) => self.write(format!("{}", metric)),
-            FileRecorderType::MaxLatencyMetric(metric) => self.write(format!("{}", metric)),
+            FileRecorderType::NumRdsMetric(metric) => self.write(format_args!("{}\n", metric)),
+            FileRecorderType::MaxLatencyMetric(metric) => self.write(format_args!("{}\n", metric)),
             _ => (),
         }
 
-        let data = self.lines.concat();
-        let file_path_unwrapped = self
-            .file_path
-            .as_ref()
-            .expect("You must set an output path for each recorder.");
-
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(true)
-            .open(&file_path_unwrapped)
-            .expect(&format!(
-                "Failed to save simulation results to {}",
-                file_path_unwrapped
-            ));
-
-        if let Err(_) = writeln!(file, "{}", data) {
-            eprintln!(
-                "Failed to save simulation results to {}",
-                file_path_unwrapped
-            );
+        let data = &self.buf;
+        let result = match &self.merged_output {
+            Some((_, merged_output)) => {
+                let filename = Self::type_to_filename(self.recorder_type);
+                let header = self.header_prefix.clone() + Self::type_to_header(self.recorder_type);
+                merged_output.append(filename, &header, data)
+            }
+            None => {
+                let file_path = self
+                    .file_path
+                    .as_ref()
+                    .expect("You must set an output path for each recorder.");
+                append_to_file(file_path, data)
+            }
+        };
+        if let Err(msg) = result {
+            self.write_error.get_or_insert(msg);
         }
     }
 }
@@ -257,63 +911,216 @@ impl RecorderTrait for FileRecorder {
         self.save();
     }
 
-    fn set_output_path(&mut self, dir_path: String) {
-        fs::create_dir_all(dir_path.clone()).expect(&format!(
-            "Failed to save simulation results to {}",
-            &dir_path
-        ));
+    fn instruments_network(&self) -> bool {
+        matches!(self.recorder_type, FileRecorderType::AccessCountsCSV)
+    }
+
+    fn flush_policy(&self) -> Option<FlushPolicy> {
+        Some(self.flush.policy)
+    }
 
-        let mut file_path = String::from(dir_path);
-        file_path.push('/');
-        file_path.push_str(Self::type_to_filename(self.recorder_type));
-        self.file_path = Some(file_path);
+    fn set_flush_policy(&mut self, policy: FlushPolicy) -> Result<(), CfgErrorMsg> {
+        self.flush.policy = policy;
+        Ok(())
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.file_path = Some(dir_path.join(Self::type_to_filename(self.recorder_type)));
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn set_merged_output(&mut self, run_id: usize, merged_output: Arc<MergedOutput>) {
+        // The merged file has a single header, written by the `MergedOutput`.
+        self.buf.clear();
+        self.num_lines = 0;
+        self.merged_output = Some((run_id, merged_output));
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.header_prefix = metadata.header_prefix();
+        self.row_prefix = metadata.row_prefix();
+        if self.merged_output.is_none() {
+            // Nothing but the header has been buffered yet.
+            self.buf = self.header_prefix.clone() + Self::type_to_header(self.recorder_type);
+        }
     }
 
-    fn record(
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+
+    fn record_phase(
         &mut self,
-        rd: usize,
-        prime: bool,
-        network: &Network,
-        absorbed: Option<&Vec<Packet>>,
+        time: SimTime,
+        _network: &Network,
+        adversary_idx: usize,
+        phase: &str,
     ) {
+        if let FileRecorderType::PhasesCSV = self.recorder_type {
+            self.flush.start_rd(time.round);
+            self.write(format_args!("{},{},{}\n", time.round, adversary_idx, phase));
+        }
+    }
+
+    fn record_injection(&mut self, _time: SimTime, _network: &Network, injected: &[Packet]) {
+        if let FileRecorderType::GroupCompletionCSV = self.recorder_type {
+            for group in injected.iter().filter_map(Packet::group) {
+                let (size, remaining) = self.open_groups.entry(group).or_default();
+                *size += 1;
+                *remaining += 1;
+            }
+        }
+    }
+
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        let (rd, prime) = (time.round, time.is_prime());
+        self.flush.start_rd(rd);
+        self.flush_if_due();
         match self.recorder_type {
             FileRecorderType::AbsorptionCSV => {
-                if !prime {
-                    return;
-                }
-                for packet in absorbed.unwrap() {
-                    self.write(format!(
-                        "{},{},{}\n",
+                for packet in absorbed.into_iter().flatten() {
+                    self.write(format_args!(
+                        "{},{},{},{},{},{},{},{}\n",
                         rd,
                         packet.id(),
-                        packet.injection_rd()
+                        packet.injection_rd(),
+                        packet.origin(),
+                        packet.src_node(),
+                        packet.dest_node(),
+                        packet.path_len(),
+                        rd - packet.injection_rd()
                     ));
                 }
             }
             FileRecorderType::BufferLoadCSV => {
                 let prime_flag = if prime { 1 } else { 0 };
-                for (from_id, to_id) in network.get_edgebuffers() {
-                    let load = network.get_edgebuffer(from_id, to_id).unwrap().buffer.len();
-                    self.write(format!(
+                let mut loads = std::mem::take(&mut self.loads);
+                network.load_snapshot(&mut loads);
+                for (from_id, to_id, load) in &loads {
+                    self.write(format_args!(
                         "{},{},{},{},{}\n",
                         rd, prime_flag, from_id, to_id, load
                     ));
                 }
+                self.loads = loads;
+            }
+            FileRecorderType::BufferLoadDeltaCSV => {
+                let prime_flag = if prime { 1 } else { 0 };
+                let mut loads = std::mem::take(&mut self.loads);
+                network.load_snapshot(&mut loads);
+                for (from_id, to_id, load) in &loads {
+                    let last_load = self
+                        .last_loads
+                        .insert((*from_id, *to_id), *load)
+                        .unwrap_or(0);
+                    if *load != last_load {
+                        self.write(format_args!(
+                            "{},{},{},{},{}\n",
+                            rd, prime_flag, from_id, to_id, load
+                        ));
+                    }
+                }
+                self.loads = loads;
             }
             FileRecorderType::SmoothedConfigLISCSV => {
                 self.write_smoothed_config_lis_lines(rd, prime, network);
             }
+            FileRecorderType::InjectionQueueLoadCSV => {
+                let prime_flag = if prime { 1 } else { 0 };
+                for node_id in network.get_nodes() {
+                    let load = network.get_injection_queue(node_id).len();
+                    self.write(format_args!("{},{},{},{}\n", rd, prime_flag, node_id, load));
+                }
+            }
+            FileRecorderType::AccessCountsCSV => {
+                // Counts are written as the accesses since the previous record.
+                let prime_flag = if prime { 1 } else { 0 };
+                let counts = network.access_counts().unwrap_or_default();
+                let last = self.last_access_counts;
+                self.write(format_args!(
+                    "{},{},{},{},{}\n",
+                    rd,
+                    prime_flag,
+                    counts.edgebuffer_lookups - last.edgebuffer_lookups,
+                    counts.packet_moves - last.packet_moves,
+                    counts.buffer_scans - last.buffer_scans
+                ));
+                self.last_access_counts = counts;
+            }
+            FileRecorderType::FlowCountsCSV => {
+                self.write_flow_counts_lines(rd, prime, network);
+            }
+            FileRecorderType::HopWaitCSV => {
+                self.write_hop_wait_lines(rd, network, absorbed);
+            }
+            FileRecorderType::AbsorbedPathCSV { hash } => {
+                for packet in absorbed.into_iter().flatten() {
+                    let route = packet.route();
+                    let path = match hash {
+                        true => format!("{:016x}", route_hash(route)),
+                        false => route
+                            .iter()
+                            .map(NodeID::to_string)
+                            .collect::<Vec<_>>()
+                            .join("-"),
+                    };
+                    self.write(format_args!(
+                        "{},{},{},{},{}\n",
+                        rd,
+                        packet.id(),
+                        packet.src_node(),
+                        packet.dest_node(),
+                        path
+                    ));
+                }
+            }
             FileRecorderType::NumRdsMetric(record) => {
                 if rd > record {
                     self.recorder_type = FileRecorderType::NumRdsMetric(rd);
                 }
             }
-            FileRecorderType::MaxLatencyMetric(record) => {
-                if !prime {
-                    return;
+            // Phase markers are written by `record_phase`.
+            FileRecorderType::PhasesCSV => (),
+            FileRecorderType::GroupCompletionCSV => {
+                for packet in absorbed.into_iter().flatten() {
+                    let Some(group) = packet.group() else {
+                        continue;
+                    };
+                    let Some((size, remaining)) = self.open_groups.get_mut(&group) else {
+                        continue;
+                    };
+                    *remaining -= 1;
+                    if *remaining == 0 {
+                        let size = *size;
+                        self.open_groups.remove(&group);
+                        self.write(format_args!(
+                            "{},{},{},{},{},{}\n",
+                            rd,
+                            group,
+                            packet.origin(),
+                            size,
+                            packet.injection_rd(),
+                            rd - packet.injection_rd()
+                        ));
+                    }
                 }
+            }
+            FileRecorderType::MaxLatencyMetric(record) => {
                 let mut max_latency = record;
-                for packet in absorbed.unwrap() {
+                for packet in absorbed.into_iter().flatten() {
                     if rd - packet.injection_rd() > max_latency {
                         max_latency = rd - packet.injection_rd();
                     }
@@ -326,23 +1133,152 @@ impl RecorderTrait for FileRecorder {
     }
 }
 
+impl Configurable for FileRecorder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let recorder_type = match config.get(RECORDER_NAME_KEY).and_then(Value::as_str) {
+            Some(BUFFER_LOAD_NAME) => match config.get(DELTA_KEY) {
+                Some(Value::Bool(true)) => Ok(FileRecorderType::BufferLoadDeltaCSV),
+                Some(Value::Bool(false)) | None => Ok(FileRecorderType::BufferLoadCSV),
+                _ => Err(String::from("Buffer load delta must be a bool.")),
+            },
+            Some(ABSORPTION_NAME) => Ok(FileRecorderType::AbsorptionCSV),
+            Some(SMOOTHED_CONFIG_LIS_NAME) => Ok(FileRecorderType::SmoothedConfigLISCSV),
+            Some(NUM_RDS_NAME) => Ok(FileRecorderType::NumRdsMetric(0)),
+            Some(MAX_LATENCY_NAME) => Ok(FileRecorderType::MaxLatencyMetric(0)),
+            Some(INJECTION_QUEUE_LOAD_NAME) => Ok(FileRecorderType::InjectionQueueLoadCSV),
+            Some(ACCESS_COUNTS_NAME) => Ok(FileRecorderType::AccessCountsCSV),
+            Some(FLOW_COUNTS_NAME) => Ok(FileRecorderType::FlowCountsCSV),
+            Some(HOP_WAIT_NAME) => Ok(FileRecorderType::HopWaitCSV),
+            Some(PHASES_NAME) => Ok(FileRecorderType::PhasesCSV),
+            Some(GROUP_COMPLETION_NAME) => Ok(FileRecorderType::GroupCompletionCSV),
+            Some(ABSORBED_PATH_NAME) => match config.get(HASH_KEY) {
+                Some(Value::Bool(hash)) => Ok(FileRecorderType::AbsorbedPathCSV { hash: *hash }),
+                None => Ok(FileRecorderType::AbsorbedPathCSV { hash: false }),
+                _ => Err(String::from("Absorbed path hash must be a bool.")),
+            },
+            _ => Err(String::from("No file recorder name found.")),
+        }?;
+        Ok(Self::new(recorder_type))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        let name = match self.recorder_type {
+            FileRecorderType::BufferLoadCSV => BUFFER_LOAD_NAME,
+            FileRecorderType::BufferLoadDeltaCSV => {
+                map.insert(DELTA_KEY.to_string(), Value::Bool(true));
+                BUFFER_LOAD_NAME
+            }
+            FileRecorderType::AbsorptionCSV => ABSORPTION_NAME,
+            FileRecorderType::SmoothedConfigLISCSV => SMOOTHED_CONFIG_LIS_NAME,
+            FileRecorderType::NumRdsMetric(_) => NUM_RDS_NAME,
+            FileRecorderType::MaxLatencyMetric(_) => MAX_LATENCY_NAME,
+            FileRecorderType::InjectionQueueLoadCSV => INJECTION_QUEUE_LOAD_NAME,
+            FileRecorderType::AccessCountsCSV => ACCESS_COUNTS_NAME,
+            FileRecorderType::FlowCountsCSV => FLOW_COUNTS_NAME,
+            FileRecorderType::HopWaitCSV => HOP_WAIT_NAME,
+            FileRecorderType::PhasesCSV => PHASES_NAME,
+            FileRecorderType::GroupCompletionCSV => GROUP_COMPLETION_NAME,
+            FileRecorderType::AbsorbedPathCSV { hash } => {
+                if hash {
+                    map.insert(HASH_KEY.to_string(), Value::Bool(true));
+                }
+                ABSORBED_PATH_NAME
+            }
+        };
+        map.insert(
+            RECORDER_NAME_KEY.to_string(),
+            Value::String(name.to_string()),
+        );
+        Value::Object(map)
+    }
+}
+
 impl FileRecorder {
+    fn write_flow_counts_lines(&mut self, rd: usize, prime: bool, network: &Network) {
+        let prime_flag = if prime { 1 } else { 0 };
+        let mut counts: HashMap<(NodeID, NodeID), (usize, usize)> = HashMap::new();
+        let mut buffers = HashMap::new();
+        for edge in network.edges() {
+            for p in edge.iter_packets() {
+                buffers.insert(p.id(), edge.ids());
+                match self.last_buffers.remove(&p.id()) {
+                    Some(last_buffer) if last_buffer == edge.ids() => continue,
+                    Some(last_buffer) => counts.entry(last_buffer).or_default().1 += 1,
+                    None => (),
+                }
+                counts.entry(edge.ids()).or_default().0 += 1;
+            }
+        }
+        // Packets which are no longer in any buffer have departed.
+        for last_buffer in self.last_buffers.values() {
+            counts.entry(*last_buffer).or_default().1 += 1;
+        }
+        self.last_buffers = buffers;
+
+        for (from_id, to_id) in network.get_edgebuffers() {
+            let (arrivals, departures) = counts.get(&(from_id, to_id)).copied().unwrap_or_default();
+            self.write(format_args!(
+                "{},{},{},{},{},{}\n",
+                rd, prime_flag, from_id, to_id, arrivals, departures
+            ));
+        }
+    }
+
+    fn write_hop_wait_lines(
+        &mut self,
+        rd: usize,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) {
+        let mut arrivals = HashMap::new();
+        let mut departed = Vec::new();
+        for edge in network.edges() {
+            for p in edge.iter_packets() {
+                let arrival = (edge.ids(), p.last_moved_rd());
+                arrivals.insert(p.id(), arrival);
+                match self.last_arrivals.get(&p.id()) {
+                    Some(last_arrival) if *last_arrival != arrival => {
+                        departed.push((p.id(), *last_arrival))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for p in absorbed.into_iter().flatten() {
+            if let Some(last_arrival) = self.last_arrivals.get(&p.id()) {
+                departed.push((p.id(), *last_arrival));
+            }
+        }
+        self.last_arrivals = arrivals;
+
+        departed.sort_unstable();
+        for (id, ((from_id, to_id), arrival_rd)) in departed {
+            self.write(format_args!(
+                "{},{},{},{},{},{}\n",
+                rd,
+                id,
+                from_id,
+                to_id,
+                arrival_rd,
+                rd - arrival_rd
+            ));
+        }
+    }
+
     fn write_smoothed_config_lis_lines(&mut self, rd: usize, prime: bool, network: &Network) {
         let prime_flag = if prime { 1 } else { 0 };
-        let edgebuffers_ids = network.get_edgebuffers();
         let mut smoothing_queue: Vec<&Packet> = Vec::new();
-        for eb_ids in edgebuffers_ids.into_iter().rev() {
-            let buffer = &network.get_edgebuffer(eb_ids.0, eb_ids.1).unwrap().buffer;
-            for packet in buffer {
-                smoothing_queue.push(packet);
-            }
+        for edge in network.edges().into_iter().rev() {
+            let eb_ids = edge.ids();
+            smoothing_queue.extend(edge.iter_packets());
 
             match Self::pop_oldest_packet(&mut smoothing_queue) {
-                None => self.write(format!(
+                None => self.write(format_args!(
                     "{},{},{},{},{},{}\n",
                     rd, prime_flag, eb_ids.0, eb_ids.1, -1, -1
                 )),
-                Some(oldest) => self.write(format!(
+                Some(oldest) => self.write(format_args!(
                     "{},{},{},{},{},{}\n",
                     rd,
                     prime_flag,
@@ -356,7 +1292,7 @@ impl FileRecorder {
         // "Negative buffers" for packets remaining in the smoothing queue.
         let mut negative_buffer_to: i64 = 0;
         while let Some(oldest) = Self::pop_oldest_packet(&mut smoothing_queue) {
-            self.write(format!(
+            self.write(format_args!(
                 "{},{},{},{},{},{}\n",
                 rd,
                 prime_flag,
@@ -370,14 +1306,13 @@ impl FileRecorder {
     }
 
     fn pop_oldest_packet<'a>(queue: &'a mut Vec<&Packet>) -> Option<&'a Packet> {
-        if queue.len() == 0 {
+        if queue.is_empty() {
             return None;
         }
         let mut min_injection_rd = usize::MAX;
-        let mut min_id = usize::MAX;
+        let mut min_id = PacketId(usize::MAX);
         let mut min_injection_idx = 0;
-        for i in 0..queue.len() {
-            let p = queue[i];
+        for (i, p) in queue.iter().enumerate() {
             if p.injection_rd() <= min_injection_rd && p.id() < min_id {
                 min_id = p.id();
                 min_injection_rd = p.injection_rd();
@@ -388,3 +1323,457 @@ impl FileRecorder {
     }
 }
 
+/// Hash a route's node ids by FNV-1a, so that hashes are stable across platforms and runs.
+fn route_hash(route: &[NodeID]) -> u64 {
+    route
+        .iter()
+        .flat_map(|id| (*id as u64).to_le_bytes())
+        .fold(0xcbf29ce484222325, |hash: u64, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+const RUNS_FILENAME: &str = "runs.csv";
+const RUNS_HEADER: &str = "output_path\n";
+
+/// A batch-level output directory into which the `FileRecorder`s of every simulation in a batch
+/// append their rows, so that each recorder type has a single file for the whole batch. Each row
+/// is prefixed with the id of the run which wrote it, and `runs.csv` maps run ids to each run's
+/// output path. Writes are serialized by a mutex, so runs may share it across threads.
+pub struct MergedOutput {
+    dir_path: PathBuf,
+    // The names of the files created so far in this batch. Existing files are truncated when
+    // first written to, so that reruns of a batch don't append to old results.
+    created: Mutex<HashSet<String>>,
+}
+
+impl MergedOutput {
+    /// Get a new `MergedOutput` writing to the given directory.
+    pub fn new(dir_path: PathBuf) -> Self {
+        fs::create_dir_all(&dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        MergedOutput {
+            dir_path,
+            created: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Record that the run with the given id writes its other outputs to `output_path`.
+    pub fn register_run(&self, run_id: usize, output_path: &Path) -> Result<(), WriteErrorMsg> {
+        self.append(
+            RUNS_FILENAME,
+            RUNS_HEADER,
+            &format!("{},{}\n", run_id, output_path.display()),
+        )
+    }
+
+    /// Append data to the given merged file, writing the header (with a `run_id` column) if this
+    /// is the first write to the file in this batch.
+    fn append(&self, filename: &str, header: &str, data: &str) -> Result<(), WriteErrorMsg> {
+        let mut created = self.created.lock().unwrap();
+        let file_path = self.dir_path.join(filename);
+        let is_new = created.insert(filename.to_string());
+
+        let mut options = fs::OpenOptions::new();
+        if is_new {
+            options.write(true).create(true).truncate(true);
+        } else {
+            options.append(true);
+        }
+        let header = if is_new {
+            format!("run_id,{}", header)
+        } else {
+            String::new()
+        };
+        options
+            .open(&file_path)
+            .and_then(|mut file| write!(file, "{}{}", header, data))
+            .map_err(|err| {
+                format!(
+                    "Failed to save simulation results to {}: {}",
+                    file_path.display(),
+                    err
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_absorption_rows() {
+        let mut factory = PacketFactory::new();
+        let mut recorder = FileRecorder::new(FileRecorderType::AbsorptionCSV);
+        let mut p = factory.create_packet(vec![0, 1, 2, 3], 2, 1);
+        p.set_path_idx(3);
+        recorder.record(SimTime::forwarding(5), &construct_path(4), Some(&vec![p]));
+        assert_eq!(recorder.buf.lines().nth(1), Some("5,0,2,0,1,3,2,3"));
+    }
+
+    #[test]
+    fn test_absorbed_paths() {
+        let mut factory = PacketFactory::new();
+        let mut p = factory.create_packet(vec![0, 1, 2, 3], 2, 1);
+        p.set_path_idx(3);
+        let absorbed = vec![p];
+        let mut recorder = FileRecorder::new(FileRecorderType::AbsorbedPathCSV { hash: false });
+        recorder.record(SimTime::injection(5), &construct_path(4), None);
+        recorder.record(SimTime::forwarding(5), &construct_path(4), Some(&absorbed));
+        assert_eq!(
+            recorder.buf.lines().skip(1).collect::<Vec<_>>(),
+            ["5,0,1,3,1-2-3"]
+        );
+
+        let config = serde_json::json!({ "recorder_name": "absorbed_path", "hash": true });
+        let mut recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        recorder.record(SimTime::forwarding(5), &construct_path(4), Some(&absorbed));
+        if let Some(recorder) = recorder.downcast_ref::<FileRecorder>() {
+            let row = format!("5,0,1,3,{:016x}", route_hash(&[1, 2, 3]));
+            assert_eq!(recorder.buf.lines().nth(1), Some(&row[..]));
+            assert_ne!(route_hash(&[1, 2, 3]), route_hash(&[1, 3, 2]));
+        }
+    }
+
+    #[test]
+    fn test_group_completion() {
+        let mut factory = PacketFactory::new();
+        let mut group = Vec::new();
+        for path in [vec![0, 1], vec![0, 1, 2]] {
+            let mut p = factory.create_packet(path, 2, 0);
+            p.set_group(Some(0));
+            group.push(p);
+        }
+        let loner = factory.create_packet(vec![0, 1], 2, 0);
+        let network = construct_path(3);
+        let config = serde_json::json!({ "recorder_name": "group_completion" });
+        let mut recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        recorder.record_injection(SimTime::injection(2), &network, &group);
+        recorder.record(
+            SimTime::forwarding(3),
+            &network,
+            Some(&vec![group[0].clone(), loner]),
+        );
+        recorder.record(
+            SimTime::forwarding(6),
+            &network,
+            Some(&vec![group[1].clone()]),
+        );
+        if let Some(recorder) = recorder.downcast_ref::<FileRecorder>() {
+            let rows: Vec<&str> = recorder.buf.lines().skip(1).collect();
+            assert_eq!(rows, ["6,0,0,2,2,4"]);
+        }
+    }
+
+    #[test]
+    fn test_hop_waits() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut recorder = FileRecorder::new(FileRecorderType::HopWaitCSV);
+
+        network.set_cur_rd(1);
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        recorder.record(SimTime::injection(1), &network, None);
+        recorder.record(SimTime::forwarding(1), &network, Some(&Vec::new()));
+        network.set_cur_rd(3);
+        network.move_packet((0, 1), (1, 2), 0);
+        recorder.record(SimTime::forwarding(3), &network, Some(&Vec::new()));
+        let absorbed = vec![network.remove_packet((1, 2), |_| true).unwrap()];
+        recorder.record(SimTime::forwarding(4), &network, Some(&absorbed));
+
+        let rows: Vec<&str> = recorder.buf.lines().skip(1).collect();
+        assert_eq!(rows, ["3,0,0,1,1,2", "4,0,1,2,3,1"]);
+    }
+
+    #[test]
+    fn test_flow_counts() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut recorder = FileRecorder::new(FileRecorderType::FlowCountsCSV);
+
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        recorder.record(SimTime::injection(1), &network, None);
+        network.move_packet((0, 1), (1, 2), 0);
+        recorder.record(SimTime::forwarding(1), &network, Some(&Vec::new()));
+        let absorbed = vec![network.remove_packet((1, 2), |_| true).unwrap()];
+        recorder.record(SimTime::injection(2), &network, None);
+        recorder.record(SimTime::forwarding(2), &network, Some(&absorbed));
+
+        assert_eq!(
+            recorder.buf,
+            "rd,prime,buffer_from,buffer_to,arrivals,departures\n\
+             1,0,0,1,1,0\n1,0,1,2,0,0\n\
+             1,1,0,1,0,1\n1,1,1,2,1,0\n\
+             2,0,0,1,0,0\n2,0,1,2,0,1\n\
+             2,1,0,1,0,0\n2,1,1,2,0,0\n"
+        );
+    }
+
+    #[test]
+    fn test_buffer_load_deltas() {
+        let config = serde_json::json!({ "recorder_name": "buffer_load", "delta": true });
+        let mut recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+
+        // The loads of buffers (0, 1) and (1, 2) at each record.
+        let mut loads = Vec::new();
+        let mut record = |time: SimTime, network: &Network, recorder: &mut Recorder| {
+            recorder.record(time, network, Some(&Vec::new()));
+            let load = |from_id, to_id| network.edge(from_id, to_id).unwrap().load();
+            loads.push(((time.round, time.is_prime()), [load(0, 1), load(1, 2)]));
+        };
+        for _ in 0..2 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        }
+        record(SimTime::injection(1), &network, &mut recorder);
+        network.move_packet((0, 1), (1, 2), 0);
+        record(SimTime::forwarding(1), &network, &mut recorder);
+        record(SimTime::injection(2), &network, &mut recorder);
+        network.move_packet((0, 1), (1, 2), 0);
+        network.remove_packet((1, 2), |_| true).unwrap();
+        record(SimTime::forwarding(2), &network, &mut recorder);
+        network.remove_packet((1, 2), |_| true).unwrap();
+        record(SimTime::injection(3), &network, &mut recorder);
+
+        let recorder = recorder.downcast_ref::<FileRecorder>().unwrap();
+        let rows: Vec<&str> = recorder.buf.lines().skip(1).collect();
+        // Nothing changed in round 2's injection, and buffer (1, 2) drops back to 0 in round 3.
+        assert_eq!(
+            rows,
+            [
+                "1,0,0,1,2",
+                "1,1,0,1,1",
+                "1,1,1,2,1",
+                "2,1,0,1,0",
+                "3,0,1,2,0"
+            ]
+        );
+
+        // Replaying the rows from all-zero loads gives back the load of every buffer at each record.
+        let mut rebuilt = [0, 0];
+        for (time, expected) in loads {
+            for row in &rows {
+                let cols: Vec<usize> = row.split(',').map(|col| col.parse().unwrap()).collect();
+                if (cols[0], cols[1] == 1) == time {
+                    rebuilt[cols[2]] = cols[4];
+                }
+            }
+            assert_eq!(rebuilt, expected, "{:?}", time);
+        }
+    }
+
+    #[test]
+    fn test_merged_output() {
+        let dir_path = std::env::temp_dir().join("aqt_sim_test_merged_output");
+        let _ = fs::remove_dir_all(&dir_path);
+        let merged_output = Arc::new(MergedOutput::new(dir_path.clone()));
+        let mut factory = PacketFactory::new();
+
+        for (run_id, num_packets) in [(0, 1), (1, 2)] {
+            let run_path = dir_path.join(format!("run_{}", run_id));
+            merged_output.register_run(run_id, &run_path).unwrap();
+            let config = serde_json::json!({ "recorder_name": "buffer_load" });
+            let mut recorder = Recorder::from_config(config).unwrap();
+            recorder.set_merged_output(run_id, merged_output.clone());
+            let mut network = construct_path(2);
+            for _ in 0..num_packets {
+                network.add_packet(factory.create_packet(vec![0, 1], 1, 0), 0, 1);
+            }
+            recorder.record(SimTime::injection(1), &network, None);
+            recorder.close();
+        }
+
+        let buffer_load = fs::read_to_string(dir_path.join("buffer_load.csv")).unwrap();
+        let runs = fs::read_to_string(dir_path.join(RUNS_FILENAME)).unwrap();
+        fs::remove_dir_all(&dir_path).unwrap();
+        assert_eq!(
+            buffer_load,
+            "run_id,rd,prime,buffer_from,buffer_to,load\n0,1,0,0,1,1\n1,1,0,0,1,2\n"
+        );
+        assert_eq!(
+            runs,
+            format!(
+                "run_id,output_path\n0,{}\n1,{}\n",
+                dir_path.join("run_0").display(),
+                dir_path.join("run_1").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_recorded_states() {
+        let network = construct_path(2);
+        for (states, expected) in [
+            ("both", vec!["1,0,0,1,0", "1,1,0,1,0"]),
+            ("prime", vec!["1,1,0,1,0"]),
+            ("non_prime", vec!["1,0,0,1,0"]),
+        ] {
+            let config = serde_json::json!({ "recorder_name": "buffer_load", "states": states });
+            let mut recorder = Recorder::from_config(config.clone()).unwrap();
+            recorder.record(SimTime::injection(1), &network, None);
+            recorder.record(SimTime::forwarding(1), &network, Some(&Vec::new()));
+            let buf = &recorder.downcast_ref::<FileRecorder>().unwrap().buf;
+            assert_eq!(
+                buf.lines().skip(1).collect::<Vec<_>>(),
+                expected,
+                "{}",
+                states
+            );
+
+            let states = RecordedStates::from_config(serde_json::json!(states)).unwrap();
+            assert_eq!(recorder.recorded_states(), states);
+            let round_trip = Recorder::from_config(recorder.to_config()).unwrap();
+            assert_eq!(round_trip.recorded_states(), states);
+        }
+        // Recording both states is the default, so it's left out of the config.
+        let config = serde_json::json!({ "recorder_name": "buffer_load", "states": "both" });
+        let recorder = Recorder::from_config(config).unwrap();
+        assert_eq!(
+            recorder.to_config(),
+            serde_json::json!({ "recorder_name": "buffer_load" })
+        );
+        let config = serde_json::json!({ "recorder_name": "buffer_load", "states": "all" });
+        assert!(Recorder::from_config(config).is_err());
+    }
+
+    #[test]
+    fn test_debug_print_diff() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let config = serde_json::json!({
+            "recorder_name": "debug_print", "changed_only": true, "color": true, "rds": [1, 2]
+        });
+        assert_eq!(
+            Recorder::from_config(config.clone()).unwrap().to_config(),
+            config
+        );
+        let mut recorder = DebugPrintRecorder::from_config(config).unwrap();
+
+        for _ in 0..2 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        }
+        let out = recorder
+            .render(SimTime::injection(1), &network, None)
+            .unwrap();
+        assert!(out.starts_with("1:\n0, 1: [") && !out.contains("1, 2:"));
+        assert!(!out.contains(MOVED_COLOR));
+
+        // The moved packet is highlighted, and the buffer it left is printed since it changed.
+        network.move_packet((0, 1), (1, 2), 0);
+        let out = recorder.render(SimTime::forwarding(1), &network, Some(&Vec::new()));
+        let moved = format!("{:?}", network.get_edgebuffer(1, 2).unwrap().buffer[0]);
+        let lines: Vec<String> = out.unwrap().lines().map(String::from).collect();
+        assert_eq!(lines[0], "1':");
+        assert!(lines[1].starts_with("0, 1: [") && !lines[1].contains(MOVED_COLOR));
+        assert_eq!(
+            lines[2],
+            format!("1, 2: [{}{}{}]", MOVED_COLOR, moved, RESET_COLOR)
+        );
+
+        // Unchanged buffers aren't printed, absorbed packets are highlighted, and later rounds
+        // aren't printed at all.
+        let absorbed = vec![network.remove_packet((1, 2), |_| true).unwrap()];
+        let out = recorder.render(SimTime::forwarding(2), &network, Some(&absorbed));
+        let expected = format!(
+            "2':\n1, 2: []\n\nAbsorbed Packets:\n{}{}{}\n\n",
+            ABSORBED_COLOR, moved, RESET_COLOR
+        );
+        assert_eq!(out.unwrap(), expected);
+        assert!(recorder
+            .render(SimTime::injection(3), &network, None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_flush_every_round() {
+        let config = serde_json::json!({ "recorder_name": "buffer_load", "flush_rounds": 1 });
+        let mut recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        let output_path = std::env::temp_dir().join("aqt_sim_test_flush");
+        let _ = fs::remove_dir_all(&output_path);
+        recorder.set_output_path(&output_path);
+        let file_path = output_path.join("buffer_load.csv");
+
+        let network = construct_path(2);
+        recorder.record(SimTime::injection(1), &network, None);
+        recorder.record(SimTime::forwarding(1), &network, Some(&Vec::new()));
+        assert!(!file_path.exists());
+        // Round 1's lines are flushed as soon as round 2 is recorded.
+        recorder.record(SimTime::injection(2), &network, None);
+        assert_eq!(
+            fs::read_to_string(&file_path).unwrap(),
+            "rd,prime,buffer_from,buffer_to,load\n1,0,0,1,0\n1,1,0,1,0\n"
+        );
+
+        let config = serde_json::json!({ "recorder_name": "frames", "flush_lines": 10 });
+        assert!(Recorder::from_config(config).is_err());
+    }
+
+    #[test]
+    fn test_record_every_rds() {
+        let config = serde_json::json!({ "recorder_name": "num_rds", "every_rds": 2 });
+        let mut recorder = Recorder::from_config(config.clone()).unwrap();
+        assert_eq!(recorder.to_config(), config);
+        let output_path = std::env::temp_dir().join("aqt_sim_test_every_rds");
+        recorder.set_output_path(&output_path);
+
+        let network = construct_path(2);
+        for rd in 1..=5 {
+            recorder.record(SimTime::injection(rd), &network, None);
+            recorder.record(SimTime::forwarding(rd), &network, Some(&Vec::new()));
+        }
+        recorder.close();
+        let data = fs::read_to_string(output_path.join("num_rds.csv")).unwrap();
+        fs::remove_dir_all(&output_path).unwrap();
+        assert_eq!(data.lines().skip(1).collect::<Vec<_>>(), ["4"]);
+
+        let config = serde_json::json!({ "recorder_name": "num_rds", "every_rds": 0 });
+        assert!(Recorder::from_config(config).is_err());
+    }
+
+    #[test]
+    fn test_every_rds_records_every_absorption() {
+        let output_path = std::env::temp_dir().join("aqt_sim_test_every_rds_absorption");
+        let _ = fs::remove_dir_all(&output_path);
+        // The run ends between recorded rounds, so its last absorptions are recorded on close.
+        let config = serde_json::json!({
+            "parallel": false,
+            "simulations": [{
+                "graph_adjacency": { "preset": "path", "num_nodes": 4 },
+                "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "adversary": { "adversary_name": "sd_path_random", "seed": 1 },
+                "threshold": { "threshold_name": "timed", "max_rds": 17 },
+                "recorders": [
+                    { "recorder_name": "absorption", "every_rds": 2 },
+                    { "recorder_name": "conservation_audit", "every_rds": 3 },
+                ],
+                "output_path": output_path.to_string_lossy(),
+            }],
+        });
+        let sim_config = crate::config::Config::from_string(config.to_string())
+            .unwrap()
+            .sim_configs[0]
+            .clone();
+        let metrics = crate::simulation::Simulation::from_config(sim_config).run();
+        let absorption = fs::read_to_string(output_path.join("absorption.csv")).unwrap();
+        let audit = fs::read_to_string(output_path.join("conservation_audit.jsonl")).unwrap();
+        fs::remove_dir_all(&output_path).unwrap();
+        assert!(metrics.num_absorbed > 1);
+        assert_eq!(
+            absorption.lines().count() - 1,
+            metrics.num_absorbed as usize
+        );
+        assert_eq!(audit, "");
+    }
+}
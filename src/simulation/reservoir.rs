@@ -0,0 +1,282 @@
+//! This module contains the `ReservoirRecorder`, which keeps a fixed-size uniform sample of
+//! per-packet latencies or buffer loads, so their distributions can be estimated from arbitrarily
+//! long runs in constant memory.
+
+use crate::config::{CfgErrorMsg, Configurable};
+use crate::network::{Network, NodeID};
+use crate::packet::{Packet, PacketId};
+use crate::simulation::metadata::Metadata;
+use crate::simulation::random::SimRng;
+use crate::simulation::recorder::{
+    RecordedStates, RecorderTrait, WriteErrorMsg, RECORDER_NAME_KEY, RESERVOIR_NAME,
+};
+use crate::simulation::time::SimTime;
+use serde_json::{Map, Number, Value};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const RESERVOIR_FILENAME: &str = "reservoir.csv";
+const SAMPLE_KEY: &str = "sample";
+const LATENCY_SAMPLE_NAME: &str = "latency";
+const BUFFER_LOAD_SAMPLE_NAME: &str = "buffer_load";
+const SIZE_KEY: &str = "size";
+const SEED_KEY: &str = "seed";
+const DEFAULT_SIZE: usize = 1000;
+
+/// The observations a `ReservoirRecorder` samples.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReservoirSample {
+    /// The latency of each absorbed packet, observed in prime states.
+    Latency,
+    /// The load of each buffer at each recorded timestep.
+    BufferLoad,
+}
+
+#[derive(Clone)]
+enum Observation {
+    Latency {
+        rd: usize,
+        id: PacketId,
+        latency: usize,
+    },
+    BufferLoad {
+        time: SimTime,
+        edge: (NodeID, NodeID),
+        load: usize,
+    },
+}
+
+/// Keeps a uniform random sample of at most `size` observations by reservoir sampling: the `n`th
+/// observation replaces a random sampled one with probability `size / n`. Once the simulation is
+/// closed, the sample is written to `reservoir.csv`, along with the total number of observations,
+/// so that unbiased estimates of the distribution can be computed from it.
+#[derive(Clone)]
+pub struct ReservoirRecorder {
+    states: RecordedStates,
+    sample_kind: ReservoirSample,
+    size: usize,
+    rng: SimRng,
+    num_observed: u64,
+    reservoir: Vec<Observation>,
+    dir_path: Option<PathBuf>,
+    // The metadata columns prepended to the header and to every row, if any.
+    header_prefix: String,
+    row_prefix: String,
+    // The first error hit saving the sample, if any.
+    write_error: Option<WriteErrorMsg>,
+}
+
+impl ReservoirRecorder {
+    /// Get a new `ReservoirRecorder` keeping at most `size` of the given observations, sampled with
+    /// the given seed if any.
+    pub fn new(sample_kind: ReservoirSample, size: usize, seed: Option<u64>) -> Self {
+        ReservoirRecorder {
+            states: RecordedStates::Both,
+            sample_kind,
+            size,
+            rng: seed.map_or_else(SimRng::new, SimRng::from_seed),
+            num_observed: 0,
+            reservoir: Vec::new(),
+            dir_path: None,
+            header_prefix: String::new(),
+            row_prefix: String::new(),
+            write_error: None,
+        }
+    }
+
+    /// Get the number of observations seen so far, sampled or not.
+    pub fn num_observed(&self) -> u64 {
+        self.num_observed
+    }
+
+    fn observe(&mut self, observation: Observation) {
+        self.num_observed += 1;
+        if self.reservoir.len() < self.size {
+            self.reservoir.push(observation);
+            return;
+        }
+        let idx = self.rng.rand_int(self.num_observed as usize);
+        if idx < self.size {
+            self.reservoir[idx] = observation;
+        }
+    }
+}
+
+impl RecorderTrait for ReservoirRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        match self.sample_kind {
+            ReservoirSample::Latency => {
+                for p in absorbed.into_iter().flatten() {
+                    self.observe(Observation::Latency {
+                        rd: time.round,
+                        id: p.id(),
+                        latency: time.round - p.injection_rd(),
+                    });
+                }
+            }
+            ReservoirSample::BufferLoad => {
+                for edge in network.edges() {
+                    self.observe(Observation::BufferLoad {
+                        time,
+                        edge: (edge.from(), edge.to()),
+                        load: edge.load(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.dir_path = Some(dir_path.to_path_buf());
+    }
+
+    fn close(&mut self) {
+        let file_path = self
+            .dir_path
+            .as_ref()
+            .expect("You must set an output path for each recorder.")
+            .join(RESERVOIR_FILENAME);
+        let mut data = self.header_prefix.clone()
+            + match self.sample_kind {
+                ReservoirSample::Latency => "rd,packet_id,latency,num_observed\n",
+                ReservoirSample::BufferLoad => "rd,prime,from,to,load,num_observed\n",
+            };
+        for observation in &self.reservoir {
+            data.push_str(&self.row_prefix);
+            match observation {
+                Observation::Latency { rd, id, latency } => {
+                    writeln!(data, "{},{},{},{}", rd, id, latency, self.num_observed)
+                }
+                Observation::BufferLoad { time, edge, load } => writeln!(
+                    data,
+                    "{},{},{},{},{},{}",
+                    time.round,
+                    if time.is_prime() { 1 } else { 0 },
+                    edge.0,
+                    edge.1,
+                    load,
+                    self.num_observed
+                ),
+            }
+            .unwrap();
+        }
+        if let Err(err) = fs::write(&file_path, data) {
+            let msg = format!(
+                "Failed to save simulation results to {}: {}",
+                file_path.display(),
+                err
+            );
+            self.write_error.get_or_insert(msg);
+        }
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.header_prefix = metadata.header_prefix();
+        self.row_prefix = metadata.row_prefix();
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+}
+
+impl Configurable for ReservoirRecorder {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let sample_kind = match config.get(SAMPLE_KEY).and_then(Value::as_str) {
+            Some(LATENCY_SAMPLE_NAME) | None => Ok(ReservoirSample::Latency),
+            Some(BUFFER_LOAD_SAMPLE_NAME) => Ok(ReservoirSample::BufferLoad),
+            Some(other) => Err(format!("No reservoir sample {}.", other)),
+        }?;
+        let size = match config.get(SIZE_KEY) {
+            Some(Value::Number(num)) => match num.as_u64() {
+                Some(size) if size > 0 => Ok(size as usize),
+                _ => Err(String::from("Reservoir size must be a positive integer.")),
+            },
+            None => Ok(DEFAULT_SIZE),
+            _ => Err(String::from("Reservoir size must be a positive integer.")),
+        }?;
+        let seed = config.get(SEED_KEY).and_then(Value::as_u64);
+        Ok(Self::new(sample_kind, size, seed))
+    }
+
+    fn to_config(&self) -> Value {
+        let sample_name = match self.sample_kind {
+            ReservoirSample::Latency => LATENCY_SAMPLE_NAME,
+            ReservoirSample::BufferLoad => BUFFER_LOAD_SAMPLE_NAME,
+        };
+        let mut map = Map::new();
+        map.insert(
+            RECORDER_NAME_KEY.to_string(),
+            Value::String(RESERVOIR_NAME.to_string()),
+        );
+        map.insert(
+            SAMPLE_KEY.to_string(),
+            Value::String(sample_name.to_string()),
+        );
+        map.insert(SIZE_KEY.to_string(), Value::Number(Number::from(self.size)));
+        map.insert(
+            SEED_KEY.to_string(),
+            Value::Number(Number::from(self.rng.seed())),
+        );
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_reservoir_keeps_uniform_sample() {
+        let network = construct_path(2);
+        let mut factory = PacketFactory::new();
+        // Packets absorbed in round 1000 with latencies 0 to 999, 10 at a time.
+        let absorbed: Vec<Packet> = (0..1000)
+            .map(|injection_rd| factory.create_packet(vec![0, 1], injection_rd, 0))
+            .collect();
+        let mut recorder = ReservoirRecorder::new(ReservoirSample::Latency, 100, Some(5));
+        for chunk in absorbed.chunks(10) {
+            recorder.record(SimTime::forwarding(1000), &network, Some(&chunk.to_vec()));
+        }
+        assert_eq!(recorder.num_observed(), 1000);
+        assert_eq!(recorder.reservoir.len(), 100);
+        // The sample's mean latency is close to the population's, 499.5.
+        let latencies: Vec<usize> = recorder
+            .reservoir
+            .iter()
+            .map(|observation| match observation {
+                Observation::Latency { latency, .. } => *latency,
+                _ => unreachable!(),
+            })
+            .collect();
+        let mean = latencies.iter().sum::<usize>() as f64 / 100.0;
+        assert!((mean - 499.5).abs() < 100.0, "mean {}", mean);
+        // Some packets from late in the run were sampled.
+        assert!(latencies.iter().any(|latency| *latency < 500));
+
+        let output_path = std::env::temp_dir().join("aqt_sim_test_reservoir");
+        recorder.set_output_path(&output_path);
+        recorder.close();
+        let data = fs::read_to_string(output_path.join(RESERVOIR_FILENAME)).unwrap();
+        assert_eq!(data.lines().count(), 101);
+        assert!(data.lines().nth(1).unwrap().ends_with(",1000"));
+        fs::remove_dir_all(output_path).unwrap();
+    }
+}
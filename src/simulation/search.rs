@@ -0,0 +1,354 @@
+//! This module contains `Search`, which looks for destabilizing adversaries by simulated annealing
+//! over numeric parameters of a simulation config, maximizing the max load of short runs.
+
+use crate::config::{
+    CfgErrorMsg, Configurable, SimConfig, METRICS_ONLY_KEY, OUTPUT_PATH_KEY, RECORDERS_KEY,
+    SEED_KEY, THRESHOLD_KEY,
+};
+use crate::simulation::experiment::set_param;
+use crate::simulation::random::SimRng;
+use crate::simulation::threshold::Threshold;
+use crate::simulation::Simulation;
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The name of the file to which a search's iterations are written.
+pub const SEARCH_HISTORY_FILENAME: &str = "search.csv";
+/// The name of the file to which a search's worst-case configuration is written.
+pub const SEARCH_RESULT_FILENAME: &str = "search.json";
+
+/// A numeric parameter searched over: a dotted path into the base simulation config (as for
+/// experiments, e.g. `adversary.sigma` or `adversary.weights.0`), and its range.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchParam {
+    pub param: String,
+    pub min: f64,
+    pub max: f64,
+    /// Whether the parameter only takes integer values.
+    pub integer: bool,
+}
+
+/// One iteration of a search: the candidate parameter values, the max load of their run, and
+/// whether the candidate was accepted as the search's next state.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchStep {
+    pub params: Vec<f64>,
+    pub max_load: u64,
+    pub accepted: bool,
+}
+
+/// The outcome of a search: every iteration, and the worst-case configuration found.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchResult {
+    pub param_names: Vec<String>,
+    pub history: Vec<SearchStep>,
+    pub best_params: Vec<f64>,
+    pub best_max_load: u64,
+    /// The full simulation config of the best candidate, with its original threshold and
+    /// recorders, ready to be run in full.
+    pub best_config: Value,
+}
+
+/// Simulated annealing over parameters of a base simulation config. Each iteration perturbs every
+/// parameter by up to `step` of its range, runs the candidate for `rds` rounds (metrics only), and
+/// accepts it if its max total load is higher, or otherwise with probability
+/// `exp((load - current_load) / temperature)`. The temperature starts at `initial_temperature` and
+/// is multiplied by `cooling` each iteration. The search starts from the base config's values of
+/// the parameters (or the middle of their ranges). Every candidate's simulation uses the same base
+/// seed (the base config's, or one drawn from the search's seed), so they see common random
+/// numbers.
+#[derive(Clone)]
+pub struct Search {
+    base: Value,
+    params: Vec<SearchParam>,
+    iterations: usize,
+    rds: usize,
+    initial_temperature: f64,
+    cooling: f64,
+    step: f64,
+    rng: SimRng,
+}
+
+impl Search {
+    /// Run the search.
+    pub fn run(&mut self) -> Result<SearchResult, CfgErrorMsg> {
+        let mut base = self.base.clone();
+        if base.get(SEED_KEY).is_none() {
+            base[SEED_KEY] = Value::from(SimRng::stream_seed(self.rng.seed(), "simulation"));
+        }
+        let mut current: Vec<f64> = self
+            .params
+            .iter()
+            .map(|param| {
+                let pointer = format!("/{}", param.param.replace('.', "/"));
+                let value = base.pointer(&pointer).and_then(Value::as_f64);
+                param.clamp(value.unwrap_or((param.min + param.max) / 2.0))
+            })
+            .collect();
+        let mut current_load = self.max_load(&base, &current)?;
+        let mut best = (current.clone(), current_load);
+        let mut history = vec![SearchStep {
+            params: current.clone(),
+            max_load: current_load,
+            accepted: true,
+        }];
+
+        let mut temperature = self.initial_temperature;
+        for _ in 0..self.iterations {
+            let candidate: Vec<f64> = self
+                .params
+                .iter()
+                .zip(&current)
+                .map(|(param, value)| {
+                    let offset = (2.0 * self.rng.rand_f64() - 1.0) * self.step;
+                    param.clamp(value + offset * (param.max - param.min))
+                })
+                .collect();
+            let load = self.max_load(&base, &candidate)?;
+            let gain = load as f64 - current_load as f64;
+            let accepted = gain >= 0.0
+                || (temperature > 0.0 && self.rng.rand_f64() < (gain / temperature).exp());
+            if load > best.1 {
+                best = (candidate.clone(), load);
+            }
+            history.push(SearchStep {
+                params: candidate.clone(),
+                max_load: load,
+                accepted,
+            });
+            if accepted {
+                current = candidate;
+                current_load = load;
+            }
+            temperature *= self.cooling;
+        }
+
+        let mut best_config = base;
+        self.set_params(&mut best_config, &best.0)?;
+        Ok(SearchResult {
+            param_names: self.params.iter().map(|p| p.param.clone()).collect(),
+            history,
+            best_params: best.0,
+            best_max_load: best.1,
+            best_config,
+        })
+    }
+
+    /// Set the given parameter values in the given config.
+    fn set_params(&self, config: &mut Value, values: &[f64]) -> Result<(), CfgErrorMsg> {
+        for (param, value) in self.params.iter().zip(values) {
+            let value = match param.integer {
+                true => Value::from(*value as i64),
+                false => Value::from(*value),
+            };
+            set_param(config, &param.param, value)?;
+        }
+        Ok(())
+    }
+
+    /// Get the max total load of a short, metrics-only run of the base config with the given
+    /// parameter values.
+    fn max_load(&self, base: &Value, values: &[f64]) -> Result<u64, CfgErrorMsg> {
+        let mut sim_cfg = base.clone();
+        self.set_params(&mut sim_cfg, values)?;
+        sim_cfg[THRESHOLD_KEY] = Threshold::timed_from_rds(self.rds).to_config();
+        sim_cfg[RECORDERS_KEY] = json!([]);
+        sim_cfg[OUTPUT_PATH_KEY] = json!("");
+        sim_cfg[METRICS_ONLY_KEY] = json!(true);
+        let mut simulation = Simulation::from_config(SimConfig::from_val(sim_cfg)?);
+        Ok(simulation.run().max_total_load)
+    }
+}
+
+impl SearchParam {
+    fn clamp(&self, value: f64) -> f64 {
+        let value = value.clamp(self.min, self.max);
+        match self.integer {
+            true => value.round(),
+            false => value,
+        }
+    }
+}
+
+impl SearchResult {
+    /// Write the search's iterations to `search.csv` and its worst-case configuration to
+    /// `search.json` in the given directory.
+    pub fn save(&self, output_path: &Path) -> io::Result<()> {
+        fs::create_dir_all(output_path)?;
+        let mut history = format!(
+            "iteration,{},max_load,accepted\n",
+            self.param_names.join(",")
+        );
+        for (i, step) in self.history.iter().enumerate() {
+            let params: Vec<String> = step.params.iter().map(f64::to_string).collect();
+            writeln!(
+                history,
+                "{},{},{},{}",
+                i,
+                params.join(","),
+                step.max_load,
+                step.accepted
+            )
+            .unwrap();
+        }
+        fs::write(output_path.join(SEARCH_HISTORY_FILENAME), history)?;
+        let result = json!({
+            "best_params": self
+                .param_names
+                .iter()
+                .zip(&self.best_params)
+                .map(|(name, value)| (name.clone(), json!(value)))
+                .collect::<serde_json::Map<_, _>>(),
+            "best_max_load": self.best_max_load,
+            "best_config": self.best_config,
+        });
+        let data = serde_json::to_string_pretty(&result).unwrap();
+        fs::write(
+            output_path.join(SEARCH_RESULT_FILENAME),
+            format!("{}\n", data),
+        )
+    }
+}
+
+const BASE_KEY: &str = "base";
+const PARAMS_KEY: &str = "params";
+const PARAM_KEY: &str = "param";
+const MIN_KEY: &str = "min";
+const MAX_KEY: &str = "max";
+const INTEGER_KEY: &str = "integer";
+const ITERATIONS_KEY: &str = "iterations";
+const RDS_KEY: &str = "rds";
+const INITIAL_TEMPERATURE_KEY: &str = "initial_temperature";
+const COOLING_KEY: &str = "cooling";
+const STEP_KEY: &str = "step";
+
+impl Configurable for Search {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let base = match config.get(BASE_KEY) {
+            Some(base @ Value::Object(_)) => Ok(base.clone()),
+            _ => Err(String::from(
+                "A search needs a base simulation config object.",
+            )),
+        }?;
+        let param_cfgs = match config.get(PARAMS_KEY) {
+            Some(Value::Array(param_cfgs)) if !param_cfgs.is_empty() => Ok(param_cfgs),
+            _ => Err(String::from("A search needs a nonempty array of params.")),
+        }?;
+        let mut params = Vec::new();
+        for param_cfg in param_cfgs {
+            let param = match param_cfg.get(PARAM_KEY) {
+                Some(Value::String(param)) => Ok(param.clone()),
+                _ => Err(String::from("Each search param needs a param path.")),
+            }?;
+            let (min, max) = match (
+                param_cfg.get(MIN_KEY).and_then(Value::as_f64),
+                param_cfg.get(MAX_KEY).and_then(Value::as_f64),
+            ) {
+                (Some(min), Some(max)) if min <= max => Ok((min, max)),
+                _ => Err(format!("Search param {} needs a min and max range.", param)),
+            }?;
+            let integer = match param_cfg.get(INTEGER_KEY) {
+                Some(Value::Bool(integer)) => Ok(*integer),
+                Some(_) => Err(String::from("Integer must be a boolean.")),
+                None => Ok(false),
+            }?;
+            params.push(SearchParam {
+                param,
+                min,
+                max,
+                integer,
+            });
+        }
+        let positive_int = |key: &str, default: usize| match config.get(key) {
+            Some(num) => match num.as_u64() {
+                Some(num) if num > 0 => Ok(num as usize),
+                _ => Err(format!("{} must be a positive integer.", key)),
+            },
+            None => Ok(default),
+        };
+        let iterations = positive_int(ITERATIONS_KEY, 100)?;
+        let rds = positive_int(RDS_KEY, 500)?;
+        let nonnegative = |key: &str, default: f64| match config.get(key) {
+            Some(num) => match num.as_f64() {
+                Some(num) if num >= 0.0 => Ok(num),
+                _ => Err(format!("{} must be a nonnegative number.", key)),
+            },
+            None => Ok(default),
+        };
+        let initial_temperature = nonnegative(INITIAL_TEMPERATURE_KEY, 1.0)?;
+        let cooling = nonnegative(COOLING_KEY, 0.95)?;
+        let step = nonnegative(STEP_KEY, 0.2)?;
+        let rng = config
+            .get(SEED_KEY)
+            .and_then(Value::as_u64)
+            .map_or_else(SimRng::new, SimRng::from_seed);
+        Ok(Search {
+            base,
+            params,
+            iterations,
+            rds,
+            initial_temperature,
+            cooling,
+            step,
+            rng,
+        })
+    }
+
+    fn to_config(&self) -> Value {
+        let params: Vec<Value> = self
+            .params
+            .iter()
+            .map(|p| json!({ PARAM_KEY: p.param, MIN_KEY: p.min, MAX_KEY: p.max, INTEGER_KEY: p.integer }))
+            .collect();
+        json!({
+            BASE_KEY: self.base,
+            PARAMS_KEY: params,
+            ITERATIONS_KEY: self.iterations,
+            RDS_KEY: self.rds,
+            INITIAL_TEMPERATURE_KEY: self.initial_temperature,
+            COOLING_KEY: self.cooling,
+            STEP_KEY: self.step,
+            SEED_KEY: self.rng.seed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_finds_overloading_utilization() {
+        // A buffer loaded past its capacity grows by the excess each round, so the max load grows
+        // with the utilization.
+        let mut search = Search::from_config(json!({
+            "base": {
+                "graph_adjacency": { "preset": "path", "num_nodes": 2 },
+                "protocol": { "protocol_name": "greedy_fifo", "capacity": 1 },
+                "adversary": {
+                    "adversary_name": "utilization",
+                    "targets": [{ "edge": [0, 1], "utilization": 0.5 }],
+                },
+            },
+            "params": [{ "param": "adversary.targets.0.utilization", "min": 0.5, "max": 3.0 }],
+            "iterations": 30,
+            "rds": 20,
+            "seed": 1,
+        }))
+        .unwrap();
+        let result = search.run().unwrap();
+        assert_eq!(result.history.len(), 31);
+        assert_eq!(result.history[0].max_load, 1);
+        assert!(result.best_params[0] > 2.0, "{:?}", result.best_params);
+        assert!(result.best_max_load >= 20, "{}", result.best_max_load);
+        assert_eq!(
+            result.best_config["adversary"]["targets"][0]["utilization"],
+            result.best_params[0]
+        );
+        // The best config keeps the base's (empty) threshold and recorders.
+        assert!(result.best_config.get("threshold").is_none());
+    }
+}
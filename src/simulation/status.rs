@@ -0,0 +1,312 @@
+//! This module contains the `StatusBoard`, which tracks the progress of every simulation in a
+//! parallel batch so that it can be printed as a periodically-refreshing status table.
+
+use crate::simulation::SimStatus;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The state of a single run in a batch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RunState {
+    Queued,
+    Running,
+    Finished,
+    Stalled,
+    Skipped,
+    Failed,
+}
+
+impl RunState {
+    /// Get the name of this state, as printed in the status table.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Stalled => "stalled",
+            Self::Skipped => "skipped",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+impl From<SimStatus> for RunState {
+    fn from(status: SimStatus) -> Self {
+        match status {
+            SimStatus::Running => Self::Running,
+            SimStatus::Finished => Self::Finished,
+            SimStatus::Stalled => Self::Stalled,
+            SimStatus::Failed => Self::Failed,
+        }
+    }
+}
+
+/// The progress of a single run, as last reported by its `Simulation`.
+#[derive(Clone, Copy)]
+struct RunStatus {
+    state: RunState,
+    rd: usize,
+    load: usize,
+    max_rds: Option<usize>,
+    // When the run started, and the round it started in.
+    started: Option<(Instant, usize)>,
+}
+
+/// The progress of a run: its rate of rounds so far, and from it, the estimated time remaining
+/// and finish time if the run has a known number of rounds (e.g. with a timed threshold).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Progress {
+    pub rd: usize,
+    pub max_rds: Option<usize>,
+    /// Unset until a round has been run.
+    pub rds_per_sec: Option<f64>,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Estimate the progress of a run which started at `started_at` in round `first_rd` and is now
+    /// at round `rd`, out of `max_rds` if known.
+    pub fn estimate(
+        started_at: Instant,
+        first_rd: usize,
+        rd: usize,
+        max_rds: Option<usize>,
+        now: Instant,
+    ) -> Self {
+        let elapsed = now.duration_since(started_at);
+        let done_rds = rd.saturating_sub(first_rd);
+        let rds_per_sec =
+            (done_rds > 0 && !elapsed.is_zero()).then(|| done_rds as f64 / elapsed.as_secs_f64());
+        let eta = max_rds.filter(|_| done_rds > 0).map(|max_rds| {
+            let left_rds = max_rds.saturating_sub(rd);
+            elapsed.mul_f64(left_rds as f64 / done_rds as f64)
+        });
+        Progress {
+            rd,
+            max_rds,
+            rds_per_sec,
+            eta,
+        }
+    }
+
+    /// Get the estimated wall-clock time at which the run will finish, if known.
+    pub fn finish_time(&self) -> Option<SystemTime> {
+        self.eta.map(|eta| SystemTime::now() + eta)
+    }
+}
+
+/// Format a duration as e.g. `1h02m03s`, `2m03s`, or `3s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+/// Format a time as its UTC time of day, `HH:MM:SS`.
+fn format_utc_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        % 86400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl RunStatus {
+    fn progress(&self, now: Instant) -> Option<Progress> {
+        let (started_at, first_rd) = self.started?;
+        Some(Progress::estimate(
+            started_at,
+            first_rd,
+            self.rd,
+            self.max_rds,
+            now,
+        ))
+    }
+}
+
+/// The progress of every run in a batch, indexed by run id. Each run's entry has its own mutex, so
+/// runs on different threads don't contend when reporting.
+pub struct StatusBoard {
+    runs: Vec<Mutex<RunStatus>>,
+    // The panic message of each failed run, by run id.
+    failures: Mutex<BTreeMap<usize, String>>,
+}
+
+impl StatusBoard {
+    /// Get a new `StatusBoard` with the given number of queued runs.
+    pub fn new(num_runs: usize) -> Self {
+        let queued = RunStatus {
+            state: RunState::Queued,
+            rd: 0,
+            load: 0,
+            max_rds: None,
+            started: None,
+        };
+        StatusBoard {
+            runs: (0..num_runs).map(|_| Mutex::new(queued)).collect(),
+            failures: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Report the progress of the given run. `max_rds` is the round by which the run will end, if
+    /// known, and is used to estimate the time remaining.
+    pub fn report(
+        &self,
+        run_id: usize,
+        state: RunState,
+        rd: usize,
+        load: usize,
+        max_rds: Option<usize>,
+    ) {
+        let mut run = self.runs[run_id].lock().unwrap();
+        if run.started.is_none() {
+            run.started = Some((Instant::now(), rd));
+        }
+        run.state = state;
+        run.rd = rd;
+        run.load = load;
+        run.max_rds = max_rds;
+    }
+
+    /// Set the state of the given run without reporting progress, e.g. when it's skipped.
+    pub fn set_state(&self, run_id: usize, state: RunState) {
+        self.runs[run_id].lock().unwrap().state = state;
+    }
+
+    /// Mark the given run as failed with the given message.
+    pub fn set_failed(&self, run_id: usize, msg: String) {
+        self.set_state(run_id, RunState::Failed);
+        self.failures.lock().unwrap().insert(run_id, msg);
+    }
+
+    /// Get the id and message of every failed run, in order of run id.
+    pub fn failures(&self) -> Vec<(usize, String)> {
+        self.failures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(run_id, msg)| (*run_id, msg.clone()))
+            .collect()
+    }
+
+    /// Returns whether every run is done, i.e. finished, stalled, skipped, or failed.
+    pub fn all_done(&self) -> bool {
+        self.runs
+            .iter()
+            .all(|run| run.lock().unwrap().state.is_done())
+    }
+
+    /// Render the status table, with one line per run.
+    pub fn render(&self) -> String {
+        let now = Instant::now();
+        let mut table = format!(
+            "{:>4} {:>10} {:>8} {:>10} {:>10} {:>10}  {}\n",
+            "sim", "round", "load", "rds/s", "eta", "finish", "state"
+        );
+        for (run_id, run) in self.runs.iter().enumerate() {
+            let run = *run.lock().unwrap();
+            let progress = run.progress(now).filter(|_| run.state == RunState::Running);
+            let rds_per_sec = progress
+                .and_then(|p| p.rds_per_sec)
+                .map_or(String::from("-"), |rate| format!("{:.1}", rate));
+            let eta = progress
+                .and_then(|p| p.eta)
+                .map_or(String::from("-"), format_duration);
+            let finish = progress
+                .and_then(|p| p.finish_time())
+                .map_or(String::from("-"), format_utc_time);
+            writeln!(
+                table,
+                "{:>4} {:>10} {:>8} {:>10} {:>10} {:>10}  {}",
+                run_id,
+                run.rd,
+                run.load,
+                rds_per_sec,
+                eta,
+                finish,
+                run.state.name()
+            )
+            .unwrap();
+        }
+        table
+    }
+}
+
+/// Get the message of a caught panic, if it has one.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_table() {
+        let board = StatusBoard::new(3);
+        board.report(0, RunState::Running, 1, 0, Some(100));
+        board.report(0, RunState::Running, 51, 7, Some(100));
+        board.report(1, RunState::Finished, 20, 0, None);
+        assert!(!board.all_done());
+
+        let table = board.render();
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows[0],
+            ["sim", "round", "load", "rds/s", "eta", "finish", "state"]
+        );
+        assert_eq!(rows[1][..3], ["0", "51", "7"]);
+        assert_eq!(rows[1][6], "running");
+        assert_eq!(rows[2], ["1", "20", "0", "-", "-", "-", "finished"]);
+        assert_eq!(rows[3], ["2", "0", "0", "-", "-", "-", "queued"]);
+
+        board.report(0, RunState::Stalled, 60, 7, Some(100));
+        board.set_state(2, RunState::Skipped);
+        assert!(board.all_done());
+    }
+
+    #[test]
+    fn test_progress_estimate() {
+        let started_at = Instant::now();
+        let now = started_at + Duration::from_secs(10);
+        let progress = Progress::estimate(started_at, 1, 101, Some(1001), now);
+        assert_eq!(progress.rds_per_sec, Some(10.0));
+        assert_eq!(progress.eta, Some(Duration::from_secs(90)));
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+        assert_eq!(format_duration(Duration::from_secs(90)), "1m30s");
+
+        let unbounded = Progress::estimate(started_at, 1, 1, None, now);
+        assert_eq!((unbounded.rds_per_sec, unbounded.eta), (None, None));
+    }
+
+    #[test]
+    fn test_failed_runs_are_reported() {
+        let board = StatusBoard::new(2);
+        board.report(0, RunState::Finished, 10, 0, None);
+        let payload = std::panic::catch_unwind(|| panic!("bad edge {}", 3)).unwrap_err();
+        board.set_failed(1, panic_message(payload.as_ref()));
+        assert!(board.all_done());
+        assert_eq!(board.failures(), [(1, String::from("bad edge 3"))]);
+        assert!(board.render().lines().last().unwrap().ends_with("failed"));
+    }
+}
@@ -3,64 +3,189 @@
 
 use crate::config::{CfgErrorMsg, Configurable};
 use crate::network::Network;
+use crate::packet::Packet;
+use crate::simulation::time::SimTime;
 use serde_json::{Map, Number, Value};
+use std::any::Any;
+use std::collections::VecDeque;
 
-/// Used to end a `Simulation`.
-#[derive(Clone)]
-pub enum Threshold {
-    Timed(TimedThreshold),
-    TotalLoad(TotalLoadThreshold),
-}
+/// Used to end a `Simulation`, behind which any `ThresholdTrait` implementation can be used.
+pub struct Threshold(Box<dyn BoxedThreshold>);
 
 impl Threshold {
+    /// Get a `Threshold` ending the `Simulation` with the given implementation, which may be
+    /// defined outside this crate.
+    pub fn new<T>(threshold: T) -> Self
+    where
+        T: ThresholdTrait + Configurable + Clone + Send + 'static,
+    {
+        Threshold(Box::new(threshold))
+    }
+
     /// Get a `TimedThreshold` with the given max number of rounds.
     pub fn timed_from_rds(max_rds: usize) -> Self {
-        Self::Timed(TimedThreshold::new(max_rds))
+        Self::new(TimedThreshold::new(max_rds))
     }
 
-    /// Check whether the `Simulation` should terminate based on the round number and network
-    /// state.
-    pub fn check_termination(&mut self, rd: usize, network: &Network) -> bool {
-        match self {
-            Self::Timed(t) => t.check_termination(rd, network),
-            Self::TotalLoad(t) => t.check_termination(rd, network),
-        }
+    /// Get the implementation behind this threshold, if it is a `T`.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.0.as_any().downcast_ref()
+    }
+
+    /// Get what the `Simulation` should do when this threshold fires.
+    pub fn mode(&self) -> ThresholdMode {
+        self.0.mode()
+    }
+
+    /// Check whether this threshold needs the network to track when each buffer was last served
+    /// (see `Network::set_service_tracked`).
+    pub fn needs_service_tracking(&self) -> bool {
+        self.0.needs_service_tracking()
+    }
+
+    /// Get the round by which this threshold is sure to fire, if it is known in advance.
+    pub fn max_rds(&self) -> Option<usize> {
+        self.0.max_rds()
+    }
+
+    /// Check whether the `Simulation` should terminate based on the current time, network state,
+    /// and the packets absorbed in the current half-round (`None` after injection).
+    pub fn check_termination(
+        &mut self,
+        time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        self.0.check_termination(time, network, absorbed)
+    }
+}
+
+impl Clone for Threshold {
+    fn clone(&self) -> Self {
+        Threshold(self.0.clone_box())
     }
 }
 
 const THRESHOLD_NAME_KEY: &str = "threshold_name";
 const TIMED_THRESHOLD_NAME: &str = "timed";
 const TOTAL_LOAD_THRESHOLD_NAME: &str = "total_load";
+const ABSORBED_THRESHOLD_NAME: &str = "absorbed";
+const MOVING_AVERAGE_LOAD_THRESHOLD_NAME: &str = "moving_average_load";
+const STARVATION_THRESHOLD_NAME: &str = "starvation";
+
+type Builder = fn(Value) -> Result<Threshold, CfgErrorMsg>;
+
+// Every built-in threshold, by its name and how to build it from its config.
+const THRESHOLDS: [(&str, Builder); 5] = [
+    (TIMED_THRESHOLD_NAME, build::<TimedThreshold>),
+    (TOTAL_LOAD_THRESHOLD_NAME, build::<TotalLoadThreshold>),
+    (ABSORBED_THRESHOLD_NAME, build::<AbsorbedThreshold>),
+    (
+        MOVING_AVERAGE_LOAD_THRESHOLD_NAME,
+        build::<MovingAverageLoadThreshold>,
+    ),
+    (STARVATION_THRESHOLD_NAME, build::<StarvationThreshold>),
+];
+
+fn build<T>(config: Value) -> Result<Threshold, CfgErrorMsg>
+where
+    T: ThresholdTrait + Configurable + Clone + Send + 'static,
+{
+    Ok(Threshold::new(T::from_config(config)?))
+}
 
 impl Configurable for Threshold {
     fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
-        // TODO: error msgs
-        let map: Map<String, Value> = config.as_object().unwrap().clone();
-        let threshold_name = match map.get(THRESHOLD_NAME_KEY) {
+        // Any threshold may pause the simulation or save a snapshot rather than terminating, or be
+        // guarded by a minimum number of rounds. The mode wraps the guard, so that it fires when
+        // the guarded condition starts holding.
+        match config
+            .get(MODE_KEY)
+            .map(|mode| ThresholdMode::from_config(mode.clone()))
+        {
+            Some(Ok(ThresholdMode::Terminate)) => {
+                let mut config = config;
+                config.as_object_mut().unwrap().remove(MODE_KEY);
+                return Self::from_config(config);
+            }
+            Some(_) => return build::<ModeThreshold>(config),
+            None => (),
+        }
+        if config.get(MIN_RDS_KEY).is_some() {
+            return build::<MinRdsThreshold>(config);
+        }
+        // Any threshold may be checked only every so many rounds.
+        if config.get(EVERY_RDS_KEY).is_some() {
+            return build::<EveryRdsThreshold>(config);
+        }
+
+        let threshold_name = match config.get(THRESHOLD_NAME_KEY) {
             Some(Value::String(name)) => Ok(name),
             _ => Err(String::from("No threshold name found.")),
         }?;
-
-        match &threshold_name[..] {
-            TIMED_THRESHOLD_NAME => Ok(Self::Timed(TimedThreshold::from_config(config).unwrap())),
-            TOTAL_LOAD_THRESHOLD_NAME => Ok(Self::TotalLoad(TotalLoadThreshold::from_config(config).unwrap())),
-            _ => Err(String::from("No threshold name found.")),
+        match THRESHOLDS.iter().find(|(name, _)| name == threshold_name) {
+            Some((_, build)) => build(config),
+            None => Err(String::from("No threshold name found.")),
         }
     }
 
     fn to_config(&self) -> Value {
-        match self {
-            Self::Timed(t) => t.to_config(),
-            Self::TotalLoad(t) => t.to_config(),
-        }
+        self.0.to_config()
     }
 }
 
 /// Trait which all `Threshold`s should implement.
 pub trait ThresholdTrait {
     /// Check whether to terminate the simulation and update any internal state of the
-    /// `Threshold.`.
-    fn check_termination(&mut self, rd: usize, network: &Network) -> bool;
+    /// `Threshold.`. `absorbed` holds the packets absorbed in the current half-round, and is
+    /// `None` when checking after injection.
+    fn check_termination(
+        &mut self,
+        time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool;
+
+    /// Get what the `Simulation` should do when this threshold fires: terminate, by default.
+    fn mode(&self) -> ThresholdMode {
+        ThresholdMode::Terminate
+    }
+
+    /// Check whether this threshold needs the network to track when each buffer was last served
+    /// (see `Network::set_service_tracked`). Most don't.
+    fn needs_service_tracking(&self) -> bool {
+        false
+    }
+
+    /// Get the round by which this threshold is sure to fire, if it is known in advance.
+    fn max_rds(&self) -> Option<usize> {
+        None
+    }
+}
+
+// The object-safe view of a threshold which `Threshold` holds, implemented for every configurable
+// `ThresholdTrait` implementation.
+trait BoxedThreshold: ThresholdTrait + Send {
+    fn clone_box(&self) -> Box<dyn BoxedThreshold>;
+    fn to_config(&self) -> Value;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> BoxedThreshold for T
+where
+    T: ThresholdTrait + Configurable + Clone + Send + 'static,
+{
+    fn clone_box(&self) -> Box<dyn BoxedThreshold> {
+        Box::new(self.clone())
+    }
+
+    fn to_config(&self) -> Value {
+        Configurable::to_config(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 /// To end a `Simulation` after a specified number of rounds has elapsed.
@@ -77,8 +202,17 @@ impl TimedThreshold {
 }
 
 impl ThresholdTrait for TimedThreshold {
-    fn check_termination(&mut self, rd: usize, _network: &Network) -> bool {
-        rd >= self.max_rds
+    fn check_termination(
+        &mut self,
+        time: SimTime,
+        _network: &Network,
+        _absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        time.round >= self.max_rds
+    }
+
+    fn max_rds(&self) -> Option<usize> {
+        Some(self.max_rds)
     }
 }
 
@@ -116,14 +250,13 @@ pub struct TotalLoadThreshold {
 }
 
 impl ThresholdTrait for TotalLoadThreshold {
-    fn check_termination(&mut self, _rd: usize, network: &Network) -> bool {
-        let mut total_load = 0;
-        let eb_ids =  network.get_edgebuffers();
-        for (from_id, to_id) in eb_ids {
-            let eb = network.get_edgebuffer(from_id, to_id).unwrap();
-            total_load += eb.buffer.len();
-        }
-        total_load >= self.max_load
+    fn check_termination(
+        &mut self,
+        _time: SimTime,
+        network: &Network,
+        _absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        network.total_load() >= self.max_load
     }
 }
 
@@ -153,3 +286,694 @@ impl Configurable for TotalLoadThreshold {
         Value::Object(map)
     }
 }
+
+/// To end a `Simulation` after a specified number of packets have been absorbed.
+#[derive(Clone)]
+pub struct AbsorbedThreshold {
+    max_absorbed: u64,
+    num_absorbed: u64,
+}
+
+impl AbsorbedThreshold {
+    /// Create a new `AbsorbedThreshold` which terminates once `max_absorbed` packets have been
+    /// absorbed.
+    pub fn new(max_absorbed: u64) -> Self {
+        AbsorbedThreshold {
+            max_absorbed,
+            num_absorbed: 0,
+        }
+    }
+}
+
+impl ThresholdTrait for AbsorbedThreshold {
+    fn check_termination(
+        &mut self,
+        _time: SimTime,
+        _network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        if let Some(absorbed) = absorbed {
+            self.num_absorbed += absorbed.len() as u64;
+        }
+        self.num_absorbed >= self.max_absorbed
+    }
+}
+
+const MAX_ABSORBED_KEY: &str = "max_absorbed";
+
+impl Configurable for AbsorbedThreshold {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let map: Map<String, Value> = config.as_object().unwrap().clone();
+        let max_absorbed = match map.get(MAX_ABSORBED_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap()),
+            _ => Err("No max absorbed found."),
+        }?;
+        Ok(Self::new(max_absorbed))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            THRESHOLD_NAME_KEY.to_string(),
+            Value::String(ABSORBED_THRESHOLD_NAME.to_string()),
+        );
+        map.insert(
+            MAX_ABSORBED_KEY.to_string(),
+            Value::Number(Number::from(self.max_absorbed)),
+        );
+
+        Value::Object(map)
+    }
+}
+
+/// To end a `Simulation` once the moving average of the total load over the last `window` rounds
+/// reaches a specified bound. The total load is sampled once per round, after forwarding, and the
+/// threshold cannot fire until `window` samples have been taken.
+#[derive(Clone)]
+pub struct MovingAverageLoadThreshold {
+    window: usize,
+    max_load: usize,
+    loads: VecDeque<usize>,
+    // A `u64`, as is the bound it's compared to, so that neither can overflow for large windows.
+    window_total: u64,
+}
+
+impl MovingAverageLoadThreshold {
+    /// Create a new `MovingAverageLoadThreshold` with the given window size (in rounds) and bound.
+    pub fn new(window: usize, max_load: usize) -> Self {
+        MovingAverageLoadThreshold {
+            window,
+            max_load,
+            loads: VecDeque::with_capacity(window),
+            window_total: 0,
+        }
+    }
+}
+
+impl ThresholdTrait for MovingAverageLoadThreshold {
+    fn check_termination(
+        &mut self,
+        _time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        // Only sample after forwarding so that each round contributes exactly one sample.
+        if absorbed.is_none() {
+            return false;
+        }
+        let load = network.total_load();
+        self.loads.push_back(load);
+        self.window_total += load as u64;
+        if self.loads.len() > self.window {
+            self.window_total -= self.loads.pop_front().unwrap() as u64;
+        }
+        self.loads.len() == self.window
+            && self.window_total >= self.max_load as u64 * self.window as u64
+    }
+}
+
+const WINDOW_KEY: &str = "window";
+
+impl Configurable for MovingAverageLoadThreshold {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let map: Map<String, Value> = config.as_object().unwrap().clone();
+        let window = match map.get(WINDOW_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
+            _ => Err("No window found."),
+        }?;
+        let max_load = match map.get(MAX_LOAD_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
+            _ => Err("No max load found."),
+        }?;
+        if window == 0 {
+            return Err(String::from("Window must be positive."));
+        }
+        Ok(Self::new(window, max_load))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            THRESHOLD_NAME_KEY.to_string(),
+            Value::String(MOVING_AVERAGE_LOAD_THRESHOLD_NAME.to_string()),
+        );
+        map.insert(
+            WINDOW_KEY.to_string(),
+            Value::Number(Number::from(self.window)),
+        );
+        map.insert(
+            MAX_LOAD_KEY.to_string(),
+            Value::Number(Number::from(self.max_load)),
+        );
+
+        Value::Object(map)
+    }
+}
+
+/// To end a `Simulation` once some nonempty buffer has gone `max_starved_rds` rounds without
+/// being served, i.e. without forwarding a packet or being empty after forwarding (see
+/// `Network::rds_since_service`). Only checked after forwarding.
+#[derive(Clone)]
+pub struct StarvationThreshold {
+    max_starved_rds: usize,
+}
+
+impl StarvationThreshold {
+    /// Create a new `StarvationThreshold` with the given number of rounds.
+    pub fn new(max_starved_rds: usize) -> Self {
+        StarvationThreshold { max_starved_rds }
+    }
+}
+
+impl ThresholdTrait for StarvationThreshold {
+    fn check_termination(
+        &mut self,
+        _time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        absorbed.is_some()
+            && network.edges().iter().any(|edge| {
+                edge.load() > 0
+                    && network
+                        .rds_since_service(edge.from(), edge.to())
+                        .is_some_and(|rds| rds >= self.max_starved_rds)
+            })
+    }
+
+    fn needs_service_tracking(&self) -> bool {
+        true
+    }
+}
+
+const MAX_STARVED_RDS_KEY: &str = "max_starved_rds";
+
+impl Configurable for StarvationThreshold {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.get(MAX_STARVED_RDS_KEY).and_then(Value::as_u64) {
+            Some(max_starved_rds) if max_starved_rds > 0 => Ok(Self::new(max_starved_rds as usize)),
+            _ => Err(String::from(
+                "Max starved rounds must be a positive integer.",
+            )),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            THRESHOLD_NAME_KEY.to_string(),
+            Value::String(STARVATION_THRESHOLD_NAME.to_string()),
+        );
+        map.insert(
+            MAX_STARVED_RDS_KEY.to_string(),
+            Value::Number(Number::from(self.max_starved_rds)),
+        );
+
+        Value::Object(map)
+    }
+}
+
+/// Wraps another `Threshold` so that it cannot end the `Simulation` before a specified round. The
+/// inner threshold is still checked every time so that any internal state stays up to date.
+/// Configured by adding a `min_rds` key to any threshold config.
+#[derive(Clone)]
+pub struct MinRdsThreshold {
+    min_rds: usize,
+    inner: Box<Threshold>,
+}
+
+impl MinRdsThreshold {
+    /// Create a new `MinRdsThreshold` guarding the given `Threshold`.
+    pub fn new(min_rds: usize, inner: Threshold) -> Self {
+        MinRdsThreshold {
+            min_rds,
+            inner: Box::new(inner),
+        }
+    }
+}
+
+impl ThresholdTrait for MinRdsThreshold {
+    fn check_termination(
+        &mut self,
+        time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        let inner_terminate = self.inner.check_termination(time, network, absorbed);
+        time.round >= self.min_rds && inner_terminate
+    }
+
+    fn mode(&self) -> ThresholdMode {
+        self.inner.mode()
+    }
+
+    fn needs_service_tracking(&self) -> bool {
+        self.inner.needs_service_tracking()
+    }
+
+    fn max_rds(&self) -> Option<usize> {
+        self.inner
+            .max_rds()
+            .map(|max_rds| max_rds.max(self.min_rds))
+    }
+}
+
+const MIN_RDS_KEY: &str = "min_rds";
+
+impl Configurable for MinRdsThreshold {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let mut map: Map<String, Value> = config.as_object().unwrap().clone();
+        let min_rds = match map.remove(MIN_RDS_KEY) {
+            Some(Value::Number(num)) => Ok(num.as_u64().unwrap() as usize),
+            Some(_) => Err(String::from("Min rounds must be a number.")),
+            None => Err(String::from("No min rounds found.")),
+        }?;
+        let inner = Threshold::from_config(Value::Object(map))?;
+        Ok(Self::new(min_rds, inner))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut config = self.inner.to_config();
+        config.as_object_mut().unwrap().insert(
+            MIN_RDS_KEY.to_string(),
+            Value::Number(Number::from(self.min_rds)),
+        );
+        config
+    }
+}
+
+/// What a `Simulation` does when its `Threshold` fires.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThresholdMode {
+    /// Stop the simulation.
+    Terminate,
+    /// Pause the simulation at the end of the round: the debugger stops and waits for commands.
+    /// Outside of the debugger, this behaves like `Snapshot`.
+    Pause,
+    /// Save a full snapshot of the network to the output path and keep running.
+    Snapshot,
+}
+
+const MODE_KEY: &str = "mode";
+const TERMINATE_MODE_NAME: &str = "terminate";
+const PAUSE_MODE_NAME: &str = "pause";
+const SNAPSHOT_MODE_NAME: &str = "snapshot";
+
+impl Configurable for ThresholdMode {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        match config.as_str() {
+            Some(TERMINATE_MODE_NAME) => Ok(Self::Terminate),
+            Some(PAUSE_MODE_NAME) => Ok(Self::Pause),
+            Some(SNAPSHOT_MODE_NAME) => Ok(Self::Snapshot),
+            _ => Err(format!("No threshold mode {}.", config)),
+        }
+    }
+
+    fn to_config(&self) -> Value {
+        match self {
+            Self::Terminate => Value::String(TERMINATE_MODE_NAME.to_string()),
+            Self::Pause => Value::String(PAUSE_MODE_NAME.to_string()),
+            Self::Snapshot => Value::String(SNAPSHOT_MODE_NAME.to_string()),
+        }
+    }
+}
+
+/// Wraps another `Threshold` so that firing it pauses the `Simulation` or saves a snapshot rather
+/// than terminating it. Since a condition usually keeps holding once it first holds, this fires
+/// only when the inner threshold fires after a check in which it did not, i.e. at the start of
+/// each episode. Configured by adding a `mode` key to any threshold config.
+#[derive(Clone)]
+pub struct ModeThreshold {
+    mode: ThresholdMode,
+    inner: Box<Threshold>,
+    inner_fired: bool,
+}
+
+impl ModeThreshold {
+    /// Create a new `ModeThreshold` with the given mode wrapping the given `Threshold`.
+    pub fn new(mode: ThresholdMode, inner: Threshold) -> Self {
+        ModeThreshold {
+            mode,
+            inner: Box::new(inner),
+            inner_fired: false,
+        }
+    }
+}
+
+impl ThresholdTrait for ModeThreshold {
+    fn check_termination(
+        &mut self,
+        time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        let inner_fired = self.inner.check_termination(time, network, absorbed);
+        let fire = inner_fired && !self.inner_fired;
+        self.inner_fired = inner_fired;
+        fire
+    }
+
+    fn mode(&self) -> ThresholdMode {
+        self.mode
+    }
+
+    fn needs_service_tracking(&self) -> bool {
+        self.inner.needs_service_tracking()
+    }
+
+    fn max_rds(&self) -> Option<usize> {
+        match self.mode {
+            ThresholdMode::Terminate => self.inner.max_rds(),
+            _ => None,
+        }
+    }
+}
+
+impl Configurable for ModeThreshold {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let mut map: Map<String, Value> = config.as_object().unwrap().clone();
+        let mode = match map.remove(MODE_KEY) {
+            Some(mode_val) => ThresholdMode::from_config(mode_val),
+            None => Err(String::from("No threshold mode found.")),
+        }?;
+        let inner = Threshold::from_config(Value::Object(map))?;
+        Ok(Self::new(mode, inner))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut config = self.inner.to_config();
+        config
+            .as_object_mut()
+            .unwrap()
+            .insert(MODE_KEY.to_string(), self.mode.to_config());
+        config
+    }
+}
+
+/// Wraps another `Threshold` so that it is only checked once every `every_rds` rounds, after
+/// forwarding in rounds divisible by `every_rds`, rather than twice every round, which saves work
+/// for thresholds which scan the network and change slowly. The packets absorbed in the skipped
+/// rounds are passed on at the next check, so thresholds counting absorptions see every one, but
+/// thresholds which sample the network every round, like moving average load and starvation,
+/// can't be wrapped. Configured by adding an `every_rds` key to any other threshold config.
+#[derive(Clone)]
+pub struct EveryRdsThreshold {
+    every_rds: usize,
+    inner: Box<Threshold>,
+    // The packets absorbed since the last check.
+    skipped_absorbed: Vec<Packet>,
+}
+
+impl EveryRdsThreshold {
+    /// Create a new `EveryRdsThreshold` checking the given `Threshold` every `every_rds` rounds.
+    pub fn new(every_rds: usize, inner: Threshold) -> Self {
+        EveryRdsThreshold {
+            every_rds,
+            inner: Box::new(inner),
+            skipped_absorbed: Vec::new(),
+        }
+    }
+}
+
+impl ThresholdTrait for EveryRdsThreshold {
+    fn check_termination(
+        &mut self,
+        time: SimTime,
+        network: &Network,
+        absorbed: Option<&Vec<Packet>>,
+    ) -> bool {
+        if let Some(absorbed) = absorbed {
+            self.skipped_absorbed.extend(absorbed.iter().cloned());
+        }
+        if !time.is_prime() || !time.round.is_multiple_of(self.every_rds) {
+            return false;
+        }
+        let absorbed = std::mem::take(&mut self.skipped_absorbed);
+        self.inner.check_termination(time, network, Some(&absorbed))
+    }
+
+    fn mode(&self) -> ThresholdMode {
+        self.inner.mode()
+    }
+
+    fn needs_service_tracking(&self) -> bool {
+        self.inner.needs_service_tracking()
+    }
+
+    fn max_rds(&self) -> Option<usize> {
+        self.inner
+            .max_rds()
+            .map(|max_rds| max_rds.div_ceil(self.every_rds).max(1) * self.every_rds)
+    }
+}
+
+const EVERY_RDS_KEY: &str = "every_rds";
+// The thresholds which sample the network every round, so can't be checked every so many rounds.
+const EVERY_RD_THRESHOLD_NAMES: [&str; 2] = [
+    MOVING_AVERAGE_LOAD_THRESHOLD_NAME,
+    STARVATION_THRESHOLD_NAME,
+];
+
+impl Configurable for EveryRdsThreshold {
+    fn from_config(config: Value) -> Result<Self, CfgErrorMsg> {
+        let mut map: Map<String, Value> = config.as_object().unwrap().clone();
+        let every_rds = match map.remove(EVERY_RDS_KEY).and_then(|every| every.as_u64()) {
+            Some(every_rds) if every_rds > 0 => Ok(every_rds as usize),
+            _ => Err(String::from(
+                "Threshold every_rds must be a positive integer.",
+            )),
+        }?;
+        if let Some(name) = map
+            .get(THRESHOLD_NAME_KEY)
+            .and_then(Value::as_str)
+            .filter(|name| EVERY_RD_THRESHOLD_NAMES.contains(name))
+        {
+            return Err(format!(
+                "The {} threshold samples the network every round, so it can't set every_rds.",
+                name
+            ));
+        }
+        let inner = Threshold::from_config(Value::Object(map))?;
+        Ok(Self::new(every_rds, inner))
+    }
+
+    fn to_config(&self) -> Value {
+        let mut config = self.inner.to_config();
+        config.as_object_mut().unwrap().insert(
+            EVERY_RDS_KEY.to_string(),
+            Value::Number(Number::from(self.every_rds)),
+        );
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::presets::construct_path;
+    use crate::packet::PacketFactory;
+
+    #[test]
+    fn test_moving_average_ignores_burst() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut threshold = MovingAverageLoadThreshold::new(3, 2);
+        let absorbed = Vec::new();
+
+        // A single burst of 4 packets followed by an empty network averages to 4/3 < 2.
+        for _ in 0..4 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+        }
+        assert!(!threshold.check_termination(SimTime::forwarding(1), &network, Some(&absorbed)));
+        network.take_buffer(0, 1);
+        assert!(!threshold.check_termination(SimTime::forwarding(2), &network, Some(&absorbed)));
+        assert!(!threshold.check_termination(SimTime::forwarding(3), &network, Some(&absorbed)));
+
+        // A sustained load of 2 fills the window and fires.
+        for _ in 0..2 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], 4, 0), 0, 1);
+        }
+        assert!(!threshold.check_termination(SimTime::forwarding(4), &network, Some(&absorbed)));
+        assert!(!threshold.check_termination(SimTime::forwarding(5), &network, Some(&absorbed)));
+        assert!(threshold.check_termination(SimTime::forwarding(6), &network, Some(&absorbed)));
+    }
+
+    #[test]
+    fn test_min_rds_guard() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+
+        let config = serde_json::json!({
+            "threshold_name": "total_load",
+            "max_load": 1,
+            "min_rds": 3,
+        });
+        let mut threshold = Threshold::from_config(config.clone()).unwrap();
+        assert!(!threshold.check_termination(SimTime::injection(1), &network, None));
+        assert!(!threshold.check_termination(SimTime::injection(2), &network, None));
+        assert!(threshold.check_termination(SimTime::injection(3), &network, None));
+        assert_eq!(threshold.to_config(), config);
+    }
+
+    #[test]
+    fn test_min_rds_guard_fires_mode_once_held() {
+        // The load is over the limit from round 1, so a snapshot threshold fires in round 3,
+        // when the guard lifts, and not again while the load stays over.
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+
+        let config = serde_json::json!({
+            "threshold_name": "total_load",
+            "max_load": 1,
+            "mode": "snapshot",
+            "min_rds": 3,
+        });
+        let mut threshold = Threshold::from_config(config.clone()).unwrap();
+        assert_eq!(threshold.mode(), ThresholdMode::Snapshot);
+        let fired: Vec<_> = (1..=5)
+            .map(|rd| threshold.check_termination(SimTime::injection(rd), &network, None))
+            .collect();
+        assert_eq!(fired, [false, false, true, false, false]);
+        assert_eq!(threshold.to_config(), config);
+    }
+
+    #[test]
+    fn test_absorbed_threshold_counts_across_records() {
+        let network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut threshold = AbsorbedThreshold::new(4);
+        // The forwarding records absorb 2, 0, 1, and 1 packets, so the threshold fires at the last,
+        // once exactly 4 packets have been absorbed, and never at injection records.
+        let fired: Vec<_> = [2, 0, 1, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(i, num_absorbed)| {
+                let rd = i + 1;
+                let absorbed = (0..num_absorbed)
+                    .map(|_| factory.create_packet(vec![0, 1], rd, 0))
+                    .collect();
+                let injection = threshold.check_termination(SimTime::injection(rd), &network, None);
+                assert!(!injection);
+                threshold.check_termination(SimTime::forwarding(rd), &network, Some(&absorbed))
+            })
+            .collect();
+        assert_eq!(fired, [false, false, false, true]);
+    }
+
+    #[test]
+    fn test_every_rds_skips_checks() {
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 1, 0), 0, 1);
+
+        let config = serde_json::json!({
+            "threshold_name": "total_load",
+            "max_load": 1,
+            "every_rds": 3,
+        });
+        let mut threshold = Threshold::from_config(config.clone()).unwrap();
+        assert_eq!(threshold.to_config(), config);
+        let fired: Vec<_> = (1..=3)
+            .flat_map(|rd| [SimTime::injection(rd), SimTime::forwarding(rd)])
+            .map(|time| threshold.check_termination(time, &network, None))
+            .collect();
+        assert_eq!(fired, [false, false, false, false, false, true]);
+
+        let timed = serde_json::json!({ "threshold_name": "timed", "max_rds": 10, "every_rds": 4 });
+        assert_eq!(Threshold::from_config(timed).unwrap().max_rds(), Some(12));
+        let zero = serde_json::json!({ "threshold_name": "timed", "max_rds": 10, "every_rds": 0 });
+        assert!(Threshold::from_config(zero).is_err());
+        let starvation = serde_json::json!({
+            "threshold_name": "starvation",
+            "max_starved_rds": 5,
+            "every_rds": 2,
+        });
+        assert!(Threshold::from_config(starvation).is_err());
+    }
+
+    #[test]
+    fn test_every_rds_counts_skipped_absorptions() {
+        let network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let config = serde_json::json!({
+            "threshold_name": "absorbed",
+            "max_absorbed": 3,
+            "every_rds": 2,
+        });
+        let mut threshold = Threshold::from_config(config).unwrap();
+        // One packet is absorbed per round, and the third is only seen at the check in round 4.
+        let fired: Vec<_> = (1..=4)
+            .map(|rd| {
+                let absorbed = vec![factory.create_packet(vec![0, 1], rd, 0)];
+                threshold.check_termination(SimTime::injection(rd), &network, None);
+                threshold.check_termination(SimTime::forwarding(rd), &network, Some(&absorbed))
+            })
+            .collect();
+        assert_eq!(fired, [false, false, false, true]);
+    }
+
+    #[test]
+    fn test_mode_threshold_fires_once_per_episode() {
+        let config = serde_json::json!({
+            "threshold_name": "total_load",
+            "max_load": 2,
+            "mode": "pause",
+        });
+        let mut threshold = Threshold::from_config(config.clone()).unwrap();
+        assert_eq!(threshold.mode(), ThresholdMode::Pause);
+        assert_eq!(threshold.to_config(), config);
+
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        let mut fired = Vec::new();
+        for rd in 1..=4 {
+            network.add_packet(factory.create_packet(vec![0, 1, 2], rd, 0), 0, 1);
+            fired.push(threshold.check_termination(SimTime::injection(rd), &network, None));
+        }
+        network.take_buffer(0, 1);
+        fired.push(threshold.check_termination(SimTime::injection(5), &network, None));
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 6, 0), 0, 1);
+        network.add_packet(factory.create_packet(vec![0, 1, 2], 6, 0), 0, 1);
+        fired.push(threshold.check_termination(SimTime::injection(6), &network, None));
+        assert_eq!(fired, [false, true, false, false, false, true]);
+    }
+
+    #[test]
+    fn test_starvation_of_unserved_buffer() {
+        use crate::adversary::path_random::SDPathRandomAdversary;
+        use crate::adversary::Adversary;
+        use crate::protocol::Protocol;
+        use crate::simulation::Simulation;
+
+        // With a capacity of 0, the buffer at the source is never served once nonempty.
+        let config = serde_json::json!({"threshold_name": "starvation", "max_starved_rds": 4});
+        let threshold = Threshold::from_config(config.clone()).unwrap();
+        assert_eq!(threshold.to_config(), config);
+        let mut simulation = Simulation::new(
+            construct_path(3),
+            Protocol::new_greedy_fifo(0),
+            vec![Adversary::new(SDPathRandomAdversary::from_seed(0))],
+            threshold,
+            Vec::new(),
+            String::new(),
+        );
+        simulation.set_metrics_only(true);
+        assert_eq!(simulation.run().num_rds, 4);
+
+        // A buffer which forwards a packet is served, even if it stays nonempty.
+        let mut network = construct_path(3);
+        let mut factory = PacketFactory::new();
+        network.set_service_tracked(true);
+        for rd in 1..=6 {
+            network.set_cur_rd(rd);
+            network.add_packet(factory.create_packet(vec![0, 1, 2], rd, 0), 0, 1);
+            network.record_service(rd, &[(0, 1)].into_iter().collect());
+        }
+        assert_eq!(network.rds_since_service(0, 1), Some(0));
+        assert_eq!(network.rds_since_service(1, 2), Some(0));
+        let mut threshold = StarvationThreshold::new(1);
+        assert!(!threshold.check_termination(SimTime::forwarding(6), &network, Some(&Vec::new())));
+    }
+}
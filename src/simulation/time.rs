@@ -0,0 +1,105 @@
+//! This module contains `SimTime`, a point in a `Simulation` given by a round and a `Phase` of
+//! that round, which is passed to recorders and thresholds.
+
+use std::fmt;
+
+/// A phase of a round, after which the simulation's state is recorded and its threshold checked.
+/// Phases are ordered as they occur in a round.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Phase {
+    /// After the adversaries have injected packets.
+    Injection,
+    /// After the protocol has forwarded packets (the "prime" state).
+    Forwarding,
+}
+
+impl Phase {
+    /// Every phase of a round, in order.
+    pub const ALL: [Phase; 2] = [Phase::Injection, Phase::Forwarding];
+
+    /// Returns whether this is the "prime" phase, i.e. the state after forwarding.
+    pub fn is_prime(&self) -> bool {
+        *self == Self::Forwarding
+    }
+
+    /// Get the phase with the given prime flag.
+    pub fn from_prime(prime: bool) -> Self {
+        if prime {
+            Self::Forwarding
+        } else {
+            Self::Injection
+        }
+    }
+
+    /// Get the name of this phase.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Injection => "injection",
+            Self::Forwarding => "forwarding",
+        }
+    }
+}
+
+/// A point in a simulation: a round and a phase of it. Times are ordered by round, then by phase.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SimTime {
+    pub round: usize,
+    pub phase: Phase,
+}
+
+impl SimTime {
+    /// Get the time of the given phase of the given round.
+    pub fn new(round: usize, phase: Phase) -> Self {
+        SimTime { round, phase }
+    }
+
+    /// Get the time after injection in the given round.
+    pub fn injection(round: usize) -> Self {
+        Self::new(round, Phase::Injection)
+    }
+
+    /// Get the time after forwarding in the given round.
+    pub fn forwarding(round: usize) -> Self {
+        Self::new(round, Phase::Forwarding)
+    }
+
+    /// Returns whether this time is in the "prime" phase, i.e. after forwarding.
+    pub fn is_prime(&self) -> bool {
+        self.phase.is_prime()
+    }
+}
+
+impl fmt::Display for SimTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "round {} ({})", self.round, self.phase.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_times_are_ordered_by_round_then_phase() {
+        let mut times = vec![
+            SimTime::forwarding(2),
+            SimTime::injection(2),
+            SimTime::forwarding(1),
+            SimTime::injection(3),
+        ];
+        times.sort();
+        assert_eq!(
+            times,
+            [
+                SimTime::forwarding(1),
+                SimTime::injection(2),
+                SimTime::forwarding(2),
+                SimTime::injection(3),
+            ]
+        );
+        assert_eq!(
+            SimTime::new(4, Phase::from_prime(true)),
+            SimTime::forwarding(4)
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! This module contains the `EventTraceRecorder`, which writes a compact trace of a simulation's
+//! events, and `replay`, which reconstructs the state of the network at any recorded round from
+//! such a trace without storing a full snapshot for every round.
+//!
+//! Traces are JSONL files. The first line holds the network's adjacency lists, as
+//! `{"graph_adjacency": [...]}`, and every other line is a single event, tagged with the round and
+//! whether it was observed in the prime (post-forwarding) state:
+//! - `{"rd":1,"prime":false,"event":"inject","packet_id":0,"path":[0,1,2],"path_idx":0,
+//!   "injection_rd":1,"origin":0}` when a packet enters a buffer for the first time,
+//! - `{"rd":1,"prime":true,"event":"move","packet_id":0,"path_idx":1}` when a packet moves along
+//!   its path,
+//! - `{"rd":2,"prime":true,"event":"absorb","packet_id":0}` when a packet is absorbed, and
+//! - `{"rd":2,"prime":true,"event":"remove","packet_id":0}` when a packet leaves the network
+//!   without being absorbed, and
+//! - `{"rd":3,"prime":false,"event":"phase","adversary":0,"phase":"paused"}` when an adversary
+//!   changes phase, which replay skips.
+
+use crate::config::{CfgErrorMsg, Configurable, ADJACENCY_KEY};
+use crate::network::Network;
+use crate::packet::{Packet, PacketFactory, PacketId, PacketPath};
+use crate::simulation::metadata::Metadata;
+use crate::simulation::recorder::{
+    append_to_file, FlushPolicy, FlushTracker, RecordedStates, RecorderTrait, WriteErrorMsg,
+    EVENT_TRACE_NAME, RECORDER_NAME_KEY,
+};
+use crate::simulation::time::SimTime;
+use hashbrown::{HashMap, HashSet};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TRACE_FILENAME: &str = "event_trace.jsonl";
+
+const RD_KEY: &str = "rd";
+const PRIME_KEY: &str = "prime";
+const EVENT_KEY: &str = "event";
+const PACKET_ID_KEY: &str = "packet_id";
+const PATH_KEY: &str = "path";
+const PATH_IDX_KEY: &str = "path_idx";
+const INJECTION_RD_KEY: &str = "injection_rd";
+const ORIGIN_KEY: &str = "origin";
+
+const INJECT_EVENT: &str = "inject";
+const MOVE_EVENT: &str = "move";
+const METADATA_KEY: &str = "metadata";
+const ABSORB_EVENT: &str = "absorb";
+const REMOVE_EVENT: &str = "remove";
+const PHASE_EVENT: &str = "phase";
+const ADVERSARY_KEY: &str = "adversary";
+const PHASE_KEY: &str = "phase";
+
+/// Writes the events between consecutive recorded states to `output_path/event_trace.jsonl`.
+/// Events are found by comparing each packet's position with its position at the previous record,
+/// so the trace is exact only if both the prime and non-prime states are recorded.
+#[derive(Clone)]
+pub struct EventTraceRecorder {
+    states: RecordedStates,
+    lines: Vec<String>,
+    pub(super) flush: FlushTracker,
+    file_path: Option<PathBuf>,
+    // The path index of every packet in the network as of the last record, by packet id.
+    positions: HashMap<PacketId, usize>,
+    header_written: bool,
+    // The metadata written to the header, if any.
+    metadata: Option<Value>,
+    // The first error hit saving lines, if any.
+    write_error: Option<WriteErrorMsg>,
+}
+
+impl EventTraceRecorder {
+    /// Get a new `EventTraceRecorder`.
+    pub fn new() -> Self {
+        EventTraceRecorder {
+            states: RecordedStates::Both,
+            lines: Vec::new(),
+            flush: FlushTracker::default(),
+            file_path: None,
+            positions: HashMap::new(),
+            header_written: false,
+            metadata: None,
+            write_error: None,
+        }
+    }
+
+    /// Write a line to the trace.
+    fn write(&mut self, line: Value) {
+        self.flush_if_due();
+        let line = format!("{}\n", line);
+        self.flush.add_line(&line);
+        self.lines.push(line);
+    }
+
+    /// Save and clear the buffered lines if the flush policy says so.
+    fn flush_if_due(&mut self) {
+        if self.flush.is_due(self.lines.len()) {
+            self.save();
+            self.lines = Vec::new();
+            self.flush.reset();
+        }
+    }
+
+    /// Write the header, with the network's adjacency lists, if it hasn't been written yet.
+    fn write_header(&mut self, network: &Network) {
+        if !self.header_written {
+            let mut header = json!({ ADJACENCY_KEY: network.to_config() });
+            if let Some(metadata) = &self.metadata {
+                header[METADATA_KEY] = metadata.clone();
+            }
+            self.write(header);
+            self.header_written = true;
+        }
+    }
+
+    /// Save the lines to the trace file.
+    fn save(&mut self) {
+        let data = self.lines.concat();
+        let file_path = self
+            .file_path
+            .as_ref()
+            .expect("You must set an output path for each recorder.");
+
+        if let Err(msg) = append_to_file(file_path, &data) {
+            self.write_error.get_or_insert(msg);
+        }
+    }
+}
+
+impl Default for EventTraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Configurable for EventTraceRecorder {
+    fn from_config(_config: Value) -> Result<Self, CfgErrorMsg> {
+        Ok(Self::new())
+    }
+
+    fn to_config(&self) -> Value {
+        json!({ RECORDER_NAME_KEY: EVENT_TRACE_NAME })
+    }
+}
+
+impl RecorderTrait for EventTraceRecorder {
+    fn record(&mut self, time: SimTime, network: &Network, absorbed: Option<&Vec<Packet>>) {
+        let (rd, prime) = (time.round, time.is_prime());
+        self.flush.start_rd(rd);
+        self.flush_if_due();
+        self.write_header(network);
+
+        let mut seen = HashSet::new();
+        for edge in network.edges() {
+            for p in edge.iter_packets() {
+                seen.insert(p.id());
+                match self.positions.insert(p.id(), p.get_path_idx()) {
+                    None => self.write(json!({
+                        RD_KEY: rd,
+                        PRIME_KEY: prime,
+                        EVENT_KEY: INJECT_EVENT,
+                        PACKET_ID_KEY: p.id(),
+                        PATH_KEY: p.get_path(),
+                        PATH_IDX_KEY: p.get_path_idx(),
+                        INJECTION_RD_KEY: p.injection_rd(),
+                        ORIGIN_KEY: p.origin(),
+                    })),
+                    Some(path_idx) if path_idx != p.get_path_idx() => self.write(json!({
+                        RD_KEY: rd,
+                        PRIME_KEY: prime,
+                        EVENT_KEY: MOVE_EVENT,
+                        PACKET_ID_KEY: p.id(),
+                        PATH_IDX_KEY: p.get_path_idx(),
+                    })),
+                    Some(_) => (),
+                }
+            }
+        }
+
+        // Packets which are no longer in any buffer have either been absorbed or removed.
+        let absorbed_ids: HashSet<PacketId> = absorbed
+            .map(|packets| packets.iter().map(|p| p.id()).collect())
+            .unwrap_or_default();
+        let mut gone: Vec<PacketId> = self
+            .positions
+            .keys()
+            .filter(|id| !seen.contains(*id))
+            .copied()
+            .collect();
+        gone.sort_unstable();
+        for id in gone {
+            self.positions.remove(&id);
+            let event = if absorbed_ids.contains(&id) {
+                ABSORB_EVENT
+            } else {
+                REMOVE_EVENT
+            };
+            self.write(json!({
+                RD_KEY: rd,
+                PRIME_KEY: prime,
+                EVENT_KEY: event,
+                PACKET_ID_KEY: id,
+            }));
+        }
+    }
+
+    fn set_output_path(&mut self, dir_path: &Path) {
+        fs::create_dir_all(dir_path).unwrap_or_else(|_| {
+            panic!(
+                "Failed to save simulation results to {}",
+                dir_path.display()
+            )
+        });
+        self.file_path = Some(dir_path.join(TRACE_FILENAME));
+    }
+
+    /// Write the header and an injection event for every packet already in the network, at the
+    /// given state, so that the trace can be replayed from it.
+    fn attach(&mut self, time: SimTime, network: &Network) {
+        self.record(time, network, None);
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) {
+        self.metadata = Some(metadata.to_json());
+    }
+
+    fn record_phase(
+        &mut self,
+        time: SimTime,
+        network: &Network,
+        adversary_idx: usize,
+        phase: &str,
+    ) {
+        self.flush.start_rd(time.round);
+        self.write_header(network);
+        self.write(json!({
+            RD_KEY: time.round,
+            PRIME_KEY: time.is_prime(),
+            EVENT_KEY: PHASE_EVENT,
+            ADVERSARY_KEY: adversary_idx,
+            PHASE_KEY: phase,
+        }));
+    }
+
+    fn close(&mut self) {
+        self.save();
+    }
+
+    fn recorded_states(&self) -> RecordedStates {
+        self.states
+    }
+
+    fn set_recorded_states(&mut self, states: RecordedStates) {
+        self.states = states;
+    }
+
+    fn write_error(&self) -> Option<&WriteErrorMsg> {
+        self.write_error.as_ref()
+    }
+
+    fn flush_policy(&self) -> Option<FlushPolicy> {
+        Some(self.flush.policy())
+    }
+
+    fn set_flush_policy(&mut self, policy: FlushPolicy) -> Result<(), CfgErrorMsg> {
+        self.flush.set_policy(policy);
+        Ok(())
+    }
+}
+
+fn get_usize(event: &Value, key: &str) -> Result<usize, CfgErrorMsg> {
+    match event.get(key).and_then(Value::as_u64) {
+        Some(val) => Ok(val as usize),
+        None => Err(format!("No {} in trace event {}.", key, event)),
+    }
+}
+
+/// Reconstruct the `Network` as it was in the given round from a trace written by an
+/// `EventTraceRecorder`: after injection if `prime` is false, and after forwarding otherwise.
+/// Packets in each buffer are in the order in which they arrived there.
+pub fn replay(trace: &str, rd: usize, prime: bool) -> Result<Network, CfgErrorMsg> {
+    let mut lines = trace.lines().filter(|line| !line.trim().is_empty());
+    let header: Value = match lines.next().map(serde_json::from_str) {
+        Some(Ok(header)) => Ok(header),
+        _ => Err(String::from("Trace has no header.")),
+    }?;
+    let mut network = match header.get(ADJACENCY_KEY) {
+        Some(adjacency) => Network::from_config(adjacency.clone()),
+        None => Err(String::from("No graph adjacency in trace header.")),
+    }?;
+
+    let mut factory = PacketFactory::new();
+    // Packets in the network, by id, along with the index of the event in which they last
+    // arrived at a buffer.
+    let mut live: HashMap<PacketId, (Packet, usize)> = HashMap::new();
+    for (seq, line) in lines.enumerate() {
+        let event: Value = serde_json::from_str(line)
+            .map_err(|err| format!("Invalid trace event {}: {}", line, err))?;
+        let event_rd = get_usize(&event, RD_KEY)?;
+        let event_prime = match event.get(PRIME_KEY) {
+            Some(Value::Bool(event_prime)) => Ok(*event_prime),
+            _ => Err(format!("No prime flag in trace event {}.", line)),
+        }?;
+        if (event_rd, event_prime) > (rd, prime) {
+            break;
+        }
+        if event.get(EVENT_KEY).and_then(Value::as_str) == Some(PHASE_EVENT) {
+            continue;
+        }
+
+        let id = PacketId(get_usize(&event, PACKET_ID_KEY)?);
+        match event.get(EVENT_KEY).and_then(Value::as_str) {
+            Some(INJECT_EVENT) => {
+                let path: Option<PacketPath> = event
+                    .get(PATH_KEY)
+                    .and_then(Value::as_array)
+                    .and_then(|ids| {
+                        ids.iter()
+                            .map(|id| id.as_u64().map(|id| id as usize))
+                            .collect()
+                    });
+                let path = path.ok_or(format!("No path in trace event {}.", line))?;
+                factory.set_origin(get_usize(&event, ORIGIN_KEY)?);
+                let p = factory.restore_packet(
+                    id,
+                    path,
+                    get_usize(&event, INJECTION_RD_KEY)?,
+                    get_usize(&event, PATH_IDX_KEY)?,
+                );
+                live.insert(id, (p, seq));
+            }
+            Some(MOVE_EVENT) => {
+                let (mut p, _) = live
+                    .remove(&id)
+                    .ok_or(format!("Packet {} moved before it was injected.", id))?;
+                p.set_path_idx(get_usize(&event, PATH_IDX_KEY)?);
+                live.insert(id, (p, seq));
+            }
+            Some(ABSORB_EVENT) | Some(REMOVE_EVENT) => {
+                live.remove(&id);
+            }
+            _ => return Err(format!("Unknown trace event {}.", line)),
+        }
+    }
+
+    let mut packets: Vec<(Packet, usize)> = live.into_values().collect();
+    packets.sort_by_key(|(_, seq)| *seq);
+    for (p, _) in packets {
+        let (from_id, to_id) = (p.cur_node().unwrap(), p.next_node().unwrap());
+        network.add_packet(p, from_id, to_id);
+    }
+    Ok(network)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adversary::path_random::SDPathRandomBurstyAdversary;
+    use crate::adversary::AdversaryTrait;
+    use crate::network::presets::construct_path;
+    use crate::protocol::Protocol;
+
+    fn buffer_ids(network: &Network) -> Vec<Vec<PacketId>> {
+        network
+            .edges()
+            .into_iter()
+            .map(|edge| {
+                let mut ids: Vec<PacketId> = edge.iter_packets().map(|p| p.id()).collect();
+                ids.sort_unstable();
+                ids
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_matches_simulation() {
+        let mut network = construct_path(5);
+        let mut protocol = Protocol::new_greedy_fifo(1);
+        let mut adversary = SDPathRandomBurstyAdversary::from_seed(3, 3);
+        let mut factory = PacketFactory::new();
+        let mut recorder = EventTraceRecorder::new();
+
+        let mut states = Vec::new();
+        for rd in 1..=30 {
+            for p in adversary.get_next_packets(&network, rd, &mut factory) {
+                protocol.add_packet(p, &mut network);
+            }
+            recorder.record(SimTime::injection(rd), &network, None);
+            states.push((rd, false, buffer_ids(&network)));
+
+            let absorbed = protocol.forward_packets(&mut network);
+            recorder.record(SimTime::forwarding(rd), &network, Some(&absorbed));
+            states.push((rd, true, buffer_ids(&network)));
+        }
+
+        let trace = recorder.lines.concat();
+        for (rd, prime, ids) in states {
+            let replayed = replay(&trace, rd, prime).unwrap();
+            assert_eq!(buffer_ids(&replayed), ids);
+        }
+    }
+}
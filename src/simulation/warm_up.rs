@@ -0,0 +1,187 @@
+//! This module contains the protocol warm-up check, which runs a simulation's protocol for a few
+//! rounds on a miniature copy of its network before the real run, so that a misconfigured protocol
+//! fails at startup rather than partway through a long run.
+
+use crate::network::{Network, NodeID};
+use crate::packet::{PacketFactory, PacketId, PacketPath};
+use crate::protocol::Protocol;
+use crate::simulation::status::panic_message;
+use hashbrown::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+
+/// The most nodes of the network which the miniature copy keeps.
+pub const WARM_UP_NUM_NODES: usize = 8;
+
+/// Run a fresh copy of the given protocol for `num_rds` rounds on the subgraph of the given
+/// network induced by its first `WARM_UP_NUM_NODES` nodes. Each round, one packet is injected at
+/// every node but the last of the path from node 0 which repeatedly takes the smallest-id
+/// unvisited neighbor, as path adversaries do. After each round's forwarding, checks that
+///
+/// - no packet was lost or duplicated,
+/// - no buffer forwarded more packets than the protocol's capacity, and
+///
+/// once the rounds are done, that at least one packet was absorbed. Returns a message describing
+/// the first violation found, if any, or the panic if the protocol panicked, e.g. because it
+/// doesn't support the network's structure.
+pub fn check_protocol(
+    protocol: &Protocol,
+    network: &Network,
+    num_rds: usize,
+) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(|| run_warm_up(protocol, network, num_rds)))
+        .unwrap_or_else(|payload| {
+            Err(format!(
+                "The protocol panicked: {}",
+                panic_message(payload.as_ref())
+            ))
+        })
+}
+
+fn run_warm_up(protocol: &Protocol, network: &Network, num_rds: usize) -> Result<(), String> {
+    let mut network = miniature(network);
+    let mut protocol = protocol.clone();
+    let mut factory = PacketFactory::new();
+    if network.get_num_nodes() == 0 {
+        return Ok(());
+    }
+    let path = deterministic_path(&network, 0);
+    if path.len() < 2 {
+        return Ok(());
+    }
+
+    let capacity = protocol.capacity();
+    let mut num_injected = 0;
+    let mut num_absorbed = 0;
+    for rd in 1..=num_rds {
+        network.set_cur_rd(rd);
+        for src_idx in 0..path.len() - 1 {
+            protocol.add_packet(
+                factory.create_packet(path.clone(), rd, src_idx),
+                &mut network,
+            );
+            num_injected += 1;
+        }
+
+        let before = positions(&network)?;
+        let absorbed = protocol.forward_packets(&mut network);
+        let after = positions(&network)?;
+        num_absorbed += absorbed.len();
+        if after.len() + num_absorbed != num_injected {
+            return Err(format!(
+                "In warm-up round {}, {} packets were injected but {} are in the network and {} \
+                 were absorbed.",
+                rd,
+                num_injected,
+                after.len(),
+                num_absorbed
+            ));
+        }
+
+        // A packet was forwarded from its buffer if it was absorbed or moved further along its
+        // path; packets sent backward don't count against the capacity.
+        let mut num_forwarded: HashMap<(NodeID, NodeID), usize> = HashMap::new();
+        let forwarded = before.iter().filter(|(id, (_, path_idx))| {
+            after
+                .get(*id)
+                .is_none_or(|(_, new_path_idx)| new_path_idx > path_idx)
+        });
+        for (_, (edge, _)) in forwarded {
+            *num_forwarded.entry(*edge).or_default() += 1;
+        }
+        if let Some((edge, num)) = num_forwarded.iter().find(|(_, num)| **num > capacity) {
+            return Err(format!(
+                "In warm-up round {}, buffer {:?} forwarded {} packets, over the protocol's \
+                 capacity of {}.",
+                rd, edge, num, capacity
+            ));
+        }
+    }
+    if num_absorbed == 0 {
+        return Err(format!(
+            "No packet was absorbed in {} warm-up rounds.",
+            num_rds
+        ));
+    }
+    Ok(())
+}
+
+/// Get the subgraph of the given network induced by its first `WARM_UP_NUM_NODES` nodes.
+fn miniature(network: &Network) -> Network {
+    let num_nodes = network.get_num_nodes().min(WARM_UP_NUM_NODES);
+    let mut mini = Network::new();
+    for _ in 0..num_nodes {
+        mini.add_node();
+    }
+    let mut edges = network.get_edgebuffers();
+    edges.sort_unstable();
+    for (from_id, to_id) in edges {
+        if from_id < num_nodes && to_id < num_nodes {
+            mini.add_edgebuffer(from_id, to_id);
+        }
+    }
+    mini
+}
+
+/// The path from the given node which repeatedly takes the smallest-id unvisited neighbor.
+fn deterministic_path(network: &Network, src_id: NodeID) -> PacketPath {
+    let mut path = vec![src_id];
+    let mut visited: HashSet<NodeID> = [src_id].into_iter().collect();
+    while let Some(next_id) = network
+        .get_neighbors(*path.last().unwrap())
+        .into_iter()
+        .filter(|id| !visited.contains(id))
+        .min()
+    {
+        visited.insert(next_id);
+        path.push(next_id);
+    }
+    path
+}
+
+// The buffer and path index of each packet in a network.
+type Positions = HashMap<PacketId, ((NodeID, NodeID), usize)>;
+
+/// Get the buffer and path index of every packet in the network, failing if a packet is in two
+/// places.
+fn positions(network: &Network) -> Result<Positions, String> {
+    let mut positions = HashMap::new();
+    for edge in network.edges() {
+        for p in edge.iter_packets() {
+            if positions
+                .insert(p.id(), (edge.ids(), p.get_path_idx()))
+                .is_some()
+            {
+                return Err(format!("Packet {:?} is in the network twice.", p.id()));
+            }
+        }
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Configurable;
+    use crate::network::presets::{construct_layered, construct_path};
+
+    #[test]
+    fn test_warm_up_catches_misconfigured_protocols() {
+        let path = construct_path(20);
+        for description in Protocol::descriptions() {
+            let protocol = Protocol::from_config(description.example_config()).unwrap();
+            assert_eq!(check_protocol(&protocol, &path, 10), Ok(()));
+        }
+        let layered = construct_layered(3, 4);
+        assert_eq!(
+            check_protocol(&Protocol::new_greedy_fifo(2), &layered, 10),
+            Ok(())
+        );
+
+        let stuck = Protocol::new_greedy_fifo(0);
+        let msg = check_protocol(&stuck, &path, 10).unwrap_err();
+        assert!(msg.contains("No packet was absorbed"), "{}", msg);
+        // OED with swap only supports paths.
+        let msg = check_protocol(&Protocol::new_oed_with_swap(), &layered, 10).unwrap_err();
+        assert!(msg.starts_with("The protocol panicked"), "{}", msg);
+    }
+}
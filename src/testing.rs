@@ -0,0 +1,179 @@
+//! This module contains helpers for golden-trace regression tests: run a seeded simulation, and
+//! compare its event trace (see `simulation::trace`) against a stored golden file, to detect
+//! behavioral drift in protocols and adversaries across refactors.
+//!
+//! A typical test looks like:
+//! ```no_run
+//! use aqt_sim::config::SimConfig;
+//! use aqt_sim::testing;
+//!
+//! let data = std::fs::read_to_string("tests/my_protocol.json").unwrap();
+//! let sim_config = SimConfig::from_val(serde_json::from_str(&data).unwrap()).unwrap();
+//! testing::check_golden(sim_config, "tests/golden/my_protocol.jsonl").unwrap();
+//! ```
+//! Run the tests with the `AQT_UPDATE_GOLDEN` environment variable set to regenerate the golden
+//! files instead of checking against them.
+//!
+//! Every adversary in the simulation must be seeded for its trace to be reproducible. Traces are
+//! compared in a canonical form, in which the events within each half-round are sorted, so that
+//! they don't depend on the order in which a network's buffers are iterated.
+
+use crate::config::{CfgErrorMsg, SimConfig, ADJACENCY_KEY};
+use crate::simulation::Simulation;
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// If this environment variable is set, `check_golden` overwrites golden files rather than
+/// checking against them.
+pub const UPDATE_GOLDEN_VAR: &str = "AQT_UPDATE_GOLDEN";
+
+const TRACE_FILENAME: &str = "event_trace.jsonl";
+
+// Distinguishes the output directories of traces run concurrently by the same process.
+static NUM_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Run the given simulation with only an event trace recorder, in a temporary output directory,
+/// and return its trace in canonical form.
+pub fn run_trace(sim_config: SimConfig) -> Result<String, CfgErrorMsg> {
+    let run_idx = NUM_RUNS.fetch_add(1, Ordering::SeqCst);
+    let output_path = env::temp_dir().join(format!("aqt_sim_golden_{}_{}", process::id(), run_idx));
+    let mut sim_config = sim_config;
+    sim_config.output_path = output_path.to_string_lossy().to_string();
+    sim_config.recorder_cfgs = json!([{ "recorder_name": "event_trace" }]);
+
+    Simulation::from_config(sim_config).run();
+    let trace = fs::read_to_string(output_path.join(TRACE_FILENAME))
+        .map_err(|err| format!("Failed to read trace: {}", err));
+    let _ = fs::remove_dir_all(&output_path);
+    canonicalize(&trace?)
+}
+
+/// Run the given simulation and overwrite the golden file with its trace.
+pub fn update_golden<P: AsRef<Path>>(
+    sim_config: SimConfig,
+    golden_path: P,
+) -> Result<(), CfgErrorMsg> {
+    let golden_path = golden_path.as_ref();
+    let trace = run_trace(sim_config)?;
+    if let Some(dir_path) = golden_path.parent() {
+        fs::create_dir_all(dir_path)
+            .map_err(|err| format!("Failed to create {}: {}", dir_path.display(), err))?;
+    }
+    fs::write(golden_path, trace)
+        .map_err(|err| format!("Failed to write {}: {}", golden_path.display(), err))
+}
+
+/// Run the given simulation and check its trace against the golden file, returning a description
+/// of the first difference if there is one. If `UPDATE_GOLDEN_VAR` is set, overwrite the golden
+/// file instead.
+pub fn check_golden<P: AsRef<Path>>(
+    sim_config: SimConfig,
+    golden_path: P,
+) -> Result<(), CfgErrorMsg> {
+    let golden_path = golden_path.as_ref();
+    if env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        return update_golden(sim_config, golden_path);
+    }
+    let golden = fs::read_to_string(golden_path).map_err(|err| {
+        format!(
+            "Failed to read golden file {} ({}); set {} to create it.",
+            golden_path.display(),
+            err,
+            UPDATE_GOLDEN_VAR
+        )
+    })?;
+    compare_traces(&canonicalize(&golden)?, &run_trace(sim_config)?)
+}
+
+/// Compare two canonical traces, returning a description of the first difference if there is one.
+pub fn compare_traces(expected: &str, actual: &str) -> Result<(), CfgErrorMsg> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line_idx = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return Ok(()),
+            (expected_line, actual_line) if expected_line == actual_line => (),
+            (expected_line, actual_line) => {
+                return Err(format!(
+                    "Trace differs from golden at line {}:\n  expected: {}\n  actual:   {}",
+                    line_idx,
+                    expected_line.unwrap_or("<end of trace>"),
+                    actual_line.unwrap_or("<end of trace>")
+                ))
+            }
+        }
+        line_idx += 1;
+    }
+}
+
+/// Put a trace in canonical form: the header's adjacency lists are sorted, and the events within
+/// each half-round are sorted by packet id. Note that the order in which packets arrived at the
+/// same buffer within a half-round is then lost when the trace is replayed.
+pub fn canonicalize(trace: &str) -> Result<String, CfgErrorMsg> {
+    let mut lines = trace.lines().filter(|line| !line.trim().is_empty());
+    let mut header: Value = match lines.next().map(serde_json::from_str) {
+        Some(Ok(header)) => Ok(header),
+        _ => Err(String::from("Trace has no header.")),
+    }?;
+    if let Some(Value::Array(adj_lists)) = header.get_mut(ADJACENCY_KEY) {
+        for adj_list in adj_lists.iter_mut() {
+            if let Value::Array(neighbors) = adj_list {
+                neighbors.sort_by_key(|id| id.as_u64());
+            }
+        }
+    }
+
+    let mut events: Vec<(u64, bool, u64, String)> = Vec::new();
+    for line in lines {
+        let event: Value = serde_json::from_str(line)
+            .map_err(|err| format!("Invalid trace event {}: {}", line, err))?;
+        let rd = event.get("rd").and_then(Value::as_u64);
+        let prime = event.get("prime").and_then(Value::as_bool);
+        let id = event.get("packet_id").and_then(Value::as_u64);
+        match (rd, prime, id) {
+            (Some(rd), Some(prime), Some(id)) => events.push((rd, prime, id, event.to_string())),
+            _ => return Err(format!("Invalid trace event {}.", line)),
+        }
+    }
+    // A stable sort, so that the order of a packet's events within a half-round is kept.
+    events.sort_by_key(|(rd, prime, id, _)| (*rd, *prime, *id));
+
+    let mut result = format!("{}\n", header);
+    for (_, _, _, event) in events {
+        result.push_str(&event);
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sim_config(seed: u64) -> SimConfig {
+        SimConfig::from_val(json!({
+            "graph_adjacency": [[1], [2], [3], [4], []],
+            "protocol": {"protocol_name": "greedy_fifo", "capacity": 1},
+            "adversary": {"adversary_name": "sd_path_random_bursty", "sigma": 2, "seed": seed},
+            "threshold": {"threshold_name": "timed", "max_rds": 30},
+            "recorders": [],
+            "output_path": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_trace_is_reproducible() {
+        let trace = run_trace(sim_config(4)).unwrap();
+        assert_eq!(
+            compare_traces(&trace, &run_trace(sim_config(4)).unwrap()),
+            Ok(())
+        );
+        assert!(compare_traces(&trace, &run_trace(sim_config(5)).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! This module contains `proptest` strategies for random networks, packet paths, and admissible
+//! injection sequences, along with checks of the invariants every protocol must uphold, so that
+//! protocol authors can property-test their implementations. It is only available with the
+//! `testkit` feature.
+//!
+//! For example, to check that a protocol neither loses, duplicates, nor teleports packets:
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn my_protocol_is_sound((adj_lists, injections) in arb_adj_lists(6)
+//!         .prop_flat_map(|adj_lists| (Just(adj_lists.clone()), arb_injections(adj_lists, 20, 2))))
+//!     {
+//!         let mut protocol = my_protocol();
+//!         run_checked(adj_lists, &injections, &mut protocol).unwrap();
+//!     }
+//! }
+//! ```
+
+use crate::network::{Network, NodeID};
+use crate::packet::{PacketFactory, PacketPath};
+pub use crate::protocol::conformance::check_forward;
+use crate::protocol::Protocol;
+use hashbrown::HashMap;
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+/// The adjacency lists of a random directed acyclic graph with between 2 and `max_nodes` nodes,
+/// in which every edge goes from a lower to a higher `NodeID`, and node `i` always has an edge to
+/// node `i + 1` so that the graph contains a path through every node.
+pub fn arb_adj_lists(max_nodes: usize) -> impl Strategy<Value = Vec<Vec<NodeID>>> {
+    (2..=max_nodes.max(2)).prop_flat_map(|num_nodes| {
+        let num_pairs = num_nodes * (num_nodes - 1) / 2;
+        prop::collection::vec(any::<bool>(), num_pairs).prop_map(move |has_edge| {
+            let mut has_edge = has_edge.into_iter();
+            (0..num_nodes)
+                .map(|from_id| {
+                    ((from_id + 1)..num_nodes)
+                        .filter(|to_id| has_edge.next().unwrap() || *to_id == from_id + 1)
+                        .collect()
+                })
+                .collect()
+        })
+    })
+}
+
+/// A random path in the graph with the given adjacency lists, of at least one edge, which starts
+/// at a random node with an out-edge and follows random out-edges until it stops at random or
+/// reaches a node with none. The graph must be acyclic and have at least one edge.
+pub fn arb_path(adj_lists: Vec<Vec<NodeID>>) -> impl Strategy<Value = PacketPath> {
+    (
+        any::<Index>(),
+        prop::collection::vec(any::<Index>(), adj_lists.len()),
+        any::<Index>(),
+    )
+        .prop_map(move |(start, steps, len)| random_path(&adj_lists, start, &steps, len))
+}
+
+fn random_path(adj_lists: &[Vec<NodeID>], start: Index, steps: &[Index], len: Index) -> PacketPath {
+    let sources: Vec<NodeID> = (0..adj_lists.len())
+        .filter(|node_id| !adj_lists[*node_id].is_empty())
+        .collect();
+    let mut path = vec![*start.get(&sources)];
+    // The number of edges in the path, if the path doesn't reach a node with no out-edges first.
+    let max_edges = 1 + len.index(adj_lists.len());
+    for step in steps.iter().take(max_edges) {
+        let neighbors = &adj_lists[*path.last().unwrap()];
+        if neighbors.is_empty() {
+            break;
+        }
+        path.push(*step.get(neighbors));
+    }
+    path
+}
+
+/// A random injection sequence of `num_rds` rounds in the graph with the given adjacency lists,
+/// given as the paths of the packets injected in each round. The sequence is admissible for a
+/// (1, `sigma`)-bounded adversary: for every edge and every `t` consecutive rounds, at most
+/// `t + sigma` of the injected paths cross the edge.
+pub fn arb_injections(
+    adj_lists: Vec<Vec<NodeID>>,
+    num_rds: usize,
+    sigma: usize,
+) -> impl Strategy<Value = Vec<Vec<PacketPath>>> {
+    let rd_paths = prop::collection::vec(arb_path(adj_lists), 0..=(sigma + 2));
+    prop::collection::vec(rd_paths, num_rds).prop_map(move |rds| {
+        // Drop paths which would exceed a token bucket of size sigma + 1 refilled once per round.
+        let mut tokens: HashMap<(NodeID, NodeID), usize> = HashMap::new();
+        rds.into_iter()
+            .map(|paths| {
+                for edge_tokens in tokens.values_mut() {
+                    *edge_tokens = (*edge_tokens + 1).min(sigma + 1);
+                }
+                paths
+                    .into_iter()
+                    .filter(|path| {
+                        let admissible = path
+                            .windows(2)
+                            .all(|edge| *tokens.entry((edge[0], edge[1])).or_insert(sigma + 1) > 0);
+                        if admissible {
+                            for edge in path.windows(2) {
+                                *tokens.get_mut(&(edge[0], edge[1])).unwrap() -= 1;
+                            }
+                        }
+                        admissible
+                    })
+                    .collect()
+            })
+            .collect()
+    })
+}
+
+/// Run the given protocol on the network with the given adjacency lists, injecting the given
+/// packets in each round (see `arb_injections`), and `check_forward` every round. Returns the
+/// total number of packets absorbed.
+pub fn run_checked(
+    adj_lists: Vec<Vec<NodeID>>,
+    injections: &[Vec<PacketPath>],
+    protocol: &mut Protocol,
+) -> Result<usize, String> {
+    let mut network = Network::from_adj_lists(adj_lists);
+    let mut factory = PacketFactory::new();
+    let mut num_absorbed = 0;
+    for (rd_idx, paths) in injections.iter().enumerate() {
+        for path in paths {
+            let p = factory.create_packet(path.clone(), rd_idx + 1, 0);
+            protocol.add_packet(p, &mut network);
+        }
+        let before = network.clone();
+        let absorbed = protocol.forward_packets(&mut network);
+        check_forward(&before, &network, &absorbed)
+            .map_err(|msg| format!("In round {}: {}", rd_idx + 1, msg))?;
+        num_absorbed += absorbed.len();
+    }
+    Ok(num_absorbed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::greedy::Greedy;
+
+    proptest! {
+        #[test]
+        fn test_greedy_protocols_are_sound(
+            (adj_lists, injections) in arb_adj_lists(6).prop_flat_map(|adj_lists| {
+                (Just(adj_lists.clone()), arb_injections(adj_lists, 20, 2))
+            })
+        ) {
+            for mut protocol in [Protocol::new_greedy_fifo(1), Protocol::new(Greedy::lis(1))] {
+                let result = run_checked(adj_lists.clone(), &injections, &mut protocol);
+                prop_assert!(result.is_ok(), "{}", result.unwrap_err());
+            }
+        }
+
+        #[test]
+        fn test_injections_are_admissible(
+            injections in arb_adj_lists(5).prop_flat_map(|adj_lists| {
+                arb_injections(adj_lists, 10, 1)
+            })
+        ) {
+            // With sigma = 1, no edge is crossed by more than 2 packets in a single round.
+            for paths in injections {
+                let mut counts: HashMap<(NodeID, NodeID), usize> = HashMap::new();
+                for path in paths {
+                    for edge in path.windows(2) {
+                        *counts.entry((edge[0], edge[1])).or_default() += 1;
+                    }
+                }
+                prop_assert!(counts.values().all(|count| *count <= 2));
+            }
+        }
+    }
+}